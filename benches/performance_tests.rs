@@ -1,18 +1,55 @@
 //! Performance benchmarks for LogStream
 
-use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId, Throughput};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId, Throughput};
 use logstream::client::LogClient;
-use logstream::config::ServerConfig;
+use logstream::config::{ClientConfig, ServerConfig};
 use logstream::server::LogServer;
-use logstream::types::{LogEntry, LogLevel};
+use logstream::types::{LogEntry, LogEntryStream, LogLevel};
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tempfile::tempdir;
+use tokio::io::AsyncBufReadExt;
 use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
+
+mod support;
+
+use support::BenchServer;
+
+/// System allocator that counts allocations, for allocation-sensitive benches
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Allocations performed by `f`
+fn count_allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
 
 /// Benchmark single client throughput
+///
+/// One server and one connected client serve every iteration, so this
+/// measures steady-state per-message cost; server startup, socket binding
+/// and connecting are outside the timed loop. Each iteration ends once the
+/// server confirms it has read everything sent.
 fn bench_single_client_throughput(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
     
@@ -20,49 +57,39 @@ fn bench_single_client_throughput(c: &mut Criterion) {
     group.measurement_time(Duration::from_secs(10));
     group.sample_size(10);
     
-    for message_count in [100, 1000, 5000].iter() {
-        group.throughput(Throughput::Elements(*message_count as u64));
-        group.bench_with_input(
-            BenchmarkId::from_parameter(message_count),
-            message_count,
-            |b, &count| {
-                b.to_async(&rt).iter(|| async move {
-                    let temp_dir = tempdir().unwrap();
-                    let socket_path = temp_dir.path().join("bench.sock");
-                    let mut config = ServerConfig::default();
-                    config.server.socket_path = socket_path.to_string_lossy().to_string();
-                    config.storage.output_directory = temp_dir.path().to_path_buf();
-
-                    let server = LogServer::new(config).await.unwrap();
-                    
-                    // Start server
-                    let server_handle = tokio::spawn(async move {
-                        server.start().await
+    // "null" disables every backend to isolate the socket and parsing path
+    for (storage, store) in [("file", true), ("null", false)] {
+        let server = BenchServer::start(&rt, store);
+        let client = server.client(&rt, "bench-client");
+
+        for message_count in [100, 1000, 5000].iter() {
+            group.throughput(Throughput::Elements(*message_count as u64));
+            group.bench_with_input(
+                BenchmarkId::new(storage, message_count),
+                message_count,
+                |b, &count| {
+                    let client = &client;
+                    b.to_async(&rt).iter(|| async move {
+                        for i in 0..count {
+                            client.info(&format!("Benchmark message {}", i)).await.unwrap();
+                        }
+                        support::confirm(client).await;
                     });
+                },
+            );
+        }
 
-                    tokio::time::sleep(Duration::from_millis(100)).await;
-
-                    let client = LogClient::connect(
-                        &socket_path.to_string_lossy(),
-                        "bench-client"
-                    ).await.unwrap();
-
-                    // Benchmark sending messages
-                    for i in 0..count {
-                        client.info(&format!("Benchmark message {}", i)).await.unwrap();
-                    }
-                    
-                    client.close().await.unwrap();
-                    server_handle.abort();
-                });
-            },
-        );
+        rt.block_on(client.close()).unwrap();
+        server.stop(&rt);
     }
     
     group.finish();
 }
 
 /// Benchmark concurrent clients
+///
+/// Like the throughput bench, the server and its clients are set up once
+/// per client count and reused by every iteration.
 fn bench_concurrent_clients(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
     
@@ -71,41 +98,24 @@ fn bench_concurrent_clients(c: &mut Criterion) {
     group.sample_size(10);
     
     for client_count in [5, 10, 20].iter() {
+        let server = BenchServer::start(&rt, true);
+        let clients = server.clients(&rt, *client_count);
+
+        group.throughput(Throughput::Elements(*client_count as u64 * 100));
         group.bench_with_input(
             BenchmarkId::new("clients", client_count),
-            client_count,
-            |b, &num_clients| {
-                b.to_async(&rt).iter(|| async move {
-                    let temp_dir = tempdir().unwrap();
-                    let socket_path = temp_dir.path().join("concurrent.sock");
-                    let mut config = ServerConfig::default();
-                    config.server.socket_path = socket_path.to_string_lossy().to_string();
-                    config.storage.output_directory = temp_dir.path().to_path_buf();
-
-                    let server = LogServer::new(config).await.unwrap();
-                    
-                    // Start server
-                    let server_handle = tokio::spawn(async move {
-                        server.start().await
-                    });
-
-                    tokio::time::sleep(Duration::from_millis(200)).await;
-
+            &clients,
+            |b, clients| {
+                b.to_async(&rt).iter(|| async {
                     // Launch concurrent clients
                     let mut handles = vec![];
-                    for i in 0..num_clients {
-                        let socket_str = socket_path.to_string_lossy().to_string();
+                    for (i, client) in clients.iter().enumerate() {
+                        let client = Arc::clone(client);
                         let handle = tokio::spawn(async move {
-                            let client = LogClient::connect(
-                                &socket_str,
-                                &format!("client-{}", i)
-                            ).await.unwrap();
-                            
                             for j in 0..100 {
                                 client.info(&format!("Message {} from client {}", j, i)).await.unwrap();
                             }
-                            
-                            client.close().await.unwrap();
+                            support::confirm(&client).await;
                         });
                         handles.push(handle);
                     }
@@ -114,11 +124,14 @@ fn bench_concurrent_clients(c: &mut Criterion) {
                     for handle in handles {
                         handle.await.unwrap();
                     }
-                    
-                    server_handle.abort();
                 });
             },
         );
+
+        for client in &clients {
+            rt.block_on(client.close()).unwrap();
+        }
+        server.stop(&rt);
     }
     
     group.finish();
@@ -146,7 +159,7 @@ fn bench_serialization(c: &mut Criterion) {
     complex_entry.fields.insert("request_id".to_string(), "req_abcdef".to_string());
     complex_entry.fields.insert("stack_trace".to_string(), "at function1()\nat function2()\nat function3()".to_string());
     complex_entry.pid = Some(12345);
-    complex_entry.hostname = Some("server-01.example.com".to_string());
+    complex_entry.hostname = Some("server-01.example.com".into());
 
     // Benchmark simple serialization
     group.bench_function("serialize_simple", |b| {
@@ -174,6 +187,99 @@ fn bench_serialization(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark parsing a connection's worth of lines, from bytes versus per-line `String`s
+///
+/// `LogEntryStream` splits the input into byte frames and parses each with
+/// `serde_json::from_slice`; the `string_lines` case is the `read_line`,
+/// `trim` and `from_str` loop it replaced.
+fn bench_ingest_parsing(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut group = c.benchmark_group("ingest_parsing");
+
+    const LINES: usize = 1000;
+    let mut input = Vec::new();
+    for i in 0..LINES {
+        let mut entry = LogEntry::new(LogLevel::Info, "ingest-bench".to_string(), format!("Request {} served", i));
+        entry.fields.insert("status".to_string(), "200".to_string());
+        entry.fields.insert("path".to_string(), "/api/v1/orders".to_string());
+        input.extend_from_slice(entry.to_json().unwrap().as_bytes());
+        input.push(b'\n');
+    }
+    group.throughput(Throughput::Elements(LINES as u64));
+
+    let parse_bytes = |input: &[u8]| {
+        rt.block_on(async {
+            let mut frames = LogEntryStream::new(input);
+            let mut parsed = 0;
+            while let Some(frame) = frames.next_frame().await {
+                black_box(frame.unwrap());
+                parsed += 1;
+            }
+            parsed
+        })
+    };
+    let parse_strings = |input: &[u8]| {
+        rt.block_on(async {
+            let mut reader = tokio::io::BufReader::new(input);
+            let mut line = String::new();
+            let mut parsed = 0;
+            while reader.read_line(&mut line).await.unwrap() > 0 {
+                black_box(LogEntry::from_json(line.trim()).unwrap());
+                line.clear();
+                parsed += 1;
+            }
+            parsed
+        })
+    };
+
+    let byte_allocs = count_allocations(|| assert_eq!(parse_bytes(&input), LINES));
+    let string_allocs = count_allocations(|| assert_eq!(parse_strings(&input), LINES));
+    println!(
+        "ingest_parsing: {} allocations for {} lines from bytes, {} via per-line strings",
+        byte_allocs, LINES, string_allocs
+    );
+
+    group.bench_function("byte_frames", |b| b.iter(|| parse_bytes(&input)));
+    group.bench_function("string_lines", |b| b.iter(|| parse_strings(&input)));
+
+    group.finish();
+}
+
+/// Benchmark stamping the hostname onto entries, owned copy vs shared
+fn bench_hostname_stamping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hostname_stamping");
+
+    let owned = "server-01.example.com".to_string();
+    let shared: Arc<str> = Arc::from(owned.as_str());
+    let template = LogEntry::new(LogLevel::Info, "test-daemon".to_string(), "Test log message".to_string());
+
+    // What the client used to do per entry (clone a `String`) versus sharing an `Arc<str>`
+    let owned_allocs = count_allocations(|| {
+        black_box(Some(owned.clone()));
+    });
+    let mut entry = template.clone();
+    let shared_allocs = count_allocations(|| {
+        entry.hostname = Some(Arc::clone(&shared));
+    });
+    println!(
+        "hostname_stamping: {} allocation(s) per entry with String clone, {} with Arc<str>",
+        owned_allocs, shared_allocs
+    );
+
+    group.bench_function("string_clone", |b| {
+        b.iter(|| black_box(Some(owned.clone())))
+    });
+
+    group.bench_function("shared_arc", |b| {
+        let mut entry = template.clone();
+        b.iter(|| {
+            entry.hostname = Some(Arc::clone(&shared));
+        })
+    });
+
+    group.finish();
+}
+
 /// Benchmark message batching
 fn bench_message_batching(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
@@ -351,6 +457,44 @@ fn bench_log_levels(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark a call below `min_level` against building the entry it used to
+///
+/// Suppressed calls return before the entry, its uuid and timestamp exist,
+/// so no server is needed: nothing is ever sent.
+fn bench_suppressed_calls(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut group = c.benchmark_group("suppressed_calls");
+
+    let client = LogClient::new_lazy(ClientConfig {
+        daemon_name: "quiet-bench".to_string(),
+        min_level: LogLevel::Info,
+        ..Default::default()
+    })
+    .unwrap();
+    // What every suppressed call cost when the entry was built before the level check
+    let build_entry = || {
+        let mut entry = LogEntry::new(LogLevel::Debug, client.daemon_name().to_string(), "Cache miss".to_string());
+        entry.fields = HashMap::new();
+        entry
+    };
+
+    let suppressed_allocs = count_allocations(|| rt.block_on(client.debug("Cache miss")).unwrap());
+    let built_allocs = count_allocations(|| {
+        black_box(build_entry());
+    });
+    println!(
+        "suppressed_calls: {} allocation(s) per suppressed call, {} to build the entry first",
+        suppressed_allocs, built_allocs
+    );
+
+    group.bench_function("suppressed_debug", |b| {
+        b.iter(|| rt.block_on(client.debug(black_box("Cache miss"))))
+    });
+    group.bench_function("entry_built_first", |b| b.iter(|| black_box(build_entry())));
+
+    group.finish();
+}
+
 /// Benchmark memory usage with large messages
 fn bench_large_messages(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
@@ -405,9 +549,12 @@ criterion_group!(
     bench_single_client_throughput,
     bench_concurrent_clients,
     bench_serialization,
+    bench_ingest_parsing,
+    bench_hostname_stamping,
     bench_message_batching,
     bench_structured_logging,
     bench_log_levels,
+    bench_suppressed_calls,
     bench_large_messages
 );
 criterion_main!(benches);
\ No newline at end of file
@@ -0,0 +1,91 @@
+//! Shared fixtures for the LogStream benchmarks
+
+use logstream::client::LogClient;
+use logstream::config::ServerConfig;
+use logstream::server::LogServer;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+/// How long an iteration waits for the server to confirm a client's entries
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A server bound once and reused across benchmark iterations
+///
+/// Starting a server per iteration makes the benches measure socket setup
+/// and backend initialization rather than steady-state throughput. The
+/// fixture binds a server in its own temporary directory before measuring
+/// starts and shuts it down when dropped.
+pub struct BenchServer {
+    server: Arc<LogServer>,
+    handle: Option<JoinHandle<logstream::Result<()>>>,
+    socket_path: String,
+    _temp_dir: TempDir,
+}
+
+impl BenchServer {
+    /// Bind a server on `rt`, with every storage backend disabled unless `store`
+    pub fn start(rt: &Runtime, store: bool) -> Self {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("bench.sock").to_string_lossy().to_string();
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_path.clone();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        if !store {
+            config.backends.disable_all();
+        }
+
+        let (server, handle) = rt.block_on(async {
+            let server = Arc::new(LogServer::new(config).await.unwrap());
+            // Once bound the socket accepts connections, so no startup sleep is needed
+            let bound = server.bind().await.unwrap();
+            (server, tokio::spawn(bound.serve()))
+        });
+
+        Self {
+            server,
+            handle: Some(handle),
+            socket_path,
+            _temp_dir: temp_dir,
+        }
+    }
+
+    /// Connect a client that stays open across iterations
+    pub fn client(&self, rt: &Runtime, daemon_name: &str) -> LogClient {
+        rt.block_on(LogClient::connect(&self.socket_path, daemon_name)).unwrap()
+    }
+
+    /// Connect `count` clients, each with its own connection
+    pub fn clients(&self, rt: &Runtime, count: usize) -> Vec<Arc<LogClient>> {
+        (0..count)
+            .map(|i| Arc::new(self.client(rt, &format!("client-{}", i))))
+            .collect()
+    }
+
+    /// Stop the server, waiting until every received entry is stored
+    pub fn stop(mut self, rt: &Runtime) {
+        self.server.shutdown();
+        if let Some(handle) = self.handle.take() {
+            rt.block_on(handle).unwrap().unwrap();
+        }
+    }
+}
+
+impl Drop for BenchServer {
+    fn drop(&mut self) {
+        self.server.shutdown();
+    }
+}
+
+/// End an iteration once the server has read everything `client` sent
+///
+/// Writes alone only measure filling the socket buffer; waiting for the
+/// server's reply to a ping charges each iteration for the server's work.
+pub async fn confirm(client: &LogClient) {
+    assert!(
+        client.flush_and_confirm(CONFIRM_TIMEOUT).await,
+        "server did not confirm benchmark entries"
+    );
+}
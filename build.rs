@@ -0,0 +1,31 @@
+//! Build script recording the git commit LogStream is built from
+//!
+//! With the `grpc` feature it also generates the gRPC service from
+//! `proto/logstream.proto`, which needs `protoc` installed.
+
+use std::process::Command;
+
+fn main() {
+    // Builds outside a checkout (e.g. from a source tarball) can pass the sha in
+    let sha = std::env::var("LOGSTREAM_GIT_SHA")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+        })
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=LOGSTREAM_GIT_SHA={}", sha);
+    println!("cargo:rerun-if-env-changed=LOGSTREAM_GIT_SHA");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/logstream.proto").expect("Failed to compile proto/logstream.proto");
+}
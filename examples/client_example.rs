@@ -1,7 +1,7 @@
 //! LogStream Client Example
 
 use logstream::client::LogClient;
-use std::collections::HashMap;
+use logstream::fields;
 use tokio::time::{sleep, Duration};
 
 #[tokio::main]
@@ -19,9 +19,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             client.info("Application started").await?;
             
             // Logging with structured fields
-            let mut fields = HashMap::new();
-            fields.insert("user_id".to_string(), "12345".to_string());
-            fields.insert("action".to_string(), "login".to_string());
+            let fields = fields! { user_id => 12345, action => "login" };
             
             client.info_with_fields("User login successful", fields).await?;
             
@@ -8,7 +8,7 @@
 //! - Showing log rotation behavior
 //! - Graceful shutdown
 
-use logstream::client::LogClient;
+use logstream::client::{LogClient, LogLevel};
 use logstream::config::ServerConfig;
 use logstream::server::LogServer;
 use std::collections::HashMap;
@@ -78,15 +78,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Simulate web requests
     for i in 0..5 {
+        // The entry gets the time taken as duration_ms
+        let request = web_client.timed("HTTP request processed", LogLevel::Info);
+        sleep(Duration::from_millis(50 + i * 10)).await;
+
         let mut fields = HashMap::new();
         fields.insert("method".to_string(), "GET".to_string());
         fields.insert("path".to_string(), format!("/api/users/{}", i));
         fields.insert("status".to_string(), "200".to_string());
-        fields.insert("duration_ms".to_string(), format!("{}", 50 + i * 10));
         fields.insert("ip".to_string(), format!("192.168.1.{}", 100 + i));
-        
-        web_client.info_with_fields("HTTP request processed", fields).await?;
-        sleep(Duration::from_millis(100)).await;
+        request.finish(fields).await?;
     }
 
     // Database Service logs
@@ -0,0 +1,26 @@
+#!/bin/bash
+# LogStream feature build check
+#
+# Builds the crate with the optional interop features switched off, one at a
+# time and together, so code behind them can't leak into the core.
+
+set -e
+
+CORE="unix-sockets,file-storage,compression"
+
+echo "Checking default features..."
+cargo check --all-targets
+
+echo "Checking without log-facade..."
+cargo check --all-targets --no-default-features --features "$CORE,tracing-interop"
+
+echo "Checking without tracing-interop..."
+cargo check --all-targets --no-default-features --features "$CORE,log-facade"
+
+echo "Checking without either..."
+cargo check --all-targets --no-default-features --features "$CORE"
+
+echo "Checking with no features at all..."
+cargo check --lib --no-default-features
+
+echo "✓ All feature combinations build"
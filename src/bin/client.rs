@@ -0,0 +1,42 @@
+//! LogStream Client Binary
+//!
+//! Forwards lines read from stdin to a LogStream server, for shell pipelines.
+
+use clap::Parser;
+use logstream::client::LogClient;
+use logstream::config::{default_socket_path, ClientConfig};
+use tokio::io::BufReader;
+
+#[derive(Parser)]
+#[command(name = "logstream-client")]
+#[command(about = "Send lines from stdin to a LogStream server")]
+#[command(version)]
+struct Args {
+    /// Daemon name to log lines under
+    #[arg(short, long)]
+    daemon: String,
+
+    /// Socket path of the server
+    #[arg(short, long, default_value_t = default_socket_path())]
+    socket: String,
+
+    /// Send lines that are JSON log entries as those entries
+    #[arg(long)]
+    parse_json: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let client = LogClient::with_config(ClientConfig {
+        socket_path: args.socket,
+        daemon_name: args.daemon,
+        ..Default::default()
+    })
+    .await?;
+
+    client.send_lines(BufReader::new(tokio::io::stdin()), args.parse_json).await?;
+    client.close().await?;
+    Ok(())
+}
@@ -0,0 +1,41 @@
+//! Version and commit of the running build
+//!
+//! Reported in the server's startup log, the `{"cmd":"config"}` and
+//! `{"cmd":"stats"}` replies, and the `logstream_build_info` metric.
+
+use serde::Serialize;
+
+/// Crate version the build was made from
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git commit the build was made from, `"unknown"` outside a checkout
+///
+/// Set by the build script, or by `LOGSTREAM_GIT_SHA` in the build environment.
+pub const GIT_SHA: &str = env!("LOGSTREAM_GIT_SHA");
+
+/// Version and commit of this build
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: VERSION,
+    git_sha: GIT_SHA,
+};
+
+/// Identifies a build of LogStream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: &'static str,
+    /// Short git commit
+    pub git_sha: &'static str,
+}
+
+impl Default for BuildInfo {
+    fn default() -> Self {
+        BUILD_INFO
+    }
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "v{} ({})", self.version, self.git_sha)
+    }
+}
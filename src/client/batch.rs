@@ -0,0 +1,168 @@
+//! Batches that send a group of entries in a single write
+
+use crate::client::logger::send_on_drop;
+use crate::client::LogClient;
+use crate::config::BatchDropPolicy;
+use crate::types::{LogEntry, LogFields, LogLevel};
+use crate::Result;
+use std::collections::HashMap;
+
+/// Entries collected from one client and sent together
+///
+/// Logging to the batch only builds entries, timestamped when logged;
+/// [`LogBatch::commit`] sends them all in one write, so they reach the
+/// server back to back with no other entry of the client in between. A batch
+/// dropped without committing is sent or discarded according to
+/// `batch_on_drop`, or [`LogBatch::on_drop`].
+pub struct LogBatch {
+    client: LogClient,
+    entries: Vec<LogEntry>,
+    on_drop: BatchDropPolicy,
+}
+
+impl LogBatch {
+    pub(crate) fn new(client: LogClient) -> Self {
+        let on_drop = client.config().batch_on_drop;
+        Self {
+            client,
+            entries: Vec::new(),
+            on_drop,
+        }
+    }
+
+    /// Choose what happens if the batch is dropped without committing
+    pub fn on_drop(mut self, policy: BatchDropPolicy) -> Self {
+        self.on_drop = policy;
+        self
+    }
+
+    /// Add a message to the batch
+    ///
+    /// Levels below `min_level` are left out, as they would not be sent.
+    pub fn log(&mut self, level: LogLevel, message: &str, fields: LogFields) {
+        if self.client.is_enabled(level) {
+            self.entries.push(self.client.build_entry(level, message, fields));
+        }
+    }
+
+    /// Add a pre-built entry to the batch, sent as-is like [`LogClient::send_entry`]
+    pub fn push(&mut self, entry: LogEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Add an info message to the batch
+    pub fn info<S: AsRef<str>>(&mut self, message: S) {
+        self.log(LogLevel::Info, message.as_ref(), HashMap::new());
+    }
+
+    /// Add an info message with fields to the batch
+    pub fn info_with_fields<S: AsRef<str>>(&mut self, message: S, fields: LogFields) {
+        self.log(LogLevel::Info, message.as_ref(), fields);
+    }
+
+    /// Add an error message to the batch
+    pub fn error<S: AsRef<str>>(&mut self, message: S) {
+        self.log(LogLevel::Error, message.as_ref(), HashMap::new());
+    }
+
+    /// Add a warning message to the batch
+    pub fn warning<S: AsRef<str>>(&mut self, message: S) {
+        self.log(LogLevel::Warning, message.as_ref(), HashMap::new());
+    }
+
+    /// Add a notice message to the batch
+    pub fn notice<S: AsRef<str>>(&mut self, message: S) {
+        self.log(LogLevel::Notice, message.as_ref(), HashMap::new());
+    }
+
+    /// Add a debug message to the batch
+    pub fn debug<S: AsRef<str>>(&mut self, message: S) {
+        self.log(LogLevel::Debug, message.as_ref(), HashMap::new());
+    }
+
+    /// Number of entries waiting to be committed
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether nothing has been added since the batch started
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Send every entry of the batch in a single write
+    pub async fn commit(mut self) -> Result<()> {
+        let entries = std::mem::take(&mut self.entries);
+        self.client.send_entries(entries).await
+    }
+}
+
+impl Drop for LogBatch {
+    fn drop(&mut self) {
+        if self.entries.is_empty() || self.on_drop == BatchDropPolicy::Discard {
+            return;
+        }
+
+        let client = self.client.clone();
+        let entries = std::mem::take(&mut self.entries);
+        send_on_drop(async move { client.send_entries(entries).await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixStream;
+    use tokio::time::{timeout, Duration};
+
+    /// Whatever arrives in the next read from the server side
+    async fn next_read(server: &mut UnixStream) -> Vec<String> {
+        let mut buf = vec![0; 64 * 1024];
+        let n = timeout(Duration::from_secs(5), server.read(&mut buf)).await.unwrap().unwrap();
+        String::from_utf8_lossy(&buf[..n])
+            .lines()
+            .map(|line| LogEntry::from_json(line).unwrap().message)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_commit_sends_one_write() {
+        let (client_end, mut server) = UnixStream::pair().unwrap();
+        let client = LogClient::with_connection(client_end, "batched").unwrap();
+
+        let mut batch = client.batch();
+        batch.info("a");
+        batch.error("b");
+        assert_eq!(batch.len(), 2);
+        batch.commit().await.unwrap();
+
+        assert_eq!(next_read(&mut server).await, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_batch_follows_policy() {
+        let (client_end, mut server) = UnixStream::pair().unwrap();
+        let client = LogClient::with_connection(client_end, "batched").unwrap();
+        assert_eq!(client.config().batch_on_drop, BatchDropPolicy::Commit);
+
+        {
+            let mut batch = client.batch().on_drop(BatchDropPolicy::Discard);
+            batch.info("discarded");
+        }
+        {
+            let mut batch = client.batch();
+            batch.info("kept");
+            batch.warning("also kept");
+        }
+        // Sent after the auto-committed batch, so anything discarded would show up first
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        client.info("after").await.unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            received.extend(next_read(&mut server).await);
+        }
+        assert_eq!(received, ["kept", "also kept", "after"]);
+    }
+}
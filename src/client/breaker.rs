@@ -0,0 +1,239 @@
+//! Circuit breaker that stops a client from waiting on a server that is down
+//!
+//! After `breaker_failure_threshold` consecutive failed sends the breaker
+//! opens and sends fail immediately for `breaker_open_ms`. The next send
+//! after that is let through as a probe: success closes the breaker, failure
+//! opens it again, and so does a probe abandoned before it finished.
+
+use crate::types::Clock;
+use crate::{LogStreamError, Result};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Sends go through; counts consecutive failures
+    Closed { failures: u32 },
+    /// Sends fail fast until the deadline
+    Open { until: Instant },
+    /// One probe send is in flight; others fail fast
+    HalfOpen,
+}
+
+/// Tracks send failures shared by every clone of a client
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+}
+
+/// A send let through by [`CircuitBreaker::check`]
+///
+/// Report how it went with [`SendAttempt::succeeded`] or
+/// [`SendAttempt::failed`]. Dropped without either, e.g. because the caller
+/// gave up on the send, a probe counts as failed so the breaker doesn't
+/// stay half-open.
+#[must_use = "report the outcome of the send"]
+pub struct SendAttempt<'a> {
+    breaker: &'a CircuitBreaker,
+    reported: bool,
+}
+
+impl SendAttempt<'_> {
+    /// Record a successful send, closing the breaker
+    pub fn succeeded(mut self) {
+        self.reported = true;
+        self.breaker.record_success();
+    }
+
+    /// Record a failed send, opening the breaker at the threshold or after a failed probe
+    pub fn failed(mut self) {
+        self.reported = true;
+        self.breaker.record_failure();
+    }
+}
+
+impl Drop for SendAttempt<'_> {
+    fn drop(&mut self) {
+        if !self.reported {
+            self.breaker.record_abandoned();
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Create a closed breaker that opens after `failure_threshold` consecutive failures
+    ///
+    /// `clock` measures how long the breaker stays open.
+    pub fn new(failure_threshold: u32, open_duration: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            clock,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether sends currently fail fast
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state(), State::Closed { .. })
+    }
+
+    /// Check whether a send may be attempted
+    ///
+    /// Fails with [`LogStreamError::Connection`] while the breaker is open,
+    /// or while another send is probing the server.
+    pub fn check(&self) -> Result<SendAttempt<'_>> {
+        let mut state = self.state();
+        match *state {
+            State::Open { until } if self.clock.now_instant() >= until => {
+                *state = State::HalfOpen;
+            }
+            current => self.reject(current)?,
+        }
+        Ok(SendAttempt { breaker: self, reported: false })
+    }
+
+    /// Fail like [`CircuitBreaker::check`] would, without starting a probe
+    ///
+    /// Lets callers skip work for a send that is certain to fail fast. Once
+    /// a probe is due this succeeds and leaves the probe to `check`.
+    pub fn fail_fast(&self) -> Result<()> {
+        match *self.state() {
+            State::Open { until } if self.clock.now_instant() >= until => Ok(()),
+            current => self.reject(current),
+        }
+    }
+
+    fn reject(&self, state: State) -> Result<()> {
+        match state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } => Err(LogStreamError::Connection(format!(
+                "Circuit breaker open for another {:?}, server unreachable",
+                until.saturating_duration_since(self.clock.now_instant())
+            ))),
+            State::HalfOpen => Err(LogStreamError::Connection(
+                "Circuit breaker open, probing server".to_string(),
+            )),
+        }
+    }
+
+    /// Record a successful send, closing the breaker
+    fn record_success(&self) {
+        *self.state() = State::Closed { failures: 0 };
+    }
+
+    /// Record a failed send, opening the breaker at the threshold or after a failed probe
+    fn record_failure(&self) {
+        let mut state = self.state();
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::Open { .. } | State::HalfOpen => self.failure_threshold,
+        };
+        *state = if failures >= self.failure_threshold {
+            State::Open {
+                until: self.clock.now_instant() + self.open_duration,
+            }
+        } else {
+            State::Closed { failures }
+        };
+    }
+
+    /// Reopen the breaker if the send abandoned was its probe
+    ///
+    /// An abandoned send says nothing about the server otherwise, so it
+    /// doesn't count towards the threshold.
+    fn record_abandoned(&self) {
+        let mut state = self.state();
+        if *state == State::HalfOpen {
+            *state = State::Open {
+                until: self.clock.now_instant() + self.open_duration,
+            };
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MockClock;
+
+    fn breaker(failure_threshold: u32) -> (CircuitBreaker, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::default());
+        (CircuitBreaker::new(failure_threshold, Duration::from_millis(50), clock.clone()), clock)
+    }
+
+    #[test]
+    fn test_opens_at_threshold_and_half_opens_after_duration() {
+        let (breaker, clock) = breaker(3);
+
+        breaker.check().unwrap().failed();
+        breaker.check().unwrap().failed();
+        assert!(breaker.check().is_ok());
+        breaker.check().unwrap().failed();
+        assert!(breaker.is_open());
+        assert!(matches!(breaker.check(), Err(LogStreamError::Connection(_))));
+
+        clock.advance(Duration::from_millis(50));
+        // One probe goes through, concurrent sends still fail fast
+        let probe = breaker.check().unwrap();
+        assert!(breaker.check().is_err());
+        // A failed probe reopens immediately
+        probe.failed();
+        assert!(breaker.check().is_err());
+
+        clock.advance(Duration::from_millis(50));
+        breaker.check().unwrap().succeeded();
+        assert!(!breaker.is_open());
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn test_abandoned_probe_reopens() {
+        let (breaker, clock) = breaker(1);
+        breaker.check().unwrap().failed();
+
+        clock.advance(Duration::from_millis(50));
+        drop(breaker.check().unwrap());
+        assert!(breaker.is_open());
+        assert!(breaker.check().is_err());
+
+        // The next probe is let through once the open period ends again
+        clock.advance(Duration::from_millis(50));
+        breaker.check().unwrap().succeeded();
+        assert!(!breaker.is_open());
+
+        // Abandoned sends while closed don't count as failures
+        drop(breaker.check().unwrap());
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn test_fail_fast_never_starts_a_probe() {
+        let (breaker, clock) = breaker(1);
+        assert!(breaker.fail_fast().is_ok());
+        breaker.check().unwrap().failed();
+        assert!(breaker.fail_fast().is_err());
+
+        clock.advance(Duration::from_millis(50));
+        assert!(breaker.fail_fast().is_ok());
+        assert!(breaker.fail_fast().is_ok());
+        // The probe is still available to the actual send
+        let _probe = breaker.check().unwrap();
+        assert!(breaker.fail_fast().is_err());
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let (breaker, _) = breaker(2);
+        breaker.check().unwrap().failed();
+        breaker.check().unwrap().succeeded();
+        breaker.check().unwrap().failed();
+        assert!(breaker.check().is_ok());
+    }
+}
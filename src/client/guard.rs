@@ -0,0 +1,105 @@
+//! Closing a client when it goes out of scope
+//!
+//! `Drop` can't await, so [`ClientGuard`] hands the close to a task on the
+//! current runtime, or blocks on it when dropped outside of one.
+
+use crate::client::logger::send_on_drop;
+use crate::client::LogClient;
+use std::ops::Deref;
+
+/// Wraps a [`LogClient`] and closes its connection when dropped
+///
+/// Dereferences to the client. The close waits behind sends already waiting
+/// for the connection, so entries logged from other tasks just before the
+/// guard drops are still written. Clones of the client share the connection
+/// and reconnect on their next send.
+pub struct ClientGuard {
+    client: Option<LogClient>,
+}
+
+impl ClientGuard {
+    /// Guard `client`
+    pub fn new(client: LogClient) -> Self {
+        Self { client: Some(client) }
+    }
+
+    /// Take the client back without closing it
+    pub fn into_inner(mut self) -> LogClient {
+        self.client.take().expect("client is only taken on into_inner or drop")
+    }
+}
+
+impl From<LogClient> for ClientGuard {
+    fn from(client: LogClient) -> Self {
+        Self::new(client)
+    }
+}
+
+impl Deref for ClientGuard {
+    type Target = LogClient;
+
+    fn deref(&self) -> &LogClient {
+        self.client.as_ref().expect("client is only taken on into_inner or drop")
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+
+        send_on_drop(async move { client.close().await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::InMemoryServer;
+    use tempfile::tempdir;
+    use tokio::time::Duration;
+
+    #[tokio::test]
+    async fn test_guard_delivers_pending_entries_on_drop() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("guard.sock")).unwrap();
+        let socket_str = server.socket_path().to_string_lossy().to_string();
+
+        {
+            let guard = ClientGuard::new(LogClient::connect(&socket_str, "guarded").await.unwrap());
+            for i in 0..3 {
+                let client = (*guard).clone();
+                tokio::spawn(async move { client.info(format!("from task {}", i)).await });
+            }
+            guard.info("direct").await.unwrap();
+        }
+
+        let entries = server.wait_for(4, Duration::from_secs(2)).await.unwrap();
+        let mut messages: Vec<String> = entries.into_iter().map(|entry| entry.message).collect();
+        messages.sort();
+        assert_eq!(messages, ["direct", "from task 0", "from task 1", "from task 2"]);
+    }
+
+    #[test]
+    fn test_guard_closes_outside_a_runtime() {
+        let temp_dir = tempdir().unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            InMemoryServer::bind(temp_dir.path().join("blocking.sock")).unwrap()
+        });
+        let socket_str = server.socket_path().to_string_lossy().to_string();
+
+        let guard = runtime.block_on(async {
+            let guard = ClientGuard::from(LogClient::connect(&socket_str, "guarded").await.unwrap());
+            guard.info("before drop").await.unwrap();
+            guard
+        });
+        drop(guard);
+
+        let entries = runtime
+            .block_on(server.wait_for(1, Duration::from_secs(2)))
+            .unwrap();
+        assert_eq!(entries[0].message, "before drop");
+    }
+}
@@ -0,0 +1,237 @@
+//! `log` crate facade that forwards records to the LogStream server
+//!
+//! Lets code written against `log::info!` and friends send its output through
+//! a [`LogClient`] without switching to another logging API.
+
+use crate::client::LogClient;
+use crate::types::LogEntry;
+use crate::{LogStreamError, Result};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::mpsc;
+
+/// Records queued for the sending task before new ones are dropped
+pub const FACADE_QUEUE_CAPACITY: usize = 1024;
+
+/// Longest [`Log::flush`] waits for the queue to drain
+pub const FACADE_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// [`log::Log`] implementation backed by a [`LogClient`]
+///
+/// Records are converted to entries on the calling thread and handed to a
+/// background task for sending, so logging never blocks on the socket. When
+/// [`FACADE_QUEUE_CAPACITY`] records are already waiting, new ones are
+/// dropped and counted in [`LogClient::dropped_by_facade_total`].
+pub struct LogStreamLog {
+    client: LogClient,
+    level: LevelFilter,
+    tx: mpsc::Sender<LogEntry>,
+    pending: Arc<Pending>,
+}
+
+/// Number of records queued but not sent yet
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    drained: Condvar,
+}
+
+impl Pending {
+    fn add(&self) {
+        *self.count.lock().unwrap() += 1;
+    }
+
+    fn done(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.drained.notify_all();
+        }
+    }
+
+    fn wait_drained(&self, timeout: Duration) {
+        let count = self.count.lock().unwrap();
+        let _ = self.drained.wait_timeout_while(count, timeout, |count| *count > 0);
+    }
+}
+
+impl LogStreamLog {
+    /// Create a logger sending through `client`
+    ///
+    /// Fails outside a Tokio runtime, which has to run the sending task.
+    pub fn new(client: LogClient, level: LevelFilter) -> Result<Self> {
+        Self::with_capacity(client, level, FACADE_QUEUE_CAPACITY)
+    }
+
+    fn with_capacity(client: LogClient, level: LevelFilter, capacity: usize) -> Result<Self> {
+        let handle = Handle::try_current().map_err(|_| {
+            LogStreamError::Client("The log facade must be created within a Tokio runtime".to_string())
+        })?;
+        let (tx, mut rx) = mpsc::channel::<LogEntry>(capacity);
+        let pending = Arc::new(Pending::default());
+
+        let sender = client.clone();
+        let sent = Arc::clone(&pending);
+        handle.spawn(async move {
+            while let Some(entry) = rx.recv().await {
+                // There is nowhere to report a failed send from inside the logger
+                let _ = sender.send_entry(entry).await;
+                sent.done();
+            }
+        });
+
+        Ok(Self {
+            client,
+            level,
+            tx,
+            pending,
+        })
+    }
+}
+
+impl Log for LogStreamLog {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // Counted before queueing so the sending task can't finish it first
+        self.pending.add();
+        let entry = LogEntry::from_log_record(record, self.client.daemon_name());
+        if self.tx.try_send(entry).is_err() {
+            self.pending.done();
+            self.client.record_dropped_by_facade(1);
+        }
+    }
+
+    /// Wait up to [`FACADE_FLUSH_TIMEOUT`] for queued records to be sent
+    ///
+    /// Returns right away on a current-thread runtime, where blocking would
+    /// stall the task draining the queue.
+    fn flush(&self) {
+        match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Ok(RuntimeFlavor::CurrentThread) => {}
+            Ok(_) => tokio::task::block_in_place(|| self.pending.wait_drained(FACADE_FLUSH_TIMEOUT)),
+            Err(_) => self.pending.wait_drained(FACADE_FLUSH_TIMEOUT),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientConfig;
+    use crate::test_util::InMemoryServer;
+    use crate::types::LogLevel;
+    use tempfile::tempdir;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_level_mapping() {
+        assert_eq!(LogLevel::from(log::Level::Error), LogLevel::Error);
+        assert_eq!(LogLevel::from(log::Level::Warn), LogLevel::Warning);
+        assert_eq!(LogLevel::from(log::Level::Info), LogLevel::Info);
+        assert_eq!(LogLevel::from(log::Level::Debug), LogLevel::Debug);
+        assert_eq!(LogLevel::from(log::Level::Trace), LogLevel::Debug);
+    }
+
+    #[tokio::test]
+    async fn test_log_facade_forwards_records() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("facade.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+        let listener = UnixListener::bind(&socket_str).unwrap();
+
+        let client = LogClient::connect(&socket_str, "legacy-app").await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        // The global logger can only be installed once per process
+        client.init_log_facade(LevelFilter::Info).unwrap();
+        assert!(client.init_log_facade(LevelFilter::Info).is_err());
+
+        // A logger that couldn't be installed is dropped along with its client's connection
+        let (other_end, peer) = UnixStream::pair().unwrap();
+        let other = LogClient::with_connection(other_end, "second-app").unwrap();
+        assert!(other.init_log_facade(LevelFilter::Info).is_err());
+        drop(other);
+        let closed = timeout(Duration::from_secs(2), BufReader::new(peer).lines().next_line()).await;
+        assert!(matches!(closed, Ok(Ok(None))));
+
+        log::debug!("filtered out by the level");
+        log::warn!(target: "legacy::db", "disk {} nearly full", "/var");
+
+        let mut lines = BufReader::new(stream).lines();
+        let line = timeout(Duration::from_secs(2), lines.next_line())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let entry = LogEntry::from_json(&line).unwrap();
+
+        assert_eq!(entry.level, LogLevel::Warning);
+        assert_eq!(entry.daemon, "legacy-app");
+        assert_eq!(entry.message, "disk /var nearly full");
+        assert_eq!(entry.fields.get("target").map(String::as_str), Some("legacy::db"));
+        assert!(entry.fields.contains_key("line"));
+    }
+
+    fn lazy_client(socket_path: &std::path::Path) -> LogClient {
+        LogClient::new_lazy(ClientConfig {
+            socket_path: socket_path.to_string_lossy().to_string(),
+            daemon_name: "legacy-app".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn log_info(logger: &LogStreamLog, n: usize) {
+        for i in 0..n {
+            logger.log(&Record::builder().args(format_args!("record {}", i)).level(log::Level::Info).build());
+        }
+    }
+
+    #[test]
+    fn test_log_facade_requires_runtime() {
+        let client = lazy_client(std::path::Path::new("/tmp/nonexistent_facade.sock"));
+        assert!(LogStreamLog::new(client.clone(), LevelFilter::Info).is_err());
+        assert!(client.init_log_facade(LevelFilter::Info).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_log_facade_drops_records_when_queue_is_full() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("full.sock")).unwrap();
+        let client = lazy_client(server.socket_path());
+        let logger = LogStreamLog::with_capacity(client.clone(), LevelFilter::Info, 2).unwrap();
+
+        // The sending task can't run on this thread until the test yields
+        log_info(&logger, 5);
+        assert_eq!(client.dropped_by_facade_total(), 3);
+
+        let entries = server.wait_for(2, Duration::from_secs(2)).await.unwrap();
+        let messages: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["record 0", "record 1"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_log_facade_flush_waits_for_queue() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("flush.sock")).unwrap();
+        let client = lazy_client(server.socket_path());
+        let logger = LogStreamLog::new(client.clone(), LevelFilter::Info).unwrap();
+
+        log_info(&logger, 100);
+        logger.flush();
+        assert_eq!(*logger.pending.count.lock().unwrap(), 0);
+        assert_eq!(client.dropped_by_facade_total(), 0);
+        assert_eq!(server.wait_for(100, Duration::from_secs(2)).await.unwrap().len(), 100);
+    }
+}
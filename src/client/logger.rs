@@ -1,21 +1,73 @@
 //! LogStream client implementation for sending logs to the centralized server
 
-use crate::config::ClientConfig;
-use crate::types::{LogEntry, LogFields, LogLevel};
+#[cfg(feature = "log-facade")]
+use crate::client::LogStreamLog;
+use crate::client::{CircuitBreaker, LevelControl, LogBatch, LogSpan, TimedOperation};
+use crate::config::{BufferFullPolicy, ClientConfig};
+use crate::types::{Clock, ControlCommand, EntryChunk, LogEntry, LogFields, LogLevel, StoragePathReply, SystemClock};
 use crate::{LogStreamError, Result};
+use rand::Rng;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::UnixStream;
-use tokio::sync::Mutex;
-use tokio::time::{timeout, Duration};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::{timeout, timeout_at, Duration, Instant};
+
+/// Upper bound on the exponential part of the reconnect backoff
+const MAX_RECONNECT_DELAY_MS: u64 = 30_000;
+
+/// Send a ping and wait for the server's single-line reply
+async fn ping(conn: &mut UnixStream) -> Result<()> {
+    let message = format!("{}\n", ControlCommand::Ping.to_json()?);
+    write_flushed(conn, &message).await?;
+    read_reply(conn).await.map(|_| ())
+}
+
+/// Read the server's reply to a control command, without the newline
+async fn read_reply(conn: &mut UnixStream) -> Result<Vec<u8>> {
+    // Only one command is ever outstanding, so the reply is everything up to the newline
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = conn.read(&mut buf).await?;
+        if n == 0 {
+            return Err(LogStreamError::Connection("Server closed the connection".to_string()));
+        }
+        reply.extend_from_slice(&buf[..n]);
+        if let Some(end) = reply.iter().position(|&b| b == b'\n') {
+            reply.truncate(end);
+            return Ok(reply);
+        }
+    }
+}
+
+/// Write a message and flush it to the socket
+async fn write_flushed(conn: &mut UnixStream, message: &str) -> Result<()> {
+    conn.write_all(message.as_bytes()).await?;
+    conn.flush().await?;
+    Ok(())
+}
 
 /// High-performance client for sending logs to LogStream server
 #[derive(Clone)]
 pub struct LogClient {
     config: ClientConfig,
     connection: Arc<Mutex<Option<UnixStream>>>,
-    hostname: String,
+    hostname: Arc<str>,
+    container_id: Option<String>,
+    keepalive_started: Arc<AtomicBool>,
+    /// Index into [`LogClient::socket_paths`] of the path last connected to
+    active_path: Arc<AtomicUsize>,
+    probe_started: Arc<AtomicBool>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    backlog: Option<Arc<Semaphore>>,
+    clock: Arc<dyn Clock>,
+    dropped_stale: Arc<AtomicU64>,
+    dropped_by_facade: Arc<AtomicU64>,
+    levels: Arc<LevelControl>,
 }
 
 impl LogClient {
@@ -30,41 +82,308 @@ impl LogClient {
         Self::with_config(config).await
     }
 
+    /// Connect to the first of several socket paths that accepts a connection
+    ///
+    /// The remaining paths become `fallback_socket_paths`, so the client keeps
+    /// using the path that worked and fails over along the list whenever
+    /// it has to reconnect.
+    pub async fn connect_any(paths: &[&str], daemon_name: &str) -> Result<Self> {
+        let (primary, fallbacks) = paths
+            .split_first()
+            .ok_or_else(|| LogStreamError::Config("At least one socket path is required".to_string()))?;
+        let config = ClientConfig {
+            socket_path: primary.to_string(),
+            fallback_socket_paths: fallbacks.iter().map(|path| path.to_string()).collect(),
+            daemon_name: daemon_name.to_string(),
+            ..Default::default()
+        };
+
+        Self::with_config(config).await
+    }
+
     /// Create a new log client with custom configuration
     pub async fn with_config(config: ClientConfig) -> Result<Self> {
+        let client = if config.use_fqdn && config.hostname_override.is_none() {
+            // The resolver blocks, possibly for its whole timeout, so keep it off the async worker threads
+            tokio::task::spawn_blocking(move || Self::new_lazy(config))
+                .await
+                .map_err(|e| LogStreamError::Client(format!("Hostname lookup task failed: {}", e)))??
+        } else {
+            Self::new_lazy(config)?
+        };
+        client.ensure_connected().await?;
+        Ok(client)
+    }
+
+    /// Create a client without connecting to the server
+    ///
+    /// Usable outside of an async context. The connection is established by
+    /// [`LogClient::preconnect`] or, failing that, by the first log call.
+    /// With `use_fqdn` set this blocks on the system resolver, so from async
+    /// code prefer [`LogClient::with_config`] or `spawn_blocking`.
+    pub fn new_lazy(config: ClientConfig) -> Result<Self> {
+        Self::new_lazy_with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create a client without connecting, timestamping and ageing entries with `clock`
+    pub fn new_lazy_with_clock(config: ClientConfig, clock: Arc<dyn Clock>) -> Result<Self> {
         config.validate()?;
         
-        let hostname = gethostname::gethostname()
+        let short_hostname = gethostname::gethostname()
             .to_string_lossy()
             .to_string();
+        let hostname = resolve_hostname(&config, short_hostname, lookup_fqdn).into();
+        let container_id = config
+            .container_id_env
+            .as_ref()
+            .and_then(|var| std::env::var(var).ok())
+            .filter(|id| !id.is_empty());
+
+        let breaker = config.breaker_failure_threshold.map(|threshold| {
+            Arc::new(CircuitBreaker::new(threshold, Duration::from_millis(config.breaker_open_ms), clock.clone()))
+        });
 
-        let client = Self {
+        let backlog = config.max_buffered_entries.map(|max| Arc::new(Semaphore::new(max)));
+
+        Ok(Self {
             config,
             connection: Arc::new(Mutex::new(None)),
             hostname,
-        };
+            container_id,
+            keepalive_started: Arc::new(AtomicBool::new(false)),
+            active_path: Arc::new(AtomicUsize::new(0)),
+            probe_started: Arc::new(AtomicBool::new(false)),
+            breaker,
+            backlog,
+            clock,
+            dropped_stale: Arc::new(AtomicU64::new(0)),
+            dropped_by_facade: Arc::new(AtomicU64::new(0)),
+            levels: Arc::new(LevelControl::default()),
+        })
+    }
 
-        client.ensure_connected().await?;
-        Ok(client)
+    /// Create a client over an already connected stream
+    ///
+    /// For sockets handed over by the caller, such as one received through
+    /// systemd socket activation, or one end of [`UnixStream::pair`] in tests.
+    /// Other settings are the [`ClientConfig`] defaults, so if the stream
+    /// breaks the client reconnects to the default socket path.
+    pub fn with_connection(stream: UnixStream, daemon_name: &str) -> Result<Self> {
+        let client = Self::new_lazy(ClientConfig {
+            daemon_name: daemon_name.to_string(),
+            ..Default::default()
+        })?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(Some(stream))),
+            ..client
+        })
+    }
+
+    /// Connect to the server now rather than on the first log call
+    pub async fn preconnect(&self) -> Result<()> {
+        self.ensure_connected().await
+    }
+
+    /// Whether the client currently holds an open connection
+    pub async fn is_connected(&self) -> bool {
+        self.connection.lock().await.is_some()
     }
 
     /// Ensure we have an active connection to the server
     async fn ensure_connected(&self) -> Result<()> {
         let mut conn_guard = self.connection.lock().await;
-        
-        if conn_guard.is_none() {
-            let connect_future = UnixStream::connect(&self.config.socket_path);
-            let conn = timeout(Duration::from_secs(self.config.timeout_seconds), connect_future)
-                .await
-                .map_err(|_| LogStreamError::Connection("Connection timeout".to_string()))?
-                .map_err(|e| LogStreamError::Connection(format!("Failed to connect: {}", e)))?;
+        self.connect_locked(&mut conn_guard).await?;
+        Ok(())
+    }
 
-            *conn_guard = Some(conn);
+    /// Connect if the slot is empty, returning the connection in it
+    ///
+    /// Takes the slot behind the already held connection lock, so callers can
+    /// reconnect without letting other sends in. The path that worked last is
+    /// tried first, then every other path in configured order.
+    async fn connect_locked<'a>(&self, slot: &'a mut Option<UnixStream>) -> Result<&'a mut UnixStream> {
+        if slot.is_none() {
+            let paths = self.socket_paths();
+            let last = self.active_path.load(Ordering::SeqCst).min(paths.len() - 1);
+            let order = std::iter::once(last).chain((0..paths.len()).filter(|&index| index != last));
+
+            let mut last_error = None;
+            for index in order {
+                match self.connect_path(paths[index]).await {
+                    Ok(conn) => {
+                        *slot = Some(conn);
+                        self.active_path.store(index, Ordering::SeqCst);
+                        self.start_keepalive();
+                        if index != 0 {
+                            self.start_primary_probe();
+                        }
+                        break;
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            if slot.is_none() {
+                return Err(last_error.expect("there is always at least one socket path"));
+            }
         }
-        
+
+        Ok(slot.as_mut().expect("connection was just established"))
+    }
+
+    /// Connect to one socket path within the connect timeout
+    async fn connect_path(&self, path: &str) -> Result<UnixStream> {
+        let connect_timeout = match self.config.connect_timeout_ms {
+            Some(ms) => Duration::from_millis(ms),
+            None => Duration::from_secs(self.config.timeout_seconds),
+        };
+        timeout(connect_timeout, UnixStream::connect(path))
+            .await
+            .map_err(|_| LogStreamError::Connection("Connection timeout".to_string()))?
+            .map_err(|e| LogStreamError::Connection(format!("Failed to connect to {}: {}", path, e)))
+    }
+
+    /// `socket_path` followed by the fallback paths
+    fn socket_paths(&self) -> Vec<&str> {
+        std::iter::once(self.config.socket_path.as_str())
+            .chain(self.config.fallback_socket_paths.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Socket path the client is using, or will try first when it reconnects
+    pub fn active_socket_path(&self) -> &str {
+        let paths = self.socket_paths();
+        paths[self.active_path.load(Ordering::SeqCst).min(paths.len() - 1)]
+    }
+
+    /// Spawn the task moving back to `socket_path` if `primary_probe_secs` is set
+    ///
+    /// While the client is on a fallback path the task tries the primary
+    /// every interval, and swaps it in for the current connection once it
+    /// accepts. It exits when back on the primary or once every clone of the
+    /// client is gone; connecting to a fallback again restarts it.
+    fn start_primary_probe(&self) {
+        let Some(secs) = self.config.primary_probe_secs else {
+            return;
+        };
+        if self.probe_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let connection = Arc::downgrade(&self.connection);
+        let primary = self.config.socket_path.clone();
+        let active_path = Arc::clone(&self.active_path);
+        let probe_started = Arc::clone(&self.probe_started);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(secs.max(1)));
+            // The first tick completes immediately
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(connection) = connection.upgrade() else {
+                    break;
+                };
+                if active_path.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                if let Ok(conn) = UnixStream::connect(&primary).await {
+                    let mut conn_guard = connection.lock().await;
+                    *conn_guard = Some(conn);
+                    active_path.store(0, Ordering::SeqCst);
+                    break;
+                }
+            }
+            probe_started.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Spawn the keep-alive task if `keepalive_secs` is set and it isn't running yet
+    ///
+    /// The task pings whichever connection is current and drops it when no
+    /// pong arrives in time, so the next log call reconnects. It exits once
+    /// every clone of the client is gone.
+    fn start_keepalive(&self) {
+        let Some(secs) = self.config.keepalive_secs else {
+            return;
+        };
+        if self.keepalive_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let connection = Arc::downgrade(&self.connection);
+        let reply_timeout = Duration::from_secs(self.config.timeout_seconds);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(secs.max(1)));
+            // The first tick completes immediately
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(connection) = connection.upgrade() else {
+                    break;
+                };
+                let mut conn_guard = connection.lock().await;
+                if let Some(ref mut conn) = *conn_guard {
+                    let alive = matches!(timeout(reply_timeout, ping(conn)).await, Ok(Ok(())));
+                    if !alive {
+                        *conn_guard = None;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Delay to wait before the given reconnect attempt
+    fn reconnect_delay(&self, attempt: u32) -> Duration {
+        backoff_delay(
+            self.config.reconnect_delay_ms,
+            self.config.reconnect_jitter_ms,
+            attempt,
+            &mut rand::thread_rng(),
+        )
+    }
+
+    /// Daemon name entries from this client are tagged with
+    pub fn daemon_name(&self) -> &str {
+        &self.config.daemon_name
+    }
+
+    /// Configuration the client was created with
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Clock the client timestamps entries and measures durations with
+    pub(crate) fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    /// Install a global `log` crate logger that forwards records through this client
+    ///
+    /// Records at or above `level` are sent asynchronously. Fails if a global
+    /// logger is already installed, or when called outside a Tokio runtime.
+    #[cfg(feature = "log-facade")]
+    pub fn init_log_facade(&self, level: log::LevelFilter) -> Result<()> {
+        // Unlike leaking it up front, a logger that isn't installed is dropped along with its client
+        log::set_boxed_logger(Box::new(LogStreamLog::new(self.clone(), level)?))
+            .map_err(|e| LogStreamError::Client(format!("Failed to install log facade: {}", e)))?;
+        log::set_max_level(level);
         Ok(())
     }
 
+    /// Start a span whose entries share a `span_id`
+    ///
+    /// A `span_end` entry with the span's duration is sent when it ends.
+    pub fn span(&self, name: &str) -> LogSpan {
+        LogSpan::new(self.clone(), name)
+    }
+
+    /// Start timing an operation, to be logged at `level` when it finishes
+    ///
+    /// One entry with `message`, the elapsed `duration_ms` and any
+    /// `outcome` is sent when the guard finishes or is dropped.
+    pub fn timed(&self, message: &str, level: LogLevel) -> TimedOperation {
+        TimedOperation::new(self.clone(), message, level)
+    }
+
     /// Log an info message
     pub async fn info<S: AsRef<str>>(&self, message: S) -> Result<()> {
         self.log(LogLevel::Info, message.as_ref(), HashMap::new()).await
@@ -75,51 +394,246 @@ impl LogClient {
         self.log(LogLevel::Info, message.as_ref(), fields).await
     }
 
+    /// Whether entries at `level` are sent, according to [`LogClient::min_level`]
+    pub fn is_enabled(&self, level: LogLevel) -> bool {
+        level <= self.min_level()
+    }
+
+    /// Least severe level sent right now
+    ///
+    /// The configured `min_level` unless [`LogClient::set_min_level`] replaced
+    /// it, made more verbose while a [`LogClient::boost_level`] lasts.
+    pub fn min_level(&self) -> LogLevel {
+        self.levels.effective(self.config.min_level, self.clock.now_instant())
+    }
+
+    /// Change the least severe level sent, for this client and all its clones
+    pub fn set_min_level(&self, level: LogLevel) {
+        self.levels.set_min_level(level);
+    }
+
+    /// Send entries down to `level` for `duration`, then go back to `min_level`
+    ///
+    /// Meant for turning on debug output for a while without a restart. A
+    /// background timer ends the boost, and it stops counting once
+    /// `duration` has passed on the client's clock even before that. A
+    /// boost never makes the client quieter, and a new one replaces the
+    /// last. [`LogClient::set_min_level`] during a boost changes the level
+    /// it returns to.
+    pub fn boost_level(&self, level: LogLevel, duration: Duration) {
+        let until = self.clock.now_instant() + duration;
+        self.levels.boost(level, until);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let levels = Arc::downgrade(&self.levels);
+            runtime.spawn(async move {
+                tokio::time::sleep(duration).await;
+                if let Some(levels) = levels.upgrade() {
+                    levels.end_boost(until);
+                }
+            });
+        }
+    }
+
     /// Log a message with specified level and fields
+    ///
+    /// Levels below `min_level` return at once, and so do sends the open
+    /// circuit breaker would fail, before the entry is built.
     pub async fn log(&self, level: LogLevel, message: &str, fields: LogFields) -> Result<()> {
-        let mut entry = LogEntry::new(level, self.config.daemon_name.clone(), message.to_string());
+        if !self.is_enabled(level) {
+            return Ok(());
+        }
+        if let Some(ref breaker) = self.breaker {
+            breaker.fail_fast()?;
+        }
+
+        self.send_entry(self.build_entry(level, message, fields)).await
+    }
+
+    /// Entry from this client, timestamped now
+    pub(crate) fn build_entry(&self, level: LogLevel, message: &str, fields: LogFields) -> LogEntry {
+        let mut entry = LogEntry::with_clock(level, self.config.daemon_name.clone(), message.to_string(), &*self.clock);
         entry.fields = fields;
-        entry.pid = Some(std::process::id());
-        entry.hostname = Some(self.hostname.clone());
+        entry
+    }
 
-        let json_data = entry.to_json()?;
-        let message = format!("{}\n", json_data);
+    /// Start a batch of entries sent together in one write
+    ///
+    /// See [`LogBatch`] for when it is sent.
+    pub fn batch(&self) -> LogBatch {
+        LogBatch::new(self.clone())
+    }
 
-        self.ensure_connected().await?;
-        
+    /// Send a pre-built entry as-is
+    ///
+    /// The id, timestamp, level, daemon and fields are kept exactly as given;
+    /// only `pid`, `hostname`, `container_id` and resource attributes are
+    /// filled in when unset. Entries below `min_level` are dropped.
+    /// While the circuit breaker is open this fails immediately without
+    /// trying to connect. When `max_buffered_entries` sends are already
+    /// waiting, this waits or fails according to `on_buffer_full`. An entry
+    /// older than `max_entry_age_secs` by then is dropped instead of sent.
+    pub async fn send_entry(&self, mut entry: LogEntry) -> Result<()> {
+        if !self.is_enabled(entry.level) {
+            return Ok(());
+        }
+        let _slot = match self.backlog {
+            Some(ref backlog) => Some(self.reserve_slot(backlog).await?),
+            None => None,
+        };
+        if self.is_stale(&entry) {
+            self.record_dropped_stale(1);
+            return Ok(());
+        }
+        self.enrich(&mut entry);
+
+        let message = self.encode(&entry)?;
+
+        self.write_through_breaker(&message).await
+    }
+
+    /// Send several entries in a single write
+    ///
+    /// Each entry is handled as by [`LogClient::send_entry`], except that the
+    /// whole group takes one place in the backlog and one attempt through the
+    /// circuit breaker.
+    pub(crate) async fn send_entries(&self, entries: Vec<LogEntry>) -> Result<()> {
+        let _slot = match self.backlog {
+            Some(ref backlog) => Some(self.reserve_slot(backlog).await?),
+            None => None,
+        };
+        let mut message = String::new();
+        for mut entry in entries.into_iter().filter(|entry| self.is_enabled(entry.level)) {
+            if self.is_stale(&entry) {
+                self.record_dropped_stale(1);
+                continue;
+            }
+            self.enrich(&mut entry);
+            message.push_str(&self.encode(&entry)?);
+        }
+        if message.is_empty() {
+            return Ok(());
+        }
+
+        self.write_through_breaker(&message).await
+    }
+
+    /// Write encoded lines, recording the outcome with the circuit breaker
+    async fn write_through_breaker(&self, message: &str) -> Result<()> {
+        let Some(ref breaker) = self.breaker else {
+            return self.write_message(message).await;
+        };
+        // Dropped if this future is cancelled, which ends a probe as failed
+        let attempt = breaker.check()?;
+        let result = self.write_message(message).await;
+        match result {
+            Ok(()) => attempt.succeeded(),
+            Err(_) => attempt.failed(),
+        }
+        result
+    }
+
+    /// Take a place in the backlog of entries waiting to be written
+    async fn reserve_slot<'a>(&self, backlog: &'a Semaphore) -> Result<tokio::sync::SemaphorePermit<'a>> {
+        let permit = match self.config.on_buffer_full {
+            BufferFullPolicy::Block => backlog.acquire().await.ok(),
+            BufferFullPolicy::Error => backlog.try_acquire().ok(),
+        };
+        permit.ok_or_else(|| LogStreamError::Client("buffer full".to_string()))
+    }
+
+    /// Whether an entry is older than `max_entry_age_secs`
+    pub(crate) fn is_stale(&self, entry: &LogEntry) -> bool {
+        self.config.max_entry_age_secs.is_some_and(|max_age| {
+            self.clock.now_utc() - entry.timestamp > chrono::Duration::seconds(max_age as i64)
+        })
+    }
+
+    /// Count entries dropped for being stale
+    pub(crate) fn record_dropped_stale(&self, count: u64) {
+        self.dropped_stale.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Entries dropped for being older than `max_entry_age_secs`
+    pub fn dropped_stale_total(&self) -> u64 {
+        self.dropped_stale.load(Ordering::Relaxed)
+    }
+
+    /// Count records the `log` facade dropped because its queue was full
+    #[cfg(feature = "log-facade")]
+    pub(crate) fn record_dropped_by_facade(&self, count: u64) {
+        self.dropped_by_facade.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Records dropped by the `log` facade because its queue was full
+    pub fn dropped_by_facade_total(&self) -> u64 {
+        self.dropped_by_facade.load(Ordering::Relaxed)
+    }
+
+    /// Whether the circuit breaker currently fails sends fast
+    ///
+    /// Always `false` when `breaker_failure_threshold` is unset.
+    pub fn is_circuit_open(&self) -> bool {
+        self.breaker.as_ref().is_some_and(|breaker| breaker.is_open())
+    }
+
+    /// Write encoded lines, reconnecting once if the connection broke
+    ///
+    /// The connection lock is held from the first attempt through the
+    /// reconnect and retry, so no other send on this client slips in between.
+    /// Entries from one client therefore reach the server in the order their
+    /// sends were called, across reconnects too.
+    async fn write_message(&self, message: &str) -> Result<()> {
         let mut conn_guard = self.connection.lock().await;
-        if let Some(ref mut conn) = *conn_guard {
-            match conn.write_all(message.as_bytes()).await {
-                Ok(_) => match conn.flush().await {
-                    Ok(_) => Ok(()),
-                    Err(_) => {
-                        // Connection broken, reset and retry
-                        *conn_guard = None;
-                        drop(conn_guard);
-                        self.ensure_connected().await?;
-                        let mut conn_guard = self.connection.lock().await;
-                        if let Some(ref mut conn) = *conn_guard {
-                            conn.write_all(message.as_bytes()).await?;
-                            conn.flush().await?;
-                        }
-                        Ok(())
-                    }
-                },
-                Err(_) => {
-                    // Connection broken, reset and retry
-                    *conn_guard = None;
-                    drop(conn_guard);
-                    self.ensure_connected().await?;
-                    let mut conn_guard = self.connection.lock().await;
-                    if let Some(ref mut conn) = *conn_guard {
-                        conn.write_all(message.as_bytes()).await?;
-                        conn.flush().await?;
-                    }
-                    Ok(())
+        let conn = self.connect_locked(&mut conn_guard).await?;
+        if write_flushed(conn, message).await.is_ok() {
+            return Ok(());
+        }
+
+        // Connection broken, reset and retry
+        *conn_guard = None;
+        tokio::time::sleep(self.reconnect_delay(0)).await;
+        let conn = self.connect_locked(&mut conn_guard).await?;
+        write_flushed(conn, message).await
+    }
+
+    /// Serialize an entry as the line(s) to send, chunked when configured
+    fn encode(&self, entry: &LogEntry) -> Result<String> {
+        // Sized up front so the entry and its newline usually fit without regrowing
+        let mut buffer = Vec::with_capacity(entry.estimated_serialized_size() + 1);
+        serde_json::to_writer(&mut buffer, entry)?;
+        let mut json_data = String::from_utf8(buffer).expect("serde_json writes UTF-8");
+        match self.config.chunk_bytes {
+            Some(max) if json_data.len() > max => {
+                let mut message = String::with_capacity(json_data.len() * 2);
+                for chunk in EntryChunk::split(&json_data, max) {
+                    message.push_str(&chunk.to_json()?);
+                    message.push('\n');
                 }
+                Ok(message)
+            }
+            _ => {
+                json_data.push('\n');
+                Ok(json_data)
             }
-        } else {
-            Ok(())
+        }
+    }
+
+    /// Fill in the client-side metadata an entry doesn't already carry
+    fn enrich(&self, entry: &mut LogEntry) {
+        if entry.pid.is_none() {
+            entry.pid = self.config.pid_source.resolve();
+        }
+        if entry.hostname.is_none() {
+            entry.hostname = Some(Arc::clone(&self.hostname));
+        }
+        if let Some(ref container_id) = self.container_id {
+            entry
+                .fields
+                .entry("container_id".to_string())
+                .or_insert_with(|| container_id.clone());
+        }
+        for (key, value) in &self.config.resource_attributes {
+            entry.resource.entry(key.clone()).or_insert_with(|| value.clone());
         }
     }
 
@@ -173,6 +687,70 @@ impl LogClient {
         self.log(LogLevel::Debug, message.as_ref(), HashMap::new()).await
     }
 
+    /// Flush buffered entries and confirm the server is still reading them
+    ///
+    /// Sends a ping after everything already written and waits for the pong,
+    /// so `true` means every earlier entry reached the server's socket. This
+    /// is weaker than an acknowledgement of storage, but cheap enough for
+    /// shutdown hooks. Returns `false` when not connected, when the
+    /// connection fails, or when no reply arrives within `wait`.
+    pub async fn flush_and_confirm(&self, wait: Duration) -> bool {
+        let deadline = Instant::now() + wait;
+        let Ok(mut conn_guard) = timeout_at(deadline, self.connection.lock()).await else {
+            return false;
+        };
+        let Some(conn) = conn_guard.as_mut() else {
+            return false;
+        };
+        // A pong arriving after the deadline would answer the next ping, so
+        // a connection that timed out is dropped like one that failed
+        let confirmed = matches!(
+            timeout_at(deadline, async {
+                conn.flush().await?;
+                ping(conn).await
+            })
+            .await,
+            Ok(Ok(()))
+        );
+        if !confirmed {
+            *conn_guard = None;
+        }
+        confirmed
+    }
+
+    /// Ask the server which file this client's entries are written to
+    ///
+    /// Sends a [`ControlCommand::Where`] for the client's daemon name and
+    /// waits up to `timeout_seconds` for the reply. Returns `None` when the
+    /// server's file backend is disabled.
+    pub async fn query_storage_path(&self) -> Result<Option<PathBuf>> {
+        let command = ControlCommand::Where { daemon: self.config.daemon_name.clone() };
+        let message = format!("{}\n", command.to_json()?);
+        let wait = Duration::from_secs(self.config.timeout_seconds);
+
+        let mut conn_guard = self.connection.lock().await;
+        let conn = self.connect_locked(&mut conn_guard).await?;
+        let result = timeout(wait, async {
+            write_flushed(conn, &message).await?;
+            read_reply(conn).await
+        })
+        .await;
+        let reply = match result {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(e)) => {
+                *conn_guard = None;
+                return Err(e);
+            }
+            Err(_) => {
+                *conn_guard = None;
+                return Err(LogStreamError::Connection("No reply to storage path query".to_string()));
+            }
+        };
+
+        let reply: StoragePathReply = serde_json::from_slice(&reply)?;
+        Ok(reply.path)
+    }
+
     /// Close the connection to the server
     pub async fn close(&self) -> Result<()> {
         let mut conn_guard = self.connection.lock().await;
@@ -183,9 +761,109 @@ impl LogClient {
     }
 }
 
+/// Run a send from a `Drop` impl, which can't await it
+///
+/// Within a Tokio runtime the send is spawned as a task. Outside of one the
+/// drop blocks on it in a fresh current-thread runtime. That fallback only
+/// works for clients that haven't connected yet (e.g. from
+/// [`LogClient::new_lazy`]) or whose connection's runtime is still running:
+/// a connection made on a runtime that has since shut down can't be driven,
+/// and the send fails.
+pub(crate) fn send_on_drop<F>(send: F)
+where
+    F: std::future::Future<Output = Result<()>> + Send + 'static,
+{
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        handle.spawn(async move {
+            let _ = send.await;
+        });
+    } else if let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        let _ = runtime.block_on(send);
+    }
+}
+
+/// Pick the hostname reported in log entries
+///
+/// An explicit override wins, then the FQDN (when enabled and resolvable),
+/// then the short system hostname.
+pub(crate) fn resolve_hostname<F>(
+    config: &ClientConfig,
+    short_hostname: String,
+    fqdn_lookup: F,
+) -> String
+where
+    F: FnOnce(&str) -> Option<String>,
+{
+    if let Some(ref hostname) = config.hostname_override {
+        return hostname.clone();
+    }
+    if config.use_fqdn {
+        if let Some(fqdn) = fqdn_lookup(&short_hostname) {
+            return fqdn;
+        }
+    }
+    short_hostname
+}
+
+/// Resolve the canonical (fully-qualified) name of a host via the system resolver
+fn lookup_fqdn(host: &str) -> Option<String> {
+    use std::ffi::{CStr, CString};
+
+    let c_host = CString::new(host).ok()?;
+    // SAFETY: an all-zero addrinfo is a valid "no hints" value
+    let mut hints: libc::addrinfo = unsafe { std::mem::zeroed() };
+    hints.ai_family = libc::AF_UNSPEC;
+    hints.ai_flags = libc::AI_CANONNAME;
+
+    let mut result: *mut libc::addrinfo = std::ptr::null_mut();
+    // SAFETY: all pointers are valid for the duration of the call and
+    // `result` is freed below on success
+    let rc = unsafe { libc::getaddrinfo(c_host.as_ptr(), std::ptr::null(), &hints, &mut result) };
+    if rc != 0 || result.is_null() {
+        return None;
+    }
+
+    // SAFETY: `result` is a valid list returned by getaddrinfo; the canonical
+    // name is only set on the first entry and lives until freeaddrinfo
+    let fqdn = unsafe {
+        let canonname = (*result).ai_canonname;
+        let fqdn = if canonname.is_null() {
+            None
+        } else {
+            CStr::from_ptr(canonname).to_str().ok().map(str::to_string)
+        };
+        libc::freeaddrinfo(result);
+        fqdn
+    };
+
+    fqdn.filter(|name| !name.is_empty())
+}
+
+/// Compute an exponential backoff delay with random jitter
+///
+/// The base delay doubles with each attempt (capped at 30s) and a uniformly
+/// distributed jitter in `0..=jitter_ms` is added on top, so a fleet of clients
+/// losing the same server doesn't reconnect in lockstep.
+pub(crate) fn backoff_delay<R: Rng + ?Sized>(
+    base_ms: u64,
+    jitter_ms: u64,
+    attempt: u32,
+    rng: &mut R,
+) -> Duration {
+    let backoff_ms = base_ms
+        .saturating_mul(1u64 << attempt.min(16))
+        .min(MAX_RECONNECT_DELAY_MS);
+    let jitter_ms = if jitter_ms > 0 { rng.gen_range(0..=jitter_ms) } else { 0 };
+    Duration::from_millis(backoff_ms + jitter_ms)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::PidSource;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
     use tempfile::tempdir;
     use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
     use tokio::net::UnixListener;
@@ -195,6 +873,104 @@ mod tests {
         UnixListener::bind(socket_path).unwrap()
     }
 
+    /// Client that is never connected, for exercising entry enrichment
+    fn offline_client(config: ClientConfig, container_id: Option<&str>) -> LogClient {
+        LogClient {
+            config,
+            connection: Arc::new(Mutex::new(None)),
+            hostname: "test-host".into(),
+            container_id: container_id.map(str::to_string),
+            keepalive_started: Arc::new(AtomicBool::new(false)),
+            active_path: Arc::new(AtomicUsize::new(0)),
+            probe_started: Arc::new(AtomicBool::new(false)),
+            breaker: None,
+            backlog: None,
+            clock: Arc::new(SystemClock),
+            dropped_stale: Arc::new(AtomicU64::new(0)),
+            dropped_by_facade: Arc::new(AtomicU64::new(0)),
+            levels: Arc::new(LevelControl::default()),
+        }
+    }
+
+    fn serialized_pid(client: &LogClient) -> serde_json::Value {
+        let mut entry = LogEntry::new(LogLevel::Info, "pid-test".to_string(), "message".to_string());
+        client.enrich(&mut entry);
+        let json: serde_json::Value = serde_json::from_str(&entry.to_json().unwrap()).unwrap();
+        json["pid"].clone()
+    }
+
+    #[test]
+    fn test_pid_source_process_id() {
+        let client = offline_client(ClientConfig::default(), None);
+        assert_eq!(serialized_pid(&client), serde_json::json!(std::process::id()));
+    }
+
+    #[test]
+    fn test_pid_source_none() {
+        let config = ClientConfig {
+            pid_source: PidSource::None,
+            ..Default::default()
+        };
+        let client = offline_client(config, None);
+        assert_eq!(serialized_pid(&client), serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_pid_source_custom() {
+        let config = ClientConfig {
+            pid_source: PidSource::Custom(4242),
+            ..Default::default()
+        };
+        let client = offline_client(config, None);
+        assert_eq!(serialized_pid(&client), serde_json::json!(4242));
+    }
+
+    #[test]
+    fn test_pid_source_from_toml() {
+        let config: ClientConfig = toml::from_str("pid_source = { Custom = 7 }").unwrap();
+        assert_eq!(config.pid_source, PidSource::Custom(7));
+
+        let config: ClientConfig = toml::from_str("pid_source = \"None\"").unwrap();
+        assert_eq!(config.pid_source, PidSource::None);
+
+        let config: ClientConfig = toml::from_str("").unwrap();
+        assert_eq!(config.pid_source, PidSource::ProcessId);
+    }
+
+    #[test]
+    fn test_container_id_field() {
+        let client = offline_client(ClientConfig::default(), Some("task-8f3a"));
+
+        let mut entry = LogEntry::new(LogLevel::Info, "ctr".to_string(), "message".to_string());
+        client.enrich(&mut entry);
+        assert_eq!(entry.fields.get("container_id").map(String::as_str), Some("task-8f3a"));
+
+        // A container_id set by the caller is left alone
+        let mut entry = LogEntry::new(LogLevel::Info, "ctr".to_string(), "message".to_string());
+        entry.fields.insert("container_id".to_string(), "explicit".to_string());
+        client.enrich(&mut entry);
+        assert_eq!(entry.fields.get("container_id").map(String::as_str), Some("explicit"));
+    }
+
+    #[test]
+    fn test_resource_attributes_are_added_to_resource() {
+        let config = ClientConfig {
+            resource_attributes: HashMap::from([
+                ("service.name".to_string(), "checkout".to_string()),
+                ("deployment.environment".to_string(), "prod".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let client = offline_client(config, None);
+
+        let mut entry = LogEntry::new(LogLevel::Info, "checkout".to_string(), "message".to_string());
+        entry.resource.insert("deployment.environment".to_string(), "canary".to_string());
+        client.enrich(&mut entry);
+        assert_eq!(entry.resource.get("service.name").map(String::as_str), Some("checkout"));
+        assert_eq!(entry.resource.get("deployment.environment").map(String::as_str), Some("canary"));
+        assert!(entry.fields.is_empty());
+    }
+
     #[tokio::test]
     async fn test_client_config_defaults() {
         let config = ClientConfig {
@@ -228,6 +1004,82 @@ mod tests {
         assert!(invalid_config2.validate().is_err());
     }
 
+    #[test]
+    fn test_backoff_delay_jitter_bounds() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let delays: Vec<Duration> = (0..100)
+            .map(|_| backoff_delay(100, 50, 0, &mut rng))
+            .collect();
+
+        for delay in &delays {
+            assert!(*delay >= Duration::from_millis(100));
+            assert!(*delay <= Duration::from_millis(150));
+        }
+
+        // Jitter must actually spread the delays out
+        let distinct: HashSet<_> = delays.iter().collect();
+        assert!(distinct.len() > 1);
+
+        // Later attempts back off exponentially before jitter is applied
+        let delay = backoff_delay(100, 50, 3, &mut rng);
+        assert!(delay >= Duration::from_millis(800));
+        assert!(delay <= Duration::from_millis(850));
+    }
+
+    #[test]
+    fn test_backoff_delay_without_jitter() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(backoff_delay(100, 0, 0, &mut rng), Duration::from_millis(100));
+        assert_eq!(backoff_delay(100, 0, 1, &mut rng), Duration::from_millis(200));
+        assert_eq!(
+            backoff_delay(100, 0, 30, &mut rng),
+            Duration::from_millis(MAX_RECONNECT_DELAY_MS)
+        );
+    }
+
+    #[test]
+    fn test_hostname_override_wins() {
+        let config = ClientConfig {
+            hostname_override: Some("node-17".to_string()),
+            use_fqdn: true,
+            ..Default::default()
+        };
+
+        let hostname = resolve_hostname(&config, "short".to_string(), |_| {
+            panic!("FQDN lookup should not run when an override is set")
+        });
+        assert_eq!(hostname, "node-17");
+    }
+
+    #[test]
+    fn test_hostname_fqdn_resolution() {
+        let config = ClientConfig {
+            use_fqdn: true,
+            ..Default::default()
+        };
+
+        let mut looked_up = None;
+        let hostname = resolve_hostname(&config, "web01".to_string(), |host| {
+            looked_up = Some(host.to_string());
+            Some(format!("{}.prod.example.com", host))
+        });
+        assert_eq!(looked_up.as_deref(), Some("web01"));
+        assert_eq!(hostname, "web01.prod.example.com");
+
+        // Unresolvable names fall back to the short hostname
+        let hostname = resolve_hostname(&config, "web01".to_string(), |_| None);
+        assert_eq!(hostname, "web01");
+    }
+
+    #[test]
+    fn test_hostname_defaults_to_short_name() {
+        let config = ClientConfig::default();
+        let hostname = resolve_hostname(&config, "web01".to_string(), |_| {
+            panic!("FQDN lookup should not run when disabled")
+        });
+        assert_eq!(hostname, "web01");
+    }
+
     #[tokio::test]
     async fn test_log_client_connection() {
         let temp_dir = tempdir().unwrap();
@@ -259,6 +1111,174 @@ mod tests {
         assert!(!client.hostname.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_with_connection_sends_over_injected_stream() {
+        let (client_end, server_end) = UnixStream::pair().unwrap();
+        let client = LogClient::with_connection(client_end, "paired-daemon").unwrap();
+        assert!(client.is_connected().await);
+
+        client.info("over a pair").await.unwrap();
+
+        let mut lines = BufReader::new(server_end).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        let entry = LogEntry::from_json(&line).unwrap();
+        assert_eq!(entry.daemon, "paired-daemon");
+        assert_eq!(entry.message, "over a pair");
+    }
+
+    #[tokio::test]
+    async fn test_levels_below_min_level_are_not_sent() {
+        /// Clock counting the timestamps it hands out, one per entry the client builds
+        #[derive(Default)]
+        struct CountingClock(AtomicUsize);
+
+        impl Clock for CountingClock {
+            fn now_utc(&self) -> chrono::DateTime<chrono::Utc> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                chrono::Utc::now()
+            }
+
+            fn now_instant(&self) -> std::time::Instant {
+                std::time::Instant::now()
+            }
+        }
+
+        let (client_end, server_end) = UnixStream::pair().unwrap();
+        let clock = Arc::new(CountingClock::default());
+        let client = LogClient {
+            config: ClientConfig {
+                daemon_name: "quiet-daemon".to_string(),
+                min_level: LogLevel::Warning,
+                ..Default::default()
+            },
+            clock: clock.clone(),
+            ..LogClient::with_connection(client_end, "quiet-daemon").unwrap()
+        };
+        assert!(!client.is_enabled(LogLevel::Info));
+        assert!(client.is_enabled(LogLevel::Error));
+
+        // Suppressed calls return before an entry, and its uuid, is created
+        client.debug("suppressed").await.unwrap();
+        client.info("suppressed").await.unwrap();
+        client.log(LogLevel::Notice, "suppressed", HashMap::new()).await.unwrap();
+        assert_eq!(clock.0.load(Ordering::SeqCst), 0);
+        client.warning("sent").await.unwrap();
+        assert_eq!(clock.0.load(Ordering::SeqCst), 1);
+        let entry = LogEntry::new(LogLevel::Notice, "quiet-daemon".to_string(), "suppressed".to_string());
+        client.send_entry(entry).await.unwrap();
+        client.close().await.unwrap();
+
+        let mut lines = BufReader::new(server_end).lines();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(LogEntry::from_json(&line).unwrap().message, "sent");
+        assert!(lines.next_line().await.unwrap().is_none());
+
+        // Suppressed calls never touch the connection, so they succeed without a server
+        let offline = offline_client(ClientConfig { min_level: LogLevel::Error, ..Default::default() }, None);
+        offline.debug("nowhere to go").await.unwrap();
+        assert!(!offline.is_connected().await);
+    }
+
+    #[tokio::test]
+    async fn test_boost_level_reverts_after_duration() {
+        use crate::types::MockClock;
+
+        let (client_end, server_end) = UnixStream::pair().unwrap();
+        let clock = Arc::new(MockClock::default());
+        let client = LogClient {
+            config: ClientConfig {
+                daemon_name: "on-call".to_string(),
+                min_level: LogLevel::Info,
+                ..Default::default()
+            },
+            clock: clock.clone(),
+            ..LogClient::with_connection(client_end, "on-call").unwrap()
+        };
+
+        client.debug("before").await.unwrap();
+        // Boosting any clone boosts them all
+        client.clone().boost_level(LogLevel::Debug, Duration::from_secs(300));
+        assert_eq!(client.min_level(), LogLevel::Debug);
+        client.debug("boosted").await.unwrap();
+        // A boost only ever adds levels
+        client.boost_level(LogLevel::Error, Duration::from_secs(300));
+        assert_eq!(client.min_level(), LogLevel::Info);
+        client.boost_level(LogLevel::Debug, Duration::from_secs(300));
+
+        clock.advance(Duration::from_secs(301));
+        assert!(!client.is_enabled(LogLevel::Debug));
+        client.debug("after").await.unwrap();
+        client.set_min_level(LogLevel::Warning);
+        client.info("quieter").await.unwrap();
+        client.warning("done").await.unwrap();
+        client.close().await.unwrap();
+
+        let mut lines = BufReader::new(server_end).lines();
+        let mut messages = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            messages.push(LogEntry::from_json(&line).unwrap().message);
+        }
+        assert_eq!(messages, ["boosted", "done"]);
+    }
+
+    #[tokio::test]
+    async fn test_new_lazy_does_not_connect() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("lazy.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+
+        // No server exists yet, so an eager client would fail here
+        let config = ClientConfig {
+            socket_path: socket_str.clone(),
+            daemon_name: "lazy-daemon".to_string(),
+            ..Default::default()
+        };
+        let client = LogClient::new_lazy(config).unwrap();
+        assert!(!client.is_connected().await);
+
+        let listener = create_test_server(&socket_str).await;
+        let accept = timeout(Duration::from_millis(100), listener.accept()).await;
+        assert!(accept.is_err(), "new_lazy must not touch the socket");
+
+        client.preconnect().await.unwrap();
+        assert!(client.is_connected().await);
+        let accept = timeout(Duration::from_secs(1), listener.accept()).await;
+        assert!(accept.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_lazy_client_connects_on_first_log() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("lazy_log.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+        let listener = create_test_server(&socket_str).await;
+
+        let config = ClientConfig {
+            socket_path: socket_str,
+            daemon_name: "lazy-daemon".to_string(),
+            ..Default::default()
+        };
+        let client = LogClient::new_lazy(config).unwrap();
+        client.info("first message").await.unwrap();
+
+        let (stream, _) = timeout(Duration::from_secs(1), listener.accept()).await.unwrap().unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).await.unwrap();
+        assert!(line.contains("first message"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_override() {
+        let config = ClientConfig {
+            socket_path: "/tmp/nonexistent_socket_lazy.sock".to_string(),
+            daemon_name: "test-daemon".to_string(),
+            connect_timeout_ms: Some(50),
+            ..Default::default()
+        };
+        let client = LogClient::new_lazy(config).unwrap();
+        assert!(matches!(client.preconnect().await, Err(LogStreamError::Connection(_))));
+    }
+
     #[tokio::test]
     async fn test_log_client_connection_timeout() {
         let socket_path = "/tmp/nonexistent_socket_12345.sock";
@@ -469,6 +1489,60 @@ mod tests {
         assert!(parsed["id"].is_string());
     }
 
+    #[tokio::test]
+    async fn test_connect_any_fails_over_to_backup() {
+        let temp_dir = tempdir().unwrap();
+        let primary = temp_dir.path().join("primary.sock").to_string_lossy().to_string();
+        let backup = temp_dir.path().join("backup.sock").to_string_lossy().to_string();
+        let listener = create_test_server(&backup).await;
+
+        let client = LogClient::connect_any(&[&primary, &backup], "failover-daemon").await.unwrap();
+        assert_eq!(client.active_socket_path(), backup);
+        client.info("via backup").await.unwrap();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).await.unwrap();
+        let entry = LogEntry::from_json(line.trim()).unwrap();
+        assert_eq!((entry.daemon.as_str(), entry.message.as_str()), ("failover-daemon", "via backup"));
+
+        assert!(matches!(
+            LogClient::connect_any(&[], "failover-daemon").await,
+            Err(LogStreamError::Config(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_primary_probe_moves_back_to_primary() {
+        let temp_dir = tempdir().unwrap();
+        let primary = temp_dir.path().join("primary.sock").to_string_lossy().to_string();
+        let backup = temp_dir.path().join("backup.sock").to_string_lossy().to_string();
+        let _backup_listener = create_test_server(&backup).await;
+
+        let client = LogClient::with_config(ClientConfig {
+            socket_path: primary.clone(),
+            fallback_socket_paths: vec![backup.clone()],
+            primary_probe_secs: Some(1),
+            daemon_name: "probe-daemon".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(client.active_socket_path(), backup);
+
+        let primary_listener = create_test_server(&primary).await;
+        let (stream, _) = timeout(Duration::from_secs(3), primary_listener.accept()).await.unwrap().unwrap();
+        // The probe swaps the connection in just after the primary accepts it
+        while client.active_socket_path() != primary {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        client.info("back on primary").await.unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).await.unwrap();
+        assert!(line.contains("back on primary"));
+    }
+
     #[tokio::test]
     async fn test_reconnection_after_disconnect() {
         let temp_dir = tempdir().unwrap();
@@ -503,4 +1577,285 @@ mod tests {
         // Try to send another message - should reconnect
         client.info("Message after reconnect").await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_concurrent_loggers_stay_ordered_across_reconnect() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("test_ordered.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+
+        let listener = create_test_server(&socket_str).await;
+        // The first connection is dropped after a few lines to force a reconnect
+        let server = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut connections = 0;
+            loop {
+                let accepted = timeout(Duration::from_secs(1), listener.accept()).await;
+                let Ok(Ok((stream, _))) = accepted else {
+                    return (received, connections);
+                };
+                connections += 1;
+                let mut lines = BufReader::new(stream).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    received.push(LogEntry::from_json(&line).unwrap());
+                    if connections == 1 && received.len() == 20 {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let client = LogClient::connect(&socket_str, "ordered-daemon").await.unwrap();
+        let loggers: Vec<_> = (0..4)
+            .map(|logger| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    for seq in 0..100 {
+                        let mut fields = HashMap::new();
+                        fields.insert("logger".to_string(), logger.to_string());
+                        fields.insert("seq".to_string(), seq.to_string());
+                        client.log(LogLevel::Info, "ordered", fields).await.unwrap();
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                    }
+                })
+            })
+            .collect();
+        for logger in loggers {
+            logger.await.unwrap();
+        }
+        client.close().await.unwrap();
+
+        let (received, connections) = server.await.unwrap();
+        assert!(connections >= 2, "no reconnect happened");
+        assert!(received.len() > 20);
+        for logger in 0..4 {
+            let seqs: Vec<u32> = received
+                .iter()
+                .filter(|entry| entry.fields["logger"] == logger.to_string())
+                .map(|entry| entry.fields["seq"].parse().unwrap())
+                .collect();
+            assert!(seqs.windows(2).all(|pair| pair[0] < pair[1]), "logger {} out of order: {:?}", logger, seqs);
+            assert_eq!(seqs.last(), Some(&99));
+        }
+    }
+
+    /// Server that accepts one connection and only reads from it once `release` fires
+    async fn stalled_server(socket_path: &str) -> tokio::sync::oneshot::Sender<()> {
+        let listener = create_test_server(socket_path).await;
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = released.await;
+            let mut buf = vec![0; 64 * 1024];
+            while let Ok(n) = stream.read(&mut buf).await {
+                if n == 0 { break; }
+            }
+        });
+        release
+    }
+
+    /// Client with a two-entry backlog plus two sends too large for the socket buffer filling it
+    async fn client_with_full_backlog(
+        socket_path: &str,
+        on_buffer_full: BufferFullPolicy,
+    ) -> (LogClient, Vec<tokio::task::JoinHandle<Result<()>>>) {
+        let config = ClientConfig {
+            socket_path: socket_path.to_string(),
+            daemon_name: "backlog-daemon".to_string(),
+            max_buffered_entries: Some(2),
+            on_buffer_full,
+            ..Default::default()
+        };
+        let client = LogClient::with_config(config).await.unwrap();
+        let large = "x".repeat(4 * 1024 * 1024);
+        let pending = (0..2)
+            .map(|_| {
+                let client = client.clone();
+                let mut fields = HashMap::new();
+                fields.insert("body".to_string(), large.clone());
+                tokio::spawn(async move { client.info_with_fields("large", fields).await })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        (client, pending)
+    }
+
+    #[tokio::test]
+    async fn test_buffer_full_errors() {
+        let temp_dir = tempdir().unwrap();
+        let socket_str = temp_dir.path().join("backlog_error.sock").to_string_lossy().to_string();
+        let release = stalled_server(&socket_str).await;
+        let (client, pending) = client_with_full_backlog(&socket_str, BufferFullPolicy::Error).await;
+
+        match client.info("no room").await {
+            Err(LogStreamError::Client(message)) => assert_eq!(message, "buffer full"),
+            other => panic!("expected buffer full error, got {:?}", other),
+        }
+
+        release.send(()).unwrap();
+        for send in pending {
+            send.await.unwrap().unwrap();
+        }
+        client.info("room again").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buffer_full_blocks_until_space_frees() {
+        let temp_dir = tempdir().unwrap();
+        let socket_str = temp_dir.path().join("backlog_block.sock").to_string_lossy().to_string();
+        let release = stalled_server(&socket_str).await;
+        let (client, pending) = client_with_full_backlog(&socket_str, BufferFullPolicy::Block).await;
+
+        let blocked = tokio::spawn(async move { client.info("waits for room").await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!blocked.is_finished());
+
+        release.send(()).unwrap();
+        for send in pending {
+            send.await.unwrap().unwrap();
+        }
+        timeout(Duration::from_secs(5), blocked).await.unwrap().unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_pings_are_answered() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("test_keepalive.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+
+        let listener = create_test_server(&socket_str).await;
+        let pings = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let server_pings = Arc::clone(&pings);
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == r#"{"cmd":"ping"}"# {
+                    server_pings.fetch_add(1, Ordering::SeqCst);
+                    writer.write_all(b"{\"pong\":true}\n").await.unwrap();
+                }
+            }
+        });
+
+        let config = ClientConfig {
+            socket_path: socket_str,
+            daemon_name: "keepalive-daemon".to_string(),
+            keepalive_secs: Some(1),
+            ..Default::default()
+        };
+        let client = LogClient::with_config(config).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert!(pings.load(Ordering::SeqCst) >= 2);
+        assert!(client.is_connected().await);
+        client.info("still usable").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_confirm() {
+        let temp_dir = tempdir().unwrap();
+
+        // A server that answers pings
+        let live_path = temp_dir.path().join("live.sock").to_string_lossy().to_string();
+        let listener = create_test_server(&live_path).await;
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == r#"{"cmd":"ping"}"# {
+                    writer.write_all(b"{\"pong\":true}\n").await.unwrap();
+                }
+            }
+        });
+        let client = LogClient::connect(&live_path, "confirm-daemon").await.unwrap();
+        client.info("before shutdown").await.unwrap();
+        assert!(client.flush_and_confirm(Duration::from_secs(1)).await);
+
+        // A server that accepted the connection but has stopped reading
+        let dead_path = temp_dir.path().join("dead.sock").to_string_lossy().to_string();
+        let listener = create_test_server(&dead_path).await;
+        let hung = tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let client = LogClient::connect(&dead_path, "confirm-daemon").await.unwrap();
+        let started = std::time::Instant::now();
+        assert!(!client.flush_and_confirm(Duration::from_millis(200)).await);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        hung.abort();
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_confirm_drops_connection_on_late_pong() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("slow.sock").to_string_lossy().to_string();
+
+        // A server that answers pings, but only after the caller gave up
+        let listener = create_test_server(&socket_path).await;
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == r#"{"cmd":"ping"}"# {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    let _ = writer.write_all(b"{\"pong\":true}\n").await;
+                }
+            }
+        });
+
+        let client = LogClient::connect(&socket_path, "slow-daemon").await.unwrap();
+        assert!(!client.flush_and_confirm(Duration::from_millis(100)).await);
+        assert!(!client.is_connected().await);
+
+        // The late pong must not confirm a later call
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(!client.flush_and_confirm(Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fails_fast_while_open() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("down.sock").to_string_lossy().to_string();
+        let client = LogClient::new_lazy(ClientConfig {
+            socket_path: socket_path.clone(),
+            daemon_name: "breaker-daemon".to_string(),
+            breaker_failure_threshold: Some(2),
+            breaker_open_ms: 300,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Failures below the threshold still try to connect
+        for _ in 0..2 {
+            let err = client.info("server down").await.unwrap_err().to_string();
+            assert!(err.contains("Failed to connect"), "{}", err);
+        }
+        assert!(client.is_circuit_open());
+
+        // With the server back, open-state calls still fail without connecting
+        let listener = create_test_server(&socket_path).await;
+        let err = client.info("fails fast").await.unwrap_err().to_string();
+        assert!(err.contains("Circuit breaker open"), "{}", err);
+        assert!(!client.is_connected().await);
+        assert!(timeout(Duration::from_millis(50), listener.accept()).await.is_err());
+
+        // Once the open period ends a probe goes through and closes the breaker
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        client.info("probe").await.unwrap();
+        assert!(!client.is_circuit_open());
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let entry = LogEntry::from_json(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(entry.message, "probe");
+
+        let config = ClientConfig {
+            breaker_failure_threshold: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(config.validate(), Err(LogStreamError::Config(_))));
+    }
 }
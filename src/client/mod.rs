@@ -1,9 +1,28 @@
 //! LogStream client implementation
 
+pub mod batch;
+pub mod breaker;
+pub mod guard;
 pub mod logger;
+pub mod pipe;
+pub mod replay;
+pub mod span;
+pub mod timed;
+pub mod verbosity;
 
 #[cfg(feature = "journald")]
 pub mod journald;
 
+#[cfg(feature = "log-facade")]
+pub mod log_facade;
+
+pub use batch::LogBatch;
+pub use breaker::{CircuitBreaker, SendAttempt};
+pub use guard::ClientGuard;
+#[cfg(feature = "log-facade")]
+pub use log_facade::LogStreamLog;
 pub use logger::LogClient;
+pub use span::LogSpan;
+pub use timed::TimedOperation;
+pub use verbosity::LevelControl;
 pub use crate::types::LogLevel;
@@ -0,0 +1,125 @@
+//! Forwarding of text lines, such as a program's output, as log entries
+//!
+//! Backs the `logstream-client` binary, which pipes stdin to a server:
+//!
+//! ```text
+//! cat app.log | logstream-client --daemon web
+//! ```
+
+use crate::client::LogClient;
+use crate::types::{LogEntry, LogLevel};
+use crate::{LogStreamError, Result};
+use std::collections::HashMap;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::time::Duration;
+
+impl LogClient {
+    /// Send each line read from `reader` as a log entry, returning how many were sent
+    ///
+    /// Lines become `Info` entries from this client's daemon; blank lines are
+    /// skipped and invalid UTF-8 is replaced rather than rejected. With
+    /// `parse_json`, lines holding a JSON [`LogEntry`] are sent as that entry
+    /// instead, and other lines are still wrapped.
+    ///
+    /// Each line is read only after the previous one was written, so a slow
+    /// server slows reading down instead of buffering input without bound.
+    /// At EOF everything is flushed and confirmed with a ping; failing that
+    /// within `timeout_seconds` is an error, as entries may have been lost.
+    pub async fn send_lines<R: AsyncBufRead + Unpin>(&self, mut reader: R, parse_json: bool) -> Result<u64> {
+        let mut line = Vec::new();
+        let mut sent = 0;
+
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).await? == 0 {
+                break;
+            }
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\n', '\r']);
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            let parsed = if parse_json { LogEntry::from_json(text).ok() } else { None };
+            match parsed {
+                Some(entry) => self.send_entry(entry).await?,
+                None => self.log(LogLevel::Info, text, HashMap::new()).await?,
+            }
+            sent += 1;
+        }
+
+        let wait = Duration::from_secs(self.config().timeout_seconds);
+        if sent > 0 && !self.flush_and_confirm(wait).await {
+            return Err(LogStreamError::Connection(
+                "Server did not confirm receipt of piped entries".to_string(),
+            ));
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::LogClient;
+    use crate::config::ServerConfig;
+    use crate::server::LogServer;
+    use crate::types::{LogEntry, LogLevel};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tokio::time::Duration;
+
+    #[tokio::test]
+    async fn test_send_lines_stores_each_line() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("pipe.sock").to_string_lossy().to_string();
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_path.clone();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let server = Arc::new(LogServer::new(config).await.unwrap());
+        let bound = server.bind().await.unwrap();
+        let handle = tokio::spawn(bound.serve());
+
+        let mut json_entry = LogEntry::new(LogLevel::Error, "web".to_string(), "from json".to_string());
+        json_entry.fields.insert("status".to_string(), "500".to_string());
+        let input = format!(
+            "first line\r\n\n  second line\n{}\n{{\"not\":\"an entry\"}}\nlast line without newline",
+            json_entry.to_json().unwrap()
+        );
+
+        let client = LogClient::connect(&socket_path, "web").await.unwrap();
+        let sent = client.send_lines(input.as_bytes(), true).await.unwrap();
+        assert_eq!(sent, 5);
+        client.close().await.unwrap();
+
+        server.shutdown();
+        handle.await.unwrap().unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("web.log")).await.unwrap();
+        let stored: Vec<LogEntry> = content.lines().map(|l| LogEntry::from_json(l).unwrap()).collect();
+        let messages: Vec<&str> = stored.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["first line", "  second line", "from json", r#"{"not":"an entry"}"#, "last line without newline"]
+        );
+        assert_eq!(stored[0].level, LogLevel::Info);
+        assert_eq!(stored[2].id, json_entry.id);
+        assert!(stored[2].matches_content(&json_entry));
+    }
+
+    #[tokio::test]
+    async fn test_send_lines_wraps_json_without_parse_flag() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("pipe.sock");
+        let server = crate::test_util::InMemoryServer::bind(&socket_path).unwrap();
+
+        let entry = LogEntry::new(LogLevel::Error, "other".to_string(), "raw".to_string());
+        let input = format!("{}\n", entry.to_json().unwrap());
+        let client = LogClient::connect(&socket_path.to_string_lossy(), "web").await.unwrap();
+        assert_eq!(client.send_lines(input.as_bytes(), false).await.unwrap(), 1);
+
+        let received = server.wait_for(1, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(received[0].level, LogLevel::Info);
+        assert_eq!(received[0].daemon, "web");
+        assert_eq!(received[0].message, input.trim_end());
+    }
+}
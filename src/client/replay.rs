@@ -0,0 +1,199 @@
+//! Replaying entries saved to a local fallback file
+//!
+//! A fallback file holds one JSON entry per line, as written by
+//! [`LogEntry::to_json`]. Once the server is reachable again the entries are
+//! sent at a limited rate, in batches that are each confirmed with a ping
+//! before they are removed from the file.
+
+use crate::client::LogClient;
+use crate::types::LogEntry;
+use crate::{LogStreamError, Result};
+use std::path::Path;
+use tokio::time::{Duration, MissedTickBehavior};
+
+impl LogClient {
+    /// Send the entries in a fallback file, removing each batch once confirmed
+    ///
+    /// Entries are sent as-is through [`LogClient::send_entry`], keeping their
+    /// ids and timestamps, at most `replay_entries_per_sec` per second. After
+    /// every `replay_batch_size` entries [`LogClient::flush_and_confirm`]
+    /// checks they reached the server, and only then are they dropped from
+    /// the file, so a failure or crash mid-replay loses nothing; at worst the
+    /// unconfirmed batch is sent again. The file is left empty once all
+    /// entries are replayed. Lines that don't parse as an entry are dropped,
+    /// and so are entries older than `max_entry_age_secs`, counted in
+    /// [`LogClient::dropped_stale_total`]. Nothing may append to the file
+    /// while it is replayed.
+    ///
+    /// Returns the number of entries replayed; a missing file replays none.
+    pub async fn replay_fallback(&self, path: impl AsRef<Path>) -> Result<usize> {
+        let path = path.as_ref();
+        let contents = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let lines: Vec<&str> = contents.lines().filter(|line| !line.trim().is_empty()).collect();
+
+        let config = self.config();
+        let confirm_wait = Duration::from_secs(config.timeout_seconds);
+        let mut pacer = tokio::time::interval(Duration::from_secs(1) / config.replay_entries_per_sec);
+        pacer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut replayed = 0;
+        for (batch_index, batch) in lines.chunks(config.replay_batch_size).enumerate() {
+            let mut stale = 0;
+            for entry in batch.iter().filter_map(|line| LogEntry::from_json(line).ok()) {
+                if self.is_stale(&entry) {
+                    stale += 1;
+                    continue;
+                }
+                pacer.tick().await;
+                self.send_entry(entry).await?;
+                replayed += 1;
+            }
+            if !self.flush_and_confirm(confirm_wait).await {
+                return Err(LogStreamError::Connection(
+                    "Server did not confirm replayed entries".to_string(),
+                ));
+            }
+
+            let rest = &lines[(batch_index * config.replay_batch_size + batch.len())..];
+            rewrite(path, rest).await?;
+            // Only now are the stale entries gone from the file for good
+            self.record_dropped_stale(stale);
+        }
+
+        Ok(replayed)
+    }
+}
+
+/// Replace the file with the given lines, atomically so a crash keeps one version
+async fn rewrite(path: &Path, lines: &[&str]) -> Result<()> {
+    let mut contents = String::with_capacity(lines.iter().map(|line| line.len() + 1).sum());
+    for line in lines {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".replay");
+    let temp_path = path.with_file_name(temp_name);
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientConfig;
+    use crate::test_util::InMemoryServer;
+    use crate::types::{LogLevel, MockClock};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn write_fallback(path: &Path, count: usize) {
+        let lines: String = (0..count)
+            .map(|i| {
+                let entry = LogEntry::new(LogLevel::Warning, "replayer".to_string(), format!("saved {}", i));
+                entry.to_json().unwrap() + "\n"
+            })
+            .collect();
+        std::fs::write(path, lines).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_delivers_each_entry_once_and_empties_file() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("replay.sock");
+        let fallback = temp_dir.path().join("fallback.jsonl");
+        write_fallback(&fallback, 50);
+
+        let client = LogClient::new_lazy(ClientConfig {
+            socket_path: socket_path.to_string_lossy().to_string(),
+            daemon_name: "replayer".to_string(),
+            connect_timeout_ms: Some(100),
+            replay_batch_size: 20,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // While the server is down nothing is removed from the file
+        assert!(client.replay_fallback(&fallback).await.is_err());
+        assert_eq!(std::fs::read_to_string(&fallback).unwrap().lines().count(), 50);
+
+        let server = InMemoryServer::bind(&socket_path).unwrap();
+        assert_eq!(client.replay_fallback(&fallback).await.unwrap(), 50);
+
+        // Confirmed, so every entry has been read by the server already
+        let mut messages: Vec<String> = server.drain_to_vec().into_iter().map(|entry| entry.message).collect();
+        assert_eq!(messages.len(), 50);
+        messages.sort();
+        messages.dedup();
+        assert_eq!(messages.len(), 50);
+        assert_eq!(std::fs::read_to_string(&fallback).unwrap(), "");
+
+        assert_eq!(client.replay_fallback(temp_dir.path().join("missing.jsonl")).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_replay_is_rate_limited() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("paced.sock");
+        let fallback = temp_dir.path().join("fallback.jsonl");
+        write_fallback(&fallback, 11);
+        let _server = InMemoryServer::bind(&socket_path).unwrap();
+
+        let client = LogClient::new_lazy(ClientConfig {
+            socket_path: socket_path.to_string_lossy().to_string(),
+            daemon_name: "replayer".to_string(),
+            replay_entries_per_sec: 20,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let started = tokio::time::Instant::now();
+        assert_eq!(client.replay_fallback(&fallback).await.unwrap(), 11);
+        // The first entry goes out at once, the other ten 50ms apart
+        assert!(started.elapsed() >= Duration::from_millis(500), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn test_replay_drops_stale_entries() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("stale.sock");
+        let fallback = temp_dir.path().join("fallback.jsonl");
+        let clock = Arc::new(MockClock::default());
+
+        let client = LogClient::new_lazy_with_clock(
+            ClientConfig {
+                socket_path: socket_path.to_string_lossy().to_string(),
+                daemon_name: "replayer".to_string(),
+                connect_timeout_ms: Some(100),
+                max_entry_age_secs: Some(60),
+                ..Default::default()
+            },
+            clock.clone(),
+        )
+        .unwrap();
+
+        // Buffered during an outage: three entries, then two more 90s later
+        let buffered = |message: &str| {
+            let entry = LogEntry::with_clock(LogLevel::Warning, "replayer".to_string(), message.to_string(), &*clock);
+            entry.to_json().unwrap() + "\n"
+        };
+        let mut lines: String = (0..3).map(|i| buffered(&format!("old {}", i))).collect();
+        clock.advance(Duration::from_secs(90));
+        lines.extend((0..2).map(|i| buffered(&format!("fresh {}", i))));
+        std::fs::write(&fallback, lines).unwrap();
+        assert!(client.replay_fallback(&fallback).await.is_err());
+
+        let server = InMemoryServer::bind(&socket_path).unwrap();
+        assert_eq!(client.replay_fallback(&fallback).await.unwrap(), 2);
+        assert_eq!(client.dropped_stale_total(), 3);
+        let messages: Vec<String> = server.drain_to_vec().into_iter().map(|entry| entry.message).collect();
+        assert_eq!(messages, vec!["fresh 0", "fresh 1"]);
+        assert_eq!(std::fs::read_to_string(&fallback).unwrap(), "");
+    }
+}
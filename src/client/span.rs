@@ -0,0 +1,210 @@
+//! Spans that group related log entries from one client
+
+use crate::client::logger::send_on_drop;
+use crate::client::LogClient;
+use crate::types::{LogFields, LogLevel};
+use crate::Result;
+use std::collections::HashMap;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Message of the entry emitted when a span ends
+pub const SPAN_END_MESSAGE: &str = "span_end";
+
+/// A logical unit of work whose entries share a `span_id`
+///
+/// Every entry logged through the span carries `span_id` and `span_name`
+/// fields. When the span ends, via [`LogSpan::end`] or on drop, a
+/// `span_end` entry with the elapsed `duration_ms`, on the client's clock,
+/// is sent. Dropped within a Tokio runtime the entry is sent from a task;
+/// outside one, the drop blocks until it is sent.
+pub struct LogSpan {
+    client: LogClient,
+    id: Uuid,
+    name: String,
+    started: Instant,
+    ended: bool,
+}
+
+impl LogSpan {
+    pub(crate) fn new(client: LogClient, name: &str) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            started: client.clock().now_instant(),
+            ended: false,
+            client,
+        }
+    }
+
+    /// Unique id shared by all entries of this span
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Name the span was started with
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Log a message within the span
+    pub async fn log(&self, level: LogLevel, message: &str, mut fields: LogFields) -> Result<()> {
+        self.attach(&mut fields);
+        self.client.log(level, message, fields).await
+    }
+
+    /// Log an info message within the span
+    pub async fn info<S: AsRef<str>>(&self, message: S) -> Result<()> {
+        self.log(LogLevel::Info, message.as_ref(), HashMap::new()).await
+    }
+
+    /// Log an info message with fields within the span
+    pub async fn info_with_fields<S: AsRef<str>>(&self, message: S, fields: LogFields) -> Result<()> {
+        self.log(LogLevel::Info, message.as_ref(), fields).await
+    }
+
+    /// Log an error message within the span
+    pub async fn error<S: AsRef<str>>(&self, message: S) -> Result<()> {
+        self.log(LogLevel::Error, message.as_ref(), HashMap::new()).await
+    }
+
+    /// Log a warning message within the span
+    pub async fn warning<S: AsRef<str>>(&self, message: S) -> Result<()> {
+        self.log(LogLevel::Warning, message.as_ref(), HashMap::new()).await
+    }
+
+    /// Log a notice message within the span
+    pub async fn notice<S: AsRef<str>>(&self, message: S) -> Result<()> {
+        self.log(LogLevel::Notice, message.as_ref(), HashMap::new()).await
+    }
+
+    /// Log a debug message within the span
+    pub async fn debug<S: AsRef<str>>(&self, message: S) -> Result<()> {
+        self.log(LogLevel::Debug, message.as_ref(), HashMap::new()).await
+    }
+
+    /// End the span, sending the `span_end` entry before returning
+    pub async fn end(mut self) -> Result<()> {
+        self.ended = true;
+        let fields = self.end_fields();
+        self.client.log(LogLevel::Info, SPAN_END_MESSAGE, fields).await
+    }
+
+    fn attach(&self, fields: &mut LogFields) {
+        fields.insert("span_id".to_string(), self.id.to_string());
+        fields.insert("span_name".to_string(), self.name.clone());
+    }
+
+    fn end_fields(&self) -> LogFields {
+        let mut fields = HashMap::new();
+        self.attach(&mut fields);
+        fields.insert(
+            "duration_ms".to_string(),
+            self.client
+                .clock()
+                .now_instant()
+                .saturating_duration_since(self.started)
+                .as_millis()
+                .to_string(),
+        );
+        fields
+    }
+}
+
+impl Drop for LogSpan {
+    fn drop(&mut self) {
+        if self.ended {
+            return;
+        }
+
+        let client = self.client.clone();
+        let fields = self.end_fields();
+        send_on_drop(async move { client.log(LogLevel::Info, SPAN_END_MESSAGE, fields).await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientConfig;
+    use crate::test_util::InMemoryServer;
+    use crate::types::MockClock;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use tokio::time::Duration;
+
+    #[tokio::test]
+    async fn test_span_entries_share_id() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("span.sock")).unwrap();
+        let clock = Arc::new(MockClock::default());
+        let config = ClientConfig {
+            socket_path: server.socket_path().to_string_lossy().to_string(),
+            daemon_name: "span-daemon".to_string(),
+            ..Default::default()
+        };
+        let client = LogClient::new_lazy_with_clock(config, clock.clone()).unwrap();
+        let span = client.span("handle_request");
+        let span_id = span.id().to_string();
+
+        span.info("request started").await.unwrap();
+        span.warning("slow backend").await.unwrap();
+        clock.advance(Duration::from_millis(25));
+        span.end().await.unwrap();
+
+        let entries = server.wait_for(3, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(entries.len(), 3);
+        for entry in entries.iter() {
+            assert_eq!(entry.fields.get("span_id"), Some(&span_id));
+            assert_eq!(entry.fields.get("span_name").map(String::as_str), Some("handle_request"));
+        }
+
+        let end = &entries[2];
+        assert_eq!(end.message, SPAN_END_MESSAGE);
+        assert_eq!(end.fields["duration_ms"], "25");
+    }
+
+    #[tokio::test]
+    async fn test_span_end_on_drop() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("span_drop.sock")).unwrap();
+
+        let client = LogClient::connect(&server.socket_path().to_string_lossy(), "span-daemon")
+            .await
+            .unwrap();
+        let span_id = {
+            let span = client.span("scoped");
+            span.info("inside").await.unwrap();
+            span.id().to_string()
+        };
+
+        let entries = server.wait_for(2, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].message, SPAN_END_MESSAGE);
+        assert_eq!(entries[1].fields.get("span_id"), Some(&span_id));
+        assert!(entries[1].fields.contains_key("duration_ms"));
+    }
+
+    #[test]
+    fn test_span_ended_on_drop_outside_runtime() {
+        let temp_dir = tempdir().unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            InMemoryServer::bind(temp_dir.path().join("blocking.sock")).unwrap()
+        });
+        let client = LogClient::new_lazy(ClientConfig {
+            socket_path: server.socket_path().to_string_lossy().to_string(),
+            daemon_name: "span-daemon".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        drop(client.span("sync"));
+
+        let entries = runtime
+            .block_on(server.wait_for(1, Duration::from_secs(2)))
+            .unwrap();
+        assert_eq!(entries[0].message, SPAN_END_MESSAGE);
+        assert_eq!(entries[0].fields["span_name"], "sync");
+    }
+}
@@ -0,0 +1,172 @@
+//! Timing an operation and logging how long it took
+
+use crate::client::logger::send_on_drop;
+use crate::client::LogClient;
+use crate::types::{LogFields, LogLevel};
+use crate::Result;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Outcome recorded for an operation whose guard is dropped while panicking
+pub const PANICKED_OUTCOME: &str = "panicked";
+
+/// Guard timing an operation, from [`LogClient::timed`]
+///
+/// When it finishes, via [`TimedOperation::finish`] or on drop, one entry
+/// is sent with the guard's message and level, the elapsed `duration_ms`
+/// on the client's clock and, once set, an `outcome` field. Dropped within
+/// a Tokio runtime the entry is sent from a task; outside one, the drop
+/// blocks until it is sent.
+pub struct TimedOperation {
+    client: LogClient,
+    message: String,
+    level: LogLevel,
+    started: Instant,
+    outcome: Option<String>,
+    finished: bool,
+}
+
+impl TimedOperation {
+    pub(crate) fn new(client: LogClient, message: &str, level: LogLevel) -> Self {
+        Self {
+            message: message.to_string(),
+            level,
+            started: client.clock().now_instant(),
+            outcome: None,
+            finished: false,
+            client,
+        }
+    }
+
+    /// Time since the operation started
+    pub fn elapsed(&self) -> Duration {
+        self.client.clock().now_instant().saturating_duration_since(self.started)
+    }
+
+    /// Record how the operation went, e.g. `"ok"` or `"timeout"`
+    ///
+    /// Sent as the `outcome` field; a later call replaces an earlier one.
+    pub fn set_outcome<S: Into<String>>(&mut self, outcome: S) {
+        self.outcome = Some(outcome.into());
+    }
+
+    /// Send the entry now, with `fields` added to it
+    pub async fn finish(mut self, fields: LogFields) -> Result<()> {
+        self.finished = true;
+        let fields = self.entry_fields(fields);
+        self.client.log(self.level, &self.message, fields).await
+    }
+
+    fn entry_fields(&self, mut fields: LogFields) -> LogFields {
+        fields.insert("duration_ms".to_string(), self.elapsed().as_millis().to_string());
+        if let Some(ref outcome) = self.outcome {
+            fields.insert("outcome".to_string(), outcome.clone());
+        }
+        fields
+    }
+}
+
+impl Drop for TimedOperation {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if self.outcome.is_none() && std::thread::panicking() {
+            self.outcome = Some(PANICKED_OUTCOME.to_string());
+        }
+
+        let client = self.client.clone();
+        let (level, message) = (self.level, std::mem::take(&mut self.message));
+        let fields = self.entry_fields(HashMap::new());
+        send_on_drop(async move { client.log(level, &message, fields).await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClientConfig;
+    use crate::test_util::InMemoryServer;
+    use crate::types::MockClock;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_timed_operation_records_duration() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("timed.sock")).unwrap();
+        let clock = Arc::new(MockClock::default());
+        let config = ClientConfig {
+            socket_path: server.socket_path().to_string_lossy().to_string(),
+            daemon_name: "timed-daemon".to_string(),
+            ..Default::default()
+        };
+        let client = LogClient::new_lazy_with_clock(config, clock.clone()).unwrap();
+
+        let mut operation = client.timed("exported report", LogLevel::Notice);
+        clock.advance(Duration::from_millis(1_500));
+        assert_eq!(operation.elapsed(), Duration::from_millis(1_500));
+        operation.set_outcome("ok");
+        let mut fields = HashMap::new();
+        fields.insert("rows".to_string(), "120".to_string());
+        operation.finish(fields).await.unwrap();
+
+        let entry = server.wait_for(1, Duration::from_secs(5)).await.unwrap().remove(0);
+        assert_eq!((entry.level, entry.message.as_str()), (LogLevel::Notice, "exported report"));
+        assert_eq!(entry.fields["duration_ms"], "1500");
+        assert_eq!(entry.fields["outcome"], "ok");
+        assert_eq!(entry.fields["rows"], "120");
+
+        // Dropped without finishing, it is sent all the same, without an outcome
+        {
+            let _operation = client.timed("cache warmed", LogLevel::Info);
+            clock.advance(Duration::from_millis(20));
+        }
+        let entry = server.wait_for(2, Duration::from_secs(5)).await.unwrap().remove(1);
+        assert_eq!(entry.message, "cache warmed");
+        assert_eq!(entry.fields["duration_ms"], "20");
+        assert!(!entry.fields.contains_key("outcome"));
+    }
+
+    #[test]
+    fn test_timed_operation_dropped_outside_runtime() {
+        let temp_dir = tempdir().unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(async {
+            InMemoryServer::bind(temp_dir.path().join("blocking.sock")).unwrap()
+        });
+        let client = LogClient::new_lazy(ClientConfig {
+            socket_path: server.socket_path().to_string_lossy().to_string(),
+            daemon_name: "timed-daemon".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Without a runtime to spawn on, the drop sends the entry before returning
+        drop(client.timed("sync job", LogLevel::Info));
+
+        let entries = runtime
+            .block_on(server.wait_for(1, Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(entries[0].message, "sync job");
+    }
+
+    #[tokio::test]
+    async fn test_timed_operation_dropped_in_panic() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("panic.sock")).unwrap();
+        let client = LogClient::connect(&server.socket_path().to_string_lossy(), "timed-daemon")
+            .await
+            .unwrap();
+
+        let operation = client.timed("migration", LogLevel::Info);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _operation = operation;
+            panic!("migration failed");
+        }));
+        assert!(result.is_err());
+
+        let entries = server.wait_for(1, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(entries[0].fields["outcome"], PANICKED_OUTCOME);
+    }
+}
@@ -0,0 +1,64 @@
+//! Runtime changes to the level a client sends at
+//!
+//! A client starts out sending at its configured `min_level`.
+//! [`LogClient::set_min_level`](crate::client::LogClient::set_min_level)
+//! replaces it for good, while
+//! [`LogClient::boost_level`](crate::client::LogClient::boost_level) makes the
+//! client more verbose for a while and then falls back on its own. Both are
+//! shared by every clone of the client.
+
+use crate::types::LogLevel;
+use parking_lot::Mutex;
+use std::time::Instant;
+
+/// Temporary verbosity, in effect until its deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Boost {
+    level: LogLevel,
+    until: Instant,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Level set at runtime, replacing the configured one
+    min_level: Option<LogLevel>,
+    boost: Option<Boost>,
+}
+
+/// Level overrides shared by every clone of a client
+#[derive(Debug, Default)]
+pub struct LevelControl {
+    state: Mutex<State>,
+}
+
+impl LevelControl {
+    /// Level entries are sent at, given the configured one and the time `now`
+    ///
+    /// An expired boost no longer counts, even before its timer clears it.
+    pub fn effective(&self, configured: LogLevel, now: Instant) -> LogLevel {
+        let state = self.state.lock();
+        let min_level = state.min_level.unwrap_or(configured);
+        match state.boost {
+            Some(boost) if now < boost.until => min_level.max(boost.level),
+            _ => min_level,
+        }
+    }
+
+    /// Replace the configured level, keeping any boost in effect
+    pub fn set_min_level(&self, level: LogLevel) {
+        self.state.lock().min_level = Some(level);
+    }
+
+    /// Send at `level` or more verbose until `until`, replacing any earlier boost
+    pub fn boost(&self, level: LogLevel, until: Instant) {
+        self.state.lock().boost = Some(Boost { level, until });
+    }
+
+    /// Drop the boost ending at `until`, unless another one replaced it
+    pub fn end_boost(&self, until: Instant) {
+        let mut state = self.state.lock();
+        if state.boost.is_some_and(|boost| boost.until == until) {
+            state.boost = None;
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Five-field cron schedules for `daemon_overrides.<name>.rotation_cron`
+//!
+//! A schedule is `minute hour day-of-month month day-of-week`, evaluated in
+//! UTC. Each field accepts `*`, a number, a range `a-b`, a step `*/n` or
+//! `a-b/n`, and comma-separated lists of these. Day of week runs from 0 to 7
+//! with both 0 and 7 meaning Sunday. As in classic cron, when both day fields
+//! are restricted a time matches if either of them does.
+
+use crate::{LogStreamError, Result};
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// How far ahead [`CronSchedule::next_after`] looks before giving up
+const MAX_SEARCH_DAYS: i64 = 366 * 5;
+
+/// Parsed cron schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    spec: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Day of month was `*`, so only day of week restricts the day
+    any_day_of_month: bool,
+    /// Day of week was `*`, so only day of month restricts the day
+    any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    /// Parse a five-field cron expression such as `"0 0 * * *"`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |reason: String| {
+            LogStreamError::Config(format!("Invalid cron schedule '{}': {}", spec, reason))
+        };
+
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(invalid(format!("expected 5 fields, found {}", fields.len())));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7).map_err(|r| invalid(format!("day of week {}", r)))?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            spec: spec.to_string(),
+            minutes: parse_field(fields[0], 0, 59).map_err(|r| invalid(format!("minute {}", r)))?,
+            hours: parse_field(fields[1], 0, 23).map_err(|r| invalid(format!("hour {}", r)))?,
+            days_of_month: parse_field(fields[2], 1, 31).map_err(|r| invalid(format!("day of month {}", r)))?,
+            months: parse_field(fields[3], 1, 12).map_err(|r| invalid(format!("month {}", r)))?,
+            days_of_week,
+            any_day_of_month: fields[2] == "*",
+            any_day_of_week: fields[4] == "*",
+        })
+    }
+
+    /// Whether the schedule fires during the minute containing `time`
+    pub fn matches(&self, time: DateTime<Utc>) -> bool {
+        self.matches_day(time) && bit(self.hours, time.hour()) && bit(self.minutes, time.minute())
+    }
+
+    /// First scheduled instant strictly after `time`, if there is one within five years
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = time.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        let limit = start + Duration::days(MAX_SEARCH_DAYS);
+
+        let mut candidate = start;
+        while candidate < limit {
+            if !self.matches_day(candidate) {
+                let start_of_day = candidate.duration_trunc(Duration::days(1)).ok()?;
+                candidate = start_of_day + Duration::days(1);
+            } else if !bit(self.hours, candidate.hour()) {
+                let start_of_hour = candidate.duration_trunc(Duration::hours(1)).ok()?;
+                candidate = start_of_hour + Duration::hours(1);
+            } else if !bit(self.minutes, candidate.minute()) {
+                candidate += Duration::minutes(1);
+            } else {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        if !bit(self.months, time.month()) {
+            return false;
+        }
+        let by_month_day = bit(self.days_of_month, time.day());
+        let by_week_day = bit(self.days_of_week, time.weekday().num_days_from_sunday());
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (false, true) => by_month_day,
+            (true, false) => by_week_day,
+            (false, false) => by_month_day || by_week_day,
+        }
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = LogStreamError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.spec)
+    }
+}
+
+fn bit(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parse one field into a bit set of the values it allows
+fn parse_field(field: &str, min: u32, max: u32) -> std::result::Result<u64, String> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("has an invalid step in '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("has a zero step in '{}'", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (low, high) = if range == "*" {
+            (min, max)
+        } else {
+            let parse = |value: &str| -> std::result::Result<u32, String> {
+                let value: u32 = value.parse().map_err(|_| format!("has an invalid value in '{}'", part))?;
+                if value < min || value > max {
+                    return Err(format!("value {} is outside {}-{}", value, min, max));
+                }
+                Ok(value)
+            };
+            match range.split_once('-') {
+                Some((low, high)) => (parse(low)?, parse(high)?),
+                None if step > 1 => (parse(range)?, max),
+                None => {
+                    let value = parse(range)?;
+                    (value, value)
+                }
+            }
+        };
+        if low > high {
+            return Err(format!("has a reversed range in '{}'", part));
+        }
+
+        for value in (low..=high).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn test_next_after() {
+        let midnight = CronSchedule::parse("0 0 * * *").unwrap();
+        assert_eq!(midnight.next_after(at(2024, 6, 1, 12, 30)), Some(at(2024, 6, 2, 0, 0)));
+        // Strictly after, even exactly on a scheduled instant
+        assert_eq!(midnight.next_after(at(2024, 6, 2, 0, 0)), Some(at(2024, 6, 3, 0, 0)));
+
+        let quarters = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        // Saturday 1 June 2024 moves on to Monday morning
+        assert_eq!(quarters.next_after(at(2024, 6, 1, 10, 0)), Some(at(2024, 6, 3, 9, 0)));
+        assert_eq!(quarters.next_after(at(2024, 6, 3, 9, 7)), Some(at(2024, 6, 3, 9, 15)));
+
+        let leap_day = CronSchedule::parse("30 6 29 2 *").unwrap();
+        assert_eq!(leap_day.next_after(at(2024, 3, 1, 0, 0)), Some(at(2028, 2, 29, 6, 30)));
+        assert!(CronSchedule::parse("0 0 31 2 *").unwrap().next_after(at(2024, 1, 1, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_day_fields() {
+        // Day of month or Sunday, written as 7
+        let schedule = CronSchedule::parse("0 0 1,15 * 7").unwrap();
+        assert!(schedule.matches(at(2024, 6, 2, 0, 0))); // Sunday
+        assert!(schedule.matches(at(2024, 6, 15, 0, 0))); // Saturday
+        assert!(!schedule.matches(at(2024, 6, 3, 0, 0)));
+        assert!(!schedule.matches(at(2024, 6, 2, 0, 1)));
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        for spec in ["", "0 0 * *", "60 0 * * *", "0 24 * * *", "0 0 0 * *", "*/0 * * * *", "5-1 * * * *", "a * * * *"] {
+            let err = CronSchedule::parse(spec).unwrap_err();
+            assert!(matches!(err, LogStreamError::Config(_)), "{}", spec);
+        }
+        assert_eq!("0 3 * * *".parse::<CronSchedule>().unwrap().to_string(), "0 3 * * *");
+    }
+}
@@ -0,0 +1,118 @@
+//! Level filter specs in the style of `env_logger`
+//!
+//! A spec such as `"web-server=debug,auth-service=warn,info"` sets the least
+//! severe level stored per daemon, with a bare level as the default for every
+//! other daemon. It can be given as `server.log_filter` in the config file or
+//! with the server's `--log-filter` flag.
+
+use crate::config::ServerConfig;
+use crate::types::LogLevel;
+use crate::{LogStreamError, Result};
+use std::collections::HashMap;
+
+/// Parsed level filter spec
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaemonLevelFilter {
+    /// Level for daemons without their own entry
+    pub default: Option<LogLevel>,
+    /// Level per daemon name
+    pub daemons: HashMap<String, LogLevel>,
+}
+
+impl DaemonLevelFilter {
+    /// Parse a comma-separated list of `daemon=level` and bare `level` directives
+    ///
+    /// Levels are case-insensitive and accept short forms such as `warn` and
+    /// `crit`. When a daemon or the default is given more than once, the last
+    /// directive wins.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |reason: String| {
+            LogStreamError::Config(format!("Invalid log filter '{}': {}", spec, reason))
+        };
+
+        let mut filter = Self::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((daemon, level)) => {
+                    let daemon = daemon.trim();
+                    if daemon.is_empty() {
+                        return Err(invalid(format!("'{}' has no daemon name", directive)));
+                    }
+                    let level = level.trim().parse().map_err(|_| {
+                        invalid(format!("'{}' is not a level in '{}'", level.trim(), directive))
+                    })?;
+                    filter.daemons.insert(daemon.to_string(), level);
+                }
+                None => {
+                    let level = directive.parse().map_err(|_| {
+                        invalid(format!("'{}' is neither a level nor daemon=level", directive))
+                    })?;
+                    filter.default = Some(level);
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Least severe level stored for `daemon`, if it is limited
+    pub fn level_for(&self, daemon: &str) -> Option<LogLevel> {
+        self.daemons.get(daemon).copied().or(self.default)
+    }
+}
+
+impl ServerConfig {
+    /// Apply a level filter on top of the configured levels
+    ///
+    /// Per-daemon levels go into `daemon_overrides`, the default into
+    /// `server.min_level`; settings the filter doesn't mention are kept.
+    pub fn apply_log_filter(&mut self, filter: &DaemonLevelFilter) {
+        if let Some(default) = filter.default {
+            self.server.min_level = Some(default);
+        }
+        for (daemon, level) in &filter.daemons {
+            self.daemon_overrides.entry(daemon.clone()).or_default().min_level = Some(*level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_global_only_spec() {
+        let filter = DaemonLevelFilter::parse("warn").unwrap();
+        assert_eq!(filter.default, Some(LogLevel::Warning));
+        assert!(filter.daemons.is_empty());
+        assert_eq!(filter.level_for("anything"), Some(LogLevel::Warning));
+        assert_eq!(DaemonLevelFilter::parse("").unwrap(), DaemonLevelFilter::default());
+    }
+
+    #[test]
+    fn test_per_daemon_overrides() {
+        let filter = DaemonLevelFilter::parse("web-server=debug, auth-service=WARN,info").unwrap();
+        assert_eq!(filter.default, Some(LogLevel::Info));
+        assert_eq!(filter.level_for("web-server"), Some(LogLevel::Debug));
+        assert_eq!(filter.level_for("auth-service"), Some(LogLevel::Warning));
+        assert_eq!(filter.level_for("billing"), Some(LogLevel::Info));
+
+        let mut config = ServerConfig::default();
+        config.daemon_overrides.entry("web-server".to_string()).or_default().format = Some("human".to_string());
+        config.apply_log_filter(&filter);
+        assert_eq!(config.server.min_level, Some(LogLevel::Info));
+        let web = config.daemon_override("web-server").unwrap();
+        assert_eq!(web.min_level, Some(LogLevel::Debug));
+        assert_eq!(web.format.as_deref(), Some("human"));
+        assert_eq!(config.daemon_override("auth-service").unwrap().min_level, Some(LogLevel::Warning));
+    }
+
+    #[test]
+    fn test_malformed_spec() {
+        for spec in ["web=loud", "=debug", "web-server", "info,web=debug=info"] {
+            match DaemonLevelFilter::parse(spec) {
+                Err(LogStreamError::Config(message)) => assert!(message.contains(spec), "{}", message),
+                other => panic!("expected {:?} to be rejected, got {:?}", spec, other),
+            }
+        }
+    }
+}
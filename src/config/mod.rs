@@ -1,8 +1,16 @@
 //! Configuration management for LogStream
 
+pub mod cron;
+pub mod filter;
 pub mod settings;
 
+pub use cron::CronSchedule;
+pub use filter::DaemonLevelFilter;
 pub use settings::{
-    BackendSettings, ClientConfig, MetricsSettings, RotationSettings, ServerConfig,
-    ServerSettings, StorageSettings,
+    compression_levels, default_socket_path, validate_compression_level, REDACTED_CONFIG_KEYS,
+    DEFAULT_COMPRESSION_LEVEL, DEFAULT_DEDICATED_QUEUE_SIZE, DEFAULT_FORWARD_QUEUE_SIZE, DEFAULT_GELF_CHUNK_SIZE, DEFAULT_INGEST_QUEUE_SIZE, DEFAULT_MEMORY_CAPACITY,
+    DEFAULT_PRIORITY_THRESHOLD, GELF_MIN_CHUNK_SIZE,
+    BackendSettings, BatchDropPolicy, BufferFullPolicy, ClientConfig, ConsoleBackendSettings, DaemonOverride, DiskFullPolicy,
+    ForwardBackendSettings, FsyncPolicy, GelfBackendSettings, GrpcSettings, ListenerSpec, MemoryBackendSettings, MetricsSettings,
+    MonotonicPolicy, Partition, PidSource, RotationSettings, ServerConfig, ServerSettings, SqliteBackendSettings, StorageSettings,
 };
@@ -1,10 +1,39 @@
 //! Configuration structures for LogStream
 
-use crate::types::LogLevel;
+use crate::config::{CronSchedule, DaemonLevelFilter};
+use crate::types::stream::{DEFAULT_MAX_JSON_DEPTH, DEFAULT_MAX_LINE_BYTES};
+use crate::types::{LineLimits, LogLevel, DEFAULT_HUMAN_MAX_FIELD_LEN, DEFAULT_MAX_REASSEMBLED_BYTES, ENTRY_JSON_KEYS};
 use crate::{LogStreamError, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 
+/// Backlog size above which queued entries are stored by severity instead of arrival
+pub const DEFAULT_PRIORITY_THRESHOLD: usize = 32;
+
+/// Default number of entries an ingest worker holds before connections wait
+pub const DEFAULT_INGEST_QUEUE_SIZE: usize = 1024;
+
+/// Default number of entries the worker of a daemon with `dedicated_worker` holds
+pub const DEFAULT_DEDICATED_QUEUE_SIZE: usize = 8192;
+
+/// Default number of entries held for the upstream
+pub const DEFAULT_FORWARD_QUEUE_SIZE: usize = 10_000;
+
+/// Default number of entries kept in memory
+pub const DEFAULT_MEMORY_CAPACITY: usize = 10_000;
+
+/// Default largest datagram, the most a GELF chunk may hold
+pub const DEFAULT_GELF_CHUNK_SIZE: usize = 8192;
+
+/// Smallest `chunk_size` accepted, leaving room for data past the chunk header
+pub const GELF_MIN_CHUNK_SIZE: usize = 512;
+
+/// Level used when `compression_level` isn't set, gzip's usual default
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
 /// Server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
@@ -16,6 +45,32 @@ pub struct ServerConfig {
     pub backends: BackendSettings,
     /// Metrics configuration
     pub metrics: MetricsSettings,
+    /// gRPC ingestion endpoint (requires the `grpc` feature)
+    #[serde(default)]
+    pub grpc: GrpcSettings,
+    /// Additional files (glob patterns, relative to this file) merged into `daemon_overrides`
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Per-daemon settings keyed by daemon name
+    #[serde(default)]
+    pub daemon_overrides: HashMap<String, DaemonOverride>,
+}
+
+/// Settings that override the server defaults for a single daemon
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonOverride {
+    /// Least severe level stored for this daemon
+    pub min_level: Option<LogLevel>,
+    /// File format for this daemon (json, human, gelf)
+    pub format: Option<String>,
+    /// Store this daemon's entries on a worker of its own instead of the shared one
+    ///
+    /// For a high-rate daemon, so its backlog doesn't delay other daemons'
+    /// entries. The worker holds `dedicated_queue_size` entries.
+    pub dedicated_worker: bool,
+    /// Cron schedule (UTC) at which this daemon's file is rotated, whatever its size or age
+    pub rotation_cron: Option<String>,
 }
 
 /// Core server settings
@@ -25,8 +80,159 @@ pub struct ServerSettings {
     pub socket_path: String,
     /// Maximum concurrent connections
     pub max_connections: usize,
+    /// Most new connections accepted per second, unlimited when unset
+    ///
+    /// Unlike `max_connections` this limits the rate of new connections;
+    /// connections beyond it wait in the socket's backlog until their turn.
+    #[serde(default)]
+    pub max_accepts_per_sec: Option<u32>,
     /// Buffer size for reading data
     pub buffer_size: usize,
+    /// Longest accepted log line in bytes; longer lines are rejected unparsed
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+    /// Deepest accepted JSON nesting in a log line
+    #[serde(default = "default_max_json_depth")]
+    pub max_json_depth: usize,
+    /// Largest entry accepted as a run of chunk lines, in bytes
+    #[serde(default = "default_max_reassembled_bytes")]
+    pub max_reassembled_bytes: usize,
+    /// Regular expressions; entries whose message matches any of them are dropped
+    #[serde(default)]
+    pub drop_patterns: Vec<String>,
+    /// Least severe level stored for daemons without their own `min_level`, all when unset
+    #[serde(default)]
+    pub min_level: Option<LogLevel>,
+    /// Level filter such as `"web=debug,auth=warn,info"`, applied by [`ServerConfig::from_file`]
+    ///
+    /// See [`DaemonLevelFilter`]; it sets `min_level` and the daemons'
+    /// `min_level` overrides.
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    /// Store lines with invalid UTF-8 with the bad bytes replaced, instead of rejecting them
+    #[serde(default)]
+    pub lossy_utf8: bool,
+    /// Write each line of a multiline message on its own line in human-readable files
+    ///
+    /// Continuation lines start with [`MULTILINE_CONTINUATION_MARKER`]; JSON
+    /// records keep the message whole either way.
+    ///
+    /// [`MULTILINE_CONTINUATION_MARKER`]: crate::types::MULTILINE_CONTINUATION_MARKER
+    #[serde(default)]
+    pub split_multiline: bool,
+    /// Seconds to wait for open connections to close on shutdown before force-closing them
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Backlog size above which entries are stored most severe first
+    #[serde(default = "default_ingest_priority_threshold")]
+    pub ingest_priority_threshold: usize,
+    /// Entries an ingest worker holds waiting to be stored
+    ///
+    /// Once that many are waiting, connections stop reading until the
+    /// worker catches up, slowing their clients down.
+    #[serde(default = "default_ingest_queue_size")]
+    pub ingest_queue_size: usize,
+    /// Entries held by the worker of each daemon with `dedicated_worker`, in place of `ingest_queue_size`
+    #[serde(default = "default_dedicated_queue_size")]
+    pub dedicated_queue_size: usize,
+    /// Sockets accepting connections besides `socket_path`
+    #[serde(default)]
+    pub listeners: Vec<ListenerSpec>,
+    /// Replace an existing file at a Unix socket path when binding
+    ///
+    /// When false, binding fails if the path exists, so a socket pre-created
+    /// with its own ownership and permissions is never clobbered.
+    #[serde(default = "default_reuse_socket")]
+    pub reuse_socket: bool,
+    /// Remove the Unix socket files the server bound when it shuts down
+    ///
+    /// Sockets passed in by systemd socket activation are left in place.
+    #[serde(default)]
+    pub unlink_on_exit: bool,
+    /// What to do with an entry timestamped before the last one stored for its daemon
+    #[serde(default)]
+    pub enforce_monotonic: MonotonicPolicy,
+    /// Store an audit entry under `logstream.audit` for every connection opened and closed
+    #[serde(default)]
+    pub audit_connections: bool,
+    /// Tag each entry with its connection's transport and, for TCP, remote address
+    ///
+    /// Written as the `_transport` and `_remote` fields, replacing any
+    /// the client set, so they can be trusted.
+    #[serde(default)]
+    pub connection_context: bool,
+}
+
+/// How the server keeps each daemon's stored timestamps non-decreasing
+///
+/// Guards audit logs against clients whose clock was set back. Entries are
+/// compared with the latest timestamp stored for the same daemon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MonotonicPolicy {
+    /// Store timestamps as received
+    #[default]
+    Off,
+    /// Move an earlier timestamp up to the latest one and add a `clamped=true` field
+    Clamp,
+    /// Reject the entry
+    Reject,
+}
+
+/// An additional socket the server accepts log connections on
+///
+/// Written in TOML as `{ unix = "/run/logstream/old.sock" }` or
+/// `{ tcp = "127.0.0.1:5140" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenerSpec {
+    /// Unix socket path
+    Unix(String),
+    /// TCP address to listen on, as `host:port`
+    Tcp(String),
+}
+
+fn default_max_line_bytes() -> usize {
+    DEFAULT_MAX_LINE_BYTES
+}
+
+fn default_max_json_depth() -> usize {
+    DEFAULT_MAX_JSON_DEPTH
+}
+
+fn default_max_reassembled_bytes() -> usize {
+    DEFAULT_MAX_REASSEMBLED_BYTES
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_ingest_priority_threshold() -> usize {
+    DEFAULT_PRIORITY_THRESHOLD
+}
+
+fn default_ingest_queue_size() -> usize {
+    DEFAULT_INGEST_QUEUE_SIZE
+}
+
+fn default_dedicated_queue_size() -> usize {
+    DEFAULT_DEDICATED_QUEUE_SIZE
+}
+
+fn default_reuse_socket() -> bool {
+    true
+}
+
+impl ServerSettings {
+    /// Limits applied to each incoming line before parsing
+    pub fn line_limits(&self) -> LineLimits {
+        LineLimits {
+            max_line_bytes: self.max_line_bytes,
+            max_json_depth: self.max_json_depth,
+            lossy_utf8: self.lossy_utf8,
+            max_reassembled_bytes: self.max_reassembled_bytes,
+        }
+    }
 }
 
 /// Storage configuration
@@ -38,6 +244,87 @@ pub struct StorageSettings {
     pub max_file_size: u64,
     /// Log rotation settings
     pub rotation: RotationSettings,
+    /// Interval for writing per-daemon level summaries in seconds (disabled if unset)
+    #[serde(default)]
+    pub summary_interval_secs: Option<u64>,
+    /// Split each daemon's log into one file per time window
+    #[serde(default)]
+    pub partition: Partition,
+    /// When log files are synced to disk
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+    /// What to do when a write fails because the disk is full
+    #[serde(default)]
+    pub on_disk_full: DiskFullPolicy,
+    /// Spread log files over this many hashed subdirectories, all in `output_directory` when 0
+    ///
+    /// A file and its archives go to the subdirectory named after the hash
+    /// of its file stem modulo this count, in hex (e.g. `0a/web-server.log`).
+    #[serde(default)]
+    pub hash_buckets: u32,
+    /// Delete archives whose entries are all older than this many days, disabled when unset
+    ///
+    /// Checked by the rotation task alongside age-based rotation, whatever
+    /// `keep_files` is. Live log files are never deleted.
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+}
+
+/// How storage reacts when the disk is full (`ENOSPC`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiskFullPolicy {
+    /// Retry the write until space frees up, holding back later entries
+    ///
+    /// Received entries wait in the ingest queue meanwhile. Once it holds
+    /// `ingest_queue_size` entries, connections stop reading, so clients
+    /// block until the disk has room again. Once the server shuts down,
+    /// entries that don't fit are dropped like `DropNewest`.
+    Block,
+    /// Drop the entry being written and count it
+    #[default]
+    DropNewest,
+    /// Delete rotated archives, oldest first, until the write fits
+    ///
+    /// Drops the entry like `DropNewest` once no archives are left.
+    DropOldest,
+}
+
+/// When written log data is forced to disk with `fsync`
+///
+/// Flushing only hands data to the OS; without a sync a power loss can
+/// still lose entries that were reported as written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FsyncPolicy {
+    /// Sync after every entry (durable but slow)
+    Always,
+    /// Sync a file before it is rotated into an archive
+    OnRotate,
+    /// Leave syncing to the OS
+    #[default]
+    Never,
+}
+
+/// Time window used to partition log files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Partition {
+    /// A single `{daemon}.log` file
+    #[default]
+    None,
+    /// One `{daemon}-YYYY-MM-DD-HH.log` file per hour
+    Hourly,
+    /// One `{daemon}-YYYY-MM-DD.log` file per day
+    Daily,
+}
+
+impl Partition {
+    /// Label of the window containing `timestamp`, or `None` when unpartitioned
+    pub fn label(&self, timestamp: DateTime<Utc>) -> Option<String> {
+        match self {
+            Partition::None => None,
+            Partition::Hourly => Some(timestamp.format("%Y-%m-%d-%H").to_string()),
+            Partition::Daily => Some(timestamp.format("%Y-%m-%d").to_string()),
+        }
+    }
 }
 
 /// Log rotation configuration
@@ -60,6 +347,37 @@ pub struct BackendSettings {
     pub journald: JournaldBackendSettings,
     /// Syslog backend  
     pub syslog: SyslogBackendSettings,
+    /// SQLite backend (requires the `sqlite` feature)
+    #[serde(default)]
+    pub sqlite: SqliteBackendSettings,
+    /// Console backend
+    #[serde(default)]
+    pub console: ConsoleBackendSettings,
+    /// Forwarding to an upstream LogStream server
+    #[serde(default)]
+    pub forward: ForwardBackendSettings,
+    /// Latest entries kept in memory
+    #[serde(default)]
+    pub memory: MemoryBackendSettings,
+    /// GELF messages sent over UDP, e.g. to Graylog
+    #[serde(default)]
+    pub gelf: GelfBackendSettings,
+}
+
+impl BackendSettings {
+    /// Turn off every backend, so entries are counted but never written
+    ///
+    /// Useful for benchmarking the socket and parsing path without disk I/O.
+    pub fn disable_all(&mut self) {
+        self.file.enabled = false;
+        self.journald.enabled = false;
+        self.syslog.enabled = false;
+        self.sqlite.enabled = false;
+        self.console.enabled = false;
+        self.forward.enabled = false;
+        self.memory.enabled = false;
+        self.gelf.enabled = false;
+    }
 }
 
 /// File backend settings
@@ -67,12 +385,84 @@ pub struct BackendSettings {
 pub struct FileBackendSettings {
     /// Enable file backend
     pub enabled: bool,
-    /// File format (json, human, syslog)
+    /// File format (json, human, gelf)
     pub format: String,
     /// Enable compression
     pub compression: bool,
-    /// Compression algorithm (gzip, lz4)
+    /// Compression algorithm (gzip, lz4, or zstd with the `zstd` feature)
     pub compression_algorithm: String,
+    /// Compression level from 1 (fastest) to 9 (smallest archives), or up to 22 for zstd
+    #[serde(default = "default_compression_level")]
+    pub compression_level: u32,
+    /// Field values longer than this are truncated in human-readable output (0 keeps them whole)
+    #[serde(default = "default_human_max_field_len")]
+    pub human_max_field_len: usize,
+    /// Written after each record, e.g. `"\r\n"` or `"\u0000"` for collectors that expect them
+    #[serde(default = "default_record_terminator")]
+    pub record_terminator: String,
+    /// Leave `fields`, `pid` and `hostname` out of JSON records when empty or unset
+    #[serde(default)]
+    pub omit_empty_fields: bool,
+    /// Write each field as a top-level `field_<name>` key in JSON records instead of under `fields`
+    #[serde(default)]
+    pub flatten_fields: bool,
+    /// Least severe level written to files, all stored entries when unset
+    #[serde(default)]
+    pub min_level: Option<LogLevel>,
+    /// Guarantee strict NDJSON output: one JSON object per `\n`-terminated line
+    ///
+    /// Requires the `json` format for every daemon and a `"\n"` record
+    /// terminator; records that would still span lines are rejected.
+    #[serde(default)]
+    pub strict_ndjson: bool,
+    /// Names written in JSON records in place of the entry's own keys, e.g. `message = "msg"`
+    ///
+    /// Keys are from [`ENTRY_JSON_KEYS`]; values are left as they are.
+    #[serde(default)]
+    pub field_names: HashMap<String, String>,
+}
+
+fn default_human_max_field_len() -> usize {
+    DEFAULT_HUMAN_MAX_FIELD_LEN
+}
+
+fn default_compression_level() -> u32 {
+    DEFAULT_COMPRESSION_LEVEL
+}
+
+/// Levels accepted by an algorithm, or `None` if the algorithm isn't supported
+pub fn compression_levels(algorithm: &str) -> Option<RangeInclusive<u32>> {
+    match algorithm {
+        "gzip" | "lz4" => Some(1..=9),
+        "zstd" => Some(1..=22),
+        _ => None,
+    }
+}
+
+/// Check that `level` is valid for `algorithm`
+pub fn validate_compression_level(algorithm: &str, level: u32) -> Result<()> {
+    let levels = compression_levels(algorithm).ok_or_else(|| {
+        LogStreamError::Config(format!("Unknown compression algorithm '{}'", algorithm))
+    })?;
+    if algorithm == "zstd" && !cfg!(feature = "zstd") {
+        return Err(LogStreamError::Config(
+            "zstd compression requires the `zstd` feature".to_string(),
+        ));
+    }
+    if !levels.contains(&level) {
+        return Err(LogStreamError::Config(format!(
+            "Compression level {} is out of range for {} ({}-{})",
+            level,
+            algorithm,
+            levels.start(),
+            levels.end()
+        )));
+    }
+    Ok(())
+}
+
+fn default_record_terminator() -> String {
+    "\n".to_string()
 }
 
 /// Journald backend settings
@@ -95,6 +485,99 @@ pub struct SyslogBackendSettings {
     pub server: Option<String>,
 }
 
+/// Console backend settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConsoleBackendSettings {
+    /// Print entries to stdout
+    pub enabled: bool,
+    /// Color for each daemon's name: a name like `"cyan"` or an ANSI SGR code like `"38;5;208"`
+    ///
+    /// Daemons not listed get a color derived from their name.
+    #[serde(default)]
+    pub daemon_colors: HashMap<String, String>,
+    /// Least severe level printed, all stored entries when unset
+    #[serde(default)]
+    pub min_level: Option<LogLevel>,
+}
+
+/// Forwarding backend settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForwardBackendSettings {
+    /// Send every stored entry on to an upstream LogStream server
+    pub enabled: bool,
+    /// Socket path of the upstream server
+    pub upstream_socket: String,
+    /// Prepended to each entry's daemon name on the upstream, e.g. `"edge-1-"`
+    pub daemon_prefix: String,
+    /// Entries held while the upstream is unreachable; once full, new entries aren't forwarded
+    pub queue_size: usize,
+}
+
+impl Default for ForwardBackendSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            upstream_socket: String::new(),
+            daemon_prefix: String::new(),
+            queue_size: DEFAULT_FORWARD_QUEUE_SIZE,
+        }
+    }
+}
+
+/// In-memory backend settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryBackendSettings {
+    /// Keep the latest stored entries in memory, readable through `tail` and `follow`
+    pub enabled: bool,
+    /// Entries kept across all daemons; the oldest is evicted beyond this
+    pub capacity: usize,
+}
+
+impl Default for MemoryBackendSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: DEFAULT_MEMORY_CAPACITY,
+        }
+    }
+}
+
+/// GELF backend settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GelfBackendSettings {
+    /// Send every stored entry as a GELF message over UDP
+    pub enabled: bool,
+    /// Address of the GELF UDP input, as `host:port`
+    pub server: String,
+    /// Largest datagram sent; longer messages are split into GELF chunks
+    pub chunk_size: usize,
+    /// Least severe level sent, all stored entries when unset
+    pub min_level: Option<LogLevel>,
+}
+
+impl Default for GelfBackendSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            server: "127.0.0.1:12201".to_string(),
+            chunk_size: DEFAULT_GELF_CHUNK_SIZE,
+            min_level: None,
+        }
+    }
+}
+
+/// SQLite backend settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SqliteBackendSettings {
+    /// Enable SQLite backend, which requires the `sqlite` feature
+    pub enabled: bool,
+    /// Database file, created if missing
+    pub path: PathBuf,
+}
+
 /// Metrics configuration
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MetricsSettings {
@@ -106,30 +589,184 @@ pub struct MetricsSettings {
     pub path: String,
 }
 
+/// gRPC ingestion endpoint configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcSettings {
+    /// Serve the `LogIngest` gRPC service
+    pub enabled: bool,
+    /// TCP address to listen on
+    pub address: String,
+}
+
+impl Default for GrpcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:50051".to_string(),
+        }
+    }
+}
+
 /// Client configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ClientConfig {
     /// Server socket path to connect to
     pub socket_path: String,
+    /// Backup socket paths tried in order when `socket_path` can't be reached
+    pub fallback_socket_paths: Vec<String>,
+    /// Interval in seconds between attempts to move back to `socket_path`
+    /// while connected to a fallback, disabled when unset
+    pub primary_probe_secs: Option<u64>,
     /// Client daemon name
     pub daemon_name: String,
-    /// Minimum log level to send
+    /// Least severe level sent; entries below it are dropped by the client
     pub min_level: LogLevel,
     /// Connection timeout in seconds
     pub timeout_seconds: u64,
+    /// Connect timeout in milliseconds, overriding `timeout_seconds` when set
+    pub connect_timeout_ms: Option<u64>,
+    /// Interval in seconds between keep-alive pings, disabled when unset
+    pub keepalive_secs: Option<u64>,
     /// Enable automatic reconnection
     pub auto_reconnect: bool,
     /// Buffer size for outgoing messages
     pub buffer_size: usize,
+    /// Base delay before reconnecting after a broken connection (milliseconds)
+    pub reconnect_delay_ms: u64,
+    /// Maximum random jitter added to each reconnect delay (milliseconds)
+    pub reconnect_jitter_ms: u64,
+    /// Hostname to report instead of the system hostname
+    pub hostname_override: Option<String>,
+    /// Report the fully-qualified domain name instead of the short hostname
+    pub use_fqdn: bool,
+    /// What to record in the `pid` field of each entry
+    pub pid_source: PidSource,
+    /// Environment variable whose value is added as a `container_id` field
+    pub container_id_env: Option<String>,
+    /// OpenTelemetry resource attributes added to each entry's `resource`
+    ///
+    /// E.g. `service.name`, `service.namespace` and `deployment.environment`;
+    /// attributes an entry already carries are kept.
+    pub resource_attributes: HashMap<String, String>,
+    /// Send entries whose JSON is longer than this many bytes as chunks of at most that size
+    ///
+    /// Lets a rare large entry through a server whose `max_line_bytes` is
+    /// smaller; chunking is disabled when unset.
+    pub chunk_bytes: Option<usize>,
+    /// Consecutive failed sends after which sends fail fast, disabled when unset
+    pub breaker_failure_threshold: Option<u32>,
+    /// How long sends fail fast once the breaker opens before one probes the server (milliseconds)
+    pub breaker_open_ms: u64,
+    /// Most entries held in memory waiting to be written to the server, unlimited when unset
+    ///
+    /// Counts every send that is waiting for the connection or writing to it,
+    /// which pile up while the server is slow to read.
+    pub max_buffered_entries: Option<usize>,
+    /// What a send does when `max_buffered_entries` are already waiting
+    pub on_buffer_full: BufferFullPolicy,
+    /// Entries older than this many seconds when they would be sent are dropped, disabled when unset
+    ///
+    /// Applies to entries replayed from a fallback file and to sends that
+    /// waited in the backlog while the server was unreachable.
+    pub max_entry_age_secs: Option<u64>,
+    /// Most entries sent per second when replaying a fallback file
+    pub replay_entries_per_sec: u32,
+    /// Entries replayed from a fallback file between confirmations
+    pub replay_batch_size: usize,
+    /// What a [`LogBatch`](crate::client::LogBatch) dropped without committing does with its entries
+    pub batch_on_drop: BatchDropPolicy,
+}
+
+/// Source of the `pid` recorded in client log entries
+///
+/// Inside containers the process id is usually `1` and says little about
+/// which task produced a log, so it can be replaced or omitted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PidSource {
+    /// The id of the current process
+    #[default]
+    ProcessId,
+    /// Leave `pid` unset
+    None,
+    /// A fixed, caller-provided id
+    Custom(u32),
+}
+
+impl PidSource {
+    /// The pid to record, if any
+    pub fn resolve(&self) -> Option<u32> {
+        match self {
+            PidSource::ProcessId => Some(std::process::id()),
+            PidSource::None => None,
+            PidSource::Custom(pid) => Some(*pid),
+        }
+    }
+}
+
+/// What a client send does when its backlog is at `max_buffered_entries`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BufferFullPolicy {
+    /// Wait until an earlier send finishes, slowing the caller down
+    #[default]
+    Block,
+    /// Fail with a "buffer full" [`LogStreamError::Client`] error
+    Error,
+}
+
+/// What a batch dropped without committing does with its entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchDropPolicy {
+    /// Send them, as a commit would
+    #[default]
+    Commit,
+    /// Throw them away
+    Discard,
+}
+
+/// Default socket path shared by the server and client
+///
+/// `$XDG_RUNTIME_DIR/logstream.sock` when the variable is set, otherwise
+/// `/tmp/logstream-$UID.sock` so users on one machine don't collide.
+pub fn default_socket_path() -> String {
+    // getuid cannot fail
+    socket_path_for(std::env::var_os("XDG_RUNTIME_DIR"), unsafe { libc::getuid() })
+}
+
+fn socket_path_for(runtime_dir: Option<std::ffi::OsString>, uid: u32) -> String {
+    match runtime_dir.filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("logstream.sock").to_string_lossy().into_owned(),
+        None => format!("/tmp/logstream-{}.sock", uid),
+    }
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             server: ServerSettings {
-                socket_path: "/tmp/logstream.sock".to_string(),
+                socket_path: default_socket_path(),
                 max_connections: 1000,
+                max_accepts_per_sec: None,
                 buffer_size: 8192,
+                max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+                max_json_depth: DEFAULT_MAX_JSON_DEPTH,
+                max_reassembled_bytes: DEFAULT_MAX_REASSEMBLED_BYTES,
+                drop_patterns: Vec::new(),
+                min_level: None,
+                log_filter: None,
+                lossy_utf8: false,
+                split_multiline: false,
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
+                ingest_priority_threshold: DEFAULT_PRIORITY_THRESHOLD,
+                ingest_queue_size: DEFAULT_INGEST_QUEUE_SIZE,
+                dedicated_queue_size: DEFAULT_DEDICATED_QUEUE_SIZE,
+                listeners: Vec::new(),
+                reuse_socket: default_reuse_socket(),
+                unlink_on_exit: false,
+                enforce_monotonic: MonotonicPolicy::Off,
+                audit_connections: false,
+                connection_context: false,
             },
             storage: StorageSettings {
                 output_directory: PathBuf::from("/var/log/logstream"),
@@ -139,9 +776,27 @@ impl Default for ServerConfig {
                     max_age_hours: 24,
                     keep_files: 7,
                 },
+                summary_interval_secs: None,
+                partition: Partition::None,
+                fsync: FsyncPolicy::Never,
+                on_disk_full: DiskFullPolicy::DropNewest,
+                hash_buckets: 0,
+                retention_days: None,
             },
             backends: BackendSettings::default(),
             metrics: MetricsSettings::default(),
+            grpc: GrpcSettings::default(),
+            include: Vec::new(),
+            daemon_overrides: HashMap::new(),
+        }
+    }
+}
+
+impl Default for SqliteBackendSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: PathBuf::from("/var/log/logstream/logstream.db"),
         }
     }
 }
@@ -153,6 +808,14 @@ impl Default for FileBackendSettings {
             format: "json".to_string(),
             compression: false,
             compression_algorithm: "gzip".to_string(),
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            human_max_field_len: DEFAULT_HUMAN_MAX_FIELD_LEN,
+            record_terminator: default_record_terminator(),
+            omit_empty_fields: false,
+            flatten_fields: false,
+            min_level: None,
+            strict_ndjson: false,
+            field_names: HashMap::new(),
         }
     }
 }
@@ -160,47 +823,779 @@ impl Default for FileBackendSettings {
 impl Default for ClientConfig {
     fn default() -> Self {
         Self {
-            socket_path: "/tmp/logstream.sock".to_string(),
+            socket_path: default_socket_path(),
+            fallback_socket_paths: Vec::new(),
+            primary_probe_secs: None,
             daemon_name: "unknown".to_string(),
-            min_level: LogLevel::Info,
+            min_level: LogLevel::Debug,
             timeout_seconds: 5,
+            connect_timeout_ms: None,
+            keepalive_secs: None,
             auto_reconnect: true,
             buffer_size: 4096,
+            reconnect_delay_ms: 50,
+            reconnect_jitter_ms: 250,
+            hostname_override: None,
+            use_fqdn: false,
+            pid_source: PidSource::ProcessId,
+            container_id_env: None,
+            resource_attributes: HashMap::new(),
+            chunk_bytes: None,
+            breaker_failure_threshold: None,
+            breaker_open_ms: 30_000,
+            max_buffered_entries: None,
+            on_buffer_full: BufferFullPolicy::Block,
+            max_entry_age_secs: None,
+            replay_entries_per_sec: 500,
+            replay_batch_size: 100,
+            batch_on_drop: BatchDropPolicy::Commit,
         }
     }
 }
 
 impl ServerConfig {
     /// Load configuration from TOML file
+    ///
+    /// Files listed in `include` are expanded and merged into
+    /// `daemon_overrides` in order, so later files win on conflicts.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
         let content = std::fs::read_to_string(path)
             .map_err(|e| LogStreamError::Config(format!("Failed to read config file: {}", e)))?;
         
-        let config: ServerConfig = toml::from_str(&content)
+        let mut config: ServerConfig = toml::from_str(&content)
             .map_err(|e| LogStreamError::Config(format!("Failed to parse config: {}", e)))?;
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        for pattern in config.include.clone() {
+            for include_path in expand_include(base_dir, &pattern)? {
+                config.merge_include(&include_path)?;
+            }
+        }
+        if let Some(ref spec) = config.server.log_filter {
+            let filter = DaemonLevelFilter::parse(spec)?;
+            config.apply_log_filter(&filter);
+        }
         
         config.validate()?;
         Ok(config)
     }
 
+    /// Validate the configuration against the machine it is to run on
+    ///
+    /// Runs [`ServerConfig::validate`], then checks what only the deployment
+    /// can tell: that the output directory and the directories of the Unix
+    /// sockets exist or can be created, and are writable. This is what
+    /// `logstream-server --check-config` runs.
+    pub fn check_deployment(&self) -> Result<()> {
+        self.validate()?;
+        let algorithm = &self.backends.file.compression_algorithm;
+        if compression_levels(algorithm).is_none() {
+            return Err(LogStreamError::Config(format!("Unknown compression algorithm '{}'", algorithm)));
+        }
+
+        check_writable_dir(&self.storage.output_directory, "Output directory")?;
+        let unix_listeners = self.server.listeners.iter().filter_map(|listener| match listener {
+            ListenerSpec::Unix(path) => Some(path.as_str()),
+            ListenerSpec::Tcp(_) => None,
+        });
+        for socket in std::iter::once(self.server.socket_path.as_str()).chain(unix_listeners) {
+            if let Some(parent) = Path::new(socket).parent() {
+                check_writable_dir(parent, &format!("Directory of socket {}", socket))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Human-readable overview of the main settings, one per line
+    pub fn summary(&self) -> String {
+        let backends = &self.backends;
+        let enabled: Vec<String> = [
+            (backends.file.enabled, format!("file ({})", backends.file.format)),
+            (backends.journald.enabled, "journald".to_string()),
+            (backends.syslog.enabled, "syslog".to_string()),
+            (backends.sqlite.enabled, "sqlite".to_string()),
+            (backends.console.enabled, "console".to_string()),
+            (backends.forward.enabled, format!("forward to {}", backends.forward.upstream_socket)),
+            (backends.memory.enabled, format!("memory ({} entries)", backends.memory.capacity)),
+            (backends.gelf.enabled, format!("gelf to {}", backends.gelf.server)),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect();
+        let rotation = &self.storage.rotation;
+
+        let mut lines = vec![format!("Socket: {}", self.server.socket_path)];
+        for listener in &self.server.listeners {
+            match listener {
+                ListenerSpec::Unix(path) => lines.push(format!("Listener: unix {}", path)),
+                ListenerSpec::Tcp(addr) => lines.push(format!("Listener: tcp {}", addr)),
+            }
+        }
+        lines.push(format!("Output directory: {}", self.storage.output_directory.display()));
+        lines.push(format!(
+            "Backends: {}",
+            if enabled.is_empty() { "none".to_string() } else { enabled.join(", ") }
+        ));
+        lines.push(if rotation.enabled {
+            format!(
+                "Rotation: at {} bytes or {}h, keeping {} archives",
+                self.storage.max_file_size, rotation.max_age_hours, rotation.keep_files
+            )
+        } else {
+            "Rotation: disabled".to_string()
+        });
+        if !self.daemon_overrides.is_empty() {
+            lines.push(format!("Daemon overrides: {}", self.daemon_overrides.len()));
+        }
+        lines.join("\n")
+    }
+
+    /// Merge one include file's `[daemon-name]` tables into `daemon_overrides`
+    fn merge_include(&mut self, path: &Path) -> Result<()> {
+        let content = read_include(path)?;
+        let overrides: HashMap<String, DaemonOverride> = toml::from_str(&content).map_err(|e| {
+            LogStreamError::Config(format!("Failed to parse include {}: {}", path.display(), e))
+        })?;
+
+        for (daemon, settings) in overrides {
+            if let Some(previous) = self.daemon_overrides.insert(daemon.clone(), settings) {
+                eprintln!(
+                    "Config include {} overrides earlier settings for daemon '{}': {:?}",
+                    path.display(),
+                    daemon,
+                    previous
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The configuration as JSON with secret values replaced
+    ///
+    /// Any key named in [`REDACTED_CONFIG_KEYS`] has its value replaced by
+    /// `"[redacted]"`, at whatever depth it appears.
+    pub fn redacted_json(&self) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        redact(&mut value, REDACTED_CONFIG_KEYS);
+        Ok(value)
+    }
+
+    /// Overrides configured for a daemon, if any
+    pub fn daemon_override(&self, daemon: &str) -> Option<&DaemonOverride> {
+        self.daemon_overrides.get(daemon)
+    }
+
     /// Validate configuration
     pub fn validate(&self) -> Result<()> {
         if self.server.socket_path.is_empty() {
             return Err(LogStreamError::Config("Socket path cannot be empty".to_string()));
         }
+        if self.server.ingest_queue_size == 0 || self.server.dedicated_queue_size == 0 {
+            return Err(LogStreamError::Config("Ingest queue sizes must be at least 1".to_string()));
+        }
+        let forward = &self.backends.forward;
+        if forward.enabled {
+            if forward.upstream_socket.is_empty() {
+                return Err(LogStreamError::Config("Forward upstream socket cannot be empty".to_string()));
+            }
+            let own_socket = forward.upstream_socket == self.server.socket_path
+                || self.server.listeners.contains(&ListenerSpec::Unix(forward.upstream_socket.clone()));
+            if own_socket {
+                return Err(LogStreamError::Config(
+                    "Forward upstream socket cannot be this server's own socket".to_string(),
+                ));
+            }
+            if forward.queue_size == 0 {
+                return Err(LogStreamError::Config("Forward queue size must be at least 1".to_string()));
+            }
+        }
+        if self.backends.sqlite.enabled && !cfg!(feature = "sqlite") {
+            return Err(LogStreamError::Config(
+                "The SQLite backend requires the `sqlite` feature".to_string(),
+            ));
+        }
+        if self.backends.memory.enabled && self.backends.memory.capacity == 0 {
+            return Err(LogStreamError::Config("Memory backend capacity must be at least 1".to_string()));
+        }
+        let gelf = &self.backends.gelf;
+        if gelf.enabled {
+            if gelf.server.is_empty() {
+                return Err(LogStreamError::Config("GELF server address cannot be empty".to_string()));
+            }
+            if gelf.chunk_size < GELF_MIN_CHUNK_SIZE {
+                return Err(LogStreamError::Config(format!(
+                    "GELF chunk size must be at least {} bytes",
+                    GELF_MIN_CHUNK_SIZE
+                )));
+            }
+        }
+        let file = &self.backends.file;
+        if file.compression {
+            validate_compression_level(&file.compression_algorithm, file.compression_level)?;
+        }
+        if self.storage.retention_days == Some(0) {
+            return Err(LogStreamError::Config("Retention must be at least 1 day".to_string()));
+        }
+        if self.server.max_accepts_per_sec == Some(0) {
+            return Err(LogStreamError::Config("max_accepts_per_sec must be at least 1".to_string()));
+        }
+        if self.backends.file.record_terminator.is_empty() {
+            return Err(LogStreamError::Config("File record terminator cannot be empty".to_string()));
+        }
+        if file.strict_ndjson {
+            if file.record_terminator != "\n" {
+                return Err(LogStreamError::Config(
+                    "strict_ndjson requires record_terminator = \"\\n\"".to_string(),
+                ));
+            }
+            let formats = std::iter::once(("backends.file", file.format.as_str())).chain(
+                self.daemon_overrides
+                    .iter()
+                    .filter_map(|(name, o)| o.format.as_deref().map(|format| (name.as_str(), format))),
+            );
+            for (owner, format) in formats {
+                if format != "json" {
+                    return Err(LogStreamError::Config(format!(
+                        "strict_ndjson requires the json format, but {} uses '{}'",
+                        owner, format
+                    )));
+                }
+            }
+        }
+        let mut renamed: Vec<&str> = Vec::new();
+        for (key, name) in &file.field_names {
+            if !ENTRY_JSON_KEYS.contains(&key.as_str()) {
+                return Err(LogStreamError::Config(format!(
+                    "Unknown key '{}' in field_names, expected one of {}",
+                    key,
+                    ENTRY_JSON_KEYS.join(", ")
+                )));
+            }
+            if name.is_empty() {
+                return Err(LogStreamError::Config(format!("field_names gives '{}' an empty name", key)));
+            }
+            renamed.push(name);
+        }
+        // Each name must end up on one key, renamed or not
+        let kept = ENTRY_JSON_KEYS.iter().filter(|key| !file.field_names.contains_key(**key));
+        let mut written: Vec<&str> = renamed.into_iter().chain(kept.copied()).collect();
+        written.sort_unstable();
+        if let Some(pair) = written.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(LogStreamError::Config(format!(
+                "field_names writes more than one key as '{}'",
+                pair[0]
+            )));
+        }
+        let mut unix_paths = vec![self.server.socket_path.as_str()];
+        for listener in &self.server.listeners {
+            match listener {
+                ListenerSpec::Unix(path) if path.is_empty() => {
+                    return Err(LogStreamError::Config("Listener socket path cannot be empty".to_string()));
+                }
+                ListenerSpec::Unix(path) if unix_paths.contains(&path.as_str()) => {
+                    return Err(LogStreamError::Config(format!("Socket {} is listed more than once", path)));
+                }
+                ListenerSpec::Unix(path) => unix_paths.push(path),
+                ListenerSpec::Tcp(addr) if addr.is_empty() => {
+                    return Err(LogStreamError::Config("Listener TCP address cannot be empty".to_string()));
+                }
+                ListenerSpec::Tcp(_) => {}
+            }
+        }
+        if let Some(ref spec) = self.server.log_filter {
+            DaemonLevelFilter::parse(spec)?;
+        }
+        for pattern in &self.server.drop_patterns {
+            regex::Regex::new(pattern).map_err(|e| {
+                LogStreamError::Config(format!("Invalid drop pattern '{}': {}", pattern, e))
+            })?;
+        }
+        for (daemon, settings) in &self.daemon_overrides {
+            if let Some(ref spec) = settings.rotation_cron {
+                CronSchedule::parse(spec).map_err(|e| {
+                    LogStreamError::Config(format!("Daemon '{}' rotation_cron: {}", daemon, e))
+                })?;
+            }
+        }
         Ok(())
     }
 }
 
+/// Config keys whose values are never reported by [`ServerConfig::redacted_json`]
+///
+/// Empty for now: no setting holds a secret yet. A setting that does, such
+/// as credentials for an upstream, must be listed here.
+pub const REDACTED_CONFIG_KEYS: &[&str] = &[];
+
+/// Replace the value of every key in `keys` by `"[redacted]"`, at any depth
+fn redact(value: &mut serde_json::Value, keys: &[&str]) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if keys.contains(&key.as_str()) {
+                    *value = serde_json::Value::String("[redacted]".to_string());
+                } else {
+                    redact(value, keys);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| redact(item, keys)),
+        _ => {}
+    }
+}
+
 impl ClientConfig {
     /// Validate configuration
     pub fn validate(&self) -> Result<()> {
         if self.socket_path.is_empty() {
             return Err(LogStreamError::Config("Socket path cannot be empty".to_string()));
         }
+        if self.fallback_socket_paths.iter().any(String::is_empty) {
+            return Err(LogStreamError::Config("Fallback socket paths cannot be empty".to_string()));
+        }
         if self.daemon_name.is_empty() {
             return Err(LogStreamError::Config("Daemon name cannot be empty".to_string()));
         }
+        if self.breaker_failure_threshold == Some(0) {
+            return Err(LogStreamError::Config(
+                "breaker_failure_threshold must be at least 1".to_string(),
+            ));
+        }
+        if self.max_buffered_entries == Some(0) {
+            return Err(LogStreamError::Config(
+                "max_buffered_entries must be at least 1".to_string(),
+            ));
+        }
+        if self.replay_entries_per_sec == 0 || self.replay_batch_size == 0 {
+            return Err(LogStreamError::Config(
+                "replay_entries_per_sec and replay_batch_size must be at least 1".to_string(),
+            ));
+        }
         Ok(())
     }
 }
+
+/// Check that `dir` is a writable directory, or could be created as one
+///
+/// A missing directory is created at startup, which needs its nearest
+/// existing ancestor to be a writable directory.
+fn check_writable_dir(dir: &Path, what: &str) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let existing = dir.ancestors().find(|path| path.exists()).unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        return Err(LogStreamError::Config(format!(
+            "{} {}: {} is not a directory",
+            what,
+            dir.display(),
+            existing.display()
+        )));
+    }
+    let writable = std::ffi::CString::new(existing.as_os_str().as_bytes())
+        .is_ok_and(|path| unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0);
+    if !writable {
+        return Err(LogStreamError::Config(format!(
+            "{} {}: {} is not writable",
+            what,
+            dir.display(),
+            existing.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Expand an include pattern into the files it names, sorted by path
+///
+/// `*` and `?` wildcards are supported in the final path component only.
+fn expand_include(base_dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let full = base_dir.join(pattern);
+    let file_pattern = full
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| LogStreamError::Config(format!("Invalid include pattern: {}", pattern)))?;
+    let dir = full.parent().unwrap_or(base_dir);
+
+    if dir.to_string_lossy().contains(['*', '?']) {
+        return Err(LogStreamError::Config(format!(
+            "Wildcards are only supported in the file name of an include: {}",
+            pattern
+        )));
+    }
+
+    if !file_pattern.contains(['*', '?']) {
+        return Ok(vec![full]);
+    }
+
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        LogStreamError::Config(format!("Failed to read include directory {}: {}", dir.display(), e))
+    })?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        if let Some(name) = name.to_str() {
+            if entry.file_type()?.is_file() && wildcard_match(file_pattern, name) {
+                paths.push(entry.path());
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Match a file name against a pattern with `*` and `?` wildcards
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Read an include file, decompressing it first if it ends in `.gz`
+fn read_include(path: &Path) -> Result<String> {
+    let read_error =
+        |e: std::io::Error| LogStreamError::Config(format!("Failed to read include {}: {}", path.display(), e));
+
+    if path.extension().and_then(|ext| ext.to_str()) != Some("gz") {
+        return std::fs::read_to_string(path).map_err(read_error);
+    }
+
+    #[cfg(feature = "compression")]
+    {
+        use std::io::Read;
+
+        let file = std::fs::File::open(path).map_err(read_error)?;
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(file)
+            .read_to_string(&mut content)
+            .map_err(read_error)?;
+        Ok(content)
+    }
+
+    #[cfg(not(feature = "compression"))]
+    Err(LogStreamError::Config(format!(
+        "Gzip-compressed include {} requires the `compression` feature",
+        path.display()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_compression_level_ranges() {
+        assert!(validate_compression_level("gzip", 1).is_ok());
+        assert!(validate_compression_level("lz4", 9).is_ok());
+        for (algorithm, level) in [("gzip", 0), ("gzip", 10), ("lz4", 0), ("lz4", 12), ("zstd", 23), ("brotli", 3)] {
+            assert!(matches!(validate_compression_level(algorithm, level), Err(LogStreamError::Config(_))));
+        }
+        assert_eq!(validate_compression_level("zstd", 19).is_ok(), cfg!(feature = "zstd"));
+    }
+
+    const BASE_CONFIG: &str = r#"
+include = ["daemons/*.toml"]
+
+[server]
+socket_path = "/tmp/logstream-include.sock"
+max_connections = 10
+buffer_size = 8192
+
+[storage]
+output_directory = "/tmp/logstream-include"
+max_file_size = 1048576
+
+[storage.rotation]
+enabled = true
+max_age_hours = 24
+keep_files = 7
+
+[backends.file]
+enabled = true
+format = "json"
+compression = false
+compression_algorithm = "gzip"
+
+[backends.journald]
+enabled = false
+syslog_identifier = "logstream"
+
+[backends.syslog]
+enabled = false
+facility = "LOG_USER"
+
+[metrics]
+enabled = false
+port = 9090
+path = "/metrics"
+
+[daemon_overrides.auth]
+min_level = "Error"
+"#;
+
+    #[test]
+    fn test_includes_merge_daemon_overrides() {
+        let temp_dir = tempdir().unwrap();
+        let daemons_dir = temp_dir.path().join("daemons");
+        std::fs::create_dir_all(&daemons_dir).unwrap();
+
+        std::fs::write(temp_dir.path().join("server.toml"), BASE_CONFIG).unwrap();
+        std::fs::write(
+            daemons_dir.join("10-api.toml"),
+            "[api]\nmin_level = \"Warning\"\n\n[auth]\nformat = \"human\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            daemons_dir.join("20-worker.toml"),
+            "[worker]\nformat = \"human\"\n\n[auth]\nmin_level = \"Debug\"\n",
+        )
+        .unwrap();
+        std::fs::write(daemons_dir.join("notes.txt"), "not a config").unwrap();
+
+        let config = ServerConfig::from_file(temp_dir.path().join("server.toml")).unwrap();
+
+        assert_eq!(config.daemon_overrides.len(), 3);
+        assert_eq!(
+            config.daemon_override("api").unwrap().min_level,
+            Some(LogLevel::Warning)
+        );
+        assert_eq!(
+            config.daemon_override("worker").unwrap().format.as_deref(),
+            Some("human")
+        );
+
+        // The last include to mention a daemon wins
+        assert_eq!(
+            config.daemon_override("auth").unwrap(),
+            &DaemonOverride {
+                min_level: Some(LogLevel::Debug),
+                format: None,
+                dedicated_worker: false,
+                rotation_cron: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_compression_level_is_validated_per_algorithm() {
+        let mut config = ServerConfig::default();
+        config.backends.file.compression_level = 12;
+        // Only checked when compression is on
+        assert!(config.validate().is_ok());
+
+        config.backends.file.compression = true;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("out of range for gzip"), "{}", err);
+
+        config.backends.file.compression_level = 9;
+        assert!(config.validate().is_ok());
+        config.backends.file.compression_algorithm = "brotli".to_string();
+        assert!(config.validate().is_err());
+
+        config.backends.file.compression_algorithm = "zstd".to_string();
+        config.backends.file.compression_level = 19;
+        assert_eq!(config.validate().is_ok(), cfg!(feature = "zstd"));
+        config.backends.file.compression_level = 23;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_sqlite_backend_requires_feature() {
+        let mut config = ServerConfig::default();
+        config.backends.sqlite.enabled = true;
+        config.backends.sqlite.path = PathBuf::from("/tmp/logstream.db");
+        assert_eq!(config.validate().is_ok(), cfg!(feature = "sqlite"));
+    }
+
+    #[test]
+    fn test_field_names_are_validated() {
+        let mut config = ServerConfig::default();
+        config.backends.file.field_names.insert("message".to_string(), "msg".to_string());
+        config.backends.file.field_names.insert("level".to_string(), "severity".to_string());
+        assert!(config.validate().is_ok());
+
+        config.backends.file.field_names.insert("mesage".to_string(), "text".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("Unknown key 'mesage'"), "{}", err);
+
+        config.backends.file.field_names.remove("mesage");
+        config.backends.file.field_names.insert("daemon".to_string(), "severity".to_string());
+        assert!(config.validate().is_err());
+        // Clashing with a key that keeps its name
+        config.backends.file.field_names.insert("daemon".to_string(), "hostname".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("'hostname'"), "{}", err);
+        // Swapping two names is fine
+        config.backends.file.field_names.insert("hostname".to_string(), "daemon".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_strict_ndjson_rejects_incompatible_settings() {
+        let mut config = ServerConfig::default();
+        config.backends.file.format = "json".to_string();
+        config.backends.file.strict_ndjson = true;
+        assert!(config.validate().is_ok());
+
+        config.backends.file.record_terminator = "\r\n".to_string();
+        assert!(matches!(config.validate(), Err(LogStreamError::Config(_))));
+        config.backends.file.record_terminator = "\n".to_string();
+
+        config.daemon_overrides.insert(
+            "legacy".to_string(),
+            DaemonOverride {
+                format: Some("human".to_string()),
+                ..Default::default()
+            },
+        );
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("legacy"), "{}", err);
+    }
+
+    #[test]
+    fn test_check_deployment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        // Missing directories are fine as long as they can be created
+        config.storage.output_directory = temp_dir.path().join("logs/new");
+        config.server.socket_path = temp_dir.path().join("run/logstream.sock").to_string_lossy().to_string();
+        config.check_deployment().unwrap();
+        assert!(config.summary().contains(&format!("Output directory: {}", temp_dir.path().join("logs/new").display())));
+
+        let file = temp_dir.path().join("not-a-dir");
+        std::fs::write(&file, "").unwrap();
+        config.storage.output_directory = file.join("logs");
+        let err = config.check_deployment().unwrap_err();
+        assert!(err.to_string().contains("is not a directory"), "{}", err);
+
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.server.listeners = vec![ListenerSpec::Unix(file.join("extra.sock").to_string_lossy().to_string())];
+        assert!(config.check_deployment().is_err());
+
+        config.server.listeners.clear();
+        config.backends.file.compression_algorithm = "brotli".to_string();
+        let err = config.check_deployment().unwrap_err();
+        assert!(err.to_string().contains("brotli"), "{}", err);
+    }
+
+    #[test]
+    fn test_gzip_include() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let temp_dir = tempdir().unwrap();
+        let daemons_dir = temp_dir.path().join("daemons");
+        std::fs::create_dir_all(&daemons_dir).unwrap();
+
+        let config = BASE_CONFIG.replace("daemons/*.toml", "daemons/*.toml.gz");
+        std::fs::write(temp_dir.path().join("server.toml"), config).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"[billing]\nmin_level = \"Notice\"\n").unwrap();
+        std::fs::write(daemons_dir.join("billing.toml.gz"), encoder.finish().unwrap()).unwrap();
+
+        let config = ServerConfig::from_file(temp_dir.path().join("server.toml")).unwrap();
+        assert_eq!(
+            config.daemon_override("billing").unwrap().min_level,
+            Some(LogLevel::Notice)
+        );
+    }
+
+    #[test]
+    fn test_missing_include_is_an_error() {
+        let temp_dir = tempdir().unwrap();
+        let config = BASE_CONFIG.replace("daemons/*.toml", "missing.toml");
+        std::fs::write(temp_dir.path().join("server.toml"), config).unwrap();
+
+        let result = ServerConfig::from_file(temp_dir.path().join("server.toml"));
+        assert!(matches!(result, Err(LogStreamError::Config(_))));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.toml", "api.toml"));
+        assert!(wildcard_match("*.toml", ".toml"));
+        assert!(wildcard_match("d?-*.toml", "d1-api.toml"));
+        assert!(!wildcard_match("*.toml", "api.toml.bak"));
+        assert!(!wildcard_match("d?-*.toml", "d12-api.toml"));
+    }
+
+    #[test]
+    fn test_default_socket_path_prefers_runtime_dir() {
+        assert_eq!(
+            socket_path_for(Some("/run/user/1000".into()), 1000),
+            "/run/user/1000/logstream.sock"
+        );
+        assert_eq!(socket_path_for(None, 1000), "/tmp/logstream-1000.sock");
+        assert_eq!(socket_path_for(Some("".into()), 0), "/tmp/logstream-0.sock");
+
+        let expected = default_socket_path();
+        assert_eq!(ServerConfig::default().server.socket_path, expected);
+        assert_eq!(ClientConfig::default().socket_path, expected);
+    }
+
+    #[test]
+    fn test_redact_replaces_secrets_at_any_depth() {
+        let mut value = serde_json::json!({
+            "auth_tokens": ["a", "b"],
+            "server": { "socket_path": "/tmp/x.sock", "auth_tokens": { "web": "secret" } },
+            "daemons": [{ "auth_tokens": "c" }],
+        });
+        redact(&mut value, &["auth_tokens"]);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "auth_tokens": "[redacted]",
+                "server": { "socket_path": "/tmp/x.sock", "auth_tokens": "[redacted]" },
+                "daemons": [{ "auth_tokens": "[redacted]" }],
+            })
+        );
+
+        // No setting holds a secret yet, so the whole configuration is reported
+        let config = ServerConfig::default();
+        assert_eq!(config.redacted_json().unwrap(), serde_json::to_value(&config).unwrap());
+    }
+
+    #[test]
+    fn test_listeners_parse_and_reject_duplicates() {
+        let mut config = ServerConfig::default();
+        config.server.socket_path = "/tmp/main.sock".to_string();
+        config.server.listeners = vec![
+            ListenerSpec::Unix("/tmp/legacy.sock".to_string()),
+            ListenerSpec::Tcp("127.0.0.1:5140".to_string()),
+        ];
+        let text = toml::to_string(&config).unwrap();
+        assert!(text.contains(r#"unix = "/tmp/legacy.sock""#));
+        let parsed: ServerConfig = toml::from_str(&text).unwrap();
+        assert_eq!(parsed.server.listeners, config.server.listeners);
+        config.validate().unwrap();
+
+        let mut duplicate = config.clone();
+        duplicate.server.listeners.push(ListenerSpec::Unix("/tmp/main.sock".to_string()));
+        assert!(matches!(duplicate.validate(), Err(LogStreamError::Config(_))));
+    }
+}
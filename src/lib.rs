@@ -47,10 +47,13 @@
 #![deny(missing_docs)]
 #![warn(clippy::all)]
 
+pub mod build_info;
 pub mod client;
 pub mod config;
 pub mod server;
 pub mod types;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 
 /// Common error types used throughout LogStream
 pub mod error {
@@ -2,9 +2,10 @@
 //!
 //! High-performance centralized log aggregation server.
 
-use clap::Parser;
-use logstream::config::ServerConfig;
-use logstream::server::LogServer;
+use clap::{Parser, Subcommand};
+use logstream::config::{DaemonLevelFilter, ServerConfig};
+use logstream::server::{shutdown_signal, validate_entries, EntryDecision, LogServer, StorageBackend};
+use logstream::LogStreamError;
 use std::path::PathBuf;
 use tracing::{error, info};
 
@@ -13,6 +14,9 @@ use tracing::{error, info};
 #[command(about = "High-performance centralized logging server")]
 #[command(version)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Configuration file path
     #[arg(short, long, default_value = "config/server.toml")]
     config: PathBuf,
@@ -29,6 +33,18 @@ struct Args {
     #[arg(short, long)]
     verbose: bool,
 
+    /// Least severe level stored, per daemon and by default, e.g. "web=debug,auth=warn,info"
+    #[arg(long)]
+    log_filter: Option<String>,
+
+    /// Accept and count entries without storing them, for transport benchmarks
+    #[arg(long)]
+    bench: bool,
+
+    /// Validate the configuration, print a summary and exit without starting the server
+    #[arg(long)]
+    check_config: bool,
+
     /// Enable journald backend
     #[cfg(feature = "journald")]
     #[arg(long)]
@@ -45,12 +61,19 @@ struct Args {
     metrics_port: u16,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Report what the configuration would do with each JSON entry read from stdin, storing nothing
+    ValidateEntries,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
-    // Initialize tracing
+    // Initialize tracing, on stderr so stdout only carries command output like the --check-config summary
     let subscriber = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
         .with_env_filter(if args.verbose {
             "logstream=debug,info"
         } else {
@@ -65,11 +88,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing::subscriber::set_global_default(subscriber)
         .expect("Failed to set tracing subscriber");
 
-    info!("Starting LogStream Server v{}", env!("CARGO_PKG_VERSION"));
-
     // Load configuration
     let mut config = if args.config.exists() {
-        ServerConfig::from_file(&args.config)?
+        match ServerConfig::from_file(&args.config) {
+            Ok(config) => config,
+            Err(e) if args.check_config => {
+                eprintln!("Invalid configuration {}: {}", args.config.display(), e);
+                std::process::exit(1);
+            }
+            Err(e) => return Err(e.into()),
+        }
     } else {
         info!("Config file not found, using defaults");
         ServerConfig::default()
@@ -82,6 +110,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if let Some(output) = args.output {
         config.storage.output_directory = output;
     }
+    if let Some(spec) = args.log_filter {
+        config.apply_log_filter(&DaemonLevelFilter::parse(&spec)?);
+    }
+
+    if args.bench {
+        config.backends.disable_all();
+        info!("Bench mode: entries are counted but not stored");
+    }
 
     #[cfg(feature = "journald")]
     if args.journald {
@@ -94,9 +130,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.metrics.port = args.metrics_port;
     }
 
+    if args.check_config {
+        // A missing file falls back to defaults when serving, but is a mistake here
+        let checked = if args.config.exists() {
+            config.check_deployment()
+        } else {
+            Err(LogStreamError::Config(format!("Config file {} not found", args.config.display())))
+        };
+        match checked {
+            Ok(()) => {
+                println!("Configuration OK: {}", args.config.display());
+                println!("{}", config.summary());
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("Invalid configuration {}: {}", args.config.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // Validate configuration
     config.validate()?;
 
+    if let Some(Command::ValidateEntries) = args.command {
+        let storage = StorageBackend::for_dry_run(&config).await?;
+        let reports = validate_entries(&storage, tokio::io::BufReader::new(tokio::io::stdin())).await?;
+        let mut counts = [0; 3];
+        for report in &reports {
+            println!("{}", report);
+            counts[match report.decision {
+                EntryDecision::Stored { .. } => 0,
+                EntryDecision::Dropped { .. } => 1,
+                EntryDecision::Rejected { .. } => 2,
+            }] += 1;
+        }
+        println!("{} stored, {} dropped, {} rejected", counts[0], counts[1], counts[2]);
+        return Ok(());
+    }
+
+    info!("Starting LogStream Server {}", logstream::build_info::BUILD_INFO);
     info!("Configuration loaded successfully");
     info!("Socket path: {}", config.server.socket_path);
     info!("Output directory: {}", config.storage.output_directory.display());
@@ -106,25 +179,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize and start server
     let server = LogServer::new(config).await?;
 
-    // Handle shutdown gracefully
-    let shutdown_signal = async {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("Failed to install CTRL+C signal handler");
-        info!("Shutdown signal received");
+    // Handle shutdown gracefully on SIGINT, and SIGTERM on Unix
+    let signal = shutdown_signal()?;
+    let stop = async {
+        let name = signal.await;
+        info!("{} received, shutting down gracefully...", name);
     };
 
     // Start server with graceful shutdown
-    tokio::select! {
-        result = server.start() => {
-            if let Err(e) = result {
-                error!("Server error: {}", e);
-                std::process::exit(1);
-            }
-        }
-        _ = shutdown_signal => {
-            info!("Shutting down gracefully...");
-        }
+    if let Err(e) = server.start_until(stop).await {
+        error!("Server error: {}", e);
+        std::process::exit(1);
     }
 
     info!("LogStream Server stopped");
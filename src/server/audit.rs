@@ -0,0 +1,158 @@
+//! Audit trail of the connections the server accepts
+//!
+//! With `server.audit_connections`, the server logs an entry when a
+//! connection opens and another when it closes, under the reserved daemon
+//! name [`AUDIT_DAEMON`]. They go through the same ingest and storage as
+//! client entries. Each carries an `event` field (`connect`, `disconnect`
+//! or `error`), a `connection_id` shared by both entries of a connection,
+//! and the peer: `peer_pid`, `peer_uid` and `peer_gid` on Unix sockets,
+//! `peer_addr` on TCP. Closing entries add `bytes_received`, `entries` and
+//! `duration_ms`, plus `error` when the connection failed.
+
+use crate::server::listener::Connection;
+use crate::types::{LogEntry, LogLevel};
+use crate::Result;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Daemon name the server's connection audit entries are stored under
+pub const AUDIT_DAEMON: &str = "logstream.audit";
+
+/// What happened to a connection, recorded as the `event` field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEvent {
+    /// The connection was accepted
+    Connect,
+    /// The client closed the connection, or the server did on shutdown
+    Disconnect,
+    /// The connection ended because of an error
+    Error,
+}
+
+impl AuditEvent {
+    /// Value of the `event` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::Connect => "connect",
+            AuditEvent::Disconnect => "disconnect",
+            AuditEvent::Error => "error",
+        }
+    }
+}
+
+/// Traffic counted over one connection
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionStats {
+    bytes_received: AtomicU64,
+    entries: AtomicU64,
+    /// Read error that ended the connection, if any
+    read_error: Mutex<Option<String>>,
+}
+
+impl ConnectionStats {
+    pub(crate) fn record_entry(&self) {
+        self.entries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_read_error(&self, error: String) {
+        *self.read_error.lock().unwrap() = Some(error);
+    }
+}
+
+/// Audit details of one open connection
+pub(crate) struct ConnectionAudit {
+    id: u64,
+    started: Instant,
+    peer: HashMap<String, String>,
+    pub(crate) stats: ConnectionStats,
+}
+
+impl ConnectionAudit {
+    /// Start auditing a just-accepted connection
+    pub(crate) fn open(id: u64, connection: &Connection) -> Self {
+        let mut peer = HashMap::new();
+        match connection {
+            Connection::Unix(stream) => {
+                peer.insert("transport".to_string(), "unix".to_string());
+                if let Ok(cred) = stream.peer_cred() {
+                    if let Some(pid) = cred.pid() {
+                        peer.insert("peer_pid".to_string(), pid.to_string());
+                    }
+                    peer.insert("peer_uid".to_string(), cred.uid().to_string());
+                    peer.insert("peer_gid".to_string(), cred.gid().to_string());
+                }
+            }
+            Connection::Tcp(stream) => {
+                peer.insert("transport".to_string(), "tcp".to_string());
+                if let Ok(addr) = stream.peer_addr() {
+                    peer.insert("peer_addr".to_string(), addr.to_string());
+                }
+            }
+        }
+        Self {
+            id,
+            started: Instant::now(),
+            peer,
+            stats: ConnectionStats::default(),
+        }
+    }
+
+    /// Entry recording that the connection opened
+    pub(crate) fn connected(&self) -> LogEntry {
+        self.entry(AuditEvent::Connect, LogLevel::Info, "Connection opened")
+    }
+
+    /// Entry recording how the connection ended, with its traffic
+    pub(crate) fn closed(&self, result: &Result<()>) -> LogEntry {
+        let read_error = self.stats.read_error.lock().unwrap().take();
+        let error = result.as_ref().err().map(ToString::to_string).or(read_error);
+        let mut entry = match error {
+            Some(ref error) => {
+                let mut entry = self.entry(AuditEvent::Error, LogLevel::Warning, "Connection failed");
+                entry.fields.insert("error".to_string(), error.clone());
+                entry
+            }
+            None => self.entry(AuditEvent::Disconnect, LogLevel::Info, "Connection closed"),
+        };
+        let stats = &self.stats;
+        entry.fields.insert("bytes_received".to_string(), stats.bytes_received.load(Ordering::Relaxed).to_string());
+        entry.fields.insert("entries".to_string(), stats.entries.load(Ordering::Relaxed).to_string());
+        entry.fields.insert("duration_ms".to_string(), self.started.elapsed().as_millis().to_string());
+        entry
+    }
+
+    fn entry(&self, event: AuditEvent, level: LogLevel, message: &str) -> LogEntry {
+        let mut entry = LogEntry::new(level, AUDIT_DAEMON.to_string(), message.to_string());
+        entry.fields.clone_from(&self.peer);
+        entry.fields.insert("event".to_string(), event.as_str().to_string());
+        entry.fields.insert("connection_id".to_string(), self.id.to_string());
+        entry
+    }
+}
+
+/// Reader counting the bytes read through it into [`ConnectionStats`]
+pub(crate) struct CountingReader<'a, R> {
+    inner: R,
+    stats: &'a ConnectionStats,
+}
+
+impl<'a, R> CountingReader<'a, R> {
+    pub(crate) fn new(inner: R, stats: &'a ConnectionStats) -> Self {
+        Self { inner, stats }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<'_, R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        self.stats.bytes_received.fetch_add(read as u64, Ordering::Relaxed);
+        result
+    }
+}
@@ -0,0 +1,170 @@
+//! Compression of rotated archives
+//!
+//! With `backends.file.compression` set, each archive is compressed as soon
+//! as it is rotated, into `{archive}.gz`, `{archive}.lz4` or `{archive}.zst`
+//! depending on `compression_algorithm`, and the uncompressed archive is
+//! removed. gzip and lz4 take a `compression_level` from 1 (fastest) to 9
+//! (smallest). gzip uses it directly; lz4 has a single compression level, so
+//! the level picks its block size instead, larger blocks compressing better.
+//! zstd uses its own levels, 1 to 22, and needs the `zstd` feature.
+
+use crate::{LogStreamError, Result};
+use std::path::{Path, PathBuf};
+
+/// Extension appended to archives compressed with an algorithm
+fn archive_extension(algorithm: &str) -> &'static str {
+    match algorithm {
+        "lz4" => "lz4",
+        "zstd" => "zst",
+        _ => "gz",
+    }
+}
+
+/// Extensions of compressed archives, as appended after `.log`
+pub const ARCHIVE_EXTENSIONS: &[&str] = &[".gz", ".lz4", ".zst"];
+
+/// Open an archive for reading, decompressing it according to its extension
+///
+/// Archives without a compression extension are read as they are.
+#[cfg(feature = "compression")]
+pub fn open_archive(path: &Path) -> Result<Box<dyn std::io::Read + Send>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Ok(Box::new(flate2::read::MultiGzDecoder::new(file))),
+        Some("lz4") => Ok(Box::new(lz4_flex::frame::FrameDecoder::new(file))),
+        #[cfg(feature = "zstd")]
+        Some("zst") => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(file)?)),
+        #[cfg(not(feature = "zstd"))]
+        Some("zst") => Err(LogStreamError::Config(format!(
+            "Reading {} requires the `zstd` feature",
+            path.display()
+        ))),
+        _ => Ok(Box::new(file)),
+    }
+}
+
+/// Compress everything read from `input` into `output`, returning `output`
+#[cfg(feature = "compression")]
+pub fn compress_to<R, W>(mut input: R, output: W, algorithm: &str, level: u32) -> Result<W>
+where
+    R: std::io::Read,
+    W: std::io::Write,
+{
+    crate::config::validate_compression_level(algorithm, level)?;
+    match algorithm {
+        "lz4" => {
+            use lz4_flex::frame::{BlockSize, FrameEncoder, FrameInfo};
+
+            let block_size = match level {
+                1..=2 => BlockSize::Max64KB,
+                3..=5 => BlockSize::Max256KB,
+                6..=8 => BlockSize::Max1MB,
+                _ => BlockSize::Max4MB,
+            };
+            let mut encoder = FrameEncoder::with_frame_info(FrameInfo::new().block_size(block_size), output);
+            std::io::copy(&mut input, &mut encoder)?;
+            Ok(encoder.finish().map_err(std::io::Error::from)?)
+        }
+        #[cfg(feature = "zstd")]
+        "zstd" => {
+            let mut encoder = zstd::stream::write::Encoder::new(output, level as i32)?;
+            std::io::copy(&mut input, &mut encoder)?;
+            Ok(encoder.finish()?)
+        }
+        _ => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::new(level));
+            std::io::copy(&mut input, &mut encoder)?;
+            Ok(encoder.finish()?)
+        }
+    }
+}
+
+/// Compress an archive next to itself and remove the original, returning the new path
+///
+/// Runs on Tokio's blocking pool. With `sync` the compressed file is
+/// flushed to disk before the original is removed.
+pub(crate) async fn compress_archive(path: &Path, algorithm: &str, level: u32, sync: bool) -> Result<PathBuf> {
+    let mut compressed_name = path.file_name().unwrap_or_default().to_os_string();
+    compressed_name.push(".");
+    compressed_name.push(archive_extension(algorithm));
+    let compressed_path = path.with_file_name(compressed_name);
+
+    #[cfg(feature = "compression")]
+    {
+        let (source, target) = (path.to_path_buf(), compressed_path.clone());
+        let algorithm = algorithm.to_string();
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let input = std::io::BufReader::new(std::fs::File::open(&source)?);
+            let output = std::io::BufWriter::new(std::fs::File::create(&target)?);
+            let file = compress_to(input, output, &algorithm, level)?
+                .into_inner()
+                .map_err(|e| e.into_error())?;
+            if sync {
+                file.sync_all()?;
+            }
+            std::fs::remove_file(&source)?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| LogStreamError::Server(format!("Archive compression task failed: {}", e)))??;
+        Ok(compressed_path)
+    }
+
+    #[cfg(not(feature = "compression"))]
+    {
+        let _ = (level, sync);
+        Err(LogStreamError::Config(format!(
+            "Compressing {} requires the `compression` feature",
+            compressed_path.display()
+        )))
+    }
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use super::*;
+
+    /// Log-like input that compresses differently at different levels
+    fn sample_input() -> Vec<u8> {
+        (0..20_000)
+            .map(|i| format!("{{\"level\":\"INFO\",\"daemon\":\"api\",\"message\":\"request {} took {}ms\"}}\n", i, i * 7 % 1000))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_higher_level_compresses_at_least_as_well() {
+        let input = sample_input();
+        for algorithm in ["gzip", "lz4"] {
+            let fast = compress_to(&input[..], Vec::new(), algorithm, 1).unwrap();
+            let small = compress_to(&input[..], Vec::new(), algorithm, 9).unwrap();
+            assert!(small.len() <= fast.len(), "{}: {} > {}", algorithm, small.len(), fast.len());
+            assert!(fast.len() < input.len());
+        }
+
+        use std::io::Read;
+        let mut decompressed = Vec::new();
+        let compressed = compress_to(&input[..], Vec::new(), "gzip", 9).unwrap();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, input);
+    }
+
+    #[test]
+    fn test_archives_read_back() {
+        use std::io::Read;
+
+        let input = sample_input();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut algorithms = vec!["gzip", "lz4"];
+        if cfg!(feature = "zstd") {
+            algorithms.push("zstd");
+        }
+        for algorithm in algorithms {
+            let path = temp_dir.path().join(format!("api.20240601-120000-000.log.{}", archive_extension(algorithm)));
+            std::fs::write(&path, compress_to(&input[..], Vec::new(), algorithm, 3).unwrap()).unwrap();
+            let mut decompressed = Vec::new();
+            open_archive(&path).unwrap().read_to_end(&mut decompressed).unwrap();
+            assert!(decompressed == input, "{} archive didn't read back", algorithm);
+        }
+    }
+}
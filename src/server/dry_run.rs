@@ -0,0 +1,168 @@
+//! Dry runs of sample entries against a configuration
+//!
+//! [`validate_entries`] puts each line of its input through the same
+//! parsing, limits, filtering and routing a server applies, and reports
+//! what would happen to it, without writing anything. Used by
+//! `logstream-server validate-entries` to try out configuration changes.
+
+use crate::server::StorageBackend;
+use crate::types::{Frame, LogEntryStream};
+use crate::Result;
+use std::fmt;
+use std::path::PathBuf;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// What a server would do with an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryDecision {
+    /// Stored by `backends`, in `file` when the file backend is one of them
+    Stored {
+        /// Log file the entry would be appended to
+        file: Option<PathBuf>,
+        /// Backends taking the entry, e.g. `"file"` or `"memory"`
+        backends: Vec<&'static str>,
+    },
+    /// Accepted but filtered out before reaching any backend
+    Dropped {
+        /// Filter that drops it
+        reason: String,
+    },
+    /// Refused as malformed, over a limit or out of order
+    Rejected {
+        /// Why it is refused
+        reason: String,
+    },
+}
+
+impl fmt::Display for EntryDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryDecision::Stored { backends, .. } if backends.is_empty() => {
+                write!(f, "accepted, but no backend takes it")
+            }
+            EntryDecision::Stored { file: Some(file), backends } => {
+                write!(f, "stored in {} via {}", file.display(), backends.join(", "))
+            }
+            EntryDecision::Stored { file: None, backends } => write!(f, "stored via {}", backends.join(", ")),
+            EntryDecision::Dropped { reason } => write!(f, "dropped: {}", reason),
+            EntryDecision::Rejected { reason } => write!(f, "rejected: {}", reason),
+        }
+    }
+}
+
+/// Decision for one line of input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineReport {
+    /// Line number, starting at 1
+    pub line: usize,
+    /// What would happen to the line's entry
+    pub decision: EntryDecision,
+}
+
+impl fmt::Display for LineReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.decision)
+    }
+}
+
+/// Report what `storage` would do with each non-blank line of `input`
+///
+/// Lines are checked one at a time, so entries split into chunks across
+/// lines are rejected as incomplete. Entries are judged in order, which
+/// matters for `enforce_monotonic`.
+pub async fn validate_entries<R: AsyncBufRead + Unpin>(storage: &StorageBackend, mut input: R) -> Result<Vec<LineReport>> {
+    let limits = storage.config().server.line_limits();
+    let mut reports = Vec::new();
+    let mut line = Vec::new();
+
+    for number in 1.. {
+        line.clear();
+        if input.read_until(b'\n', &mut line).await? == 0 {
+            break;
+        }
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        if line.last() != Some(&b'\n') {
+            line.push(b'\n');
+        }
+
+        let decision = match LogEntryStream::with_limits(&line[..], limits).next_frame().await {
+            Some(Ok(Frame::Entry(entry))) => storage.dry_run(entry),
+            Some(Ok(Frame::Control(_))) => EntryDecision::Rejected {
+                reason: "control command, not an entry".to_string(),
+            },
+            Some(Err(e)) => EntryDecision::Rejected { reason: e.to_string() },
+            None => EntryDecision::Rejected {
+                reason: "incomplete chunked entry".to_string(),
+            },
+        };
+        reports.push(LineReport { line: number, decision });
+    }
+
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MonotonicPolicy, ServerConfig};
+    use crate::types::{LogEntry, LogLevel};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_mixed_entries_are_reported() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().join("logs");
+        config.server.drop_patterns = vec!["^healthcheck".to_string()];
+        config.server.enforce_monotonic = MonotonicPolicy::Reject;
+        config.daemon_overrides.entry("noisy".to_string()).or_default().min_level = Some(LogLevel::Warning);
+        config.backends.memory.enabled = true;
+        config.backends.sqlite.enabled = cfg!(feature = "sqlite");
+        config.backends.sqlite.path = temp_dir.path().join("logs.db");
+        config.validate().unwrap();
+        let storage = StorageBackend::for_dry_run(&config).await.unwrap();
+
+        let entry = |level, daemon: &str, message: &str| LogEntry::new(level, daemon.to_string(), message.to_string());
+        let api = entry(LogLevel::Info, "api", "request served");
+        let mut late = entry(LogLevel::Info, "api", "late");
+        late.timestamp = api.timestamp - chrono::Duration::seconds(1);
+        let input = [
+            api.to_json().unwrap(),
+            entry(LogLevel::Info, "api", "healthcheck ok").to_json().unwrap(),
+            String::new(),
+            entry(LogLevel::Info, "noisy", "chatter").to_json().unwrap(),
+            entry(LogLevel::Error, "noisy", "failed").to_json().unwrap(),
+            "{not json".to_string(),
+            late.to_json().unwrap(),
+            r#"{"cmd":"ping"}"#.to_string(),
+        ]
+        .join("\n");
+
+        let reports = validate_entries(&storage, input.as_bytes()).await.unwrap();
+        let decisions: Vec<(usize, &EntryDecision)> = reports.iter().map(|r| (r.line, &r.decision)).collect();
+        let mut backends = vec!["file", "memory"];
+        if cfg!(feature = "sqlite") {
+            backends.insert(1, "sqlite");
+        }
+        let stored = |daemon: &str| EntryDecision::Stored {
+            file: Some(temp_dir.path().join("logs").join(format!("{}.log", daemon))),
+            backends: backends.clone(),
+        };
+        assert_eq!(decisions[0], (1, &stored("api")));
+        assert_eq!(decisions[1], (2, &EntryDecision::Dropped { reason: "message matches a drop pattern".to_string() }));
+        // The blank line 3 is skipped
+        assert_eq!(decisions[2], (4, &EntryDecision::Dropped { reason: "below min_level Warning".to_string() }));
+        assert_eq!(decisions[3], (5, &stored("noisy")));
+        assert!(matches!(decisions[4], (6, EntryDecision::Rejected { .. })));
+        assert!(matches!(decisions[5], (7, EntryDecision::Rejected { reason }) if reason.contains("before the last stored")));
+        assert!(matches!(decisions[6], (8, EntryDecision::Rejected { .. })));
+        assert_eq!(reports.len(), 7);
+        assert!(reports[0].to_string().starts_with("line 1: stored in "));
+
+        // Nothing was written or opened
+        assert!(!temp_dir.path().join("logs").exists());
+        assert!(!temp_dir.path().join("logs.db").exists());
+    }
+}
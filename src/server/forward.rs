@@ -0,0 +1,124 @@
+//! Forwarding of stored entries to an upstream LogStream server
+//!
+//! With `backends.forward.enabled`, a leaf server sends every entry it stores
+//! on to a central server through an internal [`LogClient`]. Entries wait in
+//! a bounded queue, so an unreachable upstream never blocks or fails local
+//! storage; once the queue is full, new entries are stored locally only.
+
+use crate::client::logger::backoff_delay;
+use crate::client::LogClient;
+use crate::config::{ClientConfig, ForwardBackendSettings};
+use crate::types::LogEntry;
+use crate::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+/// Daemon name of the forwarding client itself
+const FORWARD_CLIENT_NAME: &str = "logstream-forward";
+
+/// Queue feeding a background task that sends entries upstream
+pub struct Forwarder {
+    tx: mpsc::Sender<LogEntry>,
+    dropped: AtomicU64,
+}
+
+impl Forwarder {
+    /// Start forwarding to the configured upstream
+    ///
+    /// Doesn't connect yet; the task connects on the first entry and
+    /// reconnects with backoff whenever sending fails.
+    pub fn start(settings: &ForwardBackendSettings) -> Result<Self> {
+        let client = LogClient::new_lazy(ClientConfig {
+            socket_path: settings.upstream_socket.clone(),
+            daemon_name: FORWARD_CLIENT_NAME.to_string(),
+            ..Default::default()
+        })?;
+        let (tx, rx) = mpsc::channel(settings.queue_size.max(1));
+        tokio::spawn(run(client, rx, settings.daemon_prefix.clone()));
+
+        Ok(Self {
+            tx,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Queue an entry for the upstream without waiting
+    pub fn forward(&self, entry: &LogEntry) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(entry.clone()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Entries not forwarded because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Send queued entries upstream in order, retrying each until it goes through
+///
+/// Stops once the [`Forwarder`] is dropped and the queue is drained, or when
+/// sending fails after that, since nobody is left to wait for the upstream.
+async fn run(client: LogClient, mut rx: mpsc::Receiver<LogEntry>, daemon_prefix: String) {
+    let retry = ClientConfig::default();
+
+    while let Some(mut entry) = rx.recv().await {
+        if !daemon_prefix.is_empty() {
+            entry.daemon.insert_str(0, &daemon_prefix);
+        }
+
+        let mut attempt = 0;
+        while let Err(e) = client.send_entry(entry.clone()).await {
+            if rx.is_closed() {
+                return;
+            }
+            // Report each outage once rather than every retry
+            if attempt == 0 {
+                eprintln!("Failed to forward log entry upstream, retrying: {}", e);
+            }
+            let delay = backoff_delay(
+                retry.reconnect_delay_ms,
+                retry.reconnect_jitter_ms,
+                attempt,
+                &mut rand::thread_rng(),
+            );
+            tokio::time::sleep(delay).await;
+            attempt = attempt.saturating_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::server::StorageBackend;
+    use crate::types::LogLevel;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_unreachable_upstream_does_not_block_storage() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.backends.forward = ForwardBackendSettings {
+            enabled: true,
+            upstream_socket: temp_dir.path().join("missing.sock").to_string_lossy().to_string(),
+            queue_size: 2,
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        for i in 0..5 {
+            let entry = LogEntry::new(LogLevel::Info, "leaf".to_string(), format!("entry {}", i));
+            storage.store_entry(entry).await.unwrap();
+        }
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("leaf.log")).await.unwrap();
+        assert_eq!(content.lines().count(), 5);
+        // At most one entry in flight plus a full queue of two
+        assert!(storage.forwarder().unwrap().dropped() >= 2);
+    }
+}
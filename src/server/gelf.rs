@@ -0,0 +1,183 @@
+//! GELF output over UDP, for Graylog and other GELF inputs
+//!
+//! With `backends.gelf.enabled`, every stored entry is sent as a GELF 1.1
+//! message (see [`LogEntry::to_gelf`]) to a UDP input. Sending never waits:
+//! a message the socket can't take right away is dropped and counted, as
+//! UDP gives no delivery guarantee anyway. Messages longer than
+//! `chunk_size` are split into GELF chunks.
+
+use crate::config::GelfBackendSettings;
+use crate::types::LogEntry;
+use crate::{LogStreamError, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::net::UdpSocket;
+
+/// Magic bytes opening each GELF chunk
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+
+/// Magic bytes, message id, sequence number and sequence count
+const CHUNK_HEADER_LEN: usize = 12;
+
+/// Most chunks a GELF input reassembles into one message
+const MAX_CHUNKS: usize = 128;
+
+/// UDP socket sending entries to a GELF input
+pub struct GelfSender {
+    socket: UdpSocket,
+    chunk_size: usize,
+    dropped: AtomicU64,
+}
+
+impl GelfSender {
+    /// Resolve the configured server and bind a socket to send to it
+    pub async fn connect(settings: &GelfBackendSettings) -> Result<Self> {
+        let setup_error =
+            |e: std::io::Error| LogStreamError::Server(format!("Failed to set up GELF sender for {}: {}", settings.server, e));
+        let server = tokio::net::lookup_host(&settings.server)
+            .await
+            .map_err(setup_error)?
+            .next()
+            .ok_or_else(|| LogStreamError::Config(format!("GELF server {} has no address", settings.server)))?;
+        let local = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local).await.map_err(setup_error)?;
+        socket.connect(server).await.map_err(setup_error)?;
+
+        Ok(Self {
+            socket,
+            chunk_size: settings.chunk_size,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Send an entry without waiting, dropping it if that fails
+    pub fn send(&self, entry: &LogEntry) {
+        let sent = entry.to_gelf().ok().and_then(|message| {
+            datagrams(message.as_bytes(), self.chunk_size, rand::random::<u64>().to_be_bytes())?
+                .iter()
+                .try_for_each(|datagram| self.socket.try_send(datagram).map(drop))
+                .ok()
+        });
+        if sent.is_none() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Entries not sent, because the socket was busy or refused them or they needed too many chunks
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Split a message into the datagrams carrying it
+///
+/// A message that fits in `chunk_size` goes as is; a longer one is cut into
+/// chunks tagged with `message_id`. `None` when it would take more chunks
+/// than a GELF input accepts.
+fn datagrams(message: &[u8], chunk_size: usize, message_id: [u8; 8]) -> Option<Vec<Vec<u8>>> {
+    if message.len() <= chunk_size {
+        return Some(vec![message.to_vec()]);
+    }
+    let parts: Vec<&[u8]> = message.chunks(chunk_size - CHUNK_HEADER_LEN).collect();
+    if parts.len() > MAX_CHUNKS {
+        return None;
+    }
+    let count = parts.len() as u8;
+    Some(
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(sequence, part)| {
+                let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + part.len());
+                chunk.extend_from_slice(&CHUNK_MAGIC);
+                chunk.extend_from_slice(&message_id);
+                chunk.extend_from_slice(&[sequence as u8, count]);
+                chunk.extend_from_slice(part);
+                chunk
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ServerConfig, GELF_MIN_CHUNK_SIZE};
+    use crate::server::StorageBackend;
+    use crate::types::LogLevel;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    async fn recv(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = vec![0; 65536];
+        let len = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buf))
+            .await
+            .expect("no GELF datagram received")
+            .unwrap();
+        buf.truncate(len);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_sent_as_gelf() {
+        let input = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.backends.file.format = "gelf".to_string();
+        config.backends.gelf = GelfBackendSettings {
+            enabled: true,
+            server: input.local_addr().unwrap().to_string(),
+            chunk_size: GELF_MIN_CHUNK_SIZE,
+            min_level: Some(LogLevel::Warning),
+        };
+        config.validate().unwrap();
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        storage
+            .store_entry(LogEntry::new(LogLevel::Info, "api".to_string(), "below min_level".to_string()))
+            .await
+            .unwrap();
+        let mut entry = LogEntry::new(LogLevel::Error, "api".to_string(), "upstream timed out".to_string());
+        entry.fields.insert("route".to_string(), "/users".to_string());
+        storage.store_entry(entry).await.unwrap();
+
+        let gelf: serde_json::Value = serde_json::from_slice(&recv(&input).await).unwrap();
+        assert_eq!(gelf["short_message"], "upstream timed out");
+        assert_eq!((&gelf["level"], &gelf["_route"]), (&3.into(), &"/users".into()));
+        // The file holds the same messages, one per line
+        let content = tokio::fs::read_to_string(temp_dir.path().join("api.log")).await.unwrap();
+        let lines: Vec<serde_json::Value> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!((lines.len(), &lines[1]), (2, &gelf));
+
+        // Too long for one datagram, so it arrives in chunks of one message
+        let long = "x".repeat(GELF_MIN_CHUNK_SIZE * 3);
+        storage
+            .store_entry(LogEntry::new(LogLevel::Error, "api".to_string(), long.clone()))
+            .await
+            .unwrap();
+        let mut chunks = Vec::new();
+        loop {
+            let chunk = recv(&input).await;
+            assert!(chunk.len() <= GELF_MIN_CHUNK_SIZE && chunk[..2] == CHUNK_MAGIC);
+            let count = chunk[11] as usize;
+            chunks.push(chunk);
+            if chunks.len() == count {
+                break;
+            }
+        }
+        assert!(chunks.len() > 3);
+        assert!(chunks.iter().all(|chunk| chunk[2..10] == chunks[0][2..10]));
+        chunks.sort_by_key(|chunk| chunk[10]);
+        let message: Vec<u8> = chunks.iter().flat_map(|chunk| chunk[CHUNK_HEADER_LEN..].to_vec()).collect();
+        let gelf: serde_json::Value = serde_json::from_slice(&message).unwrap();
+        assert_eq!(gelf["short_message"], long.as_str());
+        assert_eq!(storage.gelf().unwrap().dropped(), 0);
+    }
+
+    #[test]
+    fn test_messages_needing_too_many_chunks_are_refused() {
+        let payload = GELF_MIN_CHUNK_SIZE - CHUNK_HEADER_LEN;
+        assert_eq!(datagrams(&vec![b'x'; payload * MAX_CHUNKS], GELF_MIN_CHUNK_SIZE, [0; 8]).unwrap().len(), MAX_CHUNKS);
+        assert!(datagrams(&vec![b'x'; payload * MAX_CHUNKS + 1], GELF_MIN_CHUNK_SIZE, [0; 8]).is_none());
+    }
+}
@@ -0,0 +1,254 @@
+//! gRPC ingestion for clients that can't use the socket protocol
+//!
+//! Only compiled with the `grpc` feature. [`GrpcIngest`] serves the
+//! `LogIngest` service from `proto/logstream.proto`: a client streams
+//! [`proto::LogBatch`] messages over `SubmitLogs` and receives one
+//! [`proto::BatchAck`] per batch once its entries are queued for storage.
+//! Entries go through the same [`IngestRouter`] as those from the sockets,
+//! so a daemon's entries are stored by one worker whichever way they came.
+//! [`LogServer`] serves it when `grpc.enabled` is set.
+//!
+//! [`LogServer`]: crate::server::LogServer
+
+use crate::server::{IngestRouter, StorageBackend};
+use crate::types::{LogEntry, LogLevel};
+use crate::{LogStreamError, Result};
+use chrono::{DateTime, Utc};
+use futures_core::Stream;
+use proto::log_ingest_server::{LogIngest, LogIngestServer};
+use proto::{BatchAck, LogBatch};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status, Streaming};
+use uuid::Uuid;
+
+/// Types and client generated from `proto/logstream.proto`
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("logstream.v1");
+}
+
+/// Acks waiting to be sent on one `SubmitLogs` stream
+const ACK_QUEUE_SIZE: usize = 16;
+
+/// The `LogIngest` gRPC service, handing entries to the ingest workers
+pub struct GrpcIngest {
+    storage: Arc<StorageBackend>,
+    router: IngestRouter,
+}
+
+impl GrpcIngest {
+    /// Create the service queueing entries on `router`
+    ///
+    /// `storage` counts the entries that are rejected.
+    pub fn new(storage: Arc<StorageBackend>, router: IngestRouter) -> Self {
+        Self { storage, router }
+    }
+
+    /// Serve the service on a bound listener until shutdown
+    pub async fn serve(
+        storage: Arc<StorageBackend>,
+        router: IngestRouter,
+        listener: TcpListener,
+        mut shutdown_rx: broadcast::Receiver<()>,
+    ) -> Result<()> {
+        tonic::transport::Server::builder()
+            .add_service(LogIngestServer::new(Self::new(storage, router)))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+                let _ = shutdown_rx.recv().await;
+            })
+            .await
+            .map_err(|e| LogStreamError::Server(format!("gRPC server failed: {}", e)))
+    }
+
+    /// Queue a batch for storage, returning how many entries were queued and rejected
+    ///
+    /// Invalid entries count as rejected lines, and entries the stopped
+    /// workers can no longer take as store errors.
+    async fn queue_batch(storage: &StorageBackend, router: &IngestRouter, batch: LogBatch) -> (u32, u32) {
+        let (mut accepted, mut rejected) = (0, 0);
+        for entry in batch.entries {
+            let Ok(entry) = LogEntry::try_from(entry) else {
+                storage.record_rejected_line();
+                rejected += 1;
+                continue;
+            };
+            // Waits while the worker's queue is full, holding the client back
+            match router.send(entry).await {
+                Ok(()) => accepted += 1,
+                Err(_) => {
+                    storage.record_store_error();
+                    rejected += 1;
+                }
+            }
+        }
+        (accepted, rejected)
+    }
+}
+
+type AckStream = Pin<Box<dyn Stream<Item = std::result::Result<BatchAck, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl LogIngest for GrpcIngest {
+    type SubmitLogsStream = AckStream;
+
+    async fn submit_logs(
+        &self,
+        request: Request<Streaming<LogBatch>>,
+    ) -> std::result::Result<Response<AckStream>, Status> {
+        let mut batches = request.into_inner();
+        let storage = Arc::clone(&self.storage);
+        let router = self.router.clone();
+        let (tx, rx) = mpsc::channel(ACK_QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            let mut index = 0;
+            loop {
+                let batch = match batches.message().await {
+                    Ok(Some(batch)) => batch,
+                    Ok(None) => break,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        break;
+                    }
+                };
+                let (accepted, rejected) = Self::queue_batch(&storage, &router, batch).await;
+                let ack = BatchAck { batch: index, accepted, rejected };
+                // The client went away; nothing more will be read
+                if tx.send(Ok(ack)).await.is_err() {
+                    break;
+                }
+                index += 1;
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+impl From<proto::LogLevel> for LogLevel {
+    fn from(level: proto::LogLevel) -> Self {
+        match level {
+            proto::LogLevel::Emergency => LogLevel::Emergency,
+            proto::LogLevel::Alert => LogLevel::Alert,
+            proto::LogLevel::Critical => LogLevel::Critical,
+            proto::LogLevel::Error => LogLevel::Error,
+            proto::LogLevel::Warning => LogLevel::Warning,
+            proto::LogLevel::Notice => LogLevel::Notice,
+            proto::LogLevel::Info => LogLevel::Info,
+            proto::LogLevel::Debug => LogLevel::Debug,
+        }
+    }
+}
+
+impl From<LogLevel> for proto::LogLevel {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Emergency => proto::LogLevel::Emergency,
+            LogLevel::Alert => proto::LogLevel::Alert,
+            LogLevel::Critical => proto::LogLevel::Critical,
+            LogLevel::Error => proto::LogLevel::Error,
+            LogLevel::Warning => proto::LogLevel::Warning,
+            LogLevel::Notice => proto::LogLevel::Notice,
+            LogLevel::Info => proto::LogLevel::Info,
+            LogLevel::Debug => proto::LogLevel::Debug,
+        }
+    }
+}
+
+impl TryFrom<proto::LogEntry> for LogEntry {
+    type Error = LogStreamError;
+
+    /// Convert a received entry, filling in the id and timestamp when unset
+    fn try_from(entry: proto::LogEntry) -> Result<Self> {
+        let id = if entry.id.is_empty() {
+            Uuid::new_v4()
+        } else {
+            Uuid::parse_str(&entry.id)
+                .map_err(|e| LogStreamError::Server(format!("Invalid entry id '{}': {}", entry.id, e)))?
+        };
+        let timestamp = match entry.timestamp {
+            Some(ref timestamp) => u32::try_from(timestamp.nanos)
+                .ok()
+                .and_then(|nanos| DateTime::from_timestamp(timestamp.seconds, nanos))
+                .ok_or_else(|| LogStreamError::Server(format!("Invalid timestamp {:?}", timestamp)))?,
+            None => Utc::now(),
+        };
+        let level = proto::LogLevel::try_from(entry.level)
+            .map_err(|_| LogStreamError::Server(format!("Invalid level {}", entry.level)))?;
+
+        Ok(LogEntry {
+            id,
+            timestamp,
+            level: level.into(),
+            daemon: entry.daemon,
+            message: entry.message,
+            fields: entry.fields,
+            resource: entry.resource,
+            binary_fields: entry.binary_fields.into_iter().collect(),
+            pid: entry.pid,
+            hostname: entry.hostname.map(Arc::from),
+            schema_version: entry.schema_version.max(1),
+        })
+    }
+}
+
+impl From<&LogEntry> for proto::LogEntry {
+    fn from(entry: &LogEntry) -> Self {
+        proto::LogEntry {
+            id: entry.id.to_string(),
+            timestamp: Some(prost_types::Timestamp {
+                seconds: entry.timestamp.timestamp(),
+                nanos: entry.timestamp.timestamp_subsec_nanos() as i32,
+            }),
+            level: proto::LogLevel::from(entry.level) as i32,
+            daemon: entry.daemon.clone(),
+            message: entry.message.clone(),
+            fields: entry.fields.clone(),
+            resource: entry.resource.clone(),
+            binary_fields: entry.binary_fields.iter().cloned().collect(),
+            pid: entry.pid,
+            hostname: entry.hostname.as_deref().map(str::to_string),
+            schema_version: entry.schema_version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fields;
+
+    #[test]
+    fn test_proto_entry_round_trip() {
+        let mut entry = LogEntry::new(LogLevel::Notice, "grpc-svc".to_string(), "hello".to_string());
+        entry.fields = fields! { request_id => "abc" };
+        entry.pid = Some(42);
+        entry.hostname = Some(Arc::from("web-1"));
+
+        let converted = LogEntry::try_from(proto::LogEntry::from(&entry)).unwrap();
+        assert_eq!(converted.to_json().unwrap(), entry.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_proto_entry_defaults_and_errors() {
+        let minimal = proto::LogEntry {
+            daemon: "grpc-svc".to_string(),
+            message: "no id or time".to_string(),
+            level: proto::LogLevel::Warning as i32,
+            ..Default::default()
+        };
+        let converted = LogEntry::try_from(minimal.clone()).unwrap();
+        assert_eq!(converted.level, LogLevel::Warning);
+        assert_eq!(converted.schema_version, 1);
+        assert!(Utc::now() - converted.timestamp < chrono::Duration::seconds(5));
+
+        let bad_level = proto::LogEntry { level: 8, ..minimal.clone() };
+        assert!(LogEntry::try_from(bad_level).is_err());
+        let bad_id = proto::LogEntry { id: "not-a-uuid".to_string(), ..minimal };
+        assert!(LogEntry::try_from(bad_id).is_err());
+    }
+}
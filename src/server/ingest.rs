@@ -0,0 +1,506 @@
+//! Ingest queue between client connections and storage
+//!
+//! Entries from every connection share one queue and worker, except those
+//! of daemons with `dedicated_worker` set in their override, which get a
+//! queue and worker each so a busy daemon doesn't hold the others up.
+//!
+//! Queues are bounded by `ingest_queue_size`, or `dedicated_queue_size` for
+//! dedicated workers: once a worker holds that many entries, and as many
+//! more wait in its channel, connections sending to it wait, which stops
+//! them reading and slows their clients down.
+
+use crate::config::{ServerConfig, DEFAULT_INGEST_QUEUE_SIZE, DEFAULT_PRIORITY_THRESHOLD};
+use crate::server::StorageBackend;
+use crate::types::{LogEntry, LogLevel};
+use crate::{LogStreamError, Result};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+
+/// Daemons whose keys a heap of [`IngestQueue`] holds before out of date ones are worth clearing
+const COMPACT_MIN_DAEMONS: usize = 16;
+
+/// Entries of one daemon waiting to be stored, tagged with their arrival sequence
+#[derive(Default)]
+struct DaemonBacklog {
+    entries: VecDeque<(u64, LogEntry)>,
+    /// Sequences of the waiting entries at each level, indexed by severity
+    by_level: [VecDeque<u64>; LogLevel::ALL.len()],
+}
+
+impl DaemonBacklog {
+    /// Most severe waiting level and the sequence of its oldest entry
+    fn most_severe(&self) -> Option<(usize, u64)> {
+        self.by_level
+            .iter()
+            .enumerate()
+            .find_map(|(level, seqs)| seqs.front().map(|seq| (level, *seq)))
+    }
+
+    /// Sequence of the oldest waiting entry
+    fn oldest(&self) -> Option<u64> {
+        self.entries.front().map(|(seq, _)| *seq)
+    }
+}
+
+/// Entries received from clients and waiting to be stored
+///
+/// A daemon's entries are always stored in arrival order. While the
+/// backlog is small every entry is, too. Once it grows past the priority
+/// threshold, the daemon holding the most severe entry goes first, oldest
+/// first among equally severe ones, so an emergency only waits for the
+/// entries its own daemon sent before it.
+///
+/// Pushing and popping take logarithmic time in the number of daemons with
+/// entries waiting.
+pub struct IngestQueue {
+    /// Waiting entries of each daemon, by the id it got in `ids`
+    daemons: HashMap<u64, DaemonBacklog>,
+    /// Ids of the daemons with entries waiting
+    ids: HashMap<String, u64>,
+    next_id: u64,
+    /// `(sequence, daemon id)` of each daemon's oldest entry
+    ///
+    /// Both heaps keep keys that went out of date until they reach the top
+    /// or the heap is rebuilt, see [`IngestQueue::compact`].
+    oldest: BinaryHeap<Reverse<(u64, u64)>>,
+    /// `(severity, sequence, daemon id)` of each daemon's most severe entry
+    most_severe: BinaryHeap<Reverse<(usize, u64, u64)>>,
+    next_seq: u64,
+    len: usize,
+    priority_threshold: usize,
+    capacity: usize,
+}
+
+impl IngestQueue {
+    /// Create an empty queue holding up to `capacity` entries, prioritizing
+    /// once more than `priority_threshold` wait
+    pub fn new(priority_threshold: usize, capacity: usize) -> Self {
+        Self {
+            daemons: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+            oldest: BinaryHeap::new(),
+            most_severe: BinaryHeap::new(),
+            next_seq: 0,
+            len: 0,
+            priority_threshold,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Queue an entry
+    pub fn push(&mut self, entry: LogEntry) {
+        let (seq, level) = (self.next_seq, entry.level as usize);
+        let id = match self.ids.get(&entry.daemon) {
+            Some(id) => *id,
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                self.ids.insert(entry.daemon.clone(), id);
+                self.oldest.push(Reverse((seq, id)));
+                id
+            }
+        };
+        let backlog = self.daemons.entry(id).or_default();
+        if backlog.most_severe().is_none_or(|(most, _)| level < most) {
+            self.most_severe.push(Reverse((level, seq, id)));
+        }
+        backlog.by_level[level].push_back(seq);
+        backlog.entries.push_back((seq, entry));
+        self.next_seq += 1;
+        self.len += 1;
+        self.compact();
+    }
+
+    /// Take the next entry to store
+    pub fn pop(&mut self) -> Option<LogEntry> {
+        let id = if self.len > self.priority_threshold {
+            self.most_severe_daemon()?
+        } else {
+            // Oldest entry overall: the lowest sequence among the daemon fronts
+            self.oldest_daemon()?
+        };
+
+        let backlog = self.daemons.get_mut(&id)?;
+        let previous = backlog.most_severe();
+        let (_, entry) = backlog.entries.pop_front()?;
+        // The daemon's oldest entry is also the oldest at its level
+        backlog.by_level[entry.level as usize].pop_front();
+        match backlog.oldest() {
+            Some(oldest) => {
+                self.oldest.push(Reverse((oldest, id)));
+                if let Some((level, seq)) = backlog.most_severe().filter(|key| Some(*key) != previous) {
+                    self.most_severe.push(Reverse((level, seq, id)));
+                }
+            }
+            None => {
+                self.daemons.remove(&id);
+                self.ids.remove(&entry.daemon);
+            }
+        }
+        self.len -= 1;
+        self.compact();
+        Some(entry)
+    }
+
+    /// Daemon holding the oldest entry, dropping out of date keys on the way
+    fn oldest_daemon(&mut self) -> Option<u64> {
+        while let Some(&Reverse((seq, id))) = self.oldest.peek() {
+            if self.daemons.get(&id).and_then(DaemonBacklog::oldest) == Some(seq) {
+                return Some(id);
+            }
+            self.oldest.pop();
+        }
+        None
+    }
+
+    /// Daemon holding the most severe entry, dropping out of date keys on the way
+    fn most_severe_daemon(&mut self) -> Option<u64> {
+        while let Some(&Reverse((level, seq, id))) = self.most_severe.peek() {
+            if self.daemons.get(&id).and_then(DaemonBacklog::most_severe) == Some((level, seq)) {
+                return Some(id);
+            }
+            self.most_severe.pop();
+        }
+        None
+    }
+
+    /// Rebuild a heap that holds more out of date keys than current ones
+    ///
+    /// Only the heap `pop` reads from drops them as it goes, so the other
+    /// would otherwise grow with every entry. Rebuilding takes time linear
+    /// in the number of daemons, after at least as many keys were added.
+    fn compact(&mut self) {
+        let limit = 2 * self.daemons.len().max(COMPACT_MIN_DAEMONS);
+        if self.oldest.len() > limit {
+            self.oldest = self
+                .daemons
+                .iter()
+                .filter_map(|(id, backlog)| backlog.oldest().map(|seq| Reverse((seq, *id))))
+                .collect();
+        }
+        if self.most_severe.len() > limit {
+            self.most_severe = self
+                .daemons
+                .iter()
+                .filter_map(|(id, backlog)| backlog.most_severe().map(|(level, seq)| Reverse((level, seq, *id))))
+                .collect();
+        }
+    }
+
+    /// Number of entries waiting
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no entries are waiting
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the queue holds as many entries as it takes
+    pub fn is_full(&self) -> bool {
+        self.len >= self.capacity
+    }
+
+    /// Store entries from `rx` until every sender is gone and the queue is empty
+    ///
+    /// Entries are only taken from `rx` while the queue has room, so a full
+    /// queue leaves the channel to fill and hold senders back.
+    pub async fn run(mut self, storage: Arc<StorageBackend>, mut rx: mpsc::Receiver<LogEntry>) {
+        loop {
+            // Pull in what has arrived so new entries compete by priority
+            while !self.is_full() {
+                match rx.try_recv() {
+                    Ok(entry) => self.push(entry),
+                    Err(_) => break,
+                }
+            }
+            storage.set_queue_depth(self.len());
+
+            if let Some(entry) = self.pop() {
+                store(&storage, entry).await;
+                continue;
+            }
+
+            match rx.recv().await {
+                Some(entry) => self.push(entry),
+                None => break,
+            }
+        }
+    }
+}
+
+impl Default for IngestQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRIORITY_THRESHOLD, DEFAULT_INGEST_QUEUE_SIZE)
+    }
+}
+
+/// Hands entries to the ingest worker of their daemon
+///
+/// A daemon's entries always go to the same worker, which stores them in
+/// the order they arrived.
+#[derive(Clone)]
+pub struct IngestRouter {
+    shared: mpsc::Sender<LogEntry>,
+    dedicated: Arc<HashMap<String, mpsc::Sender<LogEntry>>>,
+}
+
+impl IngestRouter {
+    /// Route every daemon to `shared` except those in `dedicated`
+    pub fn new(shared: mpsc::Sender<LogEntry>, dedicated: HashMap<String, mpsc::Sender<LogEntry>>) -> Self {
+        Self {
+            shared,
+            dedicated: Arc::new(dedicated),
+        }
+    }
+
+    /// Spawn the shared worker and one for each daemon with a dedicated worker
+    ///
+    /// Workers stop once every clone of the router is dropped and their
+    /// queues are stored.
+    pub fn start(config: &ServerConfig, storage: &Arc<StorageBackend>) -> (Self, Vec<JoinHandle<()>>) {
+        let threshold = config.server.ingest_priority_threshold;
+        let spawn = |size: usize| {
+            let (tx, rx) = mpsc::channel(size.max(1));
+            let worker = tokio::spawn(IngestQueue::new(threshold, size).run(Arc::clone(storage), rx));
+            (tx, worker)
+        };
+
+        let (shared, shared_worker) = spawn(config.server.ingest_queue_size);
+        let mut workers = vec![shared_worker];
+        let mut dedicated = HashMap::new();
+        for (daemon, daemon_override) in &config.daemon_overrides {
+            if daemon_override.dedicated_worker {
+                let (tx, worker) = spawn(config.server.dedicated_queue_size);
+                dedicated.insert(daemon.clone(), tx);
+                workers.push(worker);
+            }
+        }
+        (Self::new(shared, dedicated), workers)
+    }
+
+    /// Queue an entry on its daemon's worker, waiting while its queue is full
+    ///
+    /// Fails with [`LogStreamError::Server`] once that worker has stopped.
+    pub async fn send(&self, entry: LogEntry) -> Result<()> {
+        self.sender(&entry)
+            .send(entry)
+            .await
+            .map_err(|_| LogStreamError::Server("Ingest worker stopped".to_string()))
+    }
+
+    /// Queue an entry on its daemon's worker unless its queue is full
+    ///
+    /// For entries the server makes itself, which must not hold up the
+    /// task reporting them.
+    pub fn try_send(&self, entry: LogEntry) -> Result<()> {
+        self.sender(&entry).try_send(entry).map_err(|e| match e {
+            TrySendError::Full(_) => LogStreamError::Server("Ingest queue full".to_string()),
+            TrySendError::Closed(_) => LogStreamError::Server("Ingest worker stopped".to_string()),
+        })
+    }
+
+    fn sender(&self, entry: &LogEntry) -> &mpsc::Sender<LogEntry> {
+        self.dedicated.get(&entry.daemon).unwrap_or(&self.shared)
+    }
+}
+
+impl From<mpsc::Sender<LogEntry>> for IngestRouter {
+    /// Route every daemon to one worker
+    fn from(shared: mpsc::Sender<LogEntry>) -> Self {
+        Self::new(shared, HashMap::new())
+    }
+}
+
+/// Store an entry, counting and logging a failure since no client waits on it
+async fn store(storage: &StorageBackend, entry: LogEntry) {
+    if let Err(e) = storage.store_entry(entry).await {
+        storage.record_store_error();
+        tracing::warn!(error = %e, "Failed to store log entry");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use tempfile::tempdir;
+
+    fn entry(level: LogLevel, message: &str) -> LogEntry {
+        LogEntry::new(level, "backlog".to_string(), message.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_pinned_daemon_routes_to_its_own_worker() {
+        let (shared_tx, mut shared_rx) = mpsc::channel(64);
+        let (hot_tx, mut hot_rx) = mpsc::channel(64);
+        let router = IngestRouter::new(shared_tx, HashMap::from([("hot".to_string(), hot_tx)]));
+
+        for i in 0..5 {
+            for daemon in ["hot", "api", "db"] {
+                router.send(LogEntry::new(LogLevel::Info, daemon.to_string(), i.to_string())).await.unwrap();
+            }
+        }
+
+        let hot: Vec<(String, String)> = std::iter::from_fn(|| hot_rx.try_recv().ok())
+            .map(|entry| (entry.daemon, entry.message))
+            .collect();
+        assert_eq!(hot, (0..5).map(|i| ("hot".to_string(), i.to_string())).collect::<Vec<_>>());
+
+        let shared: Vec<LogEntry> = std::iter::from_fn(|| shared_rx.try_recv().ok()).collect();
+        assert_eq!(shared.len(), 10);
+        for daemon in ["api", "db"] {
+            let order: Vec<&str> = shared
+                .iter()
+                .filter(|entry| entry.daemon == daemon)
+                .map(|entry| entry.message.as_str())
+                .collect();
+            assert_eq!(order, ["0", "1", "2", "3", "4"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_holds_senders_back() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let router = IngestRouter::from(tx);
+        router.send(entry(LogLevel::Info, "1")).await.unwrap();
+        router.send(entry(LogLevel::Info, "2")).await.unwrap();
+
+        // No worker takes entries, so the third send waits for room
+        let mut third = Box::pin(router.send(entry(LogLevel::Info, "3")));
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), &mut third).await.is_err());
+        assert!(router.try_send(entry(LogLevel::Info, "event")).is_err());
+        assert_eq!(rx.recv().await.unwrap().message, "1");
+        third.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_started_workers_store_every_daemon() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.server.ingest_queue_size = 4;
+        config.server.dedicated_queue_size = 64;
+        config.daemon_overrides.entry("hot".to_string()).or_default().dedicated_worker = true;
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+        let (router, workers) = IngestRouter::start(&config, &storage);
+        assert_eq!(workers.len(), 2);
+        // The pinned daemon gets the larger buffer
+        assert_eq!((router.shared.max_capacity(), router.dedicated["hot"].max_capacity()), (4, 64));
+        for i in 0..20 {
+            for daemon in ["hot", "cold"] {
+                router.send(LogEntry::new(LogLevel::Info, daemon.to_string(), i.to_string())).await.unwrap();
+            }
+        }
+        drop(router);
+        for worker in workers {
+            worker.await.unwrap();
+        }
+
+        for daemon in ["hot", "cold"] {
+            let content = tokio::fs::read_to_string(temp_dir.path().join(format!("{}.log", daemon))).await.unwrap();
+            let messages: Vec<String> = content.lines().map(|line| LogEntry::from_json(line).unwrap().message).collect();
+            assert_eq!(messages, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_small_backlog_keeps_arrival_order() {
+        let mut queue = IngestQueue::new(4, 16);
+        queue.push(entry(LogLevel::Info, "first"));
+        queue.push(entry(LogLevel::Error, "second"));
+
+        assert_eq!(queue.pop().unwrap().message, "first");
+        assert_eq!(queue.pop().unwrap().message, "second");
+        assert!(queue.pop().is_none());
+    }
+
+    /// Entry `pop` should take next from `waiting`, in arrival order, found by scanning every entry
+    fn expected_next(waiting: &[LogEntry], priority_threshold: usize) -> Option<usize> {
+        if waiting.len() <= priority_threshold {
+            return (!waiting.is_empty()).then_some(0);
+        }
+        // The most severe entry, the oldest among equals, picks the daemon
+        let most_severe = (0..waiting.len()).min_by_key(|&i| (waiting[i].level, i))?;
+        waiting.iter().position(|entry| entry.daemon == waiting[most_severe].daemon)
+    }
+
+    #[test]
+    fn test_queue_matches_a_full_scan() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for priority_threshold in [0, 8, 64] {
+            let mut queue = IngestQueue::new(priority_threshold, usize::MAX);
+            let mut waiting: Vec<LogEntry> = Vec::new();
+            for i in 0..20_000 {
+                // Mostly pushes early on so the backlog crosses the threshold both ways
+                if rng.gen_bool(if i < 10_000 { 0.6 } else { 0.4 }) {
+                    let level = LogLevel::ALL[rng.gen_range(0..LogLevel::ALL.len())];
+                    let daemon = format!("daemon-{}", rng.gen_range(0..40));
+                    let entry = LogEntry::new(level, daemon, i.to_string());
+                    waiting.push(entry.clone());
+                    queue.push(entry);
+                } else {
+                    let expected = expected_next(&waiting, priority_threshold).map(|index| waiting.remove(index));
+                    let popped = queue.pop();
+                    assert_eq!(popped.map(|entry| entry.message), expected.map(|entry| entry.message));
+                }
+                assert_eq!(queue.len(), waiting.len());
+                assert!(queue.oldest.len() <= 2 * queue.daemons.len().max(COMPACT_MIN_DAEMONS));
+                assert!(queue.most_severe.len() <= 2 * queue.daemons.len().max(COMPACT_MIN_DAEMONS));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backlog_stores_severe_daemons_first_in_their_order() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let mut stored = storage.subscribers().subscribe();
+
+        // Build a backlog while no worker is running
+        let (tx, rx) = mpsc::channel(64);
+        let backlog = [
+            ("chatty", LogLevel::Debug, "chatty debug 1"),
+            ("web", LogLevel::Info, "web info 1"),
+            ("crashing", LogLevel::Info, "crashing info 1"),
+            ("crashing", LogLevel::Emergency, "crashing emergency 1"),
+            ("db", LogLevel::Critical, "db critical 1"),
+            ("chatty", LogLevel::Debug, "chatty debug 2"),
+            ("web", LogLevel::Emergency, "web emergency 1"),
+        ];
+        for (daemon, level, message) in backlog {
+            tx.send(LogEntry::new(level, daemon.to_string(), message.to_string())).await.unwrap();
+        }
+        drop(tx);
+
+        // Resume the worker; it exits once the backlog is stored
+        IngestQueue::new(0, 16).run(storage, rx).await;
+
+        let messages: Vec<String> = std::iter::from_fn(|| stored.try_recv().ok()).map(|entry| entry.message).collect();
+        // Emergencies go first, oldest first, each after its own daemon's earlier entries
+        assert_eq!(
+            messages,
+            [
+                "crashing info 1",
+                "crashing emergency 1",
+                "web info 1",
+                "web emergency 1",
+                "db critical 1",
+                "chatty debug 1",
+                "chatty debug 2",
+            ]
+        );
+    }
+}
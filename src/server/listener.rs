@@ -0,0 +1,278 @@
+//! Sockets the server accepts log connections on
+//!
+//! Besides the main Unix socket, `server.listeners` can name more Unix
+//! sockets and TCP addresses, e.g. to keep an old socket path working during
+//! a migration. Connections from all of them feed the same storage pipeline.
+//! Under systemd socket activation the sockets passed in by systemd are used
+//! instead of binding `socket_path`.
+
+use crate::config::ListenerSpec;
+use crate::types::LogFields;
+use crate::{LogStreamError, Result};
+use std::future::poll_fn;
+use std::io;
+use std::os::fd::{FromRawFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+/// Field naming the transport an entry arrived over, `unix` or `tcp`
+pub const TRANSPORT_FIELD: &str = "_transport";
+
+/// Field holding the peer address of the TCP connection an entry arrived over
+pub const REMOTE_FIELD: &str = "_remote";
+
+/// First file descriptor systemd passes to a socket-activated service
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Set once the sockets passed in by systemd are claimed, so they are claimed only once
+static LISTEN_FDS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A bound socket accepting log connections
+#[derive(Debug)]
+pub enum Listener {
+    /// Unix domain socket
+    Unix(UnixListener),
+    /// TCP socket
+    Tcp(TcpListener),
+    /// A socket passed in by systemd socket activation, which owns its address
+    Inherited(Box<Listener>),
+    /// A listener whose next accepts fail with `EMFILE`, for tests
+    #[cfg(test)]
+    Faulty {
+        /// Listener accepting once the failures are used up
+        inner: Box<Listener>,
+        /// Accepts still to fail
+        failures: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    },
+}
+
+/// An accepted log connection
+#[derive(Debug)]
+pub enum Connection {
+    /// Connection on a Unix socket
+    Unix(UnixStream),
+    /// Connection on a TCP socket
+    Tcp(TcpStream),
+}
+
+impl Connection {
+    /// Fields describing the connection, added to its entries with `server.connection_context`
+    ///
+    /// [`TRANSPORT_FIELD`] always, and [`REMOTE_FIELD`] for TCP when the
+    /// peer address can be read. Captured once when the connection is
+    /// accepted.
+    pub fn context(&self) -> LogFields {
+        let mut context = LogFields::new();
+        match self {
+            Connection::Unix(_) => {
+                context.insert(TRANSPORT_FIELD.to_string(), "unix".to_string());
+            }
+            Connection::Tcp(stream) => {
+                context.insert(TRANSPORT_FIELD.to_string(), "tcp".to_string());
+                if let Ok(addr) = stream.peer_addr() {
+                    context.insert(REMOTE_FIELD.to_string(), addr.to_string());
+                }
+            }
+        }
+        context
+    }
+}
+
+impl Listener {
+    /// Bind the socket described by `spec`
+    ///
+    /// `reuse_socket` is passed on to [`bind_unix`] for Unix sockets.
+    pub async fn bind(spec: &ListenerSpec, reuse_socket: bool) -> Result<Self> {
+        match spec {
+            ListenerSpec::Unix(path) => bind_unix(path, reuse_socket).map(Listener::Unix),
+            ListenerSpec::Tcp(addr) => TcpListener::bind(addr)
+                .await
+                .map(Listener::Tcp)
+                .map_err(|e| LogStreamError::Server(format!("Failed to bind TCP listener {}: {}", addr, e))),
+        }
+    }
+
+    /// What the listener is bound to, for binding it again
+    ///
+    /// `None` for unnamed Unix sockets or when the address can't be read.
+    pub fn spec(&self) -> Option<ListenerSpec> {
+        match self {
+            Listener::Unix(listener) => {
+                let addr = listener.local_addr().ok()?;
+                Some(ListenerSpec::Unix(addr.as_pathname()?.to_str()?.to_string()))
+            }
+            Listener::Tcp(listener) => Some(ListenerSpec::Tcp(listener.local_addr().ok()?.to_string())),
+            Listener::Inherited(inner) => inner.spec(),
+            #[cfg(test)]
+            Listener::Faulty { inner, .. } => inner.spec(),
+        }
+    }
+
+    /// Whether the socket was passed in rather than bound by this process
+    ///
+    /// Such a socket is never bound again or unlinked by the server.
+    pub fn is_inherited(&self) -> bool {
+        match self {
+            Listener::Inherited(_) => true,
+            #[cfg(test)]
+            Listener::Faulty { inner, .. } => inner.is_inherited(),
+            _ => false,
+        }
+    }
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Connection>> {
+        match self {
+            #[cfg(test)]
+            Listener::Faulty { inner, failures } => {
+                if failures.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok() {
+                    return Poll::Ready(Err(io::Error::from_raw_os_error(libc::EMFILE)));
+                }
+                inner.poll_accept(cx)
+            }
+            Listener::Inherited(inner) => inner.poll_accept(cx),
+            Listener::Unix(listener) => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, _)| Connection::Unix(stream)),
+            Listener::Tcp(listener) => listener
+                .poll_accept(cx)
+                .map_ok(|(stream, _)| Connection::Tcp(stream)),
+        }
+    }
+}
+
+/// Bind a Unix socket at `path`
+///
+/// Missing parent directories are created. An existing file at `path` is
+/// replaced when `reuse_socket` is set and is an error otherwise.
+pub fn bind_unix(path: &str, reuse_socket: bool) -> Result<UnixListener> {
+    let socket_path = Path::new(path);
+    if let Some(parent) = socket_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| {
+            LogStreamError::Config(format!(
+                "Cannot create directory {} for socket {}: {}",
+                parent.display(),
+                socket_path.display(),
+                e
+            ))
+        })?;
+    }
+
+    if socket_path.exists() {
+        if !reuse_socket {
+            return Err(LogStreamError::Server(format!(
+                "Socket path {} already exists and server.reuse_socket is false",
+                socket_path.display()
+            )));
+        }
+        std::fs::remove_file(socket_path)?;
+    }
+
+    UnixListener::bind(socket_path)
+        .map_err(|e| LogStreamError::Server(format!("Failed to bind socket: {}", e)))
+}
+
+/// Take the listening sockets passed in by systemd socket activation
+///
+/// Returns `None` unless `LISTEN_PID` names this process and `LISTEN_FDS`
+/// is set, and on every call after the first that claimed them. The
+/// variables are left set, as changing the environment isn't safe once the
+/// runtime's threads run; child processes don't match `LISTEN_PID`. Each
+/// descriptor becomes a Unix or TCP listener according to its address
+/// family. Must be called from a Tokio runtime.
+pub fn inherited_listeners() -> Result<Option<Vec<Listener>>> {
+    let for_this_process = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let count = match std::env::var("LISTEN_FDS") {
+        Ok(count) if for_this_process => count.parse::<RawFd>().map_err(|_| {
+            LogStreamError::Config(format!("Invalid LISTEN_FDS value '{}'", count))
+        })?,
+        _ => return Ok(None),
+    };
+    // Owning a descriptor twice would close it under the first listener
+    if LISTEN_FDS_TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(None);
+    }
+
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count)
+        .map(inherited_listener)
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// Remove the socket files of the Unix listeners this process bound
+///
+/// Used on shutdown with `server.unlink_on_exit`; a file that is already
+/// gone is not an error. Inherited sockets are left to systemd.
+pub(crate) fn unlink_sockets(listeners: &[Listener]) {
+    for listener in listeners {
+        let Listener::Unix(listener) = listener else {
+            continue;
+        };
+        let path = match listener.local_addr() {
+            Ok(addr) => match addr.as_pathname() {
+                Some(path) => path.to_path_buf(),
+                None => continue,
+            },
+            Err(_) => continue,
+        };
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                eprintln!("Failed to remove socket {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn inherited_listener(fd: RawFd) -> Result<Listener> {
+    let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let rc = unsafe { libc::getsockname(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len) };
+    if rc != 0 {
+        return Err(LogStreamError::Server(format!(
+            "Inherited file descriptor {} is not a socket: {}",
+            fd,
+            io::Error::last_os_error()
+        )));
+    }
+    // Don't leak the socket into processes the server spawns
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+    match libc::c_int::from(addr.ss_family) {
+        libc::AF_UNIX => {
+            let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+            listener.set_nonblocking(true)?;
+            Ok(Listener::Inherited(Box::new(Listener::Unix(UnixListener::from_std(listener)?))))
+        }
+        libc::AF_INET | libc::AF_INET6 => {
+            let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+            listener.set_nonblocking(true)?;
+            Ok(Listener::Inherited(Box::new(Listener::Tcp(TcpListener::from_std(listener)?))))
+        }
+        family => Err(LogStreamError::Server(format!(
+            "Inherited file descriptor {} has unsupported address family {}",
+            fd, family
+        ))),
+    }
+}
+
+/// Wait for a connection on any of the listeners, along with the index of the one it came from
+///
+/// Listeners are polled in turn starting after the one that accepted last,
+/// so a busy socket can't starve the others.
+pub(crate) async fn accept_any(listeners: &[Listener], next: &mut usize) -> (usize, io::Result<Connection>) {
+    poll_fn(|cx| {
+        for offset in 0..listeners.len() {
+            let index = (*next + offset) % listeners.len();
+            if let Poll::Ready(result) = listeners[index].poll_accept(cx) {
+                *next = index + 1;
+                return Poll::Ready((index, result));
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
@@ -0,0 +1,103 @@
+//! In-memory backend keeping the latest entries in a bounded ring buffer
+//!
+//! With `backends.memory.enabled`, every stored entry is also kept in memory,
+//! up to `capacity` entries across all daemons; the oldest is evicted to make
+//! room for each new one. Nothing touches the disk, which suits tests and
+//! ephemeral deployments. When the file backend is off, [`tail`] and the
+//! `follow` command read from here.
+//!
+//! [`tail`]: crate::server::StorageBackend::tail
+
+use crate::config::{MemoryBackendSettings, DEFAULT_MEMORY_CAPACITY};
+use crate::types::LogEntry;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Ring buffer of the most recently stored entries
+pub struct MemoryBackend {
+    capacity: usize,
+    entries: Mutex<VecDeque<LogEntry>>,
+}
+
+impl MemoryBackend {
+    /// Create an empty buffer holding up to `settings.capacity` entries
+    pub fn new(settings: &MemoryBackendSettings) -> Self {
+        let capacity = settings.capacity.max(1);
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_MEMORY_CAPACITY))),
+        }
+    }
+
+    /// Keep an entry, evicting the oldest one once full
+    pub fn push(&self, entry: &LogEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry.clone());
+    }
+
+    /// The last `count` entries held for a daemon, oldest first
+    pub fn recent(&self, daemon_name: &str, count: usize) -> Vec<LogEntry> {
+        let entries = self.entries.lock().unwrap();
+        let mut recent: Vec<LogEntry> = entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.daemon == daemon_name)
+            .take(count)
+            .cloned()
+            .collect();
+        recent.reverse();
+        recent
+    }
+
+    /// Number of entries held, across all daemons
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether no entry is held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::server::StorageBackend;
+    use crate::types::LogLevel;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_memory_only_backend() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().join("logs");
+        config.backends.disable_all();
+        config.backends.memory = MemoryBackendSettings { enabled: true, capacity: 5 };
+        config.validate().unwrap();
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        for i in 0..8 {
+            let entry = LogEntry::new(LogLevel::Info, "ephemeral".to_string(), format!("entry {}", i));
+            storage.store_entry(entry).await.unwrap();
+        }
+        storage
+            .store_entry(LogEntry::new(LogLevel::Info, "other".to_string(), "unrelated".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(storage.memory().unwrap().len(), 5);
+        let tail: Vec<String> = storage.tail("ephemeral", 10).await.unwrap().into_iter().map(|e| e.message).collect();
+        assert_eq!(tail, ["entry 4", "entry 5", "entry 6", "entry 7"]);
+        let tail = storage.tail("ephemeral", 2).await.unwrap();
+        assert_eq!(tail[0].message, "entry 6");
+        assert_eq!(tail[1].message, "entry 7");
+
+        storage.write_shutdown_report().await.unwrap();
+        assert!(!temp_dir.path().join("logs").exists(), "memory backend must not create files");
+    }
+}
@@ -0,0 +1,374 @@
+//! Prometheus metrics for the LogStream server
+//!
+//! Only compiled with the `metrics` feature. Each [`StorageBackend`] owns a
+//! [`Metrics`] registry, which [`LogServer`] exposes over HTTP when
+//! `metrics.enabled` is set.
+//!
+//! [`StorageBackend`]: crate::server::StorageBackend
+//! [`LogServer`]: crate::server::LogServer
+
+use crate::build_info::BUILD_INFO;
+use crate::server::{MetricsSnapshot, RotationReason, StorageBackend};
+use crate::{LogStreamError, Result};
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+/// Path the endpoint serves when `metrics.path` is empty
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+
+/// Store latency buckets in seconds, from 50µs up to 2.5s
+const STORE_LATENCY_BUCKETS: &[f64] = &[
+    0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+    2.5,
+];
+
+/// Rotation duration buckets in seconds, from 1ms up to 60s to cover compression
+const ROTATION_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// Metrics registry and the collectors recorded by the server
+pub struct Metrics {
+    registry: Registry,
+    entry_bytes: Histogram,
+    store_latency: Histogram,
+    rejected_lines: IntCounter,
+    pattern_dropped: IntCounter,
+    open_files: IntGauge,
+    queue_depth: IntGauge,
+    daemon_entries: IntGaugeVec,
+    rotations: IntCounterVec,
+    rotation_failures: IntCounter,
+    rotation_duration: Histogram,
+}
+
+impl Metrics {
+    /// Create a registry with all server collectors registered
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+
+        // 64 bytes up to 1 MiB in powers of four
+        let entry_bytes = Histogram::with_opts(
+            HistogramOpts::new(
+                "logstream_entry_bytes",
+                "Size in bytes of log entries written to file storage",
+            )
+            .buckets(exponential_buckets(64.0, 4.0, 8).map_err(metrics_error)?),
+        )
+        .map_err(metrics_error)?;
+
+        let store_latency = Histogram::with_opts(
+            HistogramOpts::new(
+                "logstream_store_latency_seconds",
+                "Time spent writing and flushing a log entry to file storage",
+            )
+            .buckets(STORE_LATENCY_BUCKETS.to_vec()),
+        )
+        .map_err(metrics_error)?;
+
+        let rejected_lines = IntCounter::new(
+            "logstream_rejected_lines_total",
+            "Incoming lines rejected as malformed or exceeding the line limits",
+        )
+        .map_err(metrics_error)?;
+
+        let pattern_dropped = IntCounter::new(
+            "logstream_pattern_dropped_total",
+            "Entries dropped because their message matched a drop pattern",
+        )
+        .map_err(metrics_error)?;
+
+        let open_files = IntGauge::new("logstream_open_files", "Log files currently open for writing")
+            .map_err(metrics_error)?;
+
+        let queue_depth = IntGauge::new(
+            "logstream_queue_depth",
+            "Entries received but not yet stored",
+        )
+        .map_err(metrics_error)?;
+
+        let daemon_entries = IntGaugeVec::new(
+            Opts::new("logstream_daemon_entries", "Entries accepted per daemon since startup"),
+            &["daemon"],
+        )
+        .map_err(metrics_error)?;
+
+        let rotations = IntCounterVec::new(
+            Opts::new("logstream_rotations_total", "Completed rotations per daemon and reason"),
+            &["daemon", "reason"],
+        )
+        .map_err(metrics_error)?;
+
+        let rotation_failures = IntCounter::new(
+            "logstream_rotation_failures_total",
+            "Rotations that failed to archive or reopen the live file",
+        )
+        .map_err(metrics_error)?;
+
+        let rotation_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "logstream_rotation_duration_seconds",
+                "Time spent archiving a live file, including compression and pruning",
+            )
+            .buckets(ROTATION_DURATION_BUCKETS.to_vec()),
+        )
+        .map_err(metrics_error)?;
+
+        let build_info = IntGaugeVec::new(
+            Opts::new("logstream_build_info", "Always 1, labelled with the running version and git commit"),
+            &["version", "sha"],
+        )
+        .map_err(metrics_error)?;
+        build_info.with_label_values(&[BUILD_INFO.version, BUILD_INFO.git_sha]).set(1);
+
+        registry.register(Box::new(build_info)).map_err(metrics_error)?;
+        registry.register(Box::new(entry_bytes.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(store_latency.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(rejected_lines.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(pattern_dropped.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(open_files.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(queue_depth.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(daemon_entries.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(rotations.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(rotation_failures.clone())).map_err(metrics_error)?;
+        registry.register(Box::new(rotation_duration.clone())).map_err(metrics_error)?;
+
+        Ok(Self {
+            registry,
+            entry_bytes,
+            store_latency,
+            rejected_lines,
+            pattern_dropped,
+            open_files,
+            queue_depth,
+            daemon_entries,
+            rotations,
+            rotation_failures,
+            rotation_duration,
+        })
+    }
+
+    /// Registry that endpoint collectors are registered with
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Record one entry written to file storage
+    pub fn observe_store(&self, bytes: usize, latency: Duration) {
+        self.entry_bytes.observe(bytes as f64);
+        self.store_latency.observe(latency.as_secs_f64());
+    }
+
+    /// Record one rejected incoming line
+    pub fn record_rejected_line(&self) {
+        self.rejected_lines.inc();
+    }
+
+    /// Record one entry dropped by a drop pattern
+    pub fn record_pattern_dropped(&self) {
+        self.pattern_dropped.inc();
+    }
+
+    /// Record one completed rotation
+    pub fn record_rotation(&self, daemon: &str, reason: RotationReason, duration: Duration) {
+        self.rotations.with_label_values(&[daemon, reason.as_str()]).inc();
+        self.rotation_duration.observe(duration.as_secs_f64());
+    }
+
+    /// Record one failed rotation
+    pub fn record_rotation_failure(&self) {
+        self.rotation_failures.inc();
+    }
+
+    /// Set the gauges derived from a [`MetricsSnapshot`]
+    pub fn update(&self, snapshot: &MetricsSnapshot) {
+        self.open_files.set(snapshot.open_files as i64);
+        self.queue_depth.set(snapshot.queue_depth as i64);
+        for (daemon, count) in &snapshot.per_daemon {
+            self.daemon_entries.with_label_values(&[daemon]).set(*count as i64);
+        }
+    }
+
+    /// Render all registered metrics in the Prometheus text format
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(metrics_error)?;
+        String::from_utf8(buffer)
+            .map_err(|e| LogStreamError::Server(format!("Metrics are not valid UTF-8: {}", e)))
+    }
+
+    /// Serve a backend's metrics over HTTP until shutdown
+    ///
+    /// Requests for `path` get the rendered metrics, refreshed from
+    /// [`StorageBackend::metrics_snapshot`], anything else a 404.
+    pub async fn serve(
+        storage: Arc<StorageBackend>,
+        listener: TcpListener,
+        path: String,
+        mut shutdown_rx: broadcast::Receiver<()>,
+    ) {
+        let path = Arc::new(if path.is_empty() {
+            DEFAULT_METRICS_PATH.to_string()
+        } else {
+            path
+        });
+
+        loop {
+            tokio::select! {
+                result = listener.accept() => {
+                    match result {
+                        Ok((stream, _)) => {
+                            let storage = Arc::clone(&storage);
+                            let path = Arc::clone(&path);
+                            tokio::spawn(async move {
+                                if let Err(e) = Self::handle_request(&storage, stream, &path).await {
+                                    eprintln!("Metrics request error: {}", e);
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to accept metrics connection: {}", e);
+                        }
+                    }
+                }
+                _ = shutdown_rx.recv() => {
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn handle_request(storage: &StorageBackend, stream: TcpStream, path: &str) -> Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await?;
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or("");
+        let target = parts.next().unwrap_or("");
+        let target = target.split('?').next().unwrap_or(target);
+
+        let (status, body) = if method == "GET" && target == path {
+            let metrics = storage.metrics();
+            metrics.update(&storage.metrics_snapshot());
+            ("200 OK", metrics.render()?)
+        } else {
+            ("404 Not Found", String::new())
+        };
+
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+
+        let mut stream = reader.into_inner();
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+fn metrics_error(e: prometheus::Error) -> LogStreamError {
+    LogStreamError::Server(format!("Metrics error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::server::StorageBackend;
+    use crate::types::{LogEntry, LogLevel};
+    use tempfile::tempdir;
+    use tokio::io::AsyncReadExt;
+
+    async fn scrape(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_exports_store_histograms() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        for i in 0..3 {
+            let entry = LogEntry::new(LogLevel::Info, "metrics-test".to_string(), format!("message {}", i));
+            storage.store_entry(entry).await.unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let server = tokio::spawn(Metrics::serve(storage, listener, String::new(), shutdown_rx));
+
+        let response = scrape(addr, DEFAULT_METRICS_PATH).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("# TYPE logstream_entry_bytes histogram"));
+        assert!(response.contains("logstream_entry_bytes_bucket{le=\"64\"}"));
+        assert!(response.contains("logstream_entry_bytes_bucket{le=\"+Inf\"} 3"));
+        assert!(response.contains("logstream_entry_bytes_count 3"));
+        assert!(response.contains("# TYPE logstream_store_latency_seconds histogram"));
+        assert!(response.contains("logstream_store_latency_seconds_bucket{le=\"0.001\"}"));
+        assert!(response.contains("logstream_store_latency_seconds_count 3"));
+        assert!(response.contains("logstream_open_files 1"));
+        assert!(response.contains("logstream_daemon_entries{daemon=\"metrics-test\"} 3"));
+        // Prometheus writes labels sorted by name
+        assert!(response.contains(&format!(
+            "logstream_build_info{{sha=\"{}\",version=\"{}\"}} 1",
+            BUILD_INFO.git_sha, BUILD_INFO.version
+        )));
+
+        let not_found = scrape(addr, "/other").await;
+        assert!(not_found.starts_with("HTTP/1.1 404 Not Found"));
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_size_rotation_is_counted() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.storage.rotation.enabled = true;
+        config.storage.max_file_size = 256;
+
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        while storage.list_archives("rotating").await.unwrap().is_empty() {
+            let entry = LogEntry::new(LogLevel::Info, "rotating".to_string(), "x".repeat(64));
+            storage.store_entry(entry).await.unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let server = tokio::spawn(Metrics::serve(storage, listener, String::new(), shutdown_rx));
+
+        let response = scrape(addr, DEFAULT_METRICS_PATH).await;
+        assert!(response.contains("logstream_rotations_total{daemon=\"rotating\",reason=\"size\"} 1"));
+        assert!(response.contains("logstream_rotation_duration_seconds_count 1"));
+        assert!(response.contains("logstream_rotation_failures_total 0"));
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap();
+    }
+}
@@ -1,17 +1,44 @@
 //! LogStream server implementation
 
+pub mod audit;
+pub mod compress;
+pub mod console;
+pub mod dry_run;
+pub mod forward;
+pub mod gelf;
+pub mod ingest;
+pub mod listener;
+pub mod memory;
 pub mod unix_socket;
 pub mod rotation;
 pub mod storage;
+pub mod subscribe;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 
 use crate::config::ServerConfig;
 use crate::Result;
+use std::future::Future;
 use std::sync::Arc;
+use crate::types::LogEntry;
 use tokio::sync::broadcast;
 
-pub use unix_socket::UnixSocketServer;
-pub use rotation::LogRotator;
-pub use storage::StorageBackend;
+pub use audit::{AuditEvent, AUDIT_DAEMON};
+pub use console::ConsoleBackend;
+pub use dry_run::{validate_entries, EntryDecision, LineReport};
+pub use forward::Forwarder;
+pub use gelf::GelfSender;
+pub use ingest::{IngestQueue, IngestRouter};
+pub use listener::{Connection, Listener, REMOTE_FIELD, TRANSPORT_FIELD};
+pub use memory::MemoryBackend;
+pub use unix_socket::{UnixSocketServer, SERVER_DAEMON};
+pub use rotation::{LogRotator, RotationEvent, RotationHook, RotationReason};
+pub use subscribe::{CatchUp, Subscribers, SUBSCRIBER_CAPACITY};
+pub use storage::{DaemonSummary, MetricsSnapshot, StorageBackend, StorageStats, SHUTDOWN_REPORT_FILE};
 
 /// Main LogStream server that coordinates all components
 pub struct LogServer {
@@ -35,14 +62,257 @@ impl LogServer {
         })
     }
 
+    /// Ask a running server to shut down gracefully
+    ///
+    /// [`LogServer::start`] returns once open connections have closed, or
+    /// were force-closed after `shutdown_timeout_secs`, and every received
+    /// entry has been stored.
+    pub fn shutdown(&self) {
+        // Fails only when nothing is running, which needs no shutdown
+        let _ = self.shutdown_tx.send(());
+    }
+
+    /// Run `hook` after every successful rotation, see [`LogRotator::on_rotate`]
+    pub fn on_rotate(&self, hook: RotationHook) {
+        self.storage.on_rotate(hook);
+    }
+
+    /// Stream every entry stored from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<LogEntry> {
+        self.storage.subscribers().subscribe()
+    }
+
+    /// Stream the entries of one daemon stored from now on
+    ///
+    /// Only that daemon's entries are cloned into the channel, which is
+    /// cleaned up once every receiver for it is dropped.
+    pub fn subscribe_daemon(&self, daemon: &str) -> broadcast::Receiver<LogEntry> {
+        self.storage.subscribers().subscribe_daemon(daemon)
+    }
+
+    /// Current counters, per-daemon totals, rates and queue depth
+    ///
+    /// Available without the `metrics` feature; the Prometheus endpoint
+    /// exports the same data.
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.storage.metrics_snapshot()
+    }
+
     /// Start the LogStream server
     pub async fn start(&self) -> Result<()> {
+        self.bind().await?.serve().await
+    }
+
+    /// Run the server until `stop` completes, then shut down gracefully
+    ///
+    /// Returns once every received entry is stored, like [`LogServer::start`]
+    /// after [`LogServer::shutdown`].
+    pub async fn start_until<F: Future>(&self, stop: F) -> Result<()> {
+        let serve = self.start();
+        tokio::pin!(serve);
+        tokio::select! {
+            result = &mut serve => result,
+            _ = stop => {
+                self.shutdown();
+                serve.await
+            }
+        }
+    }
+
+    /// Bind the server's sockets without serving yet
+    ///
+    /// Once this returns the Unix socket and any extra `server.listeners` exist and clients can connect;
+    /// their entries are processed when [`BoundServer::serve`] runs.
+    pub async fn bind(&self) -> Result<BoundServer> {
         let unix_server = UnixSocketServer::new(
             &self.config,
             Arc::clone(&self.storage),
             self.shutdown_tx.subscribe(),
         ).await?;
+        let listeners = unix_server.bind_all().await?;
+
+        #[cfg(feature = "metrics")]
+        let metrics_listener = if self.config.metrics.enabled {
+            let listener = tokio::net::TcpListener::bind(("0.0.0.0", self.config.metrics.port))
+                .await
+                .map_err(|e| {
+                    crate::LogStreamError::Server(format!("Failed to bind metrics endpoint: {}", e))
+                })?;
+            Some(listener)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "grpc")]
+        let grpc_listener = if self.config.grpc.enabled {
+            let listener = tokio::net::TcpListener::bind(&self.config.grpc.address)
+                .await
+                .map_err(|e| {
+                    crate::LogStreamError::Server(format!("Failed to bind gRPC endpoint: {}", e))
+                })?;
+            Some(listener)
+        } else {
+            None
+        };
+
+        Ok(BoundServer {
+            config: self.config.clone(),
+            storage: Arc::clone(&self.storage),
+            shutdown_tx: self.shutdown_tx.clone(),
+            unix_server,
+            listeners,
+            #[cfg(feature = "metrics")]
+            metrics_listener,
+            #[cfg(feature = "grpc")]
+            grpc_listener,
+        })
+    }
+}
+
+/// Wait for a shutdown signal, yielding its name
+///
+/// On Unix this is SIGINT or SIGTERM, the signal service managers such as
+/// systemd and Kubernetes send; elsewhere only Ctrl+C. The handlers are
+/// installed by this call, so a signal arriving before the returned future
+/// is first polled is not lost.
+pub fn shutdown_signal() -> Result<impl Future<Output = &'static str>> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        Ok(async move {
+            tokio::select! {
+                _ = interrupt.recv() => "SIGINT",
+                _ = terminate.recv() => "SIGTERM",
+            }
+        })
+    }
+
+    #[cfg(not(unix))]
+    {
+        Ok(async {
+            // Fails only if the handler can't be installed, leaving nothing to wait for
+            if tokio::signal::ctrl_c().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            "Ctrl+C"
+        })
+    }
+}
+
+/// A [`LogServer`] whose sockets are bound, returned by [`LogServer::bind`]
+pub struct BoundServer {
+    config: ServerConfig,
+    storage: Arc<StorageBackend>,
+    shutdown_tx: broadcast::Sender<()>,
+    unix_server: UnixSocketServer,
+    listeners: Vec<Listener>,
+    #[cfg(feature = "metrics")]
+    metrics_listener: Option<tokio::net::TcpListener>,
+    #[cfg(feature = "grpc")]
+    grpc_listener: Option<tokio::net::TcpListener>,
+}
+
+impl BoundServer {
+    /// Start the background tasks and accept connections until shutdown
+    ///
+    /// On shutdown a summary of the run is logged and appended to
+    /// [`SHUTDOWN_REPORT_FILE`] in the output directory. That includes a
+    /// fatal accept error, which stops the background tasks before the error
+    /// is returned.
+    pub async fn serve(self) -> Result<()> {
+        let storage = Arc::clone(&self.storage);
+        let shutdown_rx = self.shutdown_tx.subscribe();
+        tokio::spawn(async move {
+            storage.start_summary_task(shutdown_rx).await;
+        });
+
+        #[cfg(feature = "metrics")]
+        if let Some(listener) = self.metrics_listener {
+            let storage = Arc::clone(&self.storage);
+            let path = self.config.metrics.path.clone();
+            tokio::spawn(metrics::Metrics::serve(storage, listener, path, self.shutdown_tx.subscribe()));
+        }
+
+        let rotator = LogRotator::new(&self.config, Arc::clone(&self.storage)).await?;
+        let shutdown_rx = self.shutdown_tx.subscribe();
+        tokio::spawn(async move {
+            rotator.start_rotation_task(shutdown_rx).await;
+        });
+
+        // Shared by the socket and gRPC ingestion, so a daemon's entries have one worker
+        let (router, workers) = IngestRouter::start(&self.config, &self.storage);
+
+        #[cfg(feature = "grpc")]
+        let grpc_server = self.grpc_listener.map(|listener| {
+            let storage = Arc::clone(&self.storage);
+            let router = router.clone();
+            tokio::spawn(grpc::GrpcIngest::serve(storage, router, listener, self.shutdown_tx.subscribe()))
+        });
+
+        let served = self.unix_server.serve_routed(self.listeners, router).await;
+        if served.is_err() {
+            // The accept loop is gone, so the other tasks stop as on a signal
+            let _ = self.shutdown_tx.send(());
+        }
+
+        // Batches still being queued count towards the shutdown report
+        #[cfg(feature = "grpc")]
+        if let Some(grpc_server) = grpc_server {
+            match grpc_server.await {
+                Ok(Err(e)) => eprintln!("{}", e),
+                Err(e) => eprintln!("gRPC server task failed: {}", e),
+                Ok(Ok(())) => {}
+            }
+        }
+
+        // Workers stop once every router is gone, after storing what they received
+        for worker in workers {
+            let _ = worker.await;
+        }
+
+        // Every entry is stored by now, so the report holds the final counts
+        if let Err(e) = self.storage.write_shutdown_report().await {
+            eprintln!("Failed to write shutdown report: {}", e);
+        }
+        served
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn test_fatal_accept_error_still_shuts_down_and_reports() {
+        let temp_dir = tempdir().unwrap();
+        let socket_dir = temp_dir.path().join("run");
+        std::fs::create_dir(&socket_dir).unwrap();
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_dir.join("doomed.sock").to_string_lossy().to_string();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+
+        let server = LogServer::new(config).await.unwrap();
+        let mut bound = server.bind().await.unwrap();
+        let failing = bound.listeners.remove(0);
+        bound.listeners.push(Listener::Faulty {
+            inner: Box::new(failing),
+            failures: Arc::new(std::sync::atomic::AtomicUsize::new(usize::MAX)),
+        });
+        // Rebinding can't recreate the socket's directory
+        std::fs::remove_dir_all(&socket_dir).unwrap();
+        std::fs::write(&socket_dir, "").unwrap();
+        let mut shutdown_rx = server.shutdown_tx.subscribe();
 
-        unix_server.start().await
+        let result = timeout(Duration::from_secs(5), bound.serve()).await.unwrap();
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("No listener left"), "{}", err);
+        assert!(shutdown_rx.try_recv().is_ok(), "background tasks were not told to stop");
+        let report = std::fs::read_to_string(temp_dir.path().join(SHUTDOWN_REPORT_FILE)).unwrap();
+        assert!(report.contains("\"event\":\"shutdown\""), "{}", report);
     }
 }
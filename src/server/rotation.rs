@@ -3,35 +3,102 @@
 use crate::config::ServerConfig;
 use crate::server::StorageBackend;
 use crate::Result;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::broadcast;
 use tokio::time::{interval, Duration};
 
+/// A completed rotation, passed to hooks registered with [`LogRotator::on_rotate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotationEvent {
+    /// Daemon whose live file was rotated
+    pub daemon: String,
+    /// Where the rotated file was archived
+    pub archive_path: PathBuf,
+    /// Size of the archive in bytes
+    pub size_bytes: u64,
+    /// What triggered the rotation
+    pub reason: RotationReason,
+}
+
+/// Why a live file was rotated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RotationReason {
+    /// The file reached `storage.max_file_size`
+    Size,
+    /// The file outlived `rotation.max_age_hours`
+    Age,
+    /// The daemon's `rotation_cron` schedule fired
+    Cron,
+    /// Requested through [`StorageBackend::rotate`]
+    Manual,
+}
+
+impl RotationReason {
+    /// Label used for the reason in metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RotationReason::Size => "size",
+            RotationReason::Age => "age",
+            RotationReason::Cron => "cron",
+            RotationReason::Manual => "manual",
+        }
+    }
+}
+
+/// Callback run after each successful rotation
+pub type RotationHook = Box<dyn Fn(RotationEvent) + Send + Sync>;
+
 /// Log rotation manager
 pub struct LogRotator {
     config: ServerConfig,
+    storage: Arc<StorageBackend>,
 }
 
 impl LogRotator {
     /// Create a new log rotator
-    pub async fn new(config: &ServerConfig, _storage: Arc<StorageBackend>) -> Result<Self> {
+    pub async fn new(config: &ServerConfig, storage: Arc<StorageBackend>) -> Result<Self> {
         Ok(Self {
             config: config.clone(),
+            storage,
         })
     }
 
+    /// Run `hook` after every successful rotation, e.g. to upload or index the archive
+    ///
+    /// Applies to every rotation by the shared [`StorageBackend`], whether
+    /// size-based, age-based or manual. Hooks run on Tokio's blocking pool,
+    /// so a slow hook delays neither storage nor other hooks.
+    pub fn on_rotate(&self, hook: RotationHook) {
+        self.storage.on_rotate(hook);
+    }
+
+    /// Run one age- and schedule-based rotation pass, returning the number of files rotated
+    ///
+    /// Archives past `retention_days` are deleted in the same pass.
+    pub async fn run_once(&self) -> Result<usize> {
+        let expired = self.storage.rotate_expired().await?;
+        let rotated = expired + self.storage.rotate_scheduled().await?;
+        self.storage.remove_expired_archives().await?;
+        Ok(rotated)
+    }
+
     /// Start the log rotation task
     pub async fn start_rotation_task(&self, mut shutdown_rx: broadcast::Receiver<()>) {
-        if !self.config.storage.rotation.enabled {
+        if !self.config.storage.rotation.enabled && self.config.storage.retention_days.is_none() {
             return;
         }
 
-        let mut rotation_interval = interval(Duration::from_secs(3600));
+        // Cron schedules have minute resolution, so check each minute when there are any
+        let period = if self.storage.has_rotation_schedules() { 60 } else { 3600 };
+        let mut rotation_interval = interval(Duration::from_secs(period));
 
         loop {
             tokio::select! {
                 _ = rotation_interval.tick() => {
-                    // Rotation logic would go here
+                    if let Err(e) = self.run_once().await {
+                        eprintln!("Log rotation failed: {}", e);
+                    }
                 }
                 _ = shutdown_rx.recv() => {
                     break;
@@ -44,6 +111,8 @@ impl LogRotator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{Clock, LogEntry, LogLevel, MockClock};
+    use chrono::{TimeZone, Utc};
     use tempfile::tempdir;
     use tokio::time::timeout;
 
@@ -71,6 +140,151 @@ mod tests {
         assert_eq!(rotator.config.storage.rotation.keep_files, 10);
     }
 
+    #[tokio::test]
+    async fn test_age_based_rotation_with_mock_clock() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(true).await;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.storage.rotation.max_age_hours = 24;
+        let clock = Arc::new(MockClock::default());
+        let storage = Arc::new(StorageBackend::with_clock(&config, clock.clone()).await.unwrap());
+        let rotator = LogRotator::new(&config, storage.clone()).await.unwrap();
+
+        let entry = LogEntry::new(LogLevel::Info, "aged".to_string(), "old entry".to_string());
+        storage.store_entry(entry).await.unwrap();
+
+        // Not old enough yet
+        clock.advance(Duration::from_secs(23 * 3600));
+        assert_eq!(rotator.run_once().await.unwrap(), 0);
+        assert!(storage.list_archives("aged").await.unwrap().is_empty());
+
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(rotator.run_once().await.unwrap(), 1);
+
+        let archives = storage.list_archives("aged").await.unwrap();
+        assert_eq!(archives.len(), 1);
+        let archived = tokio::fs::read_to_string(&archives[0]).await.unwrap();
+        assert!(archived.contains("old entry"));
+
+        let live = config.storage.output_directory.join("aged.log");
+        assert_eq!(tokio::fs::read_to_string(live).await.unwrap(), "");
+
+        // The reopened file starts a fresh age window
+        assert_eq!(rotator.run_once().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cron_rotation_with_mock_clock() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(true).await;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.storage.rotation.max_age_hours = 0;
+        config.daemon_overrides.insert(
+            "nightly".to_string(),
+            crate::config::DaemonOverride {
+                rotation_cron: Some("0 0 * * *".to_string()),
+                ..Default::default()
+            },
+        );
+        let clock = Arc::new(MockClock::new(Utc.with_ymd_and_hms(2024, 6, 1, 23, 58, 30).unwrap()));
+        let storage = Arc::new(StorageBackend::with_clock(&config, clock.clone()).await.unwrap());
+        let rotator = LogRotator::new(&config, storage.clone()).await.unwrap();
+
+        for daemon in ["nightly", "other"] {
+            let entry = LogEntry::new(LogLevel::Info, daemon.to_string(), "small entry".to_string());
+            storage.store_entry(entry).await.unwrap();
+        }
+
+        // A minute before midnight nothing is due
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(rotator.run_once().await.unwrap(), 0);
+
+        // At midnight the scheduled daemon rotates, however small its file
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(clock.now_utc(), Utc.with_ymd_and_hms(2024, 6, 2, 0, 0, 0).unwrap());
+        assert_eq!(rotator.run_once().await.unwrap(), 1);
+        let archives = storage.list_archives("nightly").await.unwrap();
+        assert_eq!(archives.len(), 1);
+        assert!(archives[0].to_string_lossy().contains("20240602-000000"));
+        assert!(storage.list_archives("other").await.unwrap().is_empty());
+
+        // Not again until the next midnight
+        clock.advance(Duration::from_secs(23 * 3600));
+        assert_eq!(rotator.run_once().await.unwrap(), 0);
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(rotator.run_once().await.unwrap(), 1);
+        assert_eq!(storage.list_archives("nightly").await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_rotation_cron_fails_validation() {
+        let mut config = ServerConfig::default();
+        config.daemon_overrides.insert(
+            "nightly".to_string(),
+            crate::config::DaemonOverride {
+                rotation_cron: Some("0 0 * *".to_string()),
+                ..Default::default()
+            },
+        );
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("nightly"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_on_rotate_receives_archive_path() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(true).await;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let rotator = LogRotator::new(&config, storage.clone()).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        rotator.on_rotate(Box::new(move |event| {
+            let _ = tx.send(event);
+        }));
+
+        let entry = LogEntry::new(LogLevel::Info, "hooked".to_string(), "before rotation".to_string());
+        storage.store_entry(entry).await.unwrap();
+        let archive_path = storage.rotate("hooked").await.unwrap().unwrap();
+
+        let event = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(event.archive_path, archive_path);
+        assert_eq!(event.daemon, "hooked");
+        assert_eq!(event.size_bytes, tokio::fs::metadata(&archive_path).await.unwrap().len());
+        assert!(event.size_bytes > 0);
+        assert_eq!(event.reason, RotationReason::Manual);
+    }
+
+    #[tokio::test]
+    async fn test_retention_deletes_only_old_archives() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(true).await;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.storage.retention_days = Some(30);
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let rotator = LogRotator::new(&config, storage.clone()).await.unwrap();
+
+        let mut archives = Vec::new();
+        for message in ["old", "recent", "live"] {
+            let entry = LogEntry::new(LogLevel::Info, "kept".to_string(), message.to_string());
+            storage.store_entry(entry).await.unwrap();
+            if message != "live" {
+                archives.push(storage.rotate("kept").await.unwrap().unwrap());
+            }
+        }
+        let live = temp_dir.path().join("kept.log");
+        let aged = std::time::SystemTime::now() - Duration::from_secs(40 * 24 * 3600);
+        for path in [&archives[0], &live] {
+            std::fs::File::options().append(true).open(path).unwrap().set_modified(aged).unwrap();
+        }
+
+        assert_eq!(rotator.run_once().await.unwrap(), 0);
+        assert!(!archives[0].exists());
+        assert_eq!(storage.list_archives("kept").await.unwrap(), archives[1..].to_vec());
+        // However old, the live file stays
+        assert!(tokio::fs::read_to_string(&live).await.unwrap().contains("live"));
+    }
+
     #[tokio::test]
     async fn test_rotation_disabled() {
         let config = create_test_config(false).await;
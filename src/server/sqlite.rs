@@ -0,0 +1,204 @@
+//! SQLite storage backend for LogStream
+//!
+//! Only compiled with the `sqlite` feature. When `backends.sqlite.enabled` is
+//! set, [`StorageBackend`] writes every stored entry into an `entries` table
+//! so logs can be queried with SQL instead of parsing files.
+//!
+//! [`StorageBackend`]: crate::server::StorageBackend
+
+use crate::types::{LogEntry, LogFields, LogLevel, LOG_ENTRY_SCHEMA_VERSION};
+use crate::{LogStreamError, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, Row};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS entries (
+        id        TEXT PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        level     INTEGER NOT NULL,
+        daemon    TEXT NOT NULL,
+        message   TEXT NOT NULL,
+        fields    TEXT NOT NULL,
+        pid       INTEGER,
+        hostname  TEXT
+    );
+    CREATE INDEX IF NOT EXISTS entries_daemon ON entries (daemon);
+    CREATE INDEX IF NOT EXISTS entries_level ON entries (level);
+    CREATE INDEX IF NOT EXISTS entries_timestamp ON entries (timestamp);
+";
+
+/// Log entries stored in an SQLite database
+///
+/// Levels are stored as their syslog severity and timestamps as RFC 3339
+/// UTC strings, so both sort and compare correctly in SQL. Fields are stored
+/// as a JSON object.
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    /// Open or create the database at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(sqlite_error)?;
+        conn.execute_batch(SCHEMA).map_err(sqlite_error)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Insert one entry
+    pub async fn insert(&self, entry: &LogEntry) -> Result<()> {
+        let conn = Arc::clone(&self.conn);
+        let id = entry.id.to_string();
+        let timestamp = entry.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true);
+        let level = entry.level as i64;
+        let daemon = entry.daemon.clone();
+        let message = entry.message.clone();
+        let fields = serde_json::to_string(&entry.fields)?;
+        let pid = entry.pid;
+        let hostname = entry.hostname.as_deref().map(str::to_string);
+
+        // rusqlite is blocking, so keep it off the async worker threads
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            conn.execute(
+                "INSERT OR IGNORE INTO entries
+                     (id, timestamp, level, daemon, message, fields, pid, hostname)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![id, timestamp, level, daemon, message, fields, pid, hostname],
+            )
+            .map_err(sqlite_error)
+        })
+        .await
+        .map_err(|e| LogStreamError::Server(format!("SQLite task failed: {}", e)))??;
+
+        Ok(())
+    }
+
+    /// Entries matching `daemon` and `level`, oldest first
+    ///
+    /// A `None` filter matches every value.
+    pub async fn query(&self, daemon: Option<&str>, level: Option<LogLevel>) -> Result<Vec<LogEntry>> {
+        let conn = Arc::clone(&self.conn);
+        let daemon = daemon.map(str::to_string);
+        let level = level.map(|l| l as i64);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, timestamp, level, daemon, message, fields, pid, hostname
+                     FROM entries
+                     WHERE (?1 IS NULL OR daemon = ?1) AND (?2 IS NULL OR level = ?2)
+                     ORDER BY timestamp, rowid",
+                )
+                .map_err(sqlite_error)?;
+            let rows = stmt
+                .query_map(params![daemon, level], read_row)
+                .map_err(sqlite_error)?;
+
+            let mut entries = Vec::new();
+            for row in rows {
+                entries.push(row.map_err(sqlite_error)??);
+            }
+            Ok(entries)
+        })
+        .await
+        .map_err(|e| LogStreamError::Server(format!("SQLite task failed: {}", e)))?
+    }
+}
+
+/// Rebuild an entry from a row of the `entries` table
+fn read_row(row: &Row<'_>) -> rusqlite::Result<Result<LogEntry>> {
+    let id: String = row.get(0)?;
+    let timestamp: String = row.get(1)?;
+    let level: i64 = row.get(2)?;
+    let daemon: String = row.get(3)?;
+    let message: String = row.get(4)?;
+    let fields: String = row.get(5)?;
+    let pid: Option<u32> = row.get(6)?;
+    let hostname: Option<String> = row.get(7)?;
+
+    let decode = || -> Result<LogEntry> {
+        let id = Uuid::parse_str(&id)
+            .map_err(|e| LogStreamError::Server(format!("Invalid entry id {}: {}", id, e)))?;
+        let timestamp = DateTime::parse_from_rfc3339(&timestamp)
+            .map_err(|e| LogStreamError::Server(format!("Invalid timestamp {}: {}", timestamp, e)))?
+            .with_timezone(&Utc);
+        let level = usize::try_from(level)
+            .ok()
+            .and_then(|l| LogLevel::ALL.get(l).copied())
+            .ok_or_else(|| LogStreamError::Server(format!("Invalid level {}", level)))?;
+        let fields: LogFields = serde_json::from_str(&fields)?;
+
+        Ok(LogEntry {
+            id,
+            timestamp,
+            level,
+            daemon,
+            message,
+            fields,
+            resource: LogFields::new(),
+            binary_fields: Default::default(),
+            pid,
+            hostname: hostname.map(Into::into),
+            schema_version: LOG_ENTRY_SCHEMA_VERSION,
+        })
+    };
+    Ok(decode())
+}
+
+fn sqlite_error(e: rusqlite::Error) -> LogStreamError {
+    LogStreamError::Server(format!("SQLite error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::server::StorageBackend;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_entries_queryable_by_daemon_and_level() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.backends.file.enabled = false;
+        config.backends.sqlite.enabled = true;
+        config.backends.sqlite.path = temp_dir.path().join("logs.db");
+
+        let storage = StorageBackend::new(&config).await.unwrap();
+        let mut failure = LogEntry::new(LogLevel::Error, "web".to_string(), "upstream failed".to_string());
+        failure.fields.insert("status".to_string(), "502".to_string());
+        failure.pid = Some(4242);
+        storage.store_entry(failure.clone()).await.unwrap();
+        storage
+            .store_entry(LogEntry::new(LogLevel::Info, "web".to_string(), "request served".to_string()))
+            .await
+            .unwrap();
+        storage
+            .store_entry(LogEntry::new(LogLevel::Error, "db".to_string(), "deadlock".to_string()))
+            .await
+            .unwrap();
+
+        let sqlite = storage.sqlite().unwrap();
+        assert_eq!(sqlite.query(None, None).await.unwrap().len(), 3);
+        assert_eq!(sqlite.query(Some("web"), None).await.unwrap().len(), 2);
+        assert_eq!(sqlite.query(None, Some(LogLevel::Error)).await.unwrap().len(), 2);
+
+        let found = sqlite.query(Some("web"), Some(LogLevel::Error)).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, failure.id);
+        assert_eq!(found[0].message, "upstream failed");
+        assert_eq!(found[0].fields.get("status").map(String::as_str), Some("502"));
+        assert_eq!(found[0].pid, Some(4242));
+        assert_eq!(found[0].timestamp.timestamp_micros(), failure.timestamp.timestamp_micros());
+    }
+}
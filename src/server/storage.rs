@@ -1,107 +1,1943 @@
 //! Storage backend implementation for LogStream
 
-use crate::config::ServerConfig;
-use crate::types::LogEntry;
-use crate::Result;
+use crate::build_info::{BuildInfo, BUILD_INFO};
+use crate::config::{CronSchedule, DiskFullPolicy, FsyncPolicy, MonotonicPolicy, ServerConfig};
+use crate::server::compress;
+use crate::server::console::ConsoleBackend;
+use crate::server::dry_run::EntryDecision;
+use crate::server::forward::Forwarder;
+use crate::server::gelf::GelfSender;
+use crate::server::memory::MemoryBackend;
+use crate::server::rotation::{RotationEvent, RotationHook, RotationReason};
+use crate::server::subscribe::{CatchUp, Subscribers, SUBSCRIBER_CAPACITY};
+#[cfg(feature = "metrics")]
+use crate::server::metrics::Metrics;
+#[cfg(feature = "sqlite")]
+use crate::server::sqlite::SqliteStorage;
+use crate::types::{Clock, LogEntry, LogLevel, SystemClock, LOG_ENTRY_SCHEMA_VERSION};
+use crate::{LogStreamError, Result};
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use regex::RegexSet;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock as StdRwLock};
+use std::time::Instant;
 use tokio::fs::OpenOptions;
-use tokio::io::{AsyncWriteExt, BufWriter};
-use tokio::sync::RwLock;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::{interval, Duration};
+use tokio_util::sync::CancellationToken;
+
+/// Per-level entry counts for one daemon over a summary window
+#[derive(Debug, Clone, Serialize)]
+pub struct DaemonSummary {
+    /// Time the summary window was closed
+    pub timestamp: DateTime<Utc>,
+    /// Daemon the counts belong to
+    pub daemon: String,
+    /// Total entries in the window
+    pub total: u64,
+    /// Entry counts keyed by level name
+    pub counts: BTreeMap<String, u64>,
+}
+
+/// Snapshot of the entry counters kept by a [`StorageBackend`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StorageStats {
+    /// Entries that passed filtering and were handed to the enabled sinks
+    pub entries_accepted: u64,
+    /// Incoming lines rejected as malformed or exceeding limits
+    pub rejected_lines: u64,
+    /// Entries dropped because their message matched a drop pattern
+    pub pattern_dropped: u64,
+    /// Entries dropped because the disk was full
+    pub disk_full_dropped: u64,
+    /// Entries dropped because no process was reading a FIFO log file
+    pub pipe_dropped: u64,
+    /// Entries received from clients that failed to store, e.g. on a write error
+    pub store_errors: u64,
+}
+
+/// Point-in-time view of the server's counters, returned by [`StorageBackend::metrics_snapshot`]
+///
+/// Holds the same data the Prometheus endpoint exports, for embedding code
+/// that wants to poll it directly.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    /// Entry totals since startup
+    pub totals: StorageStats,
+    /// Accepted entries per daemon since startup
+    pub per_daemon: BTreeMap<String, u64>,
+    /// Seconds since the backend was created
+    pub uptime_secs: f64,
+    /// Average accepted entries per second since startup
+    pub entries_per_sec: f64,
+    /// Log files currently open for writing
+    pub open_files: usize,
+    /// Entries received but not yet stored
+    pub queue_depth: usize,
+    /// Bytes written to log files since startup
+    pub bytes_written: u64,
+    /// Log files rotated since startup
+    pub rotations: u64,
+    /// Version and commit of the running server
+    pub build: BuildInfo,
+}
+
+/// Delay between write attempts while `on_disk_full` is `Block`
+const DISK_FULL_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long entries for a FIFO without a reader are dropped before reopening it
+const PIPE_REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bytes read at a time, from the end, when tailing a log file
+const TAIL_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// File in the output directory the shutdown report is appended to
+pub const SHUTDOWN_REPORT_FILE: &str = "logstream-server.log";
+
+/// Final record of a server run, written by [`StorageBackend::write_shutdown_report`]
+#[derive(Debug, Serialize)]
+struct ShutdownReport<'a> {
+    timestamp: DateTime<Utc>,
+    event: &'static str,
+    #[serde(flatten)]
+    snapshot: &'a MetricsSnapshot,
+}
+
+/// Where a log file's records are written
+///
+/// The file itself; tests wrap it to fail writes as if the disk were full.
+trait RecordSink: AsyncWrite + Send + Sync + Unpin {
+    /// The open log file, for syncing and truncating
+    fn file(&self) -> &tokio::fs::File;
+}
+
+impl RecordSink for tokio::fs::File {
+    fn file(&self) -> &tokio::fs::File {
+        self
+    }
+}
+
+/// Open log file for a daemon along with its rotation bookkeeping
+struct LogFileWriter {
+    /// Records are written in one piece and flushed each, so nothing is buffered here
+    writer: Box<dyn RecordSink>,
+    opened_at: DateTime<Utc>,
+    /// Monotonic time of opening, so the wall clock stepping doesn't skew age rotation
+    opened_instant: Instant,
+    bytes_written: u64,
+    /// The log file is a named pipe, which is never rotated
+    fifo: bool,
+}
+
+impl LogFileWriter {
+    /// Write a whole record and flush it out to the file
+    async fn write_record(&mut self, record: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(record).await?;
+        self.writer.flush().await
+    }
+
+    /// Cut off whatever part of a failed record reached the file
+    ///
+    /// Every earlier record was flushed whole, so the file ends at
+    /// `bytes_written`; anything other processes appended since goes too.
+    async fn discard_partial(&mut self) -> std::io::Result<()> {
+        if self.fifo {
+            return Ok(());
+        }
+        // Wait out a write still in flight; it failed or is cut off below
+        let _ = self.writer.flush().await;
+        let file = self.writer.file();
+        if file.metadata().await?.len() > self.bytes_written {
+            file.set_len(self.bytes_written).await?;
+        }
+        Ok(())
+    }
+}
 
 /// Storage backend for managing log files
 pub struct StorageBackend {
     config: ServerConfig,
-    file_writers: Arc<DashMap<String, Arc<RwLock<BufWriter<tokio::fs::File>>>>>,
+    clock: Arc<dyn Clock>,
+    /// Open writers keyed by file stem (`{daemon}` or `{daemon}-{partition}`)
+    file_writers: Arc<DashMap<String, Arc<RwLock<LogFileWriter>>>>,
+    /// File stem of the partition currently open for each daemon
+    current_partitions: DashMap<String, String>,
+    summary_counts: DashMap<String, [u64; 8]>,
+    entries_accepted: AtomicU64,
+    /// Accepted entries per daemon since startup
+    daemon_counts: DashMap<String, u64>,
+    /// Latest timestamp stored per daemon, tracked for `enforce_monotonic`
+    last_timestamps: DashMap<String, DateTime<Utc>>,
+    /// Entries waiting in the ingest queue, as last reported by its worker
+    queue_depth: AtomicUsize,
+    bytes_written: AtomicU64,
+    rotations: AtomicU64,
+    started: Instant,
+    drop_patterns: Option<RegexSet>,
+    pattern_dropped: AtomicU64,
+    disk_full_dropped: AtomicU64,
+    /// File stems whose FIFO lost its reader, with when it was last opened or tried
+    disconnected_pipes: DashMap<String, Instant>,
+    pipe_dropped: AtomicU64,
+    store_errors: AtomicU64,
+    /// Cancelled on shutdown, so entries held for disk space stop waiting
+    shutting_down: CancellationToken,
+    rejected_lines: AtomicU64,
+    sync_calls: AtomicU64,
+    /// Set once an entry with a newer schema version has been reported
+    unknown_schema_warned: AtomicBool,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<Metrics>,
+    console: Option<ConsoleBackend>,
+    forwarder: Option<Forwarder>,
+    memory: Option<MemoryBackend>,
+    gelf: Option<GelfSender>,
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<SqliteStorage>,
+    rotation_hooks: StdRwLock<Vec<Arc<RotationHook>>>,
+    /// Parsed `rotation_cron` schedules keyed by daemon name
+    rotation_schedules: HashMap<String, CronSchedule>,
+    subscribers: Subscribers,
 }
 
 impl StorageBackend {
     /// Create a new storage backend
     pub async fn new(config: &ServerConfig) -> Result<Self> {
+        Self::with_clock(config, Arc::new(SystemClock)).await
+    }
+
+    /// Create a storage backend that only judges entries, for [`StorageBackend::dry_run`]
+    ///
+    /// Backends that open files or connections on startup (SQLite,
+    /// forwarding, GELF and the console) aren't started, though entries
+    /// are still reported as going to them.
+    pub async fn for_dry_run(config: &ServerConfig) -> Result<Self> {
+        let mut offline = config.clone();
+        offline.backends.sqlite.enabled = false;
+        offline.backends.forward.enabled = false;
+        offline.backends.gelf.enabled = false;
+        offline.backends.console.enabled = false;
+        let mut storage = Self::new(&offline).await?;
+        storage.config = config.clone();
+        Ok(storage)
+    }
+
+    /// Create a new storage backend reading time from the given clock
+    pub async fn with_clock(config: &ServerConfig, clock: Arc<dyn Clock>) -> Result<Self> {
         let file_writers = Arc::new(DashMap::new());
+        let drop_patterns = if config.server.drop_patterns.is_empty() {
+            None
+        } else {
+            Some(RegexSet::new(&config.server.drop_patterns).map_err(|e| {
+                LogStreamError::Config(format!("Invalid drop pattern: {}", e))
+            })?)
+        };
         Ok(Self {
             config: config.clone(),
+            clock,
             file_writers,
+            current_partitions: DashMap::new(),
+            summary_counts: DashMap::new(),
+            entries_accepted: AtomicU64::new(0),
+            daemon_counts: DashMap::new(),
+            last_timestamps: DashMap::new(),
+            queue_depth: AtomicUsize::new(0),
+            bytes_written: AtomicU64::new(0),
+            rotations: AtomicU64::new(0),
+            started: Instant::now(),
+            drop_patterns,
+            pattern_dropped: AtomicU64::new(0),
+            disk_full_dropped: AtomicU64::new(0),
+            disconnected_pipes: DashMap::new(),
+            pipe_dropped: AtomicU64::new(0),
+            store_errors: AtomicU64::new(0),
+            shutting_down: CancellationToken::new(),
+            rejected_lines: AtomicU64::new(0),
+            sync_calls: AtomicU64::new(0),
+            unknown_schema_warned: AtomicBool::new(false),
+            #[cfg(feature = "metrics")]
+            metrics: Arc::new(Metrics::new()?),
+            console: if config.backends.console.enabled {
+                Some(ConsoleBackend::new(
+                    &config.backends.console,
+                    config.backends.file.human_max_field_len,
+                )?)
+            } else {
+                None
+            },
+            forwarder: if config.backends.forward.enabled {
+                Some(Forwarder::start(&config.backends.forward)?)
+            } else {
+                None
+            },
+            memory: config.backends.memory.enabled.then(|| MemoryBackend::new(&config.backends.memory)),
+            gelf: if config.backends.gelf.enabled {
+                Some(GelfSender::connect(&config.backends.gelf).await?)
+            } else {
+                None
+            },
+            #[cfg(feature = "sqlite")]
+            sqlite: if config.backends.sqlite.enabled {
+                Some(SqliteStorage::open(&config.backends.sqlite.path)?)
+            } else {
+                None
+            },
+            rotation_hooks: StdRwLock::new(Vec::new()),
+            rotation_schedules: config
+                .daemon_overrides
+                .iter()
+                .filter_map(|(daemon, settings)| Some((daemon.clone(), settings.rotation_cron.as_deref()?)))
+                .map(|(daemon, spec)| Ok((daemon, CronSchedule::parse(spec)?)))
+                .collect::<Result<_>>()?,
+            subscribers: Subscribers::new(),
         })
     }
 
-    /// Store a log entry
-    pub async fn store_entry(&self, entry: LogEntry) -> Result<()> {
+    /// Run `hook` after every successful rotation
+    pub fn on_rotate(&self, hook: RotationHook) {
+        self.rotation_hooks
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Arc::new(hook));
+    }
+
+    /// Clock used for rotation and summary timestamps
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Configuration this backend is running with
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// Metrics recorded by this backend
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// SQLite database entries are written to, if enabled
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(&self) -> Option<&SqliteStorage> {
+        self.sqlite.as_ref()
+    }
+
+    /// Count an incoming line that was rejected before it could be stored
+    pub fn record_rejected_line(&self) {
+        self.rejected_lines.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.record_rejected_line();
+    }
+
+    /// Count an entry from a client that [`StorageBackend::store_entry`] failed to store
+    ///
+    /// The ingest worker stores entries after their connection has moved on,
+    /// so failures are counted instead of reported to the client.
+    pub fn record_store_error(&self) {
+        self.store_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of incoming lines rejected as malformed or exceeding limits
+    pub fn rejected_lines(&self) -> u64 {
+        self.rejected_lines.load(Ordering::Relaxed)
+    }
+
+    /// Number of entries dropped because their message matched a drop pattern
+    pub fn pattern_dropped_total(&self) -> u64 {
+        self.pattern_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Current entry counters
+    ///
+    /// Entries are counted as accepted even when every sink is disabled, so
+    /// a server without backends can be used to measure the transport alone.
+    pub fn stats(&self) -> StorageStats {
+        StorageStats {
+            entries_accepted: self.entries_accepted.load(Ordering::Relaxed),
+            rejected_lines: self.rejected_lines(),
+            pattern_dropped: self.pattern_dropped_total(),
+            disk_full_dropped: self.disk_full_dropped.load(Ordering::Relaxed),
+            pipe_dropped: self.pipe_dropped.load(Ordering::Relaxed),
+            store_errors: self.store_errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Subscribers to live entries
+    pub fn subscribers(&self) -> &Subscribers {
+        &self.subscribers
+    }
+
+    /// Forwarder to the upstream server, when `backends.forward` is enabled
+    pub fn forwarder(&self) -> Option<&Forwarder> {
+        self.forwarder.as_ref()
+    }
+
+    /// Entries held in memory, when `backends.memory` is enabled
+    pub fn memory(&self) -> Option<&MemoryBackend> {
+        self.memory.as_ref()
+    }
+
+    /// Sender to the GELF input, when `backends.gelf` is enabled
+    pub fn gelf(&self) -> Option<&GelfSender> {
+        self.gelf.as_ref()
+    }
+
+    /// Current counters, per-daemon totals, rates and queue depth
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let totals = self.stats();
+        let uptime_secs = self.started.elapsed().as_secs_f64();
+        let entries_per_sec = if uptime_secs > 0.0 {
+            totals.entries_accepted as f64 / uptime_secs
+        } else {
+            0.0
+        };
+        MetricsSnapshot {
+            totals,
+            per_daemon: self
+                .daemon_counts
+                .iter()
+                .map(|count| (count.key().clone(), *count.value()))
+                .collect(),
+            uptime_secs,
+            entries_per_sec,
+            open_files: self.file_writers.len(),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            rotations: self.rotations.load(Ordering::Relaxed),
+            build: BUILD_INFO,
+        }
+    }
+
+    /// Log a summary of the run and append it to [`SHUTDOWN_REPORT_FILE`]
+    ///
+    /// Called once the server has stopped and stored every entry. The report
+    /// is the final [`MetricsSnapshot`] as one JSON line with a timestamp and
+    /// `"event":"shutdown"`; the file is only written while the file backend
+    /// is enabled.
+    pub async fn write_shutdown_report(&self) -> Result<MetricsSnapshot> {
+        let snapshot = self.metrics_snapshot();
+        tracing::info!(
+            entries = snapshot.totals.entries_accepted,
+            rejected = snapshot.totals.rejected_lines,
+            bytes = snapshot.bytes_written,
+            rotations = snapshot.rotations,
+            uptime_secs = snapshot.uptime_secs,
+            per_daemon = ?snapshot.per_daemon,
+            "LogStream server session ended"
+        );
+
         if self.config.backends.file.enabled {
+            let report = ShutdownReport {
+                timestamp: self.clock.now_utc(),
+                event: "shutdown",
+                snapshot: &snapshot,
+            };
+            let mut line = serde_json::to_string(&report)?;
+            line.push('\n');
+            let path = self.config.storage.output_directory.join(SHUTDOWN_REPORT_FILE);
+            let mut file = OpenOptions::new().create(true).append(true).open(&path).await?;
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await?;
+        }
+        Ok(snapshot)
+    }
+
+    /// Record how many entries are waiting in the ingest queue
+    pub(crate) fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Store a log entry
+    pub async fn store_entry(&self, mut entry: LogEntry) -> Result<()> {
+        self.check_schema_version(&entry);
+
+        match self.filter(&entry) {
+            Some(Filtered::Pattern) => {
+                self.pattern_dropped.fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "metrics")]
+                self.metrics.record_pattern_dropped();
+                return Ok(());
+            }
+            Some(Filtered::BelowMinLevel(_)) => return Ok(()),
+            None => {}
+        }
+
+        if self.config.server.enforce_monotonic != MonotonicPolicy::Off {
+            self.enforce_monotonic(&mut entry)?;
+        }
+
+        if self.config.storage.summary_interval_secs.is_some() {
+            self.summary_counts.entry(entry.daemon.clone()).or_insert([0; 8])[entry.level as usize] += 1;
+        }
+        self.entries_accepted.fetch_add(1, Ordering::Relaxed);
+        *self.daemon_counts.entry(entry.daemon.clone()).or_insert(0) += 1;
+
+        let backends = &self.config.backends;
+        if backends.file.enabled && admits(backends.file.min_level, entry.level) {
             self.store_to_file(&entry).await?;
         }
+        if let Some(ref console) = self.console {
+            if admits(backends.console.min_level, entry.level) {
+                console.write(&entry)?;
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(ref sqlite) = self.sqlite {
+            sqlite.insert(&entry).await?;
+        }
+        if let Some(ref forwarder) = self.forwarder {
+            forwarder.forward(&entry);
+        }
+        if let Some(ref memory) = self.memory {
+            memory.push(&entry);
+        }
+        if let Some(ref gelf) = self.gelf {
+            if admits(backends.gelf.min_level, entry.level) {
+                gelf.send(&entry);
+            }
+        }
+        self.subscribers.publish(&entry);
         Ok(())
     }
 
+    /// Report what [`StorageBackend::store_entry`] would do with an entry, without storing it
+    ///
+    /// Applies drop patterns, `min_level` and `enforce_monotonic`, whose
+    /// latest timestamps the entry does advance, then lists the backends
+    /// that would take it and the file it would be appended to.
+    pub fn dry_run(&self, mut entry: LogEntry) -> EntryDecision {
+        if let Some(filtered) = self.filter(&entry) {
+            return EntryDecision::Dropped {
+                reason: filtered.to_string(),
+            };
+        }
+        if self.config.server.enforce_monotonic != MonotonicPolicy::Off {
+            if let Err(e) = self.enforce_monotonic(&mut entry) {
+                return EntryDecision::Rejected { reason: e.to_string() };
+            }
+        }
+
+        let backends = &self.config.backends;
+        let takes = [
+            ("file", backends.file.enabled && admits(backends.file.min_level, entry.level)),
+            ("console", backends.console.enabled && admits(backends.console.min_level, entry.level)),
+            ("sqlite", cfg!(feature = "sqlite") && backends.sqlite.enabled),
+            ("forward", backends.forward.enabled),
+            ("memory", backends.memory.enabled),
+            ("gelf", backends.gelf.enabled && admits(backends.gelf.min_level, entry.level)),
+        ];
+        let backends: Vec<&'static str> = takes.into_iter().filter_map(|(name, on)| on.then_some(name)).collect();
+        let file = backends
+            .contains(&"file")
+            .then(|| self.get_log_file_path(&self.file_stem(&entry.daemon, entry.timestamp)));
+        EntryDecision::Stored { file, backends }
+    }
+
+    /// Filter keeping an entry from every backend, if any
+    fn filter(&self, entry: &LogEntry) -> Option<Filtered> {
+        if self.drop_patterns.as_ref().is_some_and(|patterns| patterns.is_match(&entry.message)) {
+            return Some(Filtered::Pattern);
+        }
+        let min_level = self
+            .config
+            .daemon_override(&entry.daemon)
+            .and_then(|o| o.min_level)
+            .or(self.config.server.min_level)?;
+        (entry.level > min_level).then_some(Filtered::BelowMinLevel(min_level))
+    }
+
+    /// Clamp or reject an entry older than the latest one stored for its daemon
+    fn enforce_monotonic(&self, entry: &mut LogEntry) -> Result<()> {
+        let mut last = self.last_timestamps.entry(entry.daemon.clone()).or_insert(entry.timestamp);
+        if entry.timestamp >= *last {
+            *last = entry.timestamp;
+            return Ok(());
+        }
+        match self.config.server.enforce_monotonic {
+            MonotonicPolicy::Reject => Err(LogStreamError::Server(format!(
+                "Entry {} from {} is timestamped {}, before the last stored {}",
+                entry.id, entry.daemon, entry.timestamp, *last
+            ))),
+            _ => {
+                entry.timestamp = *last;
+                entry.fields.insert("clamped".to_string(), "true".to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Warn, once per backend, about entries from a newer wire format
+    ///
+    /// Such entries are still stored; fields this build doesn't know are
+    /// dropped. Returns whether the warning was emitted by this call.
+    fn check_schema_version(&self, entry: &LogEntry) -> bool {
+        if entry.schema_version <= LOG_ENTRY_SCHEMA_VERSION
+            || self.unknown_schema_warned.swap(true, Ordering::Relaxed)
+        {
+            return false;
+        }
+        eprintln!(
+            "Received log entry with schema version {} from {} (this server understands up to {}); unknown fields are ignored",
+            entry.schema_version, entry.daemon, LOG_ENTRY_SCHEMA_VERSION
+        );
+        true
+    }
+
     async fn store_to_file(&self, entry: &LogEntry) -> Result<()> {
         let daemon_name = &entry.daemon;
+        let stem = self.file_stem(daemon_name, entry.timestamp);
+        if let Cow::Owned(ref partition_stem) = stem {
+            self.switch_partition(daemon_name, partition_stem).await?;
+        }
         
-        let writer = if let Some(existing) = self.file_writers.get(daemon_name) {
+        if let Some(since) = self.disconnected_pipes.get(stem.as_ref()).map(|since| *since) {
+            if self.clock.now_instant().duration_since(since) < PIPE_REOPEN_INTERVAL {
+                self.pipe_dropped.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            self.disconnected_pipes.remove(stem.as_ref());
+        }
+
+        let writer = if let Some(existing) = self.file_writers.get(stem.as_ref()) {
             Arc::clone(&*existing)
         } else {
-            let file_path = self.get_log_file_path(daemon_name);
-            let writer = self.create_file_writer(&file_path).await?;
-            let writer_arc = Arc::new(RwLock::new(writer));
-            self.file_writers.insert(daemon_name.clone(), Arc::clone(&writer_arc));
-            writer_arc
+            let file_path = self.get_log_file_path(&stem);
+            let writer = match self.create_file_writer(&file_path).await {
+                Ok(writer) => writer,
+                Err(LogStreamError::Io(e)) if is_pipe_closed(&e) => {
+                    self.disconnect_pipe(&stem);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+            // Two first writes can race to open the file; both must end up
+            // sharing one writer, or rotation would only order one of them
+            let writer_arc = self
+                .file_writers
+                .entry(stem.to_string())
+                .or_insert_with(|| Arc::new(RwLock::new(writer)));
+            Arc::clone(&*writer_arc)
+        };
+
+        let format = self
+            .config
+            .daemon_override(daemon_name)
+            .and_then(|o| o.format.as_deref())
+            .unwrap_or(&self.config.backends.file.format);
+        let file_settings = &self.config.backends.file;
+        let formatted_entry = match format {
+            "json" if !file_settings.field_names.is_empty() => entry.to_json_renamed(
+                &file_settings.field_names,
+                file_settings.flatten_fields,
+                file_settings.omit_empty_fields,
+            )?,
+            "json" if file_settings.flatten_fields => entry.to_json_flat(file_settings.omit_empty_fields)?,
+            "json" if self.config.backends.file.omit_empty_fields => entry.to_json_compact()?,
+            "json" => entry.to_json()?,
+            "gelf" => entry.to_gelf()?,
+            _ if self.config.server.split_multiline => entry.to_human_readable_split(file_settings.human_max_field_len),
+            _ => entry.to_human_readable_with(self.config.backends.file.human_max_field_len),
+        };
+
+        {
+            #[cfg(feature = "metrics")]
+            let started = std::time::Instant::now();
+
+            if file_settings.strict_ndjson && formatted_entry.contains(['\n', '\r']) {
+                return Err(LogStreamError::Server(format!(
+                    "Entry {} would span several lines, which strict_ndjson forbids",
+                    entry.id
+                )));
+            }
+
+            // One buffer per record, so it reaches the file in a single write
+            // and O_APPEND keeps it whole next to other writers of the file
+            let mut record = formatted_entry.into_bytes();
+            record.extend_from_slice(self.config.backends.file.record_terminator.as_bytes());
+            let mut writer_guard = writer.write().await;
+            match writer_guard.write_record(&record).await {
+                Ok(()) => {}
+                Err(e) if is_disk_full(&e) => {
+                    if !self.handle_disk_full(&mut writer_guard, &record).await? {
+                        return Ok(());
+                    }
+                }
+                Err(e) if writer_guard.fifo && is_pipe_closed(&e) => {
+                    // The unflushed record goes with the writer; the FIFO is reopened later
+                    self.file_writers.remove_if(stem.as_ref(), |_, current| Arc::ptr_eq(current, &writer));
+                    self.disconnect_pipe(&stem);
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            }
+            if self.config.storage.fsync == FsyncPolicy::Always {
+                self.sync_file(writer_guard.writer.file()).await?;
+            }
+            let record_len = record.len();
+            writer_guard.bytes_written += record_len as u64;
+            self.bytes_written.fetch_add(record_len as u64, Ordering::Relaxed);
+
+            #[cfg(feature = "metrics")]
+            self.metrics.observe_store(record_len, started.elapsed());
+
+            if self.config.storage.rotation.enabled
+                && !writer_guard.fifo
+                && writer_guard.bytes_written >= self.config.storage.max_file_size
+            {
+                self.rotate_locked(daemon_name, &stem, &mut writer_guard, RotationReason::Size).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// File stem for a daemon's entry: the daemon name, plus the partition if enabled
+    ///
+    /// Never an earlier partition than the daemon's current one: entries
+    /// timestamped before it, backfilled or written after the clock stepped
+    /// back, go into the current partition instead of reopening an old one.
+    fn file_stem<'a>(&self, daemon_name: &'a str, timestamp: DateTime<Utc>) -> Cow<'a, str> {
+        let Some(label) = self.config.storage.partition.label(timestamp) else {
+            return Cow::Borrowed(daemon_name);
+        };
+        // Labels sort by time, and so do stems of the same daemon
+        let stem = format!("{}-{}", daemon_name, label);
+        match self.current_partitions.get(daemon_name) {
+            Some(current) if *current > stem => Cow::Owned(current.clone()),
+            _ => Cow::Owned(stem),
+        }
+    }
+
+    /// Drop an entry for a FIFO nobody reads, and hold off reopening it for a while
+    fn disconnect_pipe(&self, stem: &str) {
+        if self.disconnected_pipes.insert(stem.to_string(), self.clock.now_instant()).is_none() {
+            eprintln!("No reader on FIFO for {}, dropping its entries until one returns", stem);
+        }
+        self.pipe_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Stop holding entries for disk space
+    ///
+    /// From now on the `Block` disk-full policy drops entries that don't
+    /// fit, like `DropNewest`, so shutdown and rotation don't wait on a full
+    /// disk forever.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.cancel();
+    }
+
+    /// Apply `on_disk_full` to a record that ran out of space
+    ///
+    /// Any part of it that reached the file is cut off first, and every
+    /// retry writes the whole record again. Returns whether it was written
+    /// in the end; a dropped record is counted.
+    async fn handle_disk_full(&self, writer: &mut LogFileWriter, record: &[u8]) -> Result<bool> {
+        writer.discard_partial().await?;
+        match self.config.storage.on_disk_full {
+            DiskFullPolicy::Block => {
+                eprintln!("Disk full, holding entries until space is available");
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(DISK_FULL_RETRY_INTERVAL) => {}
+                        _ = self.shutting_down.cancelled() => break,
+                    }
+                    match writer.write_record(record).await {
+                        Ok(()) => return Ok(true),
+                        Err(e) if is_disk_full(&e) => writer.discard_partial().await?,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            DiskFullPolicy::DropOldest => {
+                while let Some(archive) = self.remove_oldest_archive().await? {
+                    eprintln!("Disk full, deleted archive {}", archive.display());
+                    match writer.write_record(record).await {
+                        Ok(()) => return Ok(true),
+                        Err(e) if is_disk_full(&e) => writer.discard_partial().await?,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            DiskFullPolicy::DropNewest => {}
+        }
+
+        self.disk_full_dropped.fetch_add(1, Ordering::Relaxed);
+        Ok(false)
+    }
+
+    /// Delete the oldest rotated archive of any daemon, returning its path
+    async fn remove_oldest_archive(&self) -> Result<Option<PathBuf>> {
+        let mut oldest: Option<((String, u32), PathBuf)> = None;
+        for directory in self.storage_directories().await? {
+            let mut entries = tokio::fs::read_dir(&directory).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let file_name = entry.file_name();
+                let Some(stem) = file_name.to_str().and_then(archive_stem) else {
+                    continue;
+                };
+                let path = entry.path();
+                let key = archive_sort_key(stem, &path);
+                if oldest.as_ref().is_none_or(|(oldest_key, _)| key < *oldest_key) {
+                    oldest = Some((key, path));
+                }
+            }
+        }
+
+        let Some((_, path)) = oldest else {
+            return Ok(None);
+        };
+        tokio::fs::remove_file(&path).await?;
+        Ok(Some(path))
+    }
+
+    /// Delete every archive last written more than `retention_days` ago, returning their paths
+    ///
+    /// An archive's modification time is when its newest entry was written,
+    /// so every entry in a deleted archive is older than the cutoff. Applies
+    /// regardless of `keep_files`, and never touches live files.
+    pub async fn remove_expired_archives(&self) -> Result<Vec<PathBuf>> {
+        let Some(retention_days) = self.config.storage.retention_days else {
+            return Ok(Vec::new());
+        };
+        let cutoff = self.clock.now_utc() - chrono::Duration::days(i64::from(retention_days));
+
+        let mut removed = Vec::new();
+        let directories = match self.storage_directories().await {
+            Ok(directories) => directories,
+            Err(LogStreamError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(removed),
+            Err(e) => return Err(e),
+        };
+        for directory in directories {
+            let mut entries = match tokio::fs::read_dir(&directory).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_name().to_str().and_then(archive_stem).is_none() {
+                    continue;
+                }
+                let modified: DateTime<Utc> = entry.metadata().await?.modified()?.into();
+                if modified < cutoff {
+                    let path = entry.path();
+                    tokio::fs::remove_file(&path).await?;
+                    removed.push(path);
+                }
+            }
+        }
+        removed.sort();
+        Ok(removed)
+    }
+
+    /// Make `stem` the daemon's current partition, flushing and closing the previous one
+    async fn switch_partition(&self, daemon_name: &str, stem: &str) -> Result<()> {
+        let previous = match self.current_partitions.get(daemon_name) {
+            // A write racing one into a later partition must not move it back
+            Some(current) if current.as_str() >= stem => return Ok(()),
+            Some(current) => Some(current.clone()),
+            None => None,
+        };
+        self.current_partitions.insert(daemon_name.to_string(), stem.to_string());
+
+        if let Some(previous) = previous {
+            if let Some((_, writer)) = self.file_writers.remove(&previous) {
+                writer.write().await.writer.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rotate the live log file of a daemon, returning the archive path
+    ///
+    /// With partitioning enabled this rotates the daemon's current partition.
+    /// Returns `None` if no file is currently open for the daemon.
+    pub async fn rotate(&self, daemon_name: &str) -> Result<Option<PathBuf>> {
+        let stem = match self.current_partitions.get(daemon_name) {
+            Some(current) => current.clone(),
+            None => daemon_name.to_string(),
+        };
+        let writer = match self.file_writers.get(&stem) {
+            Some(existing) => Arc::clone(&*existing),
+            None => return Ok(None),
+        };
+
+        let mut writer_guard = writer.write().await;
+        if writer_guard.fifo {
+            return Ok(None);
+        }
+        self.rotate_locked(daemon_name, &stem, &mut writer_guard, RotationReason::Manual).await.map(Some)
+    }
+
+    /// Rotate every daemon whose live file is older than `max_age_hours`
+    ///
+    /// Returns the number of files rotated.
+    pub async fn rotate_expired(&self) -> Result<usize> {
+        let max_age_hours = self.config.storage.rotation.max_age_hours;
+        if !self.config.storage.rotation.enabled || max_age_hours == 0 {
+            return Ok(0);
+        }
+
+        let max_age = Duration::from_secs(u64::from(max_age_hours) * 3600);
+        let writers: Vec<(String, Arc<RwLock<LogFileWriter>>)> = self
+            .file_writers
+            .iter()
+            .map(|writer| (writer.key().clone(), Arc::clone(writer.value())))
+            .collect();
+
+        let mut rotated = 0;
+        for (stem, writer) in writers {
+            let mut writer_guard = writer.write().await;
+            let age = self.clock.now_instant().saturating_duration_since(writer_guard.opened_instant);
+            if !writer_guard.fifo && age >= max_age {
+                let daemon_name = self.daemon_for_stem(&stem);
+                self.rotate_locked(&daemon_name, &stem, &mut writer_guard, RotationReason::Age).await?;
+                rotated += 1;
+            }
+        }
+
+        Ok(rotated)
+    }
+
+    /// Whether any daemon has a `rotation_cron` schedule
+    pub fn has_rotation_schedules(&self) -> bool {
+        !self.rotation_schedules.is_empty()
+    }
+
+    /// Rotate every daemon whose `rotation_cron` schedule fired since its file was opened
+    ///
+    /// Size and age don't matter; a file opened before the latest scheduled
+    /// instant is rotated, even if that instant passed some time ago.
+    /// Returns the number of files rotated.
+    pub async fn rotate_scheduled(&self) -> Result<usize> {
+        if !self.config.storage.rotation.enabled || self.rotation_schedules.is_empty() {
+            return Ok(0);
+        }
+
+        let writers: Vec<(String, Arc<RwLock<LogFileWriter>>)> = self
+            .file_writers
+            .iter()
+            .map(|writer| (writer.key().clone(), Arc::clone(writer.value())))
+            .collect();
+
+        let mut rotated = 0;
+        for (stem, writer) in writers {
+            let daemon_name = self.daemon_for_stem(&stem);
+            let Some(schedule) = self.rotation_schedules.get(&daemon_name) else {
+                continue;
+            };
+            let mut writer_guard = writer.write().await;
+            let due = !writer_guard.fifo
+                && schedule
+                    .next_after(writer_guard.opened_at)
+                    .is_some_and(|scheduled| scheduled <= self.clock.now_utc());
+            if due {
+                self.rotate_locked(&daemon_name, &stem, &mut writer_guard, RotationReason::Cron).await?;
+                rotated += 1;
+            }
+        }
+
+        Ok(rotated)
+    }
+
+    /// Daemon writing to the file with the given stem
+    fn daemon_for_stem(&self, stem: &str) -> String {
+        self.current_partitions
+            .iter()
+            .find(|current| current.value() == stem)
+            .map(|current| current.key().clone())
+            .unwrap_or_else(|| stem.to_string())
+    }
+
+    /// Archive the live file and reopen it; the caller must hold the writer lock
+    ///
+    /// Holding the lock orders rotation against every write to the file: a
+    /// record whose write took the lock first is flushed into the archive
+    /// before the rename, and one that waited for it lands in the new live
+    /// file. Nothing reaches the file between the flush and the reopen.
+    async fn rotate_locked(
+        &self,
+        daemon_name: &str,
+        stem: &str,
+        writer: &mut LogFileWriter,
+        reason: RotationReason,
+    ) -> Result<PathBuf> {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+
+        let result = self.archive_live_file(daemon_name, stem, writer, reason).await;
+
+        #[cfg(feature = "metrics")]
+        match result {
+            Ok(_) => self.metrics.record_rotation(daemon_name, reason, started.elapsed()),
+            Err(_) => self.metrics.record_rotation_failure(),
+        }
+
+        result
+    }
+
+    /// The rotation itself, with [`Self::rotate_locked`] recording its outcome
+    async fn archive_live_file(
+        &self,
+        daemon_name: &str,
+        stem: &str,
+        writer: &mut LogFileWriter,
+        reason: RotationReason,
+    ) -> Result<PathBuf> {
+        writer.writer.flush().await?;
+        if self.config.storage.fsync != FsyncPolicy::Never {
+            self.sync_file(writer.writer.file()).await?;
+        }
+
+        let live_path = self.get_log_file_path(stem);
+        let mut archive_path = self.next_archive_path(stem).await?;
+        tokio::fs::rename(&live_path, &archive_path).await?;
+        // Reopen before anything else can fail, so the writer never outlives
+        // the rename still pointing at the archive
+        *writer = self.create_file_writer(&live_path).await?;
+
+        let file_settings = &self.config.backends.file;
+        if file_settings.compression {
+            archive_path = compress::compress_archive(
+                &archive_path,
+                &file_settings.compression_algorithm,
+                file_settings.compression_level,
+                self.config.storage.fsync != FsyncPolicy::Never,
+            )
+            .await?;
+        }
+
+        self.rotations.fetch_add(1, Ordering::Relaxed);
+        self.prune_archives(stem).await?;
+        self.run_rotation_hooks(daemon_name, &archive_path, reason).await?;
+
+        Ok(archive_path)
+    }
+
+    /// Hand a completed rotation to every registered hook on the blocking pool
+    async fn run_rotation_hooks(&self, daemon_name: &str, archive_path: &Path, reason: RotationReason) -> Result<()> {
+        let hooks = self.rotation_hooks.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if hooks.is_empty() {
+            return Ok(());
+        }
+
+        let event = RotationEvent {
+            daemon: daemon_name.to_string(),
+            archive_path: archive_path.to_path_buf(),
+            size_bytes: tokio::fs::metadata(archive_path).await?.len(),
+            reason,
+        };
+        for hook in hooks {
+            let event = event.clone();
+            tokio::task::spawn_blocking(move || (*hook)(event));
+        }
+        Ok(())
+    }
+
+    /// Pick a `{daemon}.{timestamp}[-n].log` name sorting after every existing archive
+    async fn next_archive_path(&self, daemon_name: &str) -> Result<PathBuf> {
+        let now = self.clock.now_utc().format("%Y%m%d-%H%M%S-%3f").to_string();
+
+        // Rotations within the same millisecond get an increasing `-n` suffix,
+        // as do rotations after the clock stepped back behind the latest archive
+        let latest = self
+            .list_archives(daemon_name)
+            .await?
+            .iter()
+            .map(|path| archive_sort_key(daemon_name, path))
+            .max();
+        let (stamp, suffix) = match latest {
+            Some((base, n)) if base >= now => (base, Some(n + 1)),
+            _ => (now, None),
+        };
+
+        let file_name = match suffix {
+            Some(n) => format!("{}.{}-{}.log", daemon_name, stamp, n),
+            None => format!("{}.{}.log", daemon_name, stamp),
+        };
+        Ok(self.stem_directory(daemon_name).join(file_name))
+    }
+
+    /// Rotated archives of a daemon, oldest first
+    ///
+    /// For partitioned storage pass the partition's file stem
+    /// (`{daemon}-{partition}`) instead of the daemon name.
+    pub async fn list_archives(&self, daemon_name: &str) -> Result<Vec<PathBuf>> {
+        let mut archives = Vec::new();
+        let mut entries = match tokio::fs::read_dir(self.stem_directory(daemon_name)).await {
+            Ok(entries) => entries,
+            // A bucket is only created with the first file in it
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(archives),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str() else {
+                continue;
+            };
+            // Archive names are `{daemon}.{YYYYMMDD-HHMMSS-mmm}[-n].log`, plus `.gz`, `.lz4` or `.zst` if compressed.
+            // Matching the whole stem keeps out live files of daemons such as `{daemon}.1`.
+            if archive_stem(name) == Some(daemon_name) {
+                archives.push(entry.path());
+            }
+        }
+
+        archives.sort_by_key(|path| archive_sort_key(daemon_name, path));
+        Ok(archives)
+    }
+
+    /// Delete the oldest archives beyond `keep_files`
+    async fn prune_archives(&self, daemon_name: &str) -> Result<()> {
+        let keep_files = self.config.storage.rotation.keep_files as usize;
+        if keep_files == 0 {
+            return Ok(());
+        }
+
+        let archives = self.list_archives(daemon_name).await?;
+        if archives.len() > keep_files {
+            for archive in &archives[..archives.len() - keep_files] {
+                tokio::fs::remove_file(archive).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the current summary window for every daemon and start a new one
+    ///
+    /// Each daemon with entries in the window gets one record appended to
+    /// `{daemon}.summary.jsonl` in the output directory.
+    pub async fn write_summaries(&self) -> Result<Vec<DaemonSummary>> {
+        let timestamp = self.clock.now_utc();
+        let daemons: Vec<String> = self
+            .summary_counts
+            .iter()
+            .map(|counts| counts.key().clone())
+            .collect();
+
+        let mut summaries = Vec::with_capacity(daemons.len());
+        for daemon in daemons {
+            // Removing the entry atomically closes this daemon's window
+            let Some((daemon, counts)) = self.summary_counts.remove(&daemon) else {
+                continue;
+            };
+
+            let summary = DaemonSummary {
+                timestamp,
+                total: counts.iter().sum(),
+                counts: LogLevel::ALL
+                    .iter()
+                    .map(|level| (format!("{:?}", level), counts[*level as usize]))
+                    .collect(),
+                daemon,
+            };
+
+            let path = self
+                .config
+                .storage
+                .output_directory
+                .join(format!("{}.summary.jsonl", summary.daemon));
+            let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+            let mut line = serde_json::to_string(&summary)?;
+            line.push('\n');
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await?;
+
+            summaries.push(summary);
+        }
+
+        Ok(summaries)
+    }
+
+    /// Start the periodic summary task
+    pub async fn start_summary_task(&self, mut shutdown_rx: broadcast::Receiver<()>) {
+        let Some(secs) = self.config.storage.summary_interval_secs else {
+            return;
+        };
+
+        let mut summary_interval = interval(Duration::from_secs(secs.max(1)));
+        // The first tick completes immediately; skip it so each window is a full interval
+        summary_interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = summary_interval.tick() => {
+                    if let Err(e) = self.write_summaries().await {
+                        eprintln!("Failed to write summaries: {}", e);
+                    }
+                }
+                _ = shutdown_rx.recv() => {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Sync a log file's data to disk
+    async fn sync_file(&self, file: &tokio::fs::File) -> Result<()> {
+        file.sync_data().await?;
+        self.sync_calls.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// The last `count` entries in a daemon's current log file, oldest first
+    ///
+    /// The file is read backwards from its end, so this stays cheap for
+    /// large files. Only JSON records can be read back; lines that don't
+    /// parse as an entry are skipped, and archives aren't searched. Keys
+    /// renamed by `field_names` are mapped back. Without the file backend,
+    /// entries come from the memory backend instead.
+    pub async fn tail(&self, daemon_name: &str, count: usize) -> Result<Vec<LogEntry>> {
+        let Some(path) = self.log_file_path(daemon_name) else {
+            return Ok(self.memory.as_ref().map(|memory| memory.recent(daemon_name, count)).unwrap_or_default());
+        };
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let mut file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let terminator = self.config.backends.file.record_terminator.as_bytes();
+        let mut position = file.metadata().await?.len();
+        let mut tail = Vec::new();
+        // `count` whole records need one more terminator before them, unless the file starts there
+        while position > 0 && count_occurrences(&tail, terminator) <= count {
+            let step = TAIL_BLOCK_SIZE.min(position);
+            position -= step;
+            file.seek(std::io::SeekFrom::Start(position)).await?;
+            let mut block = vec![0; step as usize];
+            file.read_exact(&mut block).await?;
+            block.extend_from_slice(&tail);
+            tail = block;
+        }
+
+        let text = String::from_utf8_lossy(&tail);
+        let mut records: Vec<&str> = text.split(self.config.backends.file.record_terminator.as_str()).collect();
+        // What follows the last terminator is empty or a record still being written
+        records.pop();
+        let mut entries: Vec<LogEntry> = records
+            .iter()
+            .rev()
+            .filter_map(|record| LogEntry::from_json_renamed(record, &self.config.backends.file.field_names).ok())
+            .take(count)
+            .collect();
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// The last `count` stored entries of a daemon followed by its live ones
+    ///
+    /// Subscribes before reading the [`tail`](Self::tail), so entries
+    /// stored in between are neither missed nor repeated.
+    pub async fn catch_up(&self, daemon_name: &str, count: usize) -> Result<CatchUp> {
+        let live = self.subscribers.subscribe_daemon(daemon_name);
+        let recent = self.tail(daemon_name, count + SUBSCRIBER_CAPACITY).await?;
+        Ok(self.subscribers.catch_up(recent, count, live))
+    }
+
+    /// File the daemon's entries are currently written to, or `None` without the file backend
+    ///
+    /// With partitioning enabled this is the file for the current partition.
+    pub fn log_file_path(&self, daemon_name: &str) -> Option<PathBuf> {
+        if !self.config.backends.file.enabled {
+            return None;
+        }
+        Some(self.get_log_file_path(&self.file_stem(daemon_name, self.clock.now_utc())))
+    }
+
+    pub(crate) fn get_log_file_path(&self, stem: &str) -> PathBuf {
+        self.stem_directory(stem).join(format!("{}.log", stem))
+    }
+
+    /// Directory holding a stem's live file and archives
+    fn stem_directory(&self, stem: &str) -> PathBuf {
+        let output_directory = &self.config.storage.output_directory;
+        match self.config.storage.hash_buckets {
+            0 => output_directory.clone(),
+            buckets => output_directory.join(format!("{:02x}", fnv1a(stem.as_bytes()) % u64::from(buckets))),
+        }
+    }
+
+    /// Every directory log files may be in: the output directory, or its existing buckets
+    async fn storage_directories(&self) -> Result<Vec<PathBuf>> {
+        let output_directory = &self.config.storage.output_directory;
+        if self.config.storage.hash_buckets == 0 {
+            return Ok(vec![output_directory.clone()]);
+        }
+        let mut directories = Vec::new();
+        let mut entries = tokio::fs::read_dir(output_directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                directories.push(entry.path());
+            }
+        }
+        directories.sort();
+        Ok(directories)
+    }
+
+    /// File stems with a live log file, sorted
+    ///
+    /// With partitioning these are `{daemon}-{partition}` stems, one per
+    /// partition file still on disk.
+    pub async fn list_daemons(&self) -> Result<Vec<String>> {
+        let mut stems = Vec::new();
+        for directory in self.storage_directories().await? {
+            let mut entries = tokio::fs::read_dir(&directory).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let file_name = entry.file_name();
+                let Some(stem) = file_name
+                    .to_str()
+                    .filter(|name| *name != SHUTDOWN_REPORT_FILE)
+                    .and_then(|name| name.strip_suffix(".log"))
+                else {
+                    continue;
+                };
+                let is_archive = stem.rsplit_once('.').is_some_and(|(_, stamp)| is_archive_stamp(stamp));
+                if !is_archive {
+                    stems.push(stem.to_string());
+                }
+            }
+        }
+        stems.sort();
+        Ok(stems)
+    }
+
+    async fn create_file_writer(&self, file_path: &Path) -> Result<LogFileWriter> {
+        if self.config.storage.hash_buckets > 0 {
+            if let Some(bucket) = file_path.parent() {
+                tokio::fs::create_dir_all(bucket).await?;
+            }
+        }
+        let fifo = match tokio::fs::metadata(file_path).await {
+            Ok(metadata) => metadata.file_type().is_fifo(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        if fifo {
+            // Fail with ENXIO rather than wait for a reader to show up
+            options.custom_flags(libc::O_NONBLOCK);
+        }
+        let mut file = options.open(file_path).await?;
+        if fifo {
+            // Writes block again once open, as they would for a regular file
+            let fd = file.as_raw_fd();
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) } < 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+        // Tokio splits larger writes into 2 MiB chunks, which could tear a record
+        file.set_max_buf_size(usize::MAX);
+        let bytes_written = file.metadata().await?.len();
+        Ok(LogFileWriter {
+            writer: Box::new(file),
+            opened_at: self.clock.now_utc(),
+            opened_instant: self.clock.now_instant(),
+            bytes_written,
+            fifo,
+        })
+    }
+}
+
+/// 64-bit FNV-1a, stable across builds so files stay in their buckets
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Whether an I/O error means the disk is full
+fn is_disk_full(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::StorageFull || e.raw_os_error() == Some(libc::ENOSPC)
+}
+
+/// Whether an I/O error means a FIFO has no reader
+fn is_pipe_closed(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::BrokenPipe || e.raw_os_error() == Some(libc::ENXIO)
+}
+
+/// Whether `stamp` is an archive timestamp, `YYYYMMDD-HHMMSS-mmm[-n]`
+fn is_archive_stamp(stamp: &str) -> bool {
+    let bytes = stamp.as_bytes();
+    bytes.len() >= 19
+        && bytes[8] == b'-'
+        && bytes[15] == b'-'
+        && bytes[..19]
+            .iter()
+            .enumerate()
+            .all(|(i, b)| i == 8 || i == 15 || b.is_ascii_digit())
+}
+
+/// An archive's file name without `.log` and any compression extension
+fn strip_archive_extension(name: &str) -> Option<&str> {
+    let name = compress::ARCHIVE_EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    name.strip_suffix(".log")
+}
+
+/// File stem of an archive's file name, or `None` if it isn't an archive
+fn archive_stem(name: &str) -> Option<&str> {
+    strip_archive_extension(name)
+        .and_then(|name| name.rsplit_once('.'))
+        .filter(|(_, stamp)| is_archive_stamp(stamp))
+        .map(|(stem, _)| stem)
+}
+
+/// Sort key of an archive: its timestamp and same-millisecond suffix
+fn archive_sort_key(daemon_name: &str, path: &Path) -> (String, u32) {
+    let stamp = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix(daemon_name))
+        .and_then(|name| name.strip_prefix('.'))
+        .and_then(strip_archive_extension)
+        .unwrap_or_default();
+
+    // `YYYYMMDD-HHMMSS-mmm` is 19 characters, optionally followed by `-n`
+    match stamp.get(19..).and_then(|rest| rest.strip_prefix('-')) {
+        Some(suffix) => (stamp[..19].to_string(), suffix.parse().unwrap_or(0)),
+        None => (stamp.to_string(), 0),
+    }
+}
+
+/// Number of non-overlapping occurrences of `needle` in `haystack`
+fn count_occurrences(haystack: &[u8], needle: &[u8]) -> usize {
+    let mut count = 0;
+    let mut rest = haystack;
+    while let Some(index) = rest.windows(needle.len()).position(|window| window == needle) {
+        count += 1;
+        rest = &rest[index + needle.len()..];
+    }
+    count
+}
+
+/// Why [`StorageBackend::filter`] keeps an entry out
+enum Filtered {
+    /// Its message matches one of `drop_patterns`
+    Pattern,
+    /// It is less severe than the `min_level` applying to its daemon
+    BelowMinLevel(LogLevel),
+}
+
+impl fmt::Display for Filtered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filtered::Pattern => write!(f, "message matches a drop pattern"),
+            Filtered::BelowMinLevel(level) => write!(f, "below min_level {:?}", level),
+        }
+    }
+}
+
+/// Whether a backend limited to `min_level` takes entries at `level`
+fn admits(min_level: Option<LogLevel>, level: LogLevel) -> bool {
+    min_level.is_none_or(|min_level| level <= min_level)
+}
+
+/// Log files on a full disk, for tests
+#[cfg(test)]
+mod full_disk {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{ready, Context, Poll};
+
+    /// Log file whose next `failures` writes run out of space
+    ///
+    /// Like on a real disk, part of a failing write reaches the file before
+    /// it fails with `ENOSPC`.
+    struct FullDisk {
+        inner: tokio::fs::File,
+        failures: Arc<AtomicUsize>,
+        /// The current write was cut short and fails next
+        torn: bool,
+    }
+
+    impl AsyncWrite for FullDisk {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+            if self.failures.load(Ordering::SeqCst) == 0 {
+                return Pin::new(&mut self.inner).poll_write(cx, buf);
+            }
+            if !self.torn && buf.len() > 1 {
+                let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &buf[..buf.len() / 2]))?;
+                self.torn = true;
+                return Poll::Ready(Ok(written));
+            }
+            self.torn = false;
+            let _ = self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+            Poll::Ready(Err(std::io::Error::from_raw_os_error(libc::ENOSPC)))
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    impl RecordSink for FullDisk {
+        fn file(&self) -> &tokio::fs::File {
+            &self.inner
+        }
+    }
+
+    impl StorageBackend {
+        /// Make the next `failures` writes to the open log file of `stem` run out of space
+        ///
+        /// Returns the failures left, which the test can change to fill or
+        /// free the disk later.
+        pub(crate) async fn fail_writes(&self, stem: &str, failures: usize) -> Arc<AtomicUsize> {
+            let writer = Arc::clone(&*self.file_writers.get(stem).expect("log file not open"));
+            let mut writer = writer.write().await;
+            let failures = Arc::new(AtomicUsize::new(failures));
+            let inner = writer.writer.file().try_clone().await.unwrap();
+            writer.writer = Box::new(FullDisk { inner, failures: Arc::clone(&failures), torn: false });
+            failures
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LogLevel;
+    use tempfile::tempdir;
+    use tokio::fs;
+
+    async fn create_test_config(dir: &Path) -> ServerConfig {
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = dir.to_path_buf();
+        config.backends.file.enabled = true;
+        config.backends.file.format = "json".to_string();
+        config
+    }
+
+    #[tokio::test]
+    async fn test_storage_backend_creation() {
+        let temp_dir = tempdir().unwrap();
+        let config = create_test_config(temp_dir.path()).await;
+        
+        let backend = StorageBackend::new(&config).await;
+        assert!(backend.is_ok());
+        
+        let backend = backend.unwrap();
+        assert_eq!(backend.config.storage.output_directory, temp_dir.path());
+        assert!(backend.file_writers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_daemon_overrides_apply() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.daemon_overrides.insert(
+            "quiet".to_string(),
+            crate::config::DaemonOverride {
+                min_level: Some(LogLevel::Warning),
+                format: Some("human".to_string()),
+                dedicated_worker: false,
+                rotation_cron: None,
+            },
+        );
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        for level in [LogLevel::Error, LogLevel::Info, LogLevel::Warning] {
+            let entry = LogEntry::new(level, "quiet".to_string(), format!("{} message", level));
+            backend.store_entry(entry).await.unwrap();
+        }
+
+        let content = fs::read_to_string(temp_dir.path().join("quiet.log")).await.unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| !line.starts_with('{')));
+        assert!(!content.contains("INFO message"));
+    }
+
+    #[tokio::test]
+    async fn test_split_multiline_only_changes_human_output() {
+        use crate::types::MULTILINE_CONTINUATION_MARKER;
+
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.server.split_multiline = true;
+        config.daemon_overrides.entry("human".to_string()).or_default().format = Some("human".to_string());
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        let trace = "boom\n  at parse (parser.rs:10)\n  at main (main.rs:3)";
+        for daemon in ["json", "human"] {
+            let entry = LogEntry::new(LogLevel::Error, daemon.to_string(), trace.to_string());
+            backend.store_entry(entry).await.unwrap();
+        }
+
+        let json = fs::read_to_string(temp_dir.path().join("json.log")).await.unwrap();
+        assert_eq!(json.lines().count(), 1);
+        assert_eq!(LogEntry::from_json(json.trim_end()).unwrap().message, trace);
+
+        let human = fs::read_to_string(temp_dir.path().join("human.log")).await.unwrap();
+        let lines: Vec<&str> = human.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("human: boom"), "{}", lines[0]);
+        assert!(lines[1..].iter().all(|line| line.starts_with(MULTILINE_CONTINUATION_MARKER)));
+    }
+
+    #[tokio::test]
+    async fn test_backend_levels_apply_independently() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.file.min_level = Some(LogLevel::Debug);
+        config.backends.console.enabled = true;
+        config.backends.console.min_level = Some(LogLevel::Warning);
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        for (level, message) in [(LogLevel::Info, "cache warmed"), (LogLevel::Error, "cache lost")] {
+            let entry = LogEntry::new(level, "sinks".to_string(), message.to_string());
+            backend.store_entry(entry).await.unwrap();
+        }
+
+        let content = fs::read_to_string(temp_dir.path().join("sinks.log")).await.unwrap();
+        assert!(content.contains("cache warmed"));
+        assert!(content.contains("cache lost"));
+        let printed = backend.console.as_ref().unwrap().printed.lock().unwrap().clone();
+        assert_eq!(printed.len(), 1);
+        assert!(printed[0].contains("cache lost"));
+    }
+
+    #[tokio::test]
+    async fn test_daily_partitions_split_at_midnight() {
+        use crate::config::Partition;
+        use crate::types::MockClock;
+        use chrono::TimeZone;
+
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.partition = Partition::Daily;
+
+        let clock = Arc::new(MockClock::new(Utc.with_ymd_and_hms(2024, 6, 1, 23, 59, 0).unwrap()));
+        let backend = StorageBackend::with_clock(&config, clock.clone()).await.unwrap();
+
+        for i in 0..2 {
+            let entry = LogEntry::with_clock(LogLevel::Info, "nightly".to_string(), format!("before {}", i), &*clock);
+            backend.store_entry(entry).await.unwrap();
+        }
+
+        clock.advance(std::time::Duration::from_secs(120));
+        let entry = LogEntry::with_clock(LogLevel::Info, "nightly".to_string(), "after".to_string(), &*clock);
+        backend.store_entry(entry).await.unwrap();
+
+        // The previous day's writer is closed once the new window starts
+        assert_eq!(backend.file_writers.len(), 1);
+        assert!(backend.file_writers.contains_key("nightly-2024-06-02"));
+        assert!(!temp_dir.path().join("nightly.log").exists());
+
+        let day_one = fs::read_to_string(temp_dir.path().join("nightly-2024-06-01.log")).await.unwrap();
+        let day_two = fs::read_to_string(temp_dir.path().join("nightly-2024-06-02.log")).await.unwrap();
+        assert_eq!(day_one.lines().count(), 2);
+        assert!(day_one.contains("before 0") && day_one.contains("before 1"));
+        assert_eq!(day_two.lines().count(), 1);
+        assert!(day_two.contains("after"));
+    }
+
+    #[tokio::test]
+    async fn test_clock_stepping_back_does_not_regress_partitions() {
+        use crate::config::Partition;
+        use crate::types::MockClock;
+        use chrono::TimeZone;
+
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.partition = Partition::Daily;
+        config.storage.rotation.enabled = true;
+        config.storage.rotation.max_age_hours = 1;
+
+        let clock = Arc::new(MockClock::new(Utc.with_ymd_and_hms(2024, 6, 1, 23, 59, 0).unwrap()));
+        let backend = StorageBackend::with_clock(&config, clock.clone()).await.unwrap();
+        let store = |message: &str| {
+            let entry = LogEntry::with_clock(LogLevel::Info, "nightly".to_string(), message.to_string(), &*clock);
+            backend.store_entry(entry)
         };
 
-        let formatted_entry = match self.config.backends.file.format.as_str() {
-            "json" => entry.to_json()?,
-            _ => entry.to_human_readable(),
-        };
+        store("day one").await.unwrap();
+        clock.advance(std::time::Duration::from_secs(120));
+        store("day two").await.unwrap();
+        let first_archive = backend.rotate("nightly").await.unwrap().unwrap();
+
+        // NTP steps the wall clock back across midnight; monotonic time doesn't move
+        clock.set(Utc.with_ymd_and_hms(2024, 6, 1, 23, 59, 30).unwrap());
+        store("after step 1").await.unwrap();
+        store("after step 2").await.unwrap();
+        assert_eq!(
+            backend.log_file_path("nightly").unwrap(),
+            temp_dir.path().join("nightly-2024-06-02.log")
+        );
+        assert!(backend.file_writers.contains_key("nightly-2024-06-02"));
+        assert_eq!(backend.file_writers.len(), 1);
+
+        // The next archive still sorts after the first one
+        let second_archive = backend.rotate("nightly").await.unwrap().unwrap();
+        let archives = backend.list_archives("nightly-2024-06-02").await.unwrap();
+        assert_eq!(archives, vec![first_archive.clone(), second_archive.clone()]);
+
+        let day_one = fs::read_to_string(temp_dir.path().join("nightly-2024-06-01.log")).await.unwrap();
+        assert_eq!(day_one.lines().count(), 1);
+        assert!(fs::read_to_string(&first_archive).await.unwrap().contains("day two"));
+        let second = fs::read_to_string(&second_archive).await.unwrap();
+        assert!(second.contains("after step 1") && second.contains("after step 2"));
+
+        // Age is measured on monotonic time, so a wall clock jumping ahead doesn't rotate
+        store("reopened").await.unwrap();
+        clock.set(Utc.with_ymd_and_hms(2024, 6, 2, 5, 0, 0).unwrap());
+        assert_eq!(backend.rotate_expired().await.unwrap(), 0);
+        clock.advance(std::time::Duration::from_secs(3600));
+        assert_eq!(backend.rotate_expired().await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_partition_labels() {
+        use crate::config::Partition;
+        use chrono::TimeZone;
+
+        let timestamp = Utc.with_ymd_and_hms(2024, 6, 1, 13, 45, 0).unwrap();
+        assert_eq!(Partition::None.label(timestamp), None);
+        assert_eq!(Partition::Daily.label(timestamp).as_deref(), Some("2024-06-01"));
+        assert_eq!(Partition::Hourly.label(timestamp).as_deref(), Some("2024-06-01-13"));
+    }
+
+    #[tokio::test]
+    async fn test_fsync_on_rotate() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.fsync = FsyncPolicy::OnRotate;
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        for i in 0..5 {
+            let entry = LogEntry::new(LogLevel::Info, "audit".to_string(), format!("entry {}", i));
+            backend.store_entry(entry).await.unwrap();
+        }
+        assert_eq!(backend.sync_calls.load(Ordering::Relaxed), 0);
+
+        let archive = backend.rotate("audit").await.unwrap().unwrap();
+        assert_eq!(backend.sync_calls.load(Ordering::Relaxed), 1);
+        let content = fs::read_to_string(archive).await.unwrap();
+        assert_eq!(content.lines().count(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_write_order() {
+        let temp_dir = tempdir().unwrap();
+        let config = create_test_config(temp_dir.path()).await;
+        let backend = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let entry = LogEntry::new(LogLevel::Info, "ordered".to_string(), "stored".to_string());
+        backend.store_entry(entry).await.unwrap();
+
+        // A record still sitting in the buffer when rotation takes the lock
+        let writer = Arc::clone(&*backend.file_writers.get("ordered").unwrap());
+        let mut writer_guard = writer.write().await;
+        let buffered = LogEntry::new(LogLevel::Info, "ordered".to_string(), "buffered".to_string());
+        let record = format!("{}\n", buffered.to_json().unwrap());
+        writer_guard.writer.write_all(record.as_bytes()).await.unwrap();
+
+        // A write arriving during the rotation waits for the lock
+        let racing = tokio::spawn({
+            let backend = Arc::clone(&backend);
+            async move {
+                let entry = LogEntry::new(LogLevel::Info, "ordered".to_string(), "racing".to_string());
+                backend.store_entry(entry).await.unwrap();
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!racing.is_finished());
+
+        let archive = backend
+            .rotate_locked("ordered", "ordered", &mut writer_guard, RotationReason::Manual)
+            .await
+            .unwrap();
+        drop(writer_guard);
+        racing.await.unwrap();
+
+        let messages = |content: String| -> Vec<String> {
+            content.lines().map(|line| LogEntry::from_json(line).unwrap().message).collect()
+        };
+        let archived = messages(fs::read_to_string(&archive).await.unwrap());
+        let live = messages(fs::read_to_string(temp_dir.path().join("ordered.log")).await.unwrap());
+        assert_eq!(archived, vec!["stored", "buffered"]);
+        assert_eq!(live, vec!["racing"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_rotation_under_concurrent_writes() {
+        const WRITERS: usize = 8;
+        const PER_WRITER: usize = 200;
+
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.rotation.keep_files = 0;
+        let backend = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let rotator = tokio::spawn({
+            let (backend, done) = (Arc::clone(&backend), Arc::clone(&done));
+            async move {
+                let mut rotations = 0;
+                while !done.load(Ordering::SeqCst) {
+                    if backend.rotate("stress").await.unwrap().is_some() {
+                        rotations += 1;
+                    }
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                rotations
+            }
+        });
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|writer| {
+                let backend = Arc::clone(&backend);
+                tokio::spawn(async move {
+                    for seq in 0..PER_WRITER {
+                        let message = format!("{} {}", writer, seq);
+                        let entry = LogEntry::new(LogLevel::Info, "stress".to_string(), message);
+                        backend.store_entry(entry).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        assert!(rotator.await.unwrap() > 0);
+
+        // Reading archives oldest first, then the live file, gives each
+        // writer's entries exactly once and in the order they were written
+        let mut files = backend.list_archives("stress").await.unwrap();
+        files.push(temp_dir.path().join("stress.log"));
+        let mut seen = vec![Vec::new(); WRITERS];
+        for file in files {
+            for line in fs::read_to_string(&file).await.unwrap().lines() {
+                let message = LogEntry::from_json(line).unwrap().message;
+                let (writer, seq) = message.split_once(' ').unwrap();
+                seen[writer.parse::<usize>().unwrap()].push(seq.parse::<usize>().unwrap());
+            }
+        }
+        for sequence in seen {
+            assert_eq!(sequence, (0..PER_WRITER).collect::<Vec<_>>());
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_rotated_archives_are_compressed() {
+        use std::io::Read;
 
-        {
-            let mut writer_guard = writer.write().await;
-            writer_guard.write_all(formatted_entry.as_bytes()).await?;
-            writer_guard.write_all(b"\n").await?;
-            writer_guard.flush().await?;
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.file.compression = true;
+        config.backends.file.compression_level = 9;
+        config.storage.rotation.keep_files = 2;
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        let mut archives = Vec::new();
+        for round in 0..3 {
+            let entry = LogEntry::new(LogLevel::Info, "packed".to_string(), format!("round {}", round));
+            backend.store_entry(entry).await.unwrap();
+            archives.push(backend.rotate("packed").await.unwrap().unwrap());
         }
 
-        Ok(())
-    }
+        let newest = archives.last().unwrap();
+        assert!(newest.to_string_lossy().ends_with(".log.gz"), "{}", newest.display());
+        assert!(!newest.with_extension("").exists());
+        // Compressed archives are listed and pruned like plain ones
+        assert_eq!(backend.list_archives("packed").await.unwrap(), archives[1..].to_vec());
 
-    fn get_log_file_path(&self, daemon_name: &str) -> PathBuf {
-        self.config.storage.output_directory.join(format!("{}.log", daemon_name))
+        let mut content = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(newest).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert!(content.contains("round 2"));
     }
 
-    async fn create_file_writer(&self, file_path: &Path) -> Result<BufWriter<tokio::fs::File>> {
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(file_path)
-            .await?;
-        Ok(BufWriter::new(file))
+    #[tokio::test]
+    async fn test_fsync_always_and_never() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.fsync = FsyncPolicy::Always;
+        let backend = StorageBackend::new(&config).await.unwrap();
+        for i in 0..3 {
+            let entry = LogEntry::new(LogLevel::Info, "audit".to_string(), format!("entry {}", i));
+            backend.store_entry(entry).await.unwrap();
+        }
+        assert_eq!(backend.sync_calls.load(Ordering::Relaxed), 3);
+
+        config.storage.fsync = FsyncPolicy::Never;
+        let backend = StorageBackend::new(&config).await.unwrap();
+        let entry = LogEntry::new(LogLevel::Info, "audit".to_string(), "entry".to_string());
+        backend.store_entry(entry).await.unwrap();
+        backend.rotate("audit").await.unwrap();
+        assert_eq!(backend.sync_calls.load(Ordering::Relaxed), 0);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::types::LogLevel;
-    use tempfile::tempdir;
-    use tokio::fs;
+    #[tokio::test]
+    async fn test_drop_patterns() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.server.drop_patterns = vec!["healthcheck".to_string(), r"^GET /metrics\b".to_string()];
+        let backend = StorageBackend::new(&config).await.unwrap();
 
-    async fn create_test_config(dir: &Path) -> ServerConfig {
-        let mut config = ServerConfig::default();
-        config.storage.output_directory = dir.to_path_buf();
-        config.backends.file.enabled = true;
-        config.backends.file.format = "json".to_string();
-        config
+        let messages = [
+            "healthcheck ok",
+            "user signed in",
+            "GET /metrics 200",
+            "periodic healthcheck passed",
+            "order 42 created",
+        ];
+        for message in messages {
+            let entry = LogEntry::new(LogLevel::Info, "noisy".to_string(), message.to_string());
+            backend.store_entry(entry).await.unwrap();
+        }
+
+        assert_eq!(backend.pattern_dropped_total(), 3);
+        let content = fs::read_to_string(temp_dir.path().join("noisy.log")).await.unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("user signed in"));
+        assert!(content.contains("order 42 created"));
+        assert!(!content.contains("healthcheck"));
     }
 
     #[tokio::test]
-    async fn test_storage_backend_creation() {
+    async fn test_unknown_schema_version_warns_once_and_stores() {
         let temp_dir = tempdir().unwrap();
         let config = create_test_config(temp_dir.path()).await;
-        
-        let backend = StorageBackend::new(&config).await;
-        assert!(backend.is_ok());
-        
-        let backend = backend.unwrap();
-        assert_eq!(backend.config.storage.output_directory, temp_dir.path());
-        assert!(backend.file_writers.is_empty());
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        let current = LogEntry::new(LogLevel::Info, "mixed".to_string(), "current".to_string());
+        assert!(!backend.check_schema_version(&current));
+
+        let mut future = LogEntry::new(LogLevel::Info, "mixed".to_string(), "future".to_string());
+        future.schema_version = LOG_ENTRY_SCHEMA_VERSION + 1;
+        assert!(backend.check_schema_version(&future));
+        assert!(!backend.check_schema_version(&future));
+
+        backend.store_entry(future).await.unwrap();
+        let content = fs::read_to_string(temp_dir.path().join("mixed.log")).await.unwrap();
+        assert!(content.contains("future"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_drop_pattern_fails_validation() {
+        let mut config = ServerConfig::default();
+        config.server.drop_patterns = vec!["(unclosed".to_string()];
+        assert!(matches!(config.validate(), Err(LogStreamError::Config(_))));
     }
 
     #[tokio::test]
@@ -263,6 +2099,34 @@ mod tests {
         assert!(!log_file.exists());
     }
 
+    #[tokio::test]
+    async fn test_all_backends_disabled_counts_without_writing() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.disable_all();
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        for i in 0..5 {
+            let entry = LogEntry::new(LogLevel::Info, "null-sink".to_string(), format!("message {}", i));
+            backend.store_entry(entry).await.unwrap();
+        }
+        backend.record_rejected_line();
+
+        assert_eq!(
+            backend.stats(),
+            StorageStats {
+                entries_accepted: 5,
+                rejected_lines: 1,
+                pattern_dropped: 0,
+                disk_full_dropped: 0,
+                pipe_dropped: 0,
+                store_errors: 0,
+            }
+        );
+        let mut files = fs::read_dir(temp_dir.path()).await.unwrap();
+        assert!(files.next_entry().await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_concurrent_writes() {
         let temp_dir = tempdir().unwrap();
@@ -302,6 +2166,145 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_summaries_per_window() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.summary_interval_secs = Some(3600);
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        // First window: 2 info + 1 error
+        for level in [LogLevel::Info, LogLevel::Info, LogLevel::Error] {
+            let entry = LogEntry::new(level, "summary-daemon".to_string(), "msg".to_string());
+            backend.store_entry(entry).await.unwrap();
+        }
+        let first = backend.write_summaries().await.unwrap();
+        assert_eq!(first.len(), 1);
+
+        // Second window: 1 warning
+        let entry = LogEntry::new(LogLevel::Warning, "summary-daemon".to_string(), "msg".to_string());
+        backend.store_entry(entry).await.unwrap();
+        backend.write_summaries().await.unwrap();
+
+        let content = fs::read_to_string(temp_dir.path().join("summary-daemon.summary.jsonl"))
+            .await
+            .unwrap();
+        let records: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+
+        assert_eq!(records[0]["daemon"], "summary-daemon");
+        assert_eq!(records[0]["total"], 3);
+        assert_eq!(records[0]["counts"]["Info"], 2);
+        assert_eq!(records[0]["counts"]["Error"], 1);
+        assert_eq!(records[0]["counts"]["Warning"], 0);
+
+        assert_eq!(records[1]["total"], 1);
+        assert_eq!(records[1]["counts"]["Warning"], 1);
+        assert_eq!(records[1]["counts"]["Info"], 0);
+
+        // An empty window writes nothing
+        assert!(backend.write_summaries().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_summaries_disabled_by_default() {
+        let temp_dir = tempdir().unwrap();
+        let config = create_test_config(temp_dir.path()).await;
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        let entry = LogEntry::new(LogLevel::Info, "quiet".to_string(), "msg".to_string());
+        backend.store_entry(entry).await.unwrap();
+
+        assert!(backend.write_summaries().await.unwrap().is_empty());
+        assert!(!temp_dir.path().join("quiet.summary.jsonl").exists());
+    }
+
+    #[tokio::test]
+    async fn test_size_based_rotation() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.max_file_size = 1024;
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        for i in 0..20 {
+            let entry = LogEntry::new(LogLevel::Info, "sized".to_string(), format!("Message {}", i));
+            backend.store_entry(entry).await.unwrap();
+        }
+
+        let archives = backend.list_archives("sized").await.unwrap();
+        assert!(!archives.is_empty());
+
+        // Every entry is in exactly one file
+        let mut total_lines = fs::read_to_string(temp_dir.path().join("sized.log"))
+            .await
+            .unwrap()
+            .lines()
+            .count();
+        for archive in &archives {
+            let content = fs::read_to_string(archive).await.unwrap();
+            assert!(content.len() as u64 >= config.storage.max_file_size);
+            total_lines += content.lines().count();
+        }
+        assert_eq!(total_lines, 20);
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_configured_archives() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.rotation.keep_files = 2;
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        for i in 0..5 {
+            let entry = LogEntry::new(LogLevel::Info, "pruned".to_string(), format!("Message {}", i));
+            backend.store_entry(entry).await.unwrap();
+            backend.rotate("pruned").await.unwrap();
+        }
+
+        let archives = backend.list_archives("pruned").await.unwrap();
+        assert_eq!(archives.len(), 2);
+
+        // The newest archives survive
+        let newest = fs::read_to_string(&archives[1]).await.unwrap();
+        assert!(newest.contains("Message 4"));
+    }
+
+    #[tokio::test]
+    async fn test_pruning_skips_daemons_named_like_archives() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.rotation.keep_files = 1;
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        backend
+            .store_entry(LogEntry::new(LogLevel::Info, "worker.1".to_string(), "other daemon".to_string()))
+            .await
+            .unwrap();
+        for i in 0..3 {
+            let entry = LogEntry::new(LogLevel::Info, "worker".to_string(), format!("Message {}", i));
+            backend.store_entry(entry).await.unwrap();
+            backend.rotate("worker").await.unwrap();
+        }
+
+        let archives = backend.list_archives("worker").await.unwrap();
+        assert_eq!(archives.len(), 1);
+        assert!(!archives.contains(&temp_dir.path().join("worker.1.log")));
+        let other = fs::read_to_string(temp_dir.path().join("worker.1.log")).await.unwrap();
+        assert!(other.contains("other daemon"));
+    }
+
+    #[tokio::test]
+    async fn test_rotate_unknown_daemon() {
+        let temp_dir = tempdir().unwrap();
+        let config = create_test_config(temp_dir.path()).await;
+        let backend = StorageBackend::new(&config).await.unwrap();
+
+        assert!(backend.rotate("missing").await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_get_log_file_path() {
         let temp_dir = tempdir().unwrap();
@@ -314,4 +2317,404 @@ mod tests {
         let path2 = backend.get_log_file_path("another-daemon");
         assert_eq!(path2, temp_dir.path().join("another-daemon.log"));
     }
+
+    #[tokio::test]
+    async fn test_enforce_monotonic_clamps_or_rejects_earlier_entries() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.server.enforce_monotonic = MonotonicPolicy::Clamp;
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        let first = LogEntry::new(LogLevel::Info, "audit".to_string(), "first".to_string());
+        let mut rewound = LogEntry::new(LogLevel::Info, "audit".to_string(), "rewound".to_string());
+        rewound.timestamp = first.timestamp - chrono::Duration::seconds(30);
+        // Other daemons are tracked separately
+        let mut other = LogEntry::new(LogLevel::Info, "other".to_string(), "other".to_string());
+        other.timestamp = rewound.timestamp;
+        for entry in [first.clone(), rewound, other] {
+            storage.store_entry(entry).await.unwrap();
+        }
+
+        let content = fs::read_to_string(temp_dir.path().join("audit.log")).await.unwrap();
+        let stored: Vec<LogEntry> = content.lines().map(|line| LogEntry::from_json(line).unwrap()).collect();
+        assert_eq!(stored[0].timestamp, first.timestamp);
+        assert!(!stored[0].fields.contains_key("clamped"));
+        assert_eq!(stored[1].message, "rewound");
+        assert_eq!(stored[1].timestamp, first.timestamp);
+        assert_eq!(stored[1].fields["clamped"], "true");
+        let other = fs::read_to_string(temp_dir.path().join("other.log")).await.unwrap();
+        assert!(!LogEntry::from_json(other.trim()).unwrap().fields.contains_key("clamped"));
+
+        config.server.enforce_monotonic = MonotonicPolicy::Reject;
+        let reject_dir = tempdir().unwrap();
+        config.storage.output_directory = reject_dir.path().to_path_buf();
+        let storage = StorageBackend::new(&config).await.unwrap();
+        let mut rewound = LogEntry::new(LogLevel::Info, "audit".to_string(), "rewound".to_string());
+        rewound.timestamp = first.timestamp - chrono::Duration::seconds(30);
+        storage.store_entry(first).await.unwrap();
+        assert!(matches!(storage.store_entry(rewound).await, Err(LogStreamError::Server(_))));
+        let content = fs::read_to_string(reject_dir.path().join("audit.log")).await.unwrap();
+        assert_eq!(content.lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hash_buckets_spread_files() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.storage.hash_buckets = 8;
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        let daemons: Vec<String> = (0..40).map(|i| format!("daemon-{:02}", i)).collect();
+        for daemon in &daemons {
+            let entry = LogEntry::new(LogLevel::Info, daemon.clone(), format!("from {}", daemon));
+            storage.store_entry(entry).await.unwrap();
+        }
+
+        let mut buckets = std::collections::HashSet::new();
+        for daemon in &daemons {
+            let path = storage.log_file_path(daemon).unwrap();
+            let bucket = path.parent().unwrap();
+            assert_eq!(bucket.parent().unwrap(), temp_dir.path());
+            assert!(path.exists(), "{}", path.display());
+            buckets.insert(bucket.to_path_buf());
+
+            let tail = storage.tail(daemon, 1).await.unwrap();
+            assert_eq!(tail[0].message, format!("from {}", daemon));
+        }
+        assert!(buckets.len() > 1 && buckets.len() <= 8, "{:?}", buckets);
+        assert_eq!(storage.list_daemons().await.unwrap(), daemons);
+
+        // Archives stay next to their live file
+        let archive = storage.rotate("daemon-07").await.unwrap().unwrap();
+        assert_eq!(archive.parent(), storage.log_file_path("daemon-07").unwrap().parent());
+        assert_eq!(storage.list_archives("daemon-07").await.unwrap(), vec![archive]);
+        assert_eq!(storage.list_daemons().await.unwrap(), daemons);
+    }
+
+    #[tokio::test]
+    async fn test_custom_record_terminator() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.file.record_terminator = "\r\n".to_string();
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        let entries: Vec<LogEntry> = (0..2)
+            .map(|i| LogEntry::new(LogLevel::Info, "crlf".to_string(), format!("entry {}", i)))
+            .collect();
+        for entry in &entries {
+            storage.store_entry(entry.clone()).await.unwrap();
+        }
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("crlf.log")).await.unwrap();
+        let expected: String = entries
+            .iter()
+            .map(|entry| format!("{}\r\n", entry.to_json().unwrap()))
+            .collect();
+        assert_eq!(content, expected);
+
+        config.backends.file.record_terminator.clear();
+        assert!(matches!(config.validate(), Err(LogStreamError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_strict_ndjson_keeps_multiline_message_on_one_line() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.file.strict_ndjson = true;
+        config.backends.file.flatten_fields = true;
+        config.validate().unwrap();
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        let mut entry = LogEntry::new(LogLevel::Error, "ndjson".to_string(), "first\nsecond\r\nthird".to_string());
+        entry.fields = crate::fields! { trace => "at main\n  at run" };
+        storage.store_entry(entry.clone()).await.unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("ndjson.log")).await.unwrap();
+        assert_eq!(content.matches('\n').count(), 1);
+        assert!(content.ends_with('\n') && !content.contains('\r'));
+        let parsed: serde_json::Value = serde_json::from_str(content.trim_end()).unwrap();
+        assert_eq!(parsed["message"], "first\nsecond\r\nthird");
+    }
+
+    #[tokio::test]
+    async fn test_omit_empty_fields() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.file.omit_empty_fields = true;
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        let plain = LogEntry::new(LogLevel::Info, "compact".to_string(), "no fields".to_string());
+        let mut detailed = LogEntry::new(LogLevel::Info, "compact".to_string(), "fields".to_string());
+        detailed.fields.insert("user".to_string(), "42".to_string());
+        detailed.pid = Some(7);
+        storage.store_entry(plain.clone()).await.unwrap();
+        storage.store_entry(detailed).await.unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("compact.log")).await.unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        let raw: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        for key in ["fields", "pid", "hostname"] {
+            assert!(raw.get(key).is_none(), "{} should be omitted", key);
+        }
+        let parsed = LogEntry::from_json(lines[0]).unwrap();
+        assert_eq!(parsed.id, plain.id);
+        assert!(parsed.fields.is_empty());
+        assert_eq!(parsed.pid, None);
+        assert_eq!(parsed.hostname, None);
+
+        let parsed = LogEntry::from_json(lines[1]).unwrap();
+        assert_eq!(parsed.fields["user"], "42");
+        assert_eq!(parsed.pid, Some(7));
+        assert!(!lines[1].contains("hostname"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_concurrent_writers_never_tear_lines() {
+        let temp_dir = tempdir().unwrap();
+        let config = create_test_config(temp_dir.path()).await;
+        // Two backends stand in for two processes with their own handle on the file
+        let writers: Vec<_> = (0..2)
+            .map(|_| {
+                let config = config.clone();
+                tokio::spawn(async move {
+                    let storage = StorageBackend::new(&config).await.unwrap();
+                    for i in 0..50 {
+                        let mut entry = LogEntry::new(LogLevel::Info, "shared".to_string(), format!("entry {}", i));
+                        // Larger than the write buffer, so the entry isn't coalesced there
+                        entry.fields.insert("body".to_string(), "x".repeat(64 * 1024));
+                        storage.store_entry(entry).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("shared.log")).await.unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 100);
+        for line in lines {
+            let entry = LogEntry::from_json(line).unwrap();
+            assert_eq!(entry.fields["body"].len(), 64 * 1024);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flatten_fields() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.file.flatten_fields = true;
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        let mut entry = LogEntry::new(LogLevel::Warning, "flat".to_string(), "login".to_string());
+        entry.fields.insert("user_id".to_string(), "42".to_string());
+        entry.fields.insert("level".to_string(), "admin".to_string());
+        storage.store_entry(entry.clone()).await.unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("flat.log")).await.unwrap();
+        let raw: serde_json::Value = serde_json::from_str(content.trim_end()).unwrap();
+        assert_eq!(raw["field_user_id"], "42");
+        assert_eq!(raw["field_level"], "admin");
+        assert!(raw.get("fields").is_none());
+        assert!(raw.get("user_id").is_none());
+        assert_eq!(raw["id"], entry.id.to_string());
+        assert_eq!(raw["level"], serde_json::to_value(LogLevel::Warning).unwrap());
+        assert_eq!(raw["daemon"], "flat");
+        assert_eq!(raw["message"], "login");
+        assert_eq!(raw["schema_version"], LOG_ENTRY_SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_field_names_are_remapped() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = create_test_config(temp_dir.path()).await;
+        config.backends.file.field_names = HashMap::from([
+            ("message".to_string(), "msg".to_string()),
+            ("timestamp".to_string(), "@timestamp".to_string()),
+        ]);
+        config.validate().unwrap();
+        let storage = StorageBackend::new(&config).await.unwrap();
+
+        let entry = LogEntry::new(LogLevel::Info, "ecs".to_string(), "user logged in".to_string());
+        storage.store_entry(entry.clone()).await.unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("ecs.log")).await.unwrap();
+        let raw: serde_json::Value = serde_json::from_str(content.trim_end()).unwrap();
+        assert_eq!(raw["msg"], "user logged in");
+        assert_eq!(raw["@timestamp"], serde_json::to_value(entry.timestamp).unwrap());
+        assert!(raw.get("message").is_none() && raw.get("timestamp").is_none());
+        assert_eq!(raw["daemon"], "ecs");
+
+        // Reading the file back maps the names back
+        let tail = storage.tail("ecs", 1).await.unwrap();
+        assert_eq!((tail[0].id, tail[0].timestamp), (entry.id, entry.timestamp));
+        assert_eq!(tail[0].message, "user logged in");
+    }
+
+    async fn disk_full_storage(dir: &Path, policy: DiskFullPolicy) -> StorageBackend {
+        let mut config = create_test_config(dir).await;
+        config.storage.on_disk_full = policy;
+        config.storage.rotation.keep_files = 0;
+        StorageBackend::new(&config).await.unwrap()
+    }
+
+    fn stored_messages(content: &str) -> Vec<String> {
+        content.lines().map(|line| LogEntry::from_json(line).unwrap().message).collect()
+    }
+
+    #[tokio::test]
+    async fn test_disk_full_drop_newest() {
+        let temp_dir = tempdir().unwrap();
+        let storage = disk_full_storage(temp_dir.path(), DiskFullPolicy::DropNewest).await;
+
+        storage.store_entry(LogEntry::new(LogLevel::Info, "full".to_string(), "kept".to_string())).await.unwrap();
+        storage.fail_writes("full", 1).await;
+        storage.store_entry(LogEntry::new(LogLevel::Info, "full".to_string(), "dropped".to_string())).await.unwrap();
+        storage.store_entry(LogEntry::new(LogLevel::Info, "full".to_string(), "after".to_string())).await.unwrap();
+
+        // Nothing is left of the part of the dropped record that was written
+        let content = fs::read_to_string(temp_dir.path().join("full.log")).await.unwrap();
+        assert_eq!(stored_messages(&content), ["kept", "after"]);
+        assert_eq!(storage.stats().disk_full_dropped, 1);
+    }
+
+    #[tokio::test]
+    async fn test_disk_full_drop_oldest_deletes_archives() {
+        let temp_dir = tempdir().unwrap();
+        let storage = disk_full_storage(temp_dir.path(), DiskFullPolicy::DropOldest).await;
+
+        let mut archives = Vec::new();
+        for daemon in ["older", "newer"] {
+            storage.store_entry(LogEntry::new(LogLevel::Info, daemon.to_string(), "archived".to_string())).await.unwrap();
+            archives.push(storage.rotate(daemon).await.unwrap().unwrap());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // Space frees up once one archive is gone
+        storage.fail_writes("newer", 1).await;
+        storage.store_entry(LogEntry::new(LogLevel::Info, "newer".to_string(), "fits".to_string())).await.unwrap();
+        assert!(!archives[0].exists());
+        assert!(archives[1].exists());
+        let content = fs::read_to_string(temp_dir.path().join("newer.log")).await.unwrap();
+        assert_eq!(stored_messages(&content), ["fits"]);
+
+        // With every archive deleted and still no space, the entry is dropped
+        storage.fail_writes("newer", 2).await;
+        storage.store_entry(LogEntry::new(LogLevel::Info, "newer".to_string(), "dropped".to_string())).await.unwrap();
+        assert!(!archives[1].exists());
+        let content = fs::read_to_string(temp_dir.path().join("newer.log")).await.unwrap();
+        assert_eq!(stored_messages(&content), ["fits"]);
+        assert_eq!(storage.stats().disk_full_dropped, 1);
+    }
+
+    #[tokio::test]
+    async fn test_disk_full_block_retries_until_written() {
+        let temp_dir = tempdir().unwrap();
+        let storage = disk_full_storage(temp_dir.path(), DiskFullPolicy::Block).await;
+        storage.store_entry(LogEntry::new(LogLevel::Info, "full".to_string(), "first".to_string())).await.unwrap();
+
+        storage.fail_writes("full", 3).await;
+        let started = std::time::Instant::now();
+        storage.store_entry(LogEntry::new(LogLevel::Info, "full".to_string(), "waited".to_string())).await.unwrap();
+
+        assert!(started.elapsed() >= DISK_FULL_RETRY_INTERVAL * 2);
+        let content = fs::read_to_string(temp_dir.path().join("full.log")).await.unwrap();
+        assert_eq!(stored_messages(&content), ["first", "waited"]);
+        assert_eq!(storage.stats().disk_full_dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn test_disk_full_retries_whole_large_record() {
+        let temp_dir = tempdir().unwrap();
+        let storage = disk_full_storage(temp_dir.path(), DiskFullPolicy::Block).await;
+        storage.store_entry(LogEntry::new(LogLevel::Info, "large".to_string(), "first".to_string())).await.unwrap();
+
+        // Far larger than any write buffer, and torn by the full disk
+        let message = "x".repeat(256 * 1024);
+        storage.fail_writes("large", 1).await;
+        storage.store_entry(LogEntry::new(LogLevel::Info, "large".to_string(), message.clone())).await.unwrap();
+
+        let content = fs::read_to_string(temp_dir.path().join("large.log")).await.unwrap();
+        assert_eq!(stored_messages(&content), ["first".to_string(), message]);
+        assert_eq!(storage.stats().disk_full_dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn test_disk_full_block_gives_up_on_shutdown() {
+        let temp_dir = tempdir().unwrap();
+        let storage = Arc::new(disk_full_storage(temp_dir.path(), DiskFullPolicy::Block).await);
+        storage.store_entry(LogEntry::new(LogLevel::Info, "full".to_string(), "first".to_string())).await.unwrap();
+
+        storage.fail_writes("full", usize::MAX).await;
+        let held = tokio::spawn({
+            let storage = Arc::clone(&storage);
+            async move { storage.store_entry(LogEntry::new(LogLevel::Info, "full".to_string(), "held".to_string())).await }
+        });
+        tokio::time::sleep(DISK_FULL_RETRY_INTERVAL * 2).await;
+        assert!(!held.is_finished());
+
+        storage.begin_shutdown();
+        tokio::time::timeout(Duration::from_secs(5), held).await.unwrap().unwrap().unwrap();
+        // Rotation gets the writer back
+        assert!(storage.rotate("full").await.unwrap().is_some());
+        assert_eq!(storage.stats().disk_full_dropped, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fifo_reader_disconnect() {
+        use crate::types::MockClock;
+        use std::io::Read;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let temp_dir = tempdir().unwrap();
+        let fifo = temp_dir.path().join("piped.log");
+        let fifo_c = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo_c.as_ptr(), 0o600) }, 0);
+        let open_reader = || {
+            std::fs::OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(&fifo).unwrap()
+        };
+        let read_all = |reader: &mut std::fs::File| {
+            let mut buf = vec![0; 4096];
+            let n = reader.read(&mut buf).unwrap();
+            stored_messages(&String::from_utf8_lossy(&buf[..n]))
+        };
+
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        // A FIFO is never rotated, however much goes through it
+        config.storage.max_file_size = 1;
+        let clock = Arc::new(MockClock::default());
+        let storage = StorageBackend::with_clock(&config, clock.clone()).await.unwrap();
+        let store = |message: &str| storage.store_entry(LogEntry::new(LogLevel::Info, "piped".to_string(), message.to_string()));
+
+        let mut reader = open_reader();
+        store("first").await.unwrap();
+        assert_eq!(read_all(&mut reader), ["first"]);
+
+        // The reader goes away: entries are dropped, but storing carries on
+        drop(reader);
+        store("lost").await.unwrap();
+        store("also lost").await.unwrap();
+        clock.advance(PIPE_REOPEN_INTERVAL * 2);
+        store("no reader yet").await.unwrap();
+        assert_eq!(storage.stats().pipe_dropped, 3);
+
+        let mut reader = open_reader();
+        store("too soon").await.unwrap();
+        clock.advance(PIPE_REOPEN_INTERVAL * 2);
+        store("back").await.unwrap();
+        assert_eq!(read_all(&mut reader), ["back"]);
+        assert_eq!(storage.stats().pipe_dropped, 4);
+        assert!(std::fs::metadata(&fifo).unwrap().file_type().is_fifo());
+    }
+
+    #[test]
+    fn test_disk_full_detection() {
+        assert!(is_disk_full(&std::io::Error::from_raw_os_error(libc::ENOSPC)));
+        assert!(is_disk_full(&std::io::ErrorKind::StorageFull.into()));
+        assert!(!is_disk_full(&std::io::ErrorKind::PermissionDenied.into()));
+        assert!(is_archive_stamp("20240601-120000-000-2"));
+        assert!(!is_archive_stamp("2"));
+    }
 }
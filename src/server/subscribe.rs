@@ -0,0 +1,213 @@
+//! Live streams of stored entries
+//!
+//! Every entry the server accepts is published to subscribers: global ones
+//! get all entries, per-daemon ones only that daemon's. Per-daemon channels
+//! are created on first subscribe, so entries nobody watches are never
+//! cloned. A [`CatchUp`] bridges a daemon's recent stored entries to its
+//! live ones.
+
+use crate::types::LogEntry;
+use dashmap::DashMap;
+use std::collections::{HashSet, VecDeque};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Entries a subscriber can fall behind by before it misses some
+///
+/// A receiver that lags further gets [`broadcast::error::RecvError::Lagged`]
+/// and continues with the oldest entry still buffered.
+pub const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Broadcast channels for global and per-daemon subscribers
+pub struct Subscribers {
+    all: broadcast::Sender<LogEntry>,
+    per_daemon: DashMap<String, broadcast::Sender<LogEntry>>,
+    closed: CancellationToken,
+}
+
+impl Subscribers {
+    /// Create with no subscribers
+    pub fn new() -> Self {
+        Self {
+            all: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+            per_daemon: DashMap::new(),
+            closed: CancellationToken::new(),
+        }
+    }
+
+    /// Receive every entry published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<LogEntry> {
+        self.all.subscribe()
+    }
+
+    /// Receive the entries of one daemon published from now on
+    ///
+    /// Channels whose receivers have all been dropped are removed here and
+    /// when their daemon next logs.
+    pub fn subscribe_daemon(&self, daemon: &str) -> broadcast::Receiver<LogEntry> {
+        self.per_daemon.retain(|_, tx| tx.receiver_count() > 0);
+        self.per_daemon
+            .entry(daemon.to_string())
+            .or_insert_with(|| broadcast::channel(SUBSCRIBER_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Bridge a daemon's stored entries to its live ones
+    ///
+    /// `recent` holds the daemon's latest stored entries, oldest first, of
+    /// which the last `tail` are sent before the live ones. Subscribe with
+    /// `live` before reading `recent`, so no entry falls between the two.
+    /// Any live entry already in `recent` is skipped, so `recent` should
+    /// reach [`SUBSCRIBER_CAPACITY`] entries further back than `tail`: more
+    /// entries than that can't be stored during the read without the
+    /// receiver lagging anyway.
+    pub fn catch_up(&self, mut recent: Vec<LogEntry>, tail: usize, live: broadcast::Receiver<LogEntry>) -> CatchUp {
+        let seen = recent.iter().map(|entry| entry.id).collect();
+        let backlog = recent.split_off(recent.len().saturating_sub(tail));
+        CatchUp {
+            backlog: backlog.into(),
+            live,
+            seen,
+            closed: self.closed.clone(),
+        }
+    }
+
+    /// End every [`CatchUp`] once its backlog is sent, e.g. on shutdown
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+
+    /// Number of per-daemon channels currently held
+    pub fn daemon_channels(&self) -> usize {
+        self.per_daemon.len()
+    }
+
+    /// Send an entry to the global subscribers and those of its daemon
+    pub fn publish(&self, entry: &LogEntry) {
+        if self.all.receiver_count() > 0 {
+            let _ = self.all.send(entry.clone());
+        }
+
+        let idle = match self.per_daemon.get(&entry.daemon) {
+            Some(tx) => tx.send(entry.clone()).is_err(),
+            None => return,
+        };
+        if idle {
+            self.per_daemon
+                .remove_if(&entry.daemon, |_, tx| tx.receiver_count() == 0);
+        }
+    }
+}
+
+/// A daemon's recent entries followed by its live ones, from [`Subscribers::catch_up`]
+///
+/// Entries stored while the backlog was read show up both in the file and
+/// live; the live copies are skipped by id, so each entry is yielded once
+/// and in order.
+pub struct CatchUp {
+    backlog: VecDeque<LogEntry>,
+    live: broadcast::Receiver<LogEntry>,
+    /// Ids of stored entries that may still come in live
+    seen: HashSet<Uuid>,
+    closed: CancellationToken,
+}
+
+impl CatchUp {
+    /// The next entry, or `None` once the subscribers are closed
+    ///
+    /// Entries missed by lagging more than [`SUBSCRIBER_CAPACITY`] behind
+    /// are skipped.
+    pub async fn next(&mut self) -> Option<LogEntry> {
+        if let Some(entry) = self.backlog.pop_front() {
+            return Some(entry);
+        }
+
+        loop {
+            let received = tokio::select! {
+                received = self.live.recv() => received,
+                _ = self.closed.cancelled() => return None,
+            };
+            match received {
+                Ok(entry) if self.seen.remove(&entry.id) => continue,
+                Ok(entry) => return Some(entry),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Default for Subscribers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LogLevel;
+
+    fn entry(daemon: &str, message: &str) -> LogEntry {
+        LogEntry::new(LogLevel::Info, daemon.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn test_daemon_subscriber_only_gets_its_daemon() {
+        let subscribers = Subscribers::new();
+        let mut all = subscribers.subscribe();
+        let mut api = subscribers.subscribe_daemon("api");
+
+        subscribers.publish(&entry("api", "one"));
+        subscribers.publish(&entry("db", "two"));
+        subscribers.publish(&entry("api", "three"));
+
+        let global: Vec<String> = std::iter::from_fn(|| all.try_recv().ok()).map(|e| e.message).collect();
+        assert_eq!(global, ["one", "two", "three"]);
+        let sharded: Vec<String> = std::iter::from_fn(|| api.try_recv().ok()).map(|e| e.message).collect();
+        assert_eq!(sharded, ["one", "three"]);
+    }
+
+    #[tokio::test]
+    async fn test_catch_up_skips_overlap_once() {
+        let subscribers = Subscribers::new();
+        let live = subscribers.subscribe_daemon("api");
+        let recent = vec![entry("api", "older"), entry("api", "old"), entry("api", "overlap")];
+
+        // Stored after subscribing but before the backlog was read, including
+        // one from before the requested tail
+        subscribers.publish(&recent[0]);
+        subscribers.publish(&recent[2]);
+        subscribers.publish(&entry("api", "new"));
+
+        let mut catch_up = subscribers.catch_up(recent, 2, live);
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.push(catch_up.next().await.unwrap().message);
+        }
+        assert_eq!(received, ["old", "overlap", "new"]);
+
+        subscribers.close();
+        assert!(catch_up.next().await.is_none());
+    }
+
+    #[test]
+    fn test_idle_daemon_channels_are_removed() {
+        let subscribers = Subscribers::new();
+        let api = subscribers.subscribe_daemon("api");
+        let db = subscribers.subscribe_daemon("db");
+        assert_eq!(subscribers.daemon_channels(), 2);
+
+        // Removed when its daemon next logs
+        drop(api);
+        subscribers.publish(&entry("api", "unwatched"));
+        assert_eq!(subscribers.daemon_channels(), 1);
+
+        // Removed on the next subscribe even if the daemon stays quiet
+        drop(db);
+        let _web = subscribers.subscribe_daemon("web");
+        assert_eq!(subscribers.daemon_channels(), 1);
+    }
+}
@@ -1,14 +1,40 @@
 //! Unix socket server implementation for LogStream
 
+use crate::build_info::BUILD_INFO;
 use crate::config::ServerConfig;
-use crate::server::StorageBackend;
-use crate::types::LogEntry;
+use crate::server::audit::{ConnectionAudit, ConnectionStats, CountingReader};
+use crate::server::listener::{accept_any, bind_unix, inherited_listeners, unlink_sockets, Connection, Listener};
+use crate::server::{CatchUp, IngestRouter, StorageBackend};
+use crate::config::ListenerSpec;
+use crate::types::{ControlCommand, Frame, LineLimits, LogEntry, LogEntryStream, LogFields, LogLevel, StoragePathReply, PONG_RESPONSE};
 use crate::{LogStreamError, Result};
-use std::path::Path;
+use std::io;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::UnixListener;
 use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, MissedTickBehavior};
+
+/// Daemon name the server's own events, such as failing listeners, are stored under
+pub const SERVER_DAEMON: &str = "logstream.server";
+
+/// First pause after a failed accept, doubling with each further failure in a row
+const ACCEPT_BACKOFF_START: Duration = Duration::from_millis(5);
+
+/// Longest pause between failed accepts
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Failed accepts in a row after which a listener is bound again, or closed if that didn't help
+const ACCEPT_FAILURES_BEFORE_REBIND: u32 = 8;
+
+/// Accepts that failed in a row on one listener
+#[derive(Debug, Clone, Copy, Default)]
+struct AcceptFailures {
+    count: u32,
+    /// The listener was already bound again during this run of failures
+    rebound: bool,
+}
 
 /// Unix socket server for accepting log connections
 pub struct UnixSocketServer {
@@ -31,72 +57,405 @@ impl UnixSocketServer {
         })
     }
 
-    /// Start the Unix socket server
-    pub async fn start(mut self) -> Result<()> {
-        if Path::new(&self.config.server.socket_path).exists() {
-            std::fs::remove_file(&self.config.server.socket_path)?;
+    /// Start the Unix socket server on all configured listeners
+    pub async fn start(self) -> Result<()> {
+        let listeners = self.bind_all().await?;
+        self.serve_all(listeners).await
+    }
+
+    /// Bind the socket at the configured path
+    ///
+    /// An existing file at the path is replaced, or with `server.reuse_socket`
+    /// off makes this fail. Missing parent directories of the socket path are created. Clients can
+    /// connect as soon as this returns, though connections are only accepted
+    /// once [`UnixSocketServer::serve`] runs.
+    pub fn bind(&self) -> Result<UnixListener> {
+        bind_unix(&self.config.server.socket_path, self.config.server.reuse_socket)
+    }
+
+    /// Bind the main socket and every socket in `server.listeners`
+    ///
+    /// Under systemd socket activation the sockets systemd passed in take
+    /// the place of the main socket, which is then not bound.
+    pub async fn bind_all(&self) -> Result<Vec<Listener>> {
+        let mut listeners = match inherited_listeners()? {
+            Some(inherited) => inherited,
+            None => vec![Listener::Unix(self.bind()?)],
+        };
+        for spec in &self.config.server.listeners {
+            listeners.push(Listener::bind(spec, self.config.server.reuse_socket).await?);
         }
+        Ok(listeners)
+    }
 
-        let listener = UnixListener::bind(&self.config.server.socket_path)
-            .map_err(|e| LogStreamError::Server(format!("Failed to bind socket: {}", e)))?;
+    /// Accept connections on a listener from [`UnixSocketServer::bind`] until shutdown
+    pub async fn serve(self, listener: UnixListener) -> Result<()> {
+        self.serve_all(vec![Listener::Unix(listener)]).await
+    }
+
+    /// Accept connections on all the listeners until shutdown
+    ///
+    /// Entries from every listener are handed by an [`IngestRouter`] to the
+    /// shared ingest worker, or their daemon's dedicated one, for storage.
+    /// On shutdown all listeners are closed, open connections
+    /// get `shutdown_timeout_secs` to finish before they are force-closed,
+    /// and everything received is stored before this returns. With
+    /// `server.unlink_on_exit` the Unix socket files the server bound itself
+    /// are removed as the listeners close. With `server.max_accepts_per_sec` connections are
+    /// accepted at most that often, the rest waiting in the listen backlog.
+    ///
+    /// Failed accepts, e.g. when out of file descriptors, pause accepting
+    /// for a backoff from 5ms doubling up to 1s. A listener failing 8 times
+    /// in a row is bound again, unless systemd passed it in, and closed if
+    /// it keeps failing; once no listener is left the server shuts down and
+    /// returns the error.
+    pub async fn serve_all(self, listeners: Vec<Listener>) -> Result<()> {
+        let (router, workers) = IngestRouter::start(&self.config, &self.storage);
+        let served = self.serve_routed(listeners, router).await;
+
+        // Workers stop once every router is gone, after storing what they received
+        for worker in workers {
+            let _ = worker.await;
+        }
+        served
+    }
+
+    /// Accept connections until shutdown like [`UnixSocketServer::serve_all`], handing entries to `router`
+    ///
+    /// Returns once the connections are closed and `router` is dropped; the
+    /// workers behind it may still be storing entries, including those of
+    /// other clones of the router.
+    pub(crate) async fn serve_routed(mut self, mut listeners: Vec<Listener>, router: IngestRouter) -> Result<()> {
+        let mut connections = JoinSet::new();
+        let mut next_listener = 0;
+        // With `max_accepts_per_sec` each accept waits for a tick of the pacer
+        let mut pacer = self.config.server.max_accepts_per_sec.map(|rate| {
+            let mut pacer = tokio::time::interval(Duration::from_secs(1) / rate);
+            pacer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            pacer
+        });
+        let mut may_accept = pacer.is_none();
+        let mut connection_id = 0;
+        let mut accept_failures = vec![AcceptFailures::default(); listeners.len()];
+        let mut backoff_until = None;
+        let mut fatal = None;
 
         loop {
             tokio::select! {
-                result = listener.accept() => {
+                _ = async { pacer.as_mut().expect("only paced accepts wait").tick().await }, if !may_accept => {
+                    may_accept = true;
+                }
+                _ = async { tokio::time::sleep_until(backoff_until.expect("only backoffs wait")).await }, if backoff_until.is_some() => {
+                    backoff_until = None;
+                }
+                (index, result) = accept_any(&listeners, &mut next_listener), if may_accept && backoff_until.is_none() => {
+                    may_accept = pacer.is_none();
                     match result {
-                        Ok((stream, _)) => {
-                            let storage = Arc::clone(&self.storage);
-                            tokio::spawn(async move {
-                                let _ = Self::handle_connection(stream, storage).await;
-                            });
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to accept connection: {}", e);
+                        Ok(connection) => {
+                            accept_failures[index] = AcceptFailures::default();
+                            connection_id += 1;
+                            self.spawn_connection(&mut connections, connection, &router, connection_id);
                         }
+                        Err(e) => match Self::recover_accept(&mut listeners, &mut accept_failures, index, e, &router).await {
+                            Ok(pause) => backoff_until = Some(tokio::time::Instant::now() + pause),
+                            Err(e) => {
+                                fatal = Some(e);
+                                break;
+                            }
+                        },
                     }
                 }
+                // Reap finished connections so the set doesn't grow without bound
+                Some(_) = connections.join_next(), if !connections.is_empty() => {}
                 _ = self.shutdown_rx.recv() => {
                     break;
                 }
             }
         }
 
-        Ok(())
+        // Followers would otherwise hold their connections open until force-closed
+        self.storage.subscribers().close();
+        // Nor may a full disk hold the remaining entries forever
+        self.storage.begin_shutdown();
+        // Connections queued before shutdown may already hold sent entries
+        tokio::task::yield_now().await;
+        while let Ok((_, Ok(connection))) =
+            tokio::time::timeout(Duration::ZERO, accept_any(&listeners, &mut next_listener)).await
+        {
+            connection_id += 1;
+            self.spawn_connection(&mut connections, connection, &router, connection_id);
+        }
+        if self.config.server.unlink_on_exit {
+            unlink_sockets(&listeners);
+        }
+        drop(listeners);
+        let shutdown_timeout = Duration::from_secs(self.config.server.shutdown_timeout_secs);
+        let forced = drain_connections(&mut connections, shutdown_timeout).await;
+        if forced > 0 {
+            eprintln!(
+                "Force-closed {} connection(s) still open after the {}s shutdown timeout",
+                forced,
+                shutdown_timeout.as_secs()
+            );
+        }
+
+        fatal.map_or(Ok(()), Err)
+    }
+
+    /// Handle a failed accept on `listeners[index]`, returning how long to pause accepting
+    ///
+    /// After [`ACCEPT_FAILURES_BEFORE_REBIND`] failures in a row the listener
+    /// is bound again, once; if that fails or the failures go on, it is
+    /// closed. Inherited listeners are closed without rebinding. Errors once
+    /// no listener is left.
+    async fn recover_accept(
+        listeners: &mut Vec<Listener>,
+        failures: &mut Vec<AcceptFailures>,
+        index: usize,
+        error: io::Error,
+        router: &IngestRouter,
+    ) -> Result<Duration> {
+        let name = listeners[index].spec().map_or_else(|| "unnamed socket".to_string(), |spec| match spec {
+            ListenerSpec::Unix(path) => format!("unix {}", path),
+            ListenerSpec::Tcp(addr) => format!("tcp {}", addr),
+        });
+        let report = |level, event, message: String| {
+            eprintln!("{}", message);
+            let mut entry = LogEntry::new(level, SERVER_DAEMON.to_string(), message);
+            entry.fields.insert("event".to_string(), event);
+            entry.fields.insert("listener".to_string(), name.clone());
+            entry.fields.insert("error".to_string(), error.to_string());
+            let _ = router.try_send(entry);
+        };
+
+        let failure = &mut failures[index];
+        failure.count += 1;
+        if failure.count == 1 {
+            let message = format!("Failed to accept connection on {}: {}", name, error);
+            report(LogLevel::Warning, "accept_error".to_string(), message);
+        }
+        if failure.count < ACCEPT_FAILURES_BEFORE_REBIND {
+            return Ok((ACCEPT_BACKOFF_START * 2u32.pow(failure.count - 1)).min(ACCEPT_BACKOFF_MAX));
+        }
+
+        // The old socket is closed first so its address is free again. An
+        // inherited socket's address belongs to systemd, so it is only closed
+        let spec = Some(listeners.remove(index))
+            .filter(|listener| !listener.is_inherited() && !failure.rebound)
+            .and_then(|listener| listener.spec());
+        let rebound = match spec {
+            Some(spec) => Listener::bind(&spec, true).await.map_err(|e| eprintln!("Failed to rebind {}: {}", name, e)).ok(),
+            None => None,
+        };
+        match rebound {
+            Some(listener) => {
+                listeners.insert(index, listener);
+                *failure = AcceptFailures { count: 0, rebound: true };
+                let message = format!("Rebound {} after {} failed accepts in a row", name, ACCEPT_FAILURES_BEFORE_REBIND);
+                report(LogLevel::Warning, "listener_rebound".to_string(), message);
+            }
+            None => {
+                failures.remove(index);
+                let message = format!("Closed {} after repeated accept failures: {}", name, error);
+                report(LogLevel::Error, "listener_closed".to_string(), message);
+                if listeners.is_empty() {
+                    return Err(LogStreamError::Server(format!(
+                        "No listener left after accept failures on {}: {}",
+                        name, error
+                    )));
+                }
+            }
+        }
+        Ok(ACCEPT_BACKOFF_START)
     }
 
-    async fn handle_connection(
-        stream: UnixStream,
+    /// Handle a connection on its own task, auditing it with `server.audit_connections`
+    fn spawn_connection(
+        &self,
+        connections: &mut JoinSet<()>,
+        connection: Connection,
+        router: &IngestRouter,
+        id: u64,
+    ) {
+        let storage = Arc::clone(&self.storage);
+        let router = router.clone();
+        let limits = self.config.server.line_limits();
+        let audit = self.config.server.audit_connections.then(|| ConnectionAudit::open(id, &connection));
+        let context = self.config.server.connection_context.then(|| connection.context());
+        connections.spawn(async move {
+            if let Some(ref audit) = audit {
+                let _ = router.send(audit.connected()).await;
+            }
+            let unaudited = ConnectionStats::default();
+            let stats = audit.as_ref().map_or(&unaudited, |audit| &audit.stats);
+            let result = match connection {
+                Connection::Unix(stream) => {
+                    Self::serve_connection(stream, storage, router.clone(), limits, stats, context.as_ref()).await
+                }
+                Connection::Tcp(stream) => {
+                    Self::serve_connection(stream, storage, router.clone(), limits, stats, context.as_ref()).await
+                }
+            };
+            if let Some(ref audit) = audit {
+                let _ = router.send(audit.closed(&result)).await;
+            }
+        });
+    }
+
+    /// Serve a connection without auditing it
+    #[cfg(test)]
+    async fn handle_connection<S: AsyncRead + AsyncWrite>(
+        stream: S,
         storage: Arc<StorageBackend>,
+        router: IngestRouter,
+        limits: LineLimits,
     ) -> Result<()> {
-        let mut reader = BufReader::new(stream);
-        let mut line = String::new();
+        Self::serve_connection(stream, storage, router, limits, &ConnectionStats::default(), None).await
+    }
 
-        loop {
-            line.clear();
-            match reader.read_line(&mut line).await {
-                Ok(0) => break,
-                Ok(_) => {
-                    if let Ok(entry) = serde_json::from_str::<LogEntry>(&line.trim()) {
-                        storage.store_entry(entry).await?;
+    /// Read frames from a connection until it closes, counting its traffic into `stats`
+    ///
+    /// Fields in `context` are set on every entry, over any the client sent.
+    async fn serve_connection<S: AsyncRead + AsyncWrite>(
+        stream: S,
+        storage: Arc<StorageBackend>,
+        router: IngestRouter,
+        limits: LineLimits,
+        stats: &ConnectionStats,
+        context: Option<&LogFields>,
+    ) -> Result<()> {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut frames = LogEntryStream::with_limits(CountingReader::new(reader, stats), limits);
+
+        // Every complete line received before EOF or a read error is yielded
+        // by the stream first, so an abrupt disconnect loses only a partial line
+        while let Some(item) = frames.next_frame().await {
+            match item {
+                Ok(Frame::Entry(mut entry)) => {
+                    stats.record_entry();
+                    if let Some(context) = context {
+                        entry.fields.extend(context.iter().map(|(key, value)| (key.clone(), value.clone())));
+                    }
+                    // Waits while the worker's queue is full, holding the client back.
+                    // Workers only stop once every router is gone or on shutdown
+                    if router.send(entry).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(Frame::Control(ControlCommand::Ping)) => {
+                    let mut reply = String::with_capacity(PONG_RESPONSE.len() + 1);
+                    reply.push_str(PONG_RESPONSE);
+                    reply.push('\n');
+                    if writer.write_all(reply.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(Frame::Control(ControlCommand::Config)) => {
+                    let mut config = storage.config().redacted_json()?;
+                    config["build"] = serde_json::to_value(BUILD_INFO)?;
+                    let mut reply = config.to_string();
+                    reply.push('\n');
+                    if writer.write_all(reply.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(Frame::Control(ControlCommand::Stats)) => {
+                    let mut reply = serde_json::to_string(&storage.metrics_snapshot())?;
+                    reply.push('\n');
+                    if writer.write_all(reply.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(Frame::Control(ControlCommand::Follow { daemon, tail })) => {
+                    let catch_up = storage.catch_up(&daemon, tail).await?;
+                    Self::follow(catch_up, &mut frames, &mut writer).await;
+                    break;
+                }
+                Ok(Frame::Control(ControlCommand::Where { daemon })) => {
+                    let path = storage.log_file_path(&daemon);
+                    let mut reply = serde_json::to_string(&StoragePathReply { path })?;
+                    reply.push('\n');
+                    if writer.write_all(reply.as_bytes()).await.is_err() {
+                        break;
                     }
                 }
-                Err(_) => break,
+                Err(LogStreamError::Io(e)) => {
+                    stats.record_read_error(e.to_string());
+                    break;
+                }
+                // Malformed or oversized lines are counted and skipped
+                Err(_) => storage.record_rejected_line(),
             }
         }
 
         Ok(())
     }
+
+    /// Write entries from `catch_up` as JSON lines until the client disconnects
+    async fn follow<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+        mut catch_up: CatchUp,
+        frames: &mut LogEntryStream<R>,
+        writer: &mut W,
+    ) {
+        loop {
+            tokio::select! {
+                entry = catch_up.next() => {
+                    let Some(entry) = entry else {
+                        break;
+                    };
+                    let Ok(mut line) = entry.to_json() else {
+                        continue;
+                    };
+                    line.push('\n');
+                    if writer.write_all(line.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                // Anything more the client sends is ignored; EOF ends the follow
+                frame = frames.next_frame() => {
+                    if matches!(frame, None | Some(Err(LogStreamError::Io(_)))) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Wait up to `timeout` for connections to close, then abort the rest
+///
+/// Returns the number of connections that were force-closed.
+async fn drain_connections(connections: &mut JoinSet<()>, timeout: Duration) -> usize {
+    let drained = tokio::time::timeout(timeout, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_ok() {
+        return 0;
+    }
+
+    let forced = connections.len();
+    connections.shutdown().await;
+    forced
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::build_info::{GIT_SHA, VERSION};
+    use crate::client::LogClient;
+    use crate::config::{ListenerSpec, DEFAULT_INGEST_QUEUE_SIZE};
+    use crate::server::IngestQueue;
+    use crate::types::LogEntry;
+    use crate::types::stream::LOSSY_ENCODING_FIELD;
     use crate::types::LogLevel;
+    use std::os::unix::fs::FileTypeExt;
     use std::path::Path;
     use tempfile::tempdir;
-    use tokio::io::AsyncWriteExt;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
     use tokio::net::UnixStream;
-    use tokio::time::{timeout, Duration};
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
 
     async fn create_test_server(socket_path: &str, output_dir: &Path) -> (UnixSocketServer, Arc<StorageBackend>, broadcast::Sender<()>) {
         let mut config = ServerConfig::default();
@@ -112,6 +471,13 @@ mod tests {
         (server, storage, shutdown_tx)
     }
 
+    /// Ingest worker storing entries until the returned router and its clones are dropped
+    fn spawn_ingest(storage: &Arc<StorageBackend>) -> (IngestRouter, tokio::task::JoinHandle<()>) {
+        let (entries_tx, entries_rx) = mpsc::channel(DEFAULT_INGEST_QUEUE_SIZE);
+        let worker = tokio::spawn(IngestQueue::default().run(Arc::clone(storage), entries_rx));
+        (IngestRouter::from(entries_tx), worker)
+    }
+
     #[tokio::test]
     async fn test_unix_socket_server_creation() {
         let temp_dir = tempdir().unwrap();
@@ -145,12 +511,87 @@ mod tests {
         
         // Socket file should have been removed and recreated
         assert!(socket_path.exists());
+        assert!(std::fs::metadata(&socket_path).unwrap().file_type().is_socket());
         
         // Shutdown
         let _ = shutdown_tx.send(());
         let _ = timeout(Duration::from_secs(1), server_handle).await;
     }
 
+    #[tokio::test]
+    async fn test_existing_socket_path_is_an_error_without_reuse() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("precreated.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+        std::fs::write(&socket_path, "installer").unwrap();
+
+        let (mut server, _, _shutdown_tx) = create_test_server(&socket_str, temp_dir.path()).await;
+        server.config.server.reuse_socket = false;
+
+        match server.start().await {
+            Err(LogStreamError::Server(message)) => assert!(message.contains("already exists"), "{}", message),
+            other => panic!("expected bind to fail, got {:?}", other),
+        }
+        assert_eq!(std::fs::read_to_string(&socket_path).unwrap(), "installer");
+    }
+
+    #[tokio::test]
+    async fn test_unlink_on_exit_removes_socket_files() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("main.sock");
+        let extra_path = temp_dir.path().join("extra.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+
+        let (mut server, _, shutdown_tx) = create_test_server(&socket_str, temp_dir.path()).await;
+        server.config.server.listeners = vec![ListenerSpec::Unix(extra_path.to_string_lossy().to_string())];
+        server.config.server.unlink_on_exit = true;
+        let listeners = server.bind_all().await.unwrap();
+        assert!(socket_path.exists() && extra_path.exists());
+
+        let server_handle = tokio::spawn(server.serve_all(listeners));
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(1), server_handle).await.unwrap().unwrap().unwrap();
+
+        assert!(!socket_path.exists());
+        assert!(!extra_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_unlink_on_exit_keeps_inherited_sockets() {
+        let temp_dir = tempdir().unwrap();
+        let inherited_path = temp_dir.path().join("activated.sock");
+        let extra_path = temp_dir.path().join("extra.sock");
+        let socket_str = temp_dir.path().join("unused.sock").to_string_lossy().to_string();
+
+        let (mut server, _, shutdown_tx) = create_test_server(&socket_str, temp_dir.path()).await;
+        server.config.server.unlink_on_exit = true;
+        let inherited = UnixListener::bind(&inherited_path).unwrap();
+        let extra = UnixListener::bind(&extra_path).unwrap();
+        let listeners = vec![Listener::Inherited(Box::new(Listener::Unix(inherited))), Listener::Unix(extra)];
+
+        let server_handle = tokio::spawn(server.serve_all(listeners));
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(1), server_handle).await.unwrap().unwrap().unwrap();
+
+        assert!(inherited_path.exists());
+        assert!(!extra_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_socket_files_are_kept_on_exit_by_default() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("kept.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+
+        let (server, _, shutdown_tx) = create_test_server(&socket_str, temp_dir.path()).await;
+        let listeners = server.bind_all().await.unwrap();
+        let server_handle = tokio::spawn(server.serve_all(listeners));
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(1), server_handle).await.unwrap().unwrap().unwrap();
+
+        assert!(socket_path.exists());
+    }
+
     #[tokio::test]
     async fn test_handle_connection() {
         let temp_dir = tempdir().unwrap();
@@ -165,8 +606,9 @@ mod tests {
         
         // Handle connection in background
         let storage_clone = storage.clone();
+        let (entries_tx, worker) = spawn_ingest(&storage);
         let handle = tokio::spawn(async move {
-            UnixSocketServer::handle_connection(server, storage_clone).await
+            UnixSocketServer::handle_connection(server, storage_clone, entries_tx, LineLimits::default()).await
         });
         
         // Send a log entry
@@ -188,6 +630,7 @@ mod tests {
         // Wait for handler to complete
         let result = timeout(Duration::from_secs(1), handle).await;
         assert!(result.is_ok());
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
         
         // Verify log was stored
         let log_file = temp_dir.path().join("test-daemon.log");
@@ -196,6 +639,325 @@ mod tests {
         assert!(content.contains("Test message from handle_connection"));
     }
 
+    #[tokio::test]
+    async fn test_handle_connection_abrupt_disconnect() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.backends.file.enabled = true;
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+
+        // Three complete entries and a truncated fourth in one burst
+        let mut batch = String::new();
+        for i in 0..3 {
+            let entry = LogEntry::new(LogLevel::Info, "abrupt".to_string(), format!("Entry {}", i));
+            batch.push_str(&entry.to_json().unwrap());
+            batch.push('\n');
+        }
+        batch.push_str("{\"id\":\"truncat");
+        client.write_all(batch.as_bytes()).await.unwrap();
+        drop(client);
+
+        let (entries_tx, worker) = spawn_ingest(&storage);
+        let result = timeout(
+            Duration::from_secs(1),
+            UnixSocketServer::handle_connection(server, storage.clone(), entries_tx, LineLimits::default()),
+        )
+        .await;
+        assert!(result.unwrap().is_ok());
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("abrupt.log")).await.unwrap();
+        assert_eq!(content.lines().count(), 3);
+        for i in 0..3 {
+            assert!(content.contains(&format!("Entry {}", i)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_rejects_pathological_lines() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.backends.file.enabled = true;
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let limits = LineLimits {
+            max_line_bytes: 64 * 1024,
+            max_json_depth: 4,
+            ..Default::default()
+        };
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let (entries_tx, worker) = spawn_ingest(&storage);
+        let handle = tokio::spawn(UnixSocketServer::handle_connection(
+            server,
+            storage.clone(),
+            entries_tx,
+            limits,
+        ));
+
+        let nested = format!("{}{}\n", "[".repeat(50_000), "]".repeat(50_000));
+        let mut huge = LogEntry::new(LogLevel::Info, "guarded".to_string(), "huge".to_string());
+        huge.fields.insert("blob".to_string(), "x".repeat(4 * 1024 * 1024));
+        let valid = LogEntry::new(LogLevel::Info, "guarded".to_string(), "kept".to_string());
+
+        client.write_all(nested.as_bytes()).await.unwrap();
+        client.write_all(huge.to_json().unwrap().as_bytes()).await.unwrap();
+        client.write_all(b"\n").await.unwrap();
+        client.write_all(valid.to_json().unwrap().as_bytes()).await.unwrap();
+        client.write_all(b"\n").await.unwrap();
+        drop(client);
+
+        let result = timeout(Duration::from_secs(5), handle).await;
+        assert!(result.unwrap().unwrap().is_ok());
+        assert_eq!(storage.rejected_lines(), 2);
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("guarded.log")).await.unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains("kept"));
+        assert!(!content.contains("xxxx"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8_stored_lossily() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.server.lossy_utf8 = true;
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let (entries_tx, worker) = spawn_ingest(&storage);
+        let handle = tokio::spawn(UnixSocketServer::handle_connection(
+            server,
+            storage.clone(),
+            entries_tx,
+            config.server.line_limits(),
+        ));
+
+        // "caf" followed by a lone continuation byte and an invalid lead byte
+        let entry = LogEntry::new(LogLevel::Info, "latin1".to_string(), "MARKER".to_string());
+        let mut line = entry.to_json().unwrap().into_bytes();
+        let at = line.windows(6).position(|w| w == b"MARKER").unwrap();
+        line.splice(at..at + 6, *b"caf\x80\xff");
+        line.push(b'\n');
+        client.write_all(&line).await.unwrap();
+        drop(client);
+
+        timeout(Duration::from_secs(1), handle).await.unwrap().unwrap().unwrap();
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+        assert_eq!(storage.rejected_lines(), 0);
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("latin1.log")).await.unwrap();
+        let stored = LogEntry::from_json(content.trim()).unwrap();
+        assert_eq!(stored.message, "caf\u{FFFD}\u{FFFD}");
+        assert_eq!(stored.fields.get(LOSSY_ENCODING_FIELD).map(String::as_str), Some("lossy"));
+    }
+
+    #[tokio::test]
+    async fn test_ping_gets_pong_without_storing() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.backends.file.enabled = true;
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let (entries_tx, worker) = spawn_ingest(&storage);
+        let handle = tokio::spawn(UnixSocketServer::handle_connection(
+            server,
+            storage.clone(),
+            entries_tx,
+            LineLimits::default(),
+        ));
+
+        let (reader, mut writer) = client.into_split();
+        writer.write_all(b"{\"cmd\":\"ping\"}\n").await.unwrap();
+
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let reply = timeout(Duration::from_secs(1), lines.next_line()).await.unwrap().unwrap();
+        assert_eq!(reply.as_deref(), Some(PONG_RESPONSE));
+
+        drop(writer);
+        timeout(Duration::from_secs(1), handle).await.unwrap().unwrap().unwrap();
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+
+        assert_eq!(storage.rejected_lines(), 0);
+        let mut entries = tokio::fs::read_dir(temp_dir.path()).await.unwrap();
+        assert!(entries.next_entry().await.unwrap().is_none(), "ping must not create a log file");
+    }
+
+    #[tokio::test]
+    async fn test_config_command_returns_effective_config() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.server.max_json_depth = 7;
+        config.server.drop_patterns = vec!["^healthcheck".to_string()];
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let (entries_tx, worker) = spawn_ingest(&storage);
+        let handle = tokio::spawn(UnixSocketServer::handle_connection(
+            server,
+            storage.clone(),
+            entries_tx,
+            LineLimits::default(),
+        ));
+
+        let (reader, mut writer) = client.into_split();
+        writer.write_all(b"{\"cmd\":\"config\"}\n").await.unwrap();
+
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let reply = timeout(Duration::from_secs(1), lines.next_line()).await.unwrap().unwrap().unwrap();
+        let reported: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        let mut expected = config.redacted_json().unwrap();
+        expected["build"] = serde_json::json!({ "version": VERSION, "git_sha": GIT_SHA });
+        assert_eq!(reported, expected);
+        assert_eq!(reported["server"]["max_json_depth"], 7);
+        assert_eq!(reported["server"]["drop_patterns"][0], "^healthcheck");
+
+        drop(writer);
+        timeout(Duration::from_secs(1), handle).await.unwrap().unwrap().unwrap();
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+        assert_eq!(storage.rejected_lines(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stats_command_reports_build_info() {
+        assert!(!VERSION.is_empty());
+        assert!(!GIT_SHA.is_empty());
+
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let (entries_tx, worker) = spawn_ingest(&storage);
+        let handle = tokio::spawn(UnixSocketServer::handle_connection(
+            server,
+            storage.clone(),
+            entries_tx,
+            LineLimits::default(),
+        ));
+
+        let (reader, mut writer) = client.into_split();
+        writer.write_all(b"{\"cmd\":\"stats\"}\n").await.unwrap();
+
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let reply = timeout(Duration::from_secs(1), lines.next_line()).await.unwrap().unwrap().unwrap();
+        let reported: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reported["build"]["version"], VERSION);
+        assert_eq!(reported["build"]["git_sha"], GIT_SHA);
+        assert_eq!(reported["totals"]["entries_accepted"], 0);
+
+        drop(writer);
+        timeout(Duration::from_secs(1), handle).await.unwrap().unwrap().unwrap();
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_where_command_returns_daemon_file() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+
+        for file_enabled in [true, false] {
+            config.backends.file.enabled = file_enabled;
+            let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+            let (client, server) = UnixStream::pair().unwrap();
+            let (entries_tx, worker) = spawn_ingest(&storage);
+            let handle = tokio::spawn(UnixSocketServer::handle_connection(
+                server,
+                storage.clone(),
+                entries_tx,
+                LineLimits::default(),
+            ));
+
+            let client = LogClient::with_connection(client, "where-daemon").unwrap();
+            let path = client.query_storage_path().await.unwrap();
+            if file_enabled {
+                assert_eq!(path, Some(storage.get_log_file_path("where-daemon")));
+                assert_eq!(path, Some(temp_dir.path().join("where-daemon.log")));
+            } else {
+                assert_eq!(path, None);
+            }
+
+            client.close().await.unwrap();
+            timeout(Duration::from_secs(1), handle).await.unwrap().unwrap().unwrap();
+            timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follow_catches_up_without_gaps_or_duplicates() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = ServerConfig::default();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+        for i in 0..10 {
+            store_as(&storage, "followed", format!("before {}", i)).await;
+        }
+        store_as(&storage, "other", "not followed".to_string()).await;
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let (entries_tx, worker) = spawn_ingest(&storage);
+        let handle = tokio::spawn(UnixSocketServer::handle_connection(
+            server,
+            storage.clone(),
+            entries_tx,
+            LineLimits::default(),
+        ));
+
+        // Entries keep arriving while the follower catches up
+        let writer_task = tokio::spawn({
+            let storage = Arc::clone(&storage);
+            async move {
+                for i in 0..200 {
+                    store_as(&storage, "followed", format!("during {}", i)).await;
+                    if i % 20 == 0 {
+                        tokio::task::yield_now().await;
+                    }
+                }
+            }
+        });
+        let (reader, mut writer) = client.into_split();
+        writer.write_all(b"{\"cmd\":\"follow\",\"daemon\":\"followed\",\"tail\":3}\n").await.unwrap();
+
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let mut received = Vec::new();
+        while received.last().map(String::as_str) != Some("during 199") {
+            let line = timeout(Duration::from_secs(5), lines.next_line()).await.unwrap().unwrap().unwrap();
+            received.push(LogEntry::from_json(&line).unwrap().message);
+        }
+        writer_task.await.unwrap();
+
+        // The tail and live entries join up: everything from the tail's first entry on, once each
+        let first = received[0].clone();
+        assert!(first.starts_with("before") || first.starts_with("during"), "{}", first);
+        let all: Vec<String> = (0..10)
+            .map(|i| format!("before {}", i))
+            .chain((0..200).map(|i| format!("during {}", i)))
+            .collect();
+        let start = all.iter().position(|message| *message == first).unwrap();
+        assert!(start >= 7, "tail started at {}", first);
+        assert_eq!(received, all[start..]);
+
+        drop(writer);
+        timeout(Duration::from_secs(1), handle).await.unwrap().unwrap().unwrap();
+        timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+    }
+
+    async fn store_as(storage: &StorageBackend, daemon: &str, message: String) {
+        let entry = LogEntry::new(LogLevel::Info, daemon.to_string(), message);
+        storage.store_entry(entry).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_server_accepts_multiple_connections() {
         let temp_dir = tempdir().unwrap();
@@ -244,6 +1006,294 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_connections_are_audited() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("audited.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_str.clone();
+        config.server.audit_connections = true;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let server = UnixSocketServer::new(&config, storage.clone(), shutdown_rx).await.unwrap();
+        let listener = server.bind().unwrap();
+        let server_handle = tokio::spawn(server.serve(listener));
+
+        let client = LogClient::connect(&socket_str, "audited").await.unwrap();
+        client.info("first").await.unwrap();
+        client.info("second").await.unwrap();
+        client.close().await.unwrap();
+        sleep_until_stored(&storage, crate::server::AUDIT_DAEMON, 2).await;
+        shutdown_tx.send(()).unwrap();
+        server_handle.await.unwrap().unwrap();
+
+        let audit = storage.tail(crate::server::AUDIT_DAEMON, 10).await.unwrap();
+        let events: Vec<&str> = audit.iter().map(|entry| entry.fields["event"].as_str()).collect();
+        assert_eq!(events, ["connect", "disconnect"]);
+        let (opened, closed) = (&audit[0], &audit[1]);
+        assert_eq!(opened.fields["connection_id"], closed.fields["connection_id"]);
+        assert_eq!(opened.fields["transport"], "unix");
+        assert_eq!(opened.fields["peer_pid"], std::process::id().to_string());
+        assert_eq!(closed.fields["peer_uid"], unsafe { libc::getuid() }.to_string());
+        assert_eq!(closed.fields["entries"], "2");
+        let bytes: u64 = closed.fields["bytes_received"].parse().unwrap();
+        assert!(bytes > 2 * "second".len() as u64, "{} bytes", bytes);
+        assert!(closed.fields.contains_key("duration_ms"));
+    }
+
+    #[tokio::test]
+    async fn test_entries_carry_connection_context() {
+        use crate::server::{REMOTE_FIELD, TRANSPORT_FIELD};
+
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("context.sock");
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_path.to_string_lossy().to_string();
+        config.server.connection_context = true;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let server = UnixSocketServer::new(&config, Arc::clone(&storage), shutdown_rx).await.unwrap();
+        let tcp = Listener::bind(&ListenerSpec::Tcp("127.0.0.1:0".to_string()), false).await.unwrap();
+        let Some(ListenerSpec::Tcp(tcp_addr)) = tcp.spec() else {
+            panic!("TCP listener without an address");
+        };
+        let unix = Listener::Unix(server.bind().unwrap());
+        let server_handle = tokio::spawn(server.serve_all(vec![unix, tcp]));
+
+        // A client can't pass off its own provenance
+        let mut forged = LogEntry::new(LogLevel::Info, "edge".to_string(), "over tcp".to_string());
+        forged.fields.insert(TRANSPORT_FIELD.to_string(), "unix".to_string());
+        let mut tcp_stream = tokio::net::TcpStream::connect(&tcp_addr).await.unwrap();
+        let local_addr = tcp_stream.local_addr().unwrap();
+        tcp_stream.write_all(format!("{}\n", forged.to_json().unwrap()).as_bytes()).await.unwrap();
+        let mut unix_stream = UnixStream::connect(&socket_path).await.unwrap();
+        let local = LogEntry::new(LogLevel::Info, "local".to_string(), "over unix".to_string());
+        unix_stream.write_all(format!("{}\n", local.to_json().unwrap()).as_bytes()).await.unwrap();
+
+        sleep_until_stored(&storage, "edge", 1).await;
+        sleep_until_stored(&storage, "local", 1).await;
+        drop((tcp_stream, unix_stream));
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+
+        let edge = &storage.tail("edge", 1).await.unwrap()[0];
+        assert_eq!(edge.fields[TRANSPORT_FIELD], "tcp");
+        assert_eq!(edge.fields[REMOTE_FIELD], local_addr.to_string());
+        let local = &storage.tail("local", 1).await.unwrap()[0];
+        assert_eq!(local.fields[TRANSPORT_FIELD], "unix");
+        assert!(!local.fields.contains_key(REMOTE_FIELD));
+    }
+
+    #[tokio::test]
+    async fn test_full_disk_with_block_stalls_the_client() {
+        use crate::config::DiskFullPolicy;
+
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("full.sock");
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_path.to_string_lossy().to_string();
+        config.server.ingest_queue_size = 1;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.storage.on_disk_full = DiskFullPolicy::Block;
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let server = UnixSocketServer::new(&config, Arc::clone(&storage), shutdown_rx).await.unwrap();
+        let listener = server.bind().unwrap();
+        let server_handle = tokio::spawn(server.serve(listener));
+
+        // Open the log file, then fill the disk
+        let first = LogEntry::new(LogLevel::Info, "full".to_string(), "first".to_string());
+        storage.store_entry(first).await.unwrap();
+        let failures = storage.fail_writes("full", usize::MAX).await;
+        // Far more than the ingest queue and both socket buffers hold
+        const ENTRIES: usize = 2000;
+        let line = format!("{}\n", LogEntry::new(LogLevel::Info, "full".to_string(), "x".repeat(1024)).to_json().unwrap());
+        let mut stream = UnixStream::connect(&socket_path).await.unwrap();
+        let writer = tokio::spawn(async move {
+            for _ in 0..ENTRIES {
+                stream.write_all(line.as_bytes()).await.unwrap();
+            }
+            stream
+        });
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!writer.is_finished(), "client kept sending while the disk was full");
+        assert_eq!(storage.stats().entries_accepted, 2);
+
+        // With room again the client gets through and nothing was lost
+        failures.store(0, std::sync::atomic::Ordering::SeqCst);
+        drop(timeout(Duration::from_secs(10), writer).await.unwrap().unwrap());
+        sleep_until_stored(&storage, "full", ENTRIES + 1).await;
+        assert_eq!(storage.stats().disk_full_dropped, 0);
+
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+    }
+
+    /// Wait until `count` entries of a daemon are readable from storage
+    async fn sleep_until_stored(storage: &StorageBackend, daemon: &str, count: usize) {
+        timeout(Duration::from_secs(5), async {
+            while storage.tail(daemon, count).await.unwrap().len() < count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("entries not stored in time");
+    }
+
+    #[tokio::test]
+    async fn test_accept_rate_is_limited() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("paced.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_str.clone();
+        config.server.max_accepts_per_sec = Some(100);
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let server = UnixSocketServer::new(&config, storage, shutdown_rx).await.unwrap();
+        let listener = server.bind().unwrap();
+        let server_handle = tokio::spawn(server.serve(listener));
+
+        // Connecting only queues in the backlog; a pong means the server accepted
+        let started = tokio::time::Instant::now();
+        let clients: Vec<_> = (0..100)
+            .map(|_| {
+                let socket_str = socket_str.clone();
+                tokio::spawn(async move {
+                    let stream = UnixStream::connect(&socket_str).await.unwrap();
+                    let (reader, mut writer) = stream.into_split();
+                    writer.write_all(b"{\"cmd\":\"ping\"}\n").await.unwrap();
+                    let mut lines = tokio::io::BufReader::new(reader).lines();
+                    assert_eq!(lines.next_line().await.unwrap().as_deref(), Some(PONG_RESPONSE));
+                    tokio::time::Instant::now()
+                })
+            })
+            .collect();
+        let mut accepted = Vec::new();
+        for client in clients {
+            accepted.push(timeout(Duration::from_secs(10), client).await.unwrap().unwrap() - started);
+        }
+        accepted.sort();
+
+        // 100 accepts at 100 per second span about a second, half of them about half
+        let elapsed = *accepted.last().unwrap();
+        assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+        assert!(accepted[49] >= Duration::from_millis(400), "{:?}", accepted[49]);
+
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+    }
+
+    /// Serve on `socket_path` through a listener whose first `failures` accepts fail
+    async fn serve_faulty(
+        socket_path: &Path,
+        output_dir: &Path,
+        failures: usize,
+    ) -> (Arc<StorageBackend>, broadcast::Sender<()>, tokio::task::JoinHandle<Result<()>>) {
+        let socket_str = socket_path.to_string_lossy().to_string();
+        let (server, storage, shutdown_tx) = create_test_server(&socket_str, output_dir).await;
+        let listener = Listener::Faulty {
+            inner: Box::new(Listener::Unix(server.bind().unwrap())),
+            failures: Arc::new(std::sync::atomic::AtomicUsize::new(failures)),
+        };
+        (storage, shutdown_tx, tokio::spawn(server.serve_all(vec![listener])))
+    }
+
+    async fn ping(socket_path: &Path) {
+        let stream = UnixStream::connect(socket_path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer.write_all(b"{\"cmd\":\"ping\"}\n").await.unwrap();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let reply = timeout(Duration::from_secs(5), lines.next_line()).await.unwrap().unwrap();
+        assert_eq!(reply.as_deref(), Some(PONG_RESPONSE));
+    }
+
+    #[tokio::test]
+    async fn test_accept_errors_back_off_and_recover() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("faulty.sock");
+        let started = tokio::time::Instant::now();
+        let (storage, shutdown_tx, server_handle) = serve_faulty(&socket_path, temp_dir.path(), 3).await;
+
+        ping(&socket_path).await;
+        // Three failures pause accepting for 5 + 10 + 20 ms rather than spinning
+        assert!(started.elapsed() >= Duration::from_millis(35), "{:?}", started.elapsed());
+
+        sleep_until_stored(&storage, SERVER_DAEMON, 1).await;
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+        // Only the first failure of a run is reported
+        let events = storage.tail(SERVER_DAEMON, 10).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].fields["event"], "accept_error");
+        assert_eq!(events[0].fields["listener"], format!("unix {}", socket_path.display()));
+        assert!(events[0].fields["error"].contains("Too many open files"), "{:?}", events[0].fields);
+    }
+
+    #[tokio::test]
+    async fn test_persistently_failing_listener_is_rebound() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("rebind.sock");
+        let (storage, shutdown_tx, server_handle) =
+            serve_faulty(&socket_path, temp_dir.path(), ACCEPT_FAILURES_BEFORE_REBIND as usize).await;
+
+        sleep_until_stored(&storage, SERVER_DAEMON, 2).await;
+        ping(&socket_path).await;
+        let _ = shutdown_tx.send(());
+        timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+        let events = storage.tail(SERVER_DAEMON, 10).await.unwrap();
+        let events: Vec<&str> = events.iter().map(|entry| entry.fields["event"].as_str()).collect();
+        assert_eq!(events, ["accept_error", "listener_rebound"]);
+    }
+
+    #[tokio::test]
+    async fn test_server_stops_once_no_listener_is_left() {
+        let temp_dir = tempdir().unwrap();
+        let socket_dir = temp_dir.path().join("run");
+        let socket_path = socket_dir.join("doomed.sock");
+        std::fs::create_dir(&socket_dir).unwrap();
+        let (storage, _shutdown_tx, server_handle) =
+            serve_faulty(&socket_path, temp_dir.path(), ACCEPT_FAILURES_BEFORE_REBIND as usize).await;
+        // Rebinding can't recreate the socket's directory
+        std::fs::remove_dir_all(&socket_dir).unwrap();
+        std::fs::write(&socket_dir, "").unwrap();
+
+        let result = timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap();
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("No listener left"), "{}", err);
+        let events = storage.tail(SERVER_DAEMON, 10).await.unwrap();
+        let events: Vec<&str> = events.iter().map(|entry| entry.fields["event"].as_str()).collect();
+        assert_eq!(events, ["accept_error", "listener_closed"]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_inherited_listener_is_closed_not_rebound() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("activated.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+        let (server, storage, _shutdown_tx) = create_test_server(&socket_str, temp_dir.path()).await;
+        let inherited = Listener::Inherited(Box::new(Listener::Unix(server.bind().unwrap())));
+        let inode = std::os::unix::fs::MetadataExt::ino(&std::fs::metadata(&socket_path).unwrap());
+        let listener = Listener::Faulty {
+            inner: Box::new(inherited),
+            failures: Arc::new(std::sync::atomic::AtomicUsize::new(usize::MAX)),
+        };
+
+        let result = timeout(Duration::from_secs(5), server.serve_all(vec![listener])).await.unwrap();
+        assert!(result.unwrap_err().to_string().contains("No listener left"));
+        let events = storage.tail(SERVER_DAEMON, 10).await.unwrap();
+        let events: Vec<&str> = events.iter().map(|entry| entry.fields["event"].as_str()).collect();
+        assert_eq!(events, ["accept_error", "listener_closed"]);
+        // The socket file systemd created was not replaced
+        assert_eq!(std::os::unix::fs::MetadataExt::ino(&std::fs::metadata(&socket_path).unwrap()), inode);
+    }
+
     #[tokio::test]
     async fn test_server_handles_invalid_json() {
         let temp_dir = tempdir().unwrap();
@@ -372,4 +1422,76 @@ mod tests {
         let lines: Vec<&str> = content.lines().collect();
         assert_eq!(lines.len(), 50); // 10 clients * 5 messages each
     }
+
+    #[tokio::test]
+    async fn test_shutdown_force_closes_stuck_connection() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("stuck.sock");
+        let mut config = ServerConfig::default();
+        config.server.socket_path = socket_path.to_string_lossy().to_string();
+        config.server.shutdown_timeout_secs = 1;
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+
+        let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let server = UnixSocketServer::new(&config, storage, shutdown_rx).await.unwrap();
+        let listener = server.bind().unwrap();
+        let server_handle = tokio::spawn(server.serve(listener));
+
+        // A client that connects, sends an entry and then never closes
+        let mut client = UnixStream::connect(&socket_path).await.unwrap();
+        let entry = LogEntry::new(LogLevel::Info, "stuck".to_string(), "still here".to_string());
+        client.write_all(format!("{}\n", entry.to_json().unwrap()).as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        shutdown_tx.send(()).unwrap();
+        let result = timeout(Duration::from_secs(3), server_handle).await;
+        assert!(result.expect("shutdown should finish within the timeout").unwrap().is_ok());
+
+        // The server side was force-closed, and the entry was still stored
+        let mut reader = tokio::io::BufReader::new(client);
+        let mut line = String::new();
+        assert_eq!(reader.read_line(&mut line).await.unwrap(), 0);
+        let content = tokio::fs::read_to_string(temp_dir.path().join("stuck.log")).await.unwrap();
+        assert_eq!(content.lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_drain_connections_counts_force_closed() {
+        let mut connections = JoinSet::new();
+        connections.spawn(async {});
+        connections.spawn(std::future::pending::<()>());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let forced = drain_connections(&mut connections, Duration::from_millis(100)).await;
+        assert_eq!(forced, 1);
+        assert!(connections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bind_creates_missing_socket_dir() {
+        let temp_dir = tempdir().unwrap();
+        let socket_path = temp_dir.path().join("run/nested/logstream.sock");
+        let socket_str = socket_path.to_string_lossy().to_string();
+
+        let (server, _, _) = create_test_server(&socket_str, temp_dir.path()).await;
+        let _listener = server.bind().unwrap();
+
+        assert!(socket_path.parent().unwrap().is_dir());
+        UnixStream::connect(&socket_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bind_reports_uncreatable_socket_dir() {
+        let temp_dir = tempdir().unwrap();
+        let blocker = temp_dir.path().join("file");
+        std::fs::write(&blocker, "").unwrap();
+        let socket_str = blocker.join("logstream.sock").to_string_lossy().to_string();
+
+        let (server, _, _) = create_test_server(&socket_str, temp_dir.path()).await;
+        match server.bind() {
+            Err(LogStreamError::Config(message)) => assert!(message.contains(&socket_str)),
+            other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+        }
+    }
 }
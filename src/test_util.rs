@@ -0,0 +1,156 @@
+//! Helpers for testing code that logs through a [`LogClient`]
+//!
+//! Enabled by the `test-util` feature. [`InMemoryServer`] speaks the client
+//! protocol but keeps received entries in memory, so tests can assert on
+//! what was logged without running a [`LogServer`] or reading log files.
+//!
+//! [`LogClient`]: crate::client::LogClient
+//! [`LogServer`]: crate::server::LogServer
+
+use crate::types::{ControlCommand, Frame, LogEntry, LogEntryStream, PONG_RESPONSE};
+use crate::{LogStreamError, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
+
+/// A socket that collects the entries clients send to it
+///
+/// Pings are answered so [`LogClient::flush_and_confirm`] works; other
+/// control commands are ignored. The listener stops and the socket file is
+/// removed when the server is dropped.
+///
+/// [`LogClient::flush_and_confirm`]: crate::client::LogClient::flush_and_confirm
+pub struct InMemoryServer {
+    socket_path: PathBuf,
+    entries: watch::Sender<Vec<LogEntry>>,
+    accept_task: JoinHandle<()>,
+}
+
+impl InMemoryServer {
+    /// Bind a socket at `socket_path` and start accepting connections
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn bind(socket_path: impl AsRef<Path>) -> Result<Self> {
+        let socket_path = socket_path.as_ref().to_path_buf();
+        let listener = UnixListener::bind(&socket_path).map_err(|e| {
+            LogStreamError::Server(format!(
+                "Failed to bind test socket {}: {}",
+                socket_path.display(),
+                e
+            ))
+        })?;
+        let (entries, _) = watch::channel(Vec::new());
+        let accept_task = tokio::spawn(accept(listener, entries.clone()));
+
+        Ok(Self {
+            socket_path,
+            entries,
+            accept_task,
+        })
+    }
+
+    /// Path clients connect to
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Wait until at least `n` entries were received, returning all of them
+    ///
+    /// Fails with [`LogStreamError::Server`] if they don't arrive within
+    /// `timeout`.
+    pub async fn wait_for(&self, n: usize, timeout: Duration) -> Result<Vec<LogEntry>> {
+        let mut rx = self.entries.subscribe();
+        let waited = tokio::time::timeout(timeout, rx.wait_for(|entries| entries.len() >= n))
+            .await
+            .map(|received| received.map(|entries| entries.clone()));
+        match waited {
+            Ok(Ok(entries)) => Ok(entries),
+            // The sender lives in `self`, so the channel can't close here
+            Ok(Err(_)) => unreachable!("entry channel closed while the server exists"),
+            Err(_) => Err(LogStreamError::Server(format!(
+                "Received {} of {} expected entries within {:?}",
+                self.entries.borrow().len(),
+                n,
+                timeout
+            ))),
+        }
+    }
+
+    /// Take every entry received so far, leaving none collected
+    pub fn drain_to_vec(&self) -> Vec<LogEntry> {
+        let mut drained = Vec::new();
+        self.entries.send_modify(|entries| drained = std::mem::take(entries));
+        drained
+    }
+}
+
+impl Drop for InMemoryServer {
+    fn drop(&mut self) {
+        // Aborting the accept task drops its JoinSet, closing every connection
+        self.accept_task.abort();
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+async fn accept(listener: UnixListener, entries: watch::Sender<Vec<LogEntry>>) {
+    let mut connections = JoinSet::new();
+    while let Ok((stream, _)) = listener.accept().await {
+        connections.spawn(collect(stream, entries.clone()));
+        // Reap finished connections so the set doesn't grow without bound
+        while connections.try_join_next().is_some() {}
+    }
+}
+
+async fn collect(stream: UnixStream, entries: watch::Sender<Vec<LogEntry>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut frames = LogEntryStream::new(reader);
+
+    while let Some(item) = frames.next_frame().await {
+        match item {
+            Ok(Frame::Entry(entry)) => entries.send_modify(|entries| entries.push(entry)),
+            Ok(Frame::Control(ControlCommand::Ping)) => {
+                let reply = format!("{}\n", PONG_RESPONSE);
+                if writer.write_all(reply.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+            Ok(Frame::Control(_)) => {}
+            Err(LogStreamError::Io(_)) => break,
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::LogClient;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_in_memory_server_collects_entries() {
+        let temp_dir = tempdir().unwrap();
+        let server = InMemoryServer::bind(temp_dir.path().join("test.sock")).unwrap();
+
+        let client = LogClient::connect(&server.socket_path().to_string_lossy(), "captured")
+            .await
+            .unwrap();
+        client.info("first").await.unwrap();
+        client.warning("second").await.unwrap();
+        client.error("third").await.unwrap();
+
+        let entries = server.wait_for(3, Duration::from_secs(2)).await.unwrap();
+        let messages: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["first", "second", "third"]);
+        assert!(entries.iter().all(|e| e.daemon == "captured"));
+        // Only the socket was created; nothing was written to log files
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        assert_eq!(server.drain_to_vec().len(), 3);
+        assert!(server.drain_to_vec().is_empty());
+        assert!(server.wait_for(1, Duration::from_millis(50)).await.is_err());
+    }
+}
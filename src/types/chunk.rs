@@ -0,0 +1,88 @@
+//! Chunked transfer of entries longer than one line may be
+//!
+//! An entry whose JSON exceeds the server's `max_line_bytes` can be sent as
+//! a run of chunk lines, `{"chunk":"<piece of entry JSON>","more":true}`,
+//! ending with one whose `more` is `false`. The server joins the pieces and
+//! parses the result as a single entry.
+
+use serde::{Deserialize, Serialize};
+
+/// Default limit on the size of an entry reassembled from chunks (16 MiB)
+pub const DEFAULT_MAX_REASSEMBLED_BYTES: usize = 16 * 1024 * 1024;
+
+/// One piece of an entry's JSON sent on its own line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EntryChunk {
+    /// The next piece of the entry's JSON
+    pub chunk: String,
+    /// Whether more pieces follow; the last chunk of an entry sets `false`
+    pub more: bool,
+}
+
+impl EntryChunk {
+    /// Split serialized entry JSON into chunks
+    ///
+    /// Each chunk's `chunk` string takes at most `max_bytes` bytes once
+    /// escaped for JSON (at least one character per chunk), so every chunk
+    /// line stays within `max_bytes` plus a small constant overhead.
+    pub fn split(json: &str, max_bytes: usize) -> Vec<EntryChunk> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut escaped_len = 0;
+
+        for (i, c) in json.char_indices() {
+            let len = escaped_char_len(c);
+            if i > start && escaped_len + len > max_bytes {
+                chunks.push(EntryChunk {
+                    chunk: json[start..i].to_string(),
+                    more: true,
+                });
+                start = i;
+                escaped_len = 0;
+            }
+            escaped_len += len;
+        }
+
+        chunks.push(EntryChunk {
+            chunk: json[start..].to_string(),
+            more: false,
+        });
+        chunks
+    }
+
+    /// Serialize to a single JSON line (without the newline)
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+/// Bytes `c` takes inside a JSON string as written by serde_json
+fn escaped_char_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{08}' | '\u{0c}' => 2,
+        c if (c as u32) < 0x20 => 6,
+        c => c.len_utf8(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_respects_escaped_size() {
+        let json = r#"{"message":"line one\nline \"two\" é"}"#;
+        let chunks = EntryChunk::split(json, 8);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks[..chunks.len() - 1].iter().all(|c| c.more));
+        assert!(!chunks.last().unwrap().more);
+        for chunk in &chunks {
+            let quoted = serde_json::to_string(&chunk.chunk).unwrap();
+            assert!(quoted.len() - 2 <= 8, "{} is too long", quoted);
+        }
+        let joined: String = chunks.iter().map(|c| c.chunk.as_str()).collect();
+        assert_eq!(joined, json);
+    }
+}
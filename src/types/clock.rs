@@ -0,0 +1,107 @@
+//! Time sources used by LogStream
+//!
+//! Components that make decisions based on time (rotation age, summaries)
+//! read it through a [`Clock`] so tests can control it with [`MockClock`]
+//! instead of sleeping.
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+/// Source of wall-clock and monotonic time
+pub trait Clock: Send + Sync {
+    /// Current wall-clock time
+    fn now_utc(&self) -> DateTime<Utc>;
+
+    /// Current monotonic time
+    fn now_instant(&self) -> Instant;
+}
+
+/// Clock backed by the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Manually driven clock for tests
+///
+/// Time only moves when [`MockClock::advance`] or [`MockClock::set`] is called.
+#[derive(Debug)]
+pub struct MockClock {
+    state: Mutex<(DateTime<Utc>, Instant)>,
+}
+
+impl MockClock {
+    /// Create a mock clock starting at the given wall-clock time
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            state: Mutex::new((start, Instant::now())),
+        }
+    }
+
+    /// Move both wall-clock and monotonic time forward
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.state.lock();
+        state.0 += chrono::Duration::from_std(by).expect("duration out of range");
+        state.1 += by;
+    }
+
+    /// Set the wall-clock time, leaving monotonic time untouched
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.state.lock().0 = now;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for MockClock {
+    fn now_utc(&self) -> DateTime<Utc> {
+        self.state.lock().0
+    }
+
+    fn now_instant(&self) -> Instant {
+        self.state.lock().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_mock_clock_advance() {
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let instant = clock.now_instant();
+
+        assert_eq!(clock.now_utc(), start);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now_utc(), start + chrono::Duration::seconds(90));
+        assert_eq!(clock.now_instant() - instant, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn test_mock_clock_set() {
+        let clock = MockClock::default();
+        let instant = clock.now_instant();
+        let target = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+
+        clock.set(target);
+        assert_eq!(clock.now_utc(), target);
+        assert_eq!(clock.now_instant(), instant);
+    }
+}
@@ -0,0 +1,84 @@
+//! Control messages exchanged on log connections
+//!
+//! A client may send a control command on its own line in place of a log
+//! entry. Commands are answered on the same connection and never stored.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Reply the server writes for a [`ControlCommand::Ping`]
+pub const PONG_RESPONSE: &str = r#"{"pong":true}"#;
+
+/// Command sent by a client instead of a log entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Keep-alive probe, serialized as `{"cmd":"ping"}`
+    Ping,
+    /// Request the server's effective configuration, serialized as `{"cmd":"config"}`
+    ///
+    /// Answered with the live [`ServerConfig`] as one JSON line, secrets
+    /// redacted.
+    ///
+    /// [`ServerConfig`]: crate::config::ServerConfig
+    Config,
+    /// Request the server's counters, serialized as `{"cmd":"stats"}`
+    ///
+    /// Answered with a [`MetricsSnapshot`] as one JSON line, including the
+    /// server's version and git commit.
+    ///
+    /// [`MetricsSnapshot`]: crate::server::MetricsSnapshot
+    Stats,
+    /// Ask where entries from a daemon are written, serialized as `{"cmd":"where","daemon":"..."}`
+    ///
+    /// Answered with a [`StoragePathReply`] as one JSON line.
+    Where {
+        /// Daemon whose file is wanted
+        daemon: String,
+    },
+    /// Stream a daemon's entries, serialized as `{"cmd":"follow","daemon":"...","tail":N}`
+    ///
+    /// The server first sends the last `tail` entries from the daemon's log
+    /// file, then each entry as it is stored, one JSON line per entry, until
+    /// the client disconnects. Other commands on the connection are ignored.
+    Follow {
+        /// Daemon to follow
+        daemon: String,
+        /// Stored entries to send before the live ones
+        #[serde(default)]
+        tail: usize,
+    },
+}
+
+/// Reply to a [`ControlCommand::Where`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoragePathReply {
+    /// The daemon's current log file, or `None` when the file backend is disabled
+    pub path: Option<PathBuf>,
+}
+
+impl ControlCommand {
+    /// Serialize to a single JSON line (without the newline)
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_wire_format() {
+        assert_eq!(ControlCommand::Ping.to_json().unwrap(), r#"{"cmd":"ping"}"#);
+        let parsed: ControlCommand = serde_json::from_str(r#"{"cmd":"ping"}"#).unwrap();
+        assert_eq!(parsed, ControlCommand::Ping);
+        assert_eq!(ControlCommand::Config.to_json().unwrap(), r#"{"cmd":"config"}"#);
+        assert_eq!(ControlCommand::Stats.to_json().unwrap(), r#"{"cmd":"stats"}"#);
+        let query = ControlCommand::Where { daemon: "api".to_string() };
+        assert_eq!(query.to_json().unwrap(), r#"{"cmd":"where","daemon":"api"}"#);
+        let follow: ControlCommand = serde_json::from_str(r#"{"cmd":"follow","daemon":"api"}"#).unwrap();
+        assert_eq!(follow, ControlCommand::Follow { daemon: "api".to_string(), tail: 0 });
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"cmd":"reboot"}"#).is_err());
+    }
+}
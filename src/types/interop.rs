@@ -0,0 +1,178 @@
+//! Conversions from the `log` and `tracing` ecosystems into LogStream entries
+//!
+//! Both map their levels onto the closest [`LogLevel`] (`Trace` becomes
+//! `Debug`, there being nothing finer) and record where the log came from as
+//! `target`, `module_path`, `file` and `line` fields. The `log` facade and
+//! tracing layers build their entries here so the mapping lives in one place.
+//!
+//! The `log` half needs the `log-facade` feature and the `tracing` half the
+//! `tracing-interop` feature; both are on by default.
+
+use crate::types::{LogEntry, LogLevel};
+#[cfg(feature = "tracing-interop")]
+use std::fmt;
+
+#[cfg(feature = "log-facade")]
+impl From<log::Level> for LogLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => LogLevel::Error,
+            log::Level::Warn => LogLevel::Warning,
+            log::Level::Info => LogLevel::Info,
+            log::Level::Debug | log::Level::Trace => LogLevel::Debug,
+        }
+    }
+}
+
+#[cfg(feature = "tracing-interop")]
+impl From<&tracing::Level> for LogLevel {
+    fn from(level: &tracing::Level) -> Self {
+        match *level {
+            tracing::Level::ERROR => LogLevel::Error,
+            tracing::Level::WARN => LogLevel::Warning,
+            tracing::Level::INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+#[cfg(feature = "log-facade")]
+impl From<&log::Record<'_>> for LogEntry {
+    /// Convert a record, using its target as the daemon name
+    fn from(record: &log::Record<'_>) -> Self {
+        Self::from_log_record(record, record.target())
+    }
+}
+
+impl LogEntry {
+    /// Build an entry from a `log` record, tagged with `daemon`
+    #[cfg(feature = "log-facade")]
+    pub fn from_log_record(record: &log::Record<'_>, daemon: &str) -> Self {
+        let mut entry = LogEntry::new(record.level().into(), daemon.to_string(), record.args().to_string());
+        entry.add_source(record.target(), record.module_path(), record.file(), record.line());
+        entry
+    }
+
+    /// Build an entry from a `tracing` event, tagged with `daemon`
+    ///
+    /// The event's `message` becomes the entry message and its other fields
+    /// are added as entry fields, formatted with `Debug` unless they are
+    /// strings.
+    #[cfg(feature = "tracing-interop")]
+    pub fn from_tracing_event(event: &tracing::Event<'_>, daemon: &str) -> Self {
+        let metadata = event.metadata();
+        let mut entry = LogEntry::new(metadata.level().into(), daemon.to_string(), String::new());
+        event.record(&mut EventVisitor(&mut entry));
+        entry.add_source(metadata.target(), metadata.module_path(), metadata.file(), metadata.line());
+        entry
+    }
+
+    fn add_source(&mut self, target: &str, module_path: Option<&str>, file: Option<&str>, line: Option<u32>) {
+        self.fields.insert("target".to_string(), target.to_string());
+        if let Some(module_path) = module_path {
+            self.fields.insert("module_path".to_string(), module_path.to_string());
+        }
+        if let Some(file) = file {
+            self.fields.insert("file".to_string(), file.to_string());
+        }
+        if let Some(line) = line {
+            self.fields.insert("line".to_string(), line.to_string());
+        }
+    }
+}
+
+/// Copies a tracing event's fields into an entry
+#[cfg(feature = "tracing-interop")]
+struct EventVisitor<'a>(&'a mut LogEntry);
+
+#[cfg(feature = "tracing-interop")]
+impl EventVisitor<'_> {
+    fn insert(&mut self, field: &tracing::field::Field, value: String) {
+        if field.name() == "message" {
+            self.0.message = value;
+        } else {
+            self.0.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+#[cfg(feature = "tracing-interop")]
+impl tracing::field::Visit for EventVisitor<'_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.insert(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "tracing-interop")]
+    use std::sync::{Arc, Mutex};
+    #[cfg(feature = "tracing-interop")]
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    #[cfg(feature = "tracing-interop")]
+    use tracing_subscriber::Layer;
+
+    #[cfg(feature = "log-facade")]
+    #[test]
+    fn test_log_record_conversion() {
+        // The record borrows its formatted arguments, so convert it in the same statement
+        let entry = LogEntry::from(
+            &log::Record::builder()
+                .args(format_args!("disk {} nearly full", "/var"))
+                .level(log::Level::Warn)
+                .target("legacy::db")
+                .module_path(Some("legacy::db::pool"))
+                .file(Some("src/db/pool.rs"))
+                .line(Some(42))
+                .build(),
+        );
+        assert_eq!(entry.level, LogLevel::Warning);
+        assert_eq!(entry.daemon, "legacy::db");
+        assert_eq!(entry.message, "disk /var nearly full");
+        assert_eq!(entry.fields["target"], "legacy::db");
+        assert_eq!(entry.fields["module_path"], "legacy::db::pool");
+        assert_eq!(entry.fields["file"], "src/db/pool.rs");
+        assert_eq!(entry.fields["line"], "42");
+
+        let trace = log::Record::builder().level(log::Level::Trace).target("t").build();
+        let entry = LogEntry::from_log_record(&trace, "my-daemon");
+        assert_eq!((entry.level, entry.daemon.as_str()), (LogLevel::Debug, "my-daemon"));
+        assert!(!entry.fields.contains_key("line"));
+    }
+
+    /// Layer converting every event it sees
+    #[cfg(feature = "tracing-interop")]
+    struct Capture(Arc<Mutex<Vec<LogEntry>>>);
+
+    #[cfg(feature = "tracing-interop")]
+    impl<S: tracing::Subscriber> Layer<S> for Capture {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            self.0.lock().unwrap().push(LogEntry::from_tracing_event(event, "traced"));
+        }
+    }
+
+    #[cfg(feature = "tracing-interop")]
+    #[test]
+    fn test_tracing_event_conversion() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(Capture(Arc::clone(&captured)));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::error!(target: "payments", order_id = 17, user = "ada", "charge {} failed", "card");
+        });
+
+        let entries = captured.lock().unwrap();
+        let entry = &entries[0];
+        assert_eq!(entry.level, LogLevel::Error);
+        assert_eq!(entry.daemon, "traced");
+        assert_eq!(entry.message, "charge card failed");
+        assert_eq!(entry.fields["order_id"], "17");
+        assert_eq!(entry.fields["user"], "ada");
+        assert_eq!(entry.fields["target"], "payments");
+        assert!(entry.fields["file"].ends_with("interop.rs"));
+    }
+}
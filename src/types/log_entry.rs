@@ -1,14 +1,125 @@
 //! Log entry types and utilities
 
+use crate::types::{Clock, LogEntryStream};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::ser::SerializeMap;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
+use std::sync::Arc;
+use tokio::io::AsyncRead;
 use uuid::Uuid;
 
+/// Field values longer than this many bytes are truncated in human-readable output
+pub const DEFAULT_HUMAN_MAX_FIELD_LEN: usize = 256;
+
+/// Wire format version of [`LogEntry`] written by this build
+///
+/// Bump when fields are added whose absence or presence other versions need
+/// to know about. Entries without a `schema_version` are version 1.
+pub const LOG_ENTRY_SCHEMA_VERSION: u32 = 1;
+
+/// Prefix of the top-level keys fields get in [`LogEntry::to_json_flat`]
+///
+/// Keeps a field named e.g. `level` from clashing with the entry's own keys.
+pub const FLATTENED_FIELD_PREFIX: &str = "field_";
+
+/// Top-level JSON keys of a [`LogEntry`], which [`LogEntry::to_json_renamed`] can rename
+pub const ENTRY_JSON_KEYS: &[&str] = &[
+    "id",
+    "timestamp",
+    "level",
+    "daemon",
+    "message",
+    "fields",
+    "resource",
+    "binary_fields",
+    "pid",
+    "hostname",
+    "schema_version",
+];
+
+/// GELF version written by [`LogEntry::to_gelf`]
+pub const GELF_VERSION: &str = "1.1";
+
+/// Start of each continuation line in [`LogEntry::to_human_readable_split`]
+pub const MULTILINE_CONTINUATION_MARKER: &str = "    | ";
+
+/// JSON of an entry besides its strings: keys, punctuation, id, timestamp,
+/// level, pid and schema version, at their longest
+const ENTRY_JSON_OVERHEAD: usize = 256;
+
+/// Quotes, colon and comma around each key/value pair in a JSON map
+const MAP_ENTRY_JSON_OVERHEAD: usize = 6;
+
+/// Escape control characters other than tab for display on a terminal
+///
+/// Newlines become `\n`, other controls (including ESC, DEL and C1 codes)
+/// become `\u{..}`, so the text stays on one line and can't drive the
+/// terminal. Text without control characters is returned as is.
+pub fn escape_control(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(|c| c.is_control() && c != '\t') {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        if c.is_control() && c != '\t' {
+            escaped.extend(c.escape_default());
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
 /// Type alias for log fields
 pub type LogFields = HashMap<String, String>;
 
+/// Build [`LogFields`] from `key => value` pairs
+///
+/// Keys are identifiers or string literals; values are anything implementing
+/// `ToString`.
+///
+/// ```
+/// use logstream::fields;
+///
+/// let fields = fields! { user_id => 123, action => "login", "http.status" => 200 };
+/// assert_eq!(fields["user_id"], "123");
+/// assert_eq!(fields["http.status"], "200");
+/// ```
+#[macro_export]
+macro_rules! fields {
+    (@key $key:ident) => { stringify!($key).to_string() };
+    (@key $key:literal) => { $key.to_string() };
+    ($($key:tt => $value:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut fields = $crate::types::LogFields::new();
+        $(fields.insert($crate::fields!(@key $key), $value.to_string());)*
+        fields
+    }};
+}
+
+/// Name of the GELF additional field a field `key` is written as
+///
+/// GELF only allows `[A-Za-z0-9_.-]` in names, so other characters become
+/// `_`, and reserves `_id`, so a field named `id` is written as `_field_id`.
+pub fn gelf_field_key(key: &str) -> String {
+    if key == "id" {
+        return "_field_id".to_string();
+    }
+    let mut name = String::with_capacity(key.len() + 1);
+    name.push('_');
+    name.extend(
+        key.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') { c } else { '_' }),
+    );
+    name
+}
+
 /// Log severity levels compatible with syslog and journald
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum LogLevel {
@@ -30,6 +141,61 @@ pub enum LogLevel {
     Debug = 7,
 }
 
+impl LogLevel {
+    /// All levels from most to least severe
+    pub const ALL: [LogLevel; 8] = [
+        LogLevel::Emergency,
+        LogLevel::Alert,
+        LogLevel::Critical,
+        LogLevel::Error,
+        LogLevel::Warning,
+        LogLevel::Notice,
+        LogLevel::Info,
+        LogLevel::Debug,
+    ];
+
+    /// ANSI escape sequence that resets colors set by [`LogLevel::ansi_color`]
+    pub const ANSI_RESET: &'static str = "\x1b[0m";
+
+    /// ANSI escape sequence used to color this level on a terminal
+    pub fn ansi_color(&self) -> &'static str {
+        match self {
+            LogLevel::Emergency => "\x1b[1;37;41m",
+            LogLevel::Alert => "\x1b[1;35m",
+            LogLevel::Critical => "\x1b[1;31m",
+            LogLevel::Error => "\x1b[31m",
+            LogLevel::Warning => "\x1b[33m",
+            LogLevel::Notice => "\x1b[36m",
+            LogLevel::Info => "\x1b[32m",
+            LogLevel::Debug => "\x1b[90m",
+        }
+    }
+
+    /// Syslog severity (RFC 5424), 0 for Emergency through 7 for Debug
+    pub fn syslog_severity(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = crate::LogStreamError;
+
+    /// Parse a level name, case-insensitively, in full or short form (`warn`, `crit`)
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "emergency" | "emerg" => Ok(LogLevel::Emergency),
+            "alert" => Ok(LogLevel::Alert),
+            "critical" | "crit" => Ok(LogLevel::Critical),
+            "error" | "err" => Ok(LogLevel::Error),
+            "warning" | "warn" => Ok(LogLevel::Warning),
+            "notice" => Ok(LogLevel::Notice),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(crate::LogStreamError::Config(format!("Unknown log level '{}'", name))),
+        }
+    }
+}
+
 impl fmt::Display for LogLevel {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -52,6 +218,11 @@ pub struct LogEntry {
     pub id: Uuid,
     
     /// Timestamp when the log was created
+    ///
+    /// Written as RFC 3339. Read from RFC 3339, or from epoch seconds or
+    /// milliseconds for clients that send numbers; entries without one get
+    /// the time they are parsed.
+    #[serde(default = "Utc::now", deserialize_with = "deserialize_timestamp")]
     pub timestamp: DateTime<Utc>,
     
     /// Log severity level
@@ -64,13 +235,174 @@ pub struct LogEntry {
     pub message: String,
     
     /// Additional structured fields
+    #[serde(default)]
     pub fields: LogFields,
+
+    /// OpenTelemetry resource attributes describing the sender, such as `service.name`
+    ///
+    /// Kept apart from `fields`, which describe the event itself. Left out
+    /// of the JSON when empty.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub resource: LogFields,
+
+    /// Keys of `fields` whose values are binary data in standard base64
+    ///
+    /// Set by [`LogEntry::add_binary_field`]. Marking the keys apart from
+    /// the values keeps any text value from reading as binary. Left out of
+    /// the JSON when empty.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub binary_fields: BTreeSet<String>,
     
     /// Process ID that generated the log
     pub pid: Option<u32>,
     
     /// Hostname where the log was generated
-    pub hostname: Option<String>,
+    ///
+    /// Shared so a client can stamp every entry without allocating a copy.
+    pub hostname: Option<Arc<str>>,
+
+    /// Wire format version the sender used
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+}
+
+fn default_schema_version() -> u32 {
+    1
+}
+
+/// Epoch numbers at least this large are milliseconds rather than seconds
+///
+/// As seconds this would be in the year 5138; as milliseconds it is March 1973.
+const EPOCH_MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+fn deserialize_timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    struct TimestampVisitor;
+
+    impl Visitor<'_> for TimestampVisitor {
+        type Value = DateTime<Utc>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an RFC 3339 timestamp or epoch seconds or milliseconds")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            DateTime::parse_from_rfc3339(value)
+                .map(|timestamp| timestamp.with_timezone(&Utc))
+                .map_err(|e| E::custom(format!("invalid timestamp '{}': {}", value, e)))
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            let timestamp = if value.abs() >= EPOCH_MILLIS_THRESHOLD {
+                DateTime::from_timestamp_millis(value)
+            } else {
+                DateTime::from_timestamp(value, 0)
+            };
+            timestamp.ok_or_else(|| E::custom(format!("epoch timestamp {} out of range", value)))
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+            let value = i64::try_from(value)
+                .map_err(|_| E::custom(format!("epoch timestamp {} out of range", value)))?;
+            self.visit_i64(value)
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+            // Fractional epoch seconds, as sent by e.g. Python's time.time()
+            let millis = if value.abs() >= EPOCH_MILLIS_THRESHOLD as f64 { value } else { value * 1000.0 };
+            if !millis.is_finite() || millis.abs() >= i64::MAX as f64 {
+                return Err(E::custom(format!("epoch timestamp {} out of range", value)));
+            }
+            DateTime::from_timestamp_millis(millis.round() as i64)
+                .ok_or_else(|| E::custom(format!("epoch timestamp {} out of range", value)))
+        }
+    }
+
+    deserializer.deserialize_any(TimestampVisitor)
+}
+
+/// [`LogEntry`] as written by [`LogEntry::to_json_compact`]
+#[derive(Serialize)]
+struct CompactEntry<'a> {
+    id: &'a Uuid,
+    timestamp: &'a DateTime<Utc>,
+    level: LogLevel,
+    daemon: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    fields: &'a LogFields,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    resource: &'a LogFields,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    binary_fields: &'a BTreeSet<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<&'a str>,
+    schema_version: u32,
+}
+
+/// [`LogEntry`] as written by [`LogEntry::to_json_flat`] and [`LogEntry::to_json_renamed`]
+struct KeyedEntry<'a> {
+    entry: &'a LogEntry,
+    flatten: bool,
+    omit_unset: bool,
+    /// Key written in place of each renamed entry key
+    names: Option<&'a HashMap<String, String>>,
+}
+
+impl KeyedEntry<'_> {
+    fn key<'k>(&'k self, key: &'k str) -> &'k str {
+        self.names.and_then(|names| names.get(key)).map_or(key, String::as_str)
+    }
+}
+
+impl Serialize for KeyedEntry<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entry = self.entry;
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry(self.key("id"), &entry.id)?;
+        map.serialize_entry(self.key("timestamp"), &entry.timestamp)?;
+        map.serialize_entry(self.key("level"), &entry.level)?;
+        map.serialize_entry(self.key("daemon"), &entry.daemon)?;
+        map.serialize_entry(self.key("message"), &entry.message)?;
+
+        if !self.flatten {
+            // Same order and omissions as the derived and compact forms
+            if !(self.omit_unset && entry.fields.is_empty()) {
+                map.serialize_entry(self.key("fields"), &entry.fields)?;
+            }
+            if !entry.resource.is_empty() {
+                map.serialize_entry(self.key("resource"), &entry.resource)?;
+            }
+            if !entry.binary_fields.is_empty() {
+                map.serialize_entry(self.key("binary_fields"), &entry.binary_fields)?;
+            }
+        }
+        if !(self.omit_unset && entry.pid.is_none()) {
+            map.serialize_entry(self.key("pid"), &entry.pid)?;
+        }
+        if !(self.omit_unset && entry.hostname.is_none()) {
+            map.serialize_entry(self.key("hostname"), &entry.hostname)?;
+        }
+        map.serialize_entry(self.key("schema_version"), &entry.schema_version)?;
+        if !self.flatten {
+            return map.end();
+        }
+
+        if !entry.resource.is_empty() {
+            let resource: BTreeMap<_, _> = entry.resource.iter().collect();
+            map.serialize_entry(self.key("resource"), &resource)?;
+        }
+        if !entry.binary_fields.is_empty() {
+            map.serialize_entry(self.key("binary_fields"), &entry.binary_fields)?;
+        }
+        let mut fields: Vec<_> = entry.fields.iter().collect();
+        fields.sort_unstable_by_key(|(key, _)| key.as_str());
+        for (key, value) in fields {
+            map.serialize_entry(&format!("{}{}", FLATTENED_FIELD_PREFIX, key), value)?;
+        }
+        map.end()
+    }
 }
 
 impl LogEntry {
@@ -83,9 +415,66 @@ impl LogEntry {
             daemon,
             message,
             fields: HashMap::new(),
+            resource: HashMap::new(),
+            binary_fields: BTreeSet::new(),
             pid: None,
             hostname: None,
+            schema_version: LOG_ENTRY_SCHEMA_VERSION,
+        }
+    }
+
+    /// Create a new log entry timestamped by the given clock
+    pub fn with_clock(level: LogLevel, daemon: String, message: String, clock: &dyn Clock) -> Self {
+        Self {
+            timestamp: clock.now_utc(),
+            ..Self::new(level, daemon, message)
+        }
+    }
+
+    /// Create an entry with a nil id and the Unix epoch as timestamp
+    ///
+    /// Meant for tests: entries built this way from the same arguments are
+    /// identical, down to their serialized JSON.
+    pub fn with_fixed_id_and_time(level: LogLevel, daemon: String, message: String) -> Self {
+        Self {
+            id: Uuid::nil(),
+            timestamp: DateTime::UNIX_EPOCH,
+            ..Self::new(level, daemon, message)
+        }
+    }
+
+    /// Whether both entries have the same level, daemon, message and fields
+    ///
+    /// Fields count as the same only when the same ones hold binary data.
+    /// Ignores `id`, `timestamp`, `pid`, `hostname`, `resource` and
+    /// `schema_version`, which differ between otherwise identical entries.
+    pub fn matches_content(&self, other: &LogEntry) -> bool {
+        self.level == other.level
+            && self.daemon == other.daemon
+            && self.message == other.message
+            && self.fields == other.fields
+            && self.binary_fields == other.binary_fields
+    }
+
+    /// Add a field holding binary data, such as a hash or a small payload
+    ///
+    /// The bytes are kept in `fields` as their base64 encoding, so they pass
+    /// through JSON and every backend unchanged, and the key is added to
+    /// `binary_fields`. Read them back with [`LogEntry::binary_field`].
+    pub fn add_binary_field(&mut self, key: impl Into<String>, bytes: &[u8]) {
+        let key = key.into();
+        self.fields.insert(key.clone(), BASE64.encode(bytes));
+        self.binary_fields.insert(key);
+    }
+
+    /// Bytes of a field added with [`LogEntry::add_binary_field`]
+    ///
+    /// `None` when the field is missing or holds text, whatever the text.
+    pub fn binary_field(&self, key: &str) -> Option<Vec<u8>> {
+        if !self.binary_fields.contains(key) {
+            return None;
         }
+        BASE64.decode(self.fields.get(key)?).ok()
     }
 
     /// Serialize to JSON string
@@ -93,16 +482,212 @@ impl LogEntry {
         serde_json::to_string(self)
     }
 
+    /// Serialize to JSON, leaving out `fields` when empty and `pid` and `hostname` when unset
+    ///
+    /// The result parses back with [`LogEntry::from_json`] into an equal entry.
+    pub fn to_json_compact(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&CompactEntry {
+            id: &self.id,
+            timestamp: &self.timestamp,
+            level: self.level,
+            daemon: &self.daemon,
+            message: &self.message,
+            fields: &self.fields,
+            resource: &self.resource,
+            binary_fields: &self.binary_fields,
+            pid: self.pid,
+            hostname: self.hostname.as_deref(),
+            schema_version: self.schema_version,
+        })
+    }
+
+    /// Serialize to JSON with each field as a top-level key instead of under `fields`
+    ///
+    /// Field keys get the [`FLATTENED_FIELD_PREFIX`], so `user_id` becomes
+    /// `field_user_id`, and are written in key order. Resource attributes
+    /// stay under `resource`. With `omit_unset`, `pid` and `hostname` are
+    /// left out when unset.
+    pub fn to_json_flat(&self, omit_unset: bool) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&KeyedEntry {
+            entry: self,
+            flatten: true,
+            omit_unset,
+            names: None,
+        })
+    }
+
+    /// Serialize to JSON with the entry's own keys renamed, e.g. `message` to `msg`
+    ///
+    /// `field_names` maps keys from [`ENTRY_JSON_KEYS`] to the names written
+    /// instead; values are unchanged and unlisted keys keep their names.
+    /// `flatten` and `omit_unset` work as in [`LogEntry::to_json_flat`] and,
+    /// without `flatten`, [`LogEntry::to_json_compact`]. Read the result back
+    /// with [`LogEntry::from_json_renamed`].
+    pub fn to_json_renamed(
+        &self,
+        field_names: &HashMap<String, String>,
+        flatten: bool,
+        omit_unset: bool,
+    ) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&KeyedEntry {
+            entry: self,
+            flatten,
+            omit_unset,
+            names: Some(field_names),
+        })
+    }
+
+    /// Serialize as a GELF 1.1 message for Graylog
+    ///
+    /// `short_message` is the first line of the message, with the whole
+    /// message as `full_message` when it has more. `host` is the hostname,
+    /// or the daemon name when unset, and `level` the syslog severity.
+    /// The daemon, pid, resource attributes and fields become additional
+    /// `_`-prefixed keys, fields last so they win on a clash; see
+    /// [`gelf_field_key`] for how names are made valid.
+    pub fn to_gelf(&self) -> Result<String, serde_json::Error> {
+        let message = self.message.trim_end_matches(['\r', '\n']);
+        let short_message = message.lines().next().unwrap_or_default();
+        let timestamp = self.timestamp.timestamp_micros() as f64 / 1_000_000.0;
+
+        let mut gelf = serde_json::Map::new();
+        gelf.insert("version".to_string(), GELF_VERSION.into());
+        gelf.insert("host".to_string(), self.hostname.as_deref().unwrap_or(&self.daemon).into());
+        gelf.insert("short_message".to_string(), short_message.into());
+        if short_message.len() < message.len() {
+            gelf.insert("full_message".to_string(), message.into());
+        }
+        gelf.insert("timestamp".to_string(), timestamp.into());
+        gelf.insert("level".to_string(), self.level.syslog_severity().into());
+        gelf.insert("_daemon".to_string(), self.daemon.as_str().into());
+        if let Some(pid) = self.pid {
+            gelf.insert("_pid".to_string(), pid.into());
+        }
+        for (key, value) in self.resource.iter().chain(&self.fields) {
+            gelf.insert(gelf_field_key(key), value.as_str().into());
+        }
+        serde_json::to_string(&gelf)
+    }
+
+    /// Bytes [`LogEntry::to_json`] is expected to produce, without serializing
+    ///
+    /// Sums the lengths of the daemon, message, hostname, fields and resource
+    /// attributes plus a fixed overhead. This bounds the real length from
+    /// above unless strings need escaping, which can make them grow up to
+    /// sixfold; meant for sizing buffers and metrics, not for limits.
+    pub fn estimated_serialized_size(&self) -> usize {
+        let map_size = |map: &LogFields| {
+            map.iter()
+                .map(|(key, value)| key.len() + value.len() + MAP_ENTRY_JSON_OVERHEAD)
+                .sum::<usize>()
+        };
+        ENTRY_JSON_OVERHEAD
+            + self.daemon.len()
+            + self.message.len()
+            + self.hostname.as_deref().map_or(0, str::len)
+            + map_size(&self.fields)
+            + map_size(&self.resource)
+    }
+
     /// Format as human-readable string
+    ///
+    /// Fields follow the message as `key=value` pairs sorted by key, with
+    /// values over [`DEFAULT_HUMAN_MAX_FIELD_LEN`] bytes truncated and
+    /// binary fields shown as `<N bytes>`.
     pub fn to_human_readable(&self) -> String {
+        self.to_human_readable_with(DEFAULT_HUMAN_MAX_FIELD_LEN)
+    }
+
+    /// Format as human-readable string, truncating field values over `max_field_len` bytes
+    ///
+    /// A truncated value ends in `…(N bytes)` giving its full length. Zero
+    /// disables truncation. Control characters other than tab are escaped
+    /// (see [`escape_control`]) so a client can't inject terminal escape
+    /// sequences or fake log lines.
+    pub fn to_human_readable_with(&self, max_field_len: usize) -> String {
         let timestamp = self.timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
-        format!("{} {} {}: {}", timestamp, self.level, self.daemon, self.message)
+        let mut line = format!(
+            "{} {} {}: {}",
+            timestamp,
+            self.level,
+            escape_control(&self.daemon),
+            escape_control(&self.message)
+        );
+        self.push_fields(&mut line, max_field_len);
+        line
+    }
+
+    /// Format as human-readable lines, one per line of the message
+    ///
+    /// The first line is formatted like [`LogEntry::to_human_readable_with`]
+    /// with the fields after it; each further message line follows on its own
+    /// line after the [`MULTILINE_CONTINUATION_MARKER`], so stack traces stay
+    /// readable. The lines are joined with `\n`.
+    pub fn to_human_readable_split(&self, max_field_len: usize) -> String {
+        let mut lines = self.message.lines();
+        let first = Self {
+            message: lines.next().unwrap_or_default().to_string(),
+            ..self.clone()
+        };
+        let mut output = first.to_human_readable_with(max_field_len);
+        for line in lines {
+            output.push('\n');
+            output.push_str(MULTILINE_CONTINUATION_MARKER);
+            output.push_str(&escape_control(line));
+        }
+        output
+    }
+
+    /// Append ` key=value` for each field, sorted by key and truncated as in
+    /// [`LogEntry::to_human_readable_with`]
+    pub(crate) fn push_fields(&self, line: &mut String, max_field_len: usize) {
+        let mut keys: Vec<&String> = self.fields.keys().collect();
+        keys.sort();
+        for key in keys {
+            let value = &self.fields[key];
+            line.push(' ');
+            line.push_str(&escape_control(key));
+            line.push('=');
+            if let Some(bytes) = self.binary_field(key) {
+                line.push_str(&format!("<{} bytes>", bytes.len()));
+            } else if max_field_len > 0 && value.len() > max_field_len {
+                let mut end = max_field_len;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                line.push_str(&escape_control(&value[..end]));
+                line.push_str(&format!("…({} bytes)", value.len()));
+            } else {
+                line.push_str(&escape_control(value));
+            }
+        }
     }
     
     /// Deserialize from JSON string
     pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
         serde_json::from_str(json)
     }
+
+    /// Parse JSON written by [`LogEntry::to_json_renamed`] with the same `field_names`
+    pub fn from_json_renamed(json: &str, field_names: &HashMap<String, String>) -> Result<Self, serde_json::Error> {
+        if field_names.is_empty() {
+            return Self::from_json(json);
+        }
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        if let Some(object) = value.as_object_mut() {
+            for (key, renamed) in field_names {
+                if let Some(field) = object.remove(renamed) {
+                    object.insert(key.clone(), field);
+                }
+            }
+        }
+        serde_json::from_value(value)
+    }
+
+    /// Parse newline-delimited JSON entries from a reader as they arrive
+    pub fn stream_from_reader<R: AsyncRead + Unpin>(reader: R) -> LogEntryStream<R> {
+        LogEntryStream::new(reader)
+    }
 }
 
 #[cfg(test)]
@@ -172,13 +757,13 @@ mod tests {
         entry.fields.insert("error_code".to_string(), "DB001".to_string());
         entry.fields.insert("retry_count".to_string(), "3".to_string());
         entry.pid = Some(12345);
-        entry.hostname = Some("server01".to_string());
+        entry.hostname = Some("server01".into());
 
         assert_eq!(entry.fields.len(), 2);
         assert_eq!(entry.fields.get("error_code"), Some(&"DB001".to_string()));
         assert_eq!(entry.fields.get("retry_count"), Some(&"3".to_string()));
         assert_eq!(entry.pid, Some(12345));
-        assert_eq!(entry.hostname, Some("server01".to_string()));
+        assert_eq!(entry.hostname.as_deref(), Some("server01"));
     }
 
     #[test]
@@ -218,6 +803,75 @@ mod tests {
         assert_eq!(deserialized.pid, Some(5678));
     }
 
+    #[test]
+    fn test_old_format_entry_without_schema_version() {
+        let json = r#"{
+            "id": "7f1c2a9e-3b4d-4e5f-8a6b-9c0d1e2f3a4b",
+            "timestamp": "2024-01-02T03:04:05Z",
+            "level": "Info",
+            "daemon": "legacy",
+            "message": "from an old client",
+            "fields": {},
+            "pid": 42,
+            "hostname": "old-host"
+        }"#;
+
+        let entry = LogEntry::from_json(json).unwrap();
+        assert_eq!(entry.schema_version, 1);
+        assert_eq!(entry.message, "from an old client");
+    }
+
+    #[test]
+    fn test_timestamp_formats_parse_to_the_same_instant() {
+        let expected = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.250Z").unwrap().with_timezone(&Utc);
+        let with_timestamp = |timestamp: &str| {
+            let json = format!(
+                r#"{{"id": "7f1c2a9e-3b4d-4e5f-8a6b-9c0d1e2f3a4b", "timestamp": {}, "level": "Info", "daemon": "http", "message": "hi"}}"#,
+                timestamp
+            );
+            LogEntry::from_json(&json).map(|entry| entry.timestamp)
+        };
+
+        assert_eq!(with_timestamp("\"2024-01-02T03:04:05.250Z\"").unwrap(), expected);
+        assert_eq!(with_timestamp("\"2024-01-02T04:04:05.250+01:00\"").unwrap(), expected);
+        assert_eq!(with_timestamp("1704164645250").unwrap(), expected);
+        assert_eq!(with_timestamp("1704164645.25").unwrap(), expected);
+        assert_eq!(with_timestamp("1704164645").unwrap(), expected - chrono::Duration::milliseconds(250));
+        assert!(with_timestamp("\"yesterday\"").is_err());
+        assert!(with_timestamp("true").is_err());
+    }
+
+    #[test]
+    fn test_missing_timestamp_gets_receipt_time() {
+        let before = Utc::now();
+        let entry = LogEntry::from_json(
+            r#"{"id": "7f1c2a9e-3b4d-4e5f-8a6b-9c0d1e2f3a4b", "level": "Warning", "daemon": "http", "message": "no clock"}"#,
+        )
+        .unwrap();
+        assert!(entry.timestamp >= before && entry.timestamp <= Utc::now());
+    }
+
+    #[test]
+    fn test_future_format_entry_with_unknown_fields() {
+        let json = r#"{
+            "id": "7f1c2a9e-3b4d-4e5f-8a6b-9c0d1e2f3a4b",
+            "timestamp": "2024-01-02T03:04:05Z",
+            "level": "Warning",
+            "daemon": "future",
+            "message": "from a newer client",
+            "fields": {},
+            "pid": null,
+            "hostname": null,
+            "schema_version": 99,
+            "source_location": {"file": "main.rs", "line": 7}
+        }"#;
+
+        let entry = LogEntry::from_json(json).unwrap();
+        assert_eq!(entry.schema_version, 99);
+        assert_eq!(entry.level, LogLevel::Warning);
+        assert!(entry.to_json().unwrap().contains("\"schema_version\":99"));
+    }
+
     #[test]
     fn test_log_entry_human_readable() {
         let entry = LogEntry::new(
@@ -235,6 +889,77 @@ mod tests {
         assert!(readable.chars().filter(|&c| c == ':').count() >= 3);
     }
 
+    #[test]
+    fn test_human_readable_truncates_long_fields() {
+        let mut entry = LogEntry::new(LogLevel::Error, "worker".to_string(), "job failed".to_string());
+        let blob = "A".repeat(10 * 1024);
+        entry.fields.insert("payload".to_string(), blob.clone());
+        entry.fields.insert("job".to_string(), "resize".to_string());
+
+        let readable = entry.to_human_readable_with(64);
+        assert!(readable.ends_with(&format!(
+            "job=resize payload={}…(10240 bytes)",
+            "A".repeat(64)
+        )));
+        assert!(entry.to_human_readable().len() < 1024);
+        assert!(entry.to_human_readable_with(0).contains(&blob));
+
+        let json = entry.to_json().unwrap();
+        let parsed = LogEntry::from_json(&json).unwrap();
+        assert_eq!(parsed.fields["payload"], blob);
+    }
+
+    #[test]
+    fn test_human_readable_truncates_on_char_boundary() {
+        let mut entry = LogEntry::new(LogLevel::Info, "d".to_string(), "m".to_string());
+        entry.fields.insert("text".to_string(), "é".repeat(10));
+
+        // 5 bytes falls inside the third two-byte character
+        assert!(entry.to_human_readable_with(5).ends_with("text=éé…(20 bytes)"));
+    }
+
+    #[test]
+    fn test_log_entry_with_clock() {
+        use crate::types::MockClock;
+        use chrono::TimeZone;
+
+        let start = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let entry = LogEntry::with_clock(LogLevel::Info, "d".to_string(), "m".to_string(), &clock);
+        assert_eq!(entry.timestamp, start);
+    }
+
+    #[test]
+    fn test_matches_content_ignores_identity() {
+        let mut first = LogEntry::new(LogLevel::Warning, "svc".to_string(), "disk low".to_string());
+        first.fields.insert("mount".to_string(), "/var".to_string());
+        first.pid = Some(1);
+        let mut second = LogEntry::new(LogLevel::Warning, "svc".to_string(), "disk low".to_string());
+        second.fields.insert("mount".to_string(), "/var".to_string());
+        second.hostname = Some("other-host".into());
+
+        assert_ne!(first.id, second.id);
+        assert!(first.matches_content(&second));
+        assert!(second.matches_content(&first));
+
+        second.fields.insert("mount".to_string(), "/home".to_string());
+        assert!(!first.matches_content(&second));
+        second.fields = first.fields.clone();
+        second.level = LogLevel::Error;
+        assert!(!first.matches_content(&second));
+    }
+
+    #[test]
+    fn test_with_fixed_id_and_time() {
+        let first = LogEntry::with_fixed_id_and_time(LogLevel::Info, "svc".to_string(), "ready".to_string());
+        let second = LogEntry::with_fixed_id_and_time(LogLevel::Info, "svc".to_string(), "ready".to_string());
+
+        assert_eq!(first.id, Uuid::nil());
+        assert_eq!(first.timestamp.timestamp(), 0);
+        assert_eq!(first.to_json().unwrap(), second.to_json().unwrap());
+        assert!(first.matches_content(&LogEntry::new(LogLevel::Info, "svc".to_string(), "ready".to_string())));
+    }
+
     #[test]
     fn test_multiple_log_entries_unique_ids() {
         let entry1 = LogEntry::new(LogLevel::Info, "daemon1".to_string(), "msg1".to_string());
@@ -263,7 +988,7 @@ mod tests {
         original.fields.insert("field_with_newline".to_string(), "line1\nline2".to_string());
         original.fields.insert("field_with_tab".to_string(), "col1\tcol2".to_string());
         original.pid = Some(99999);
-        original.hostname = Some("test-host.example.com".to_string());
+        original.hostname = Some("test-host.example.com".into());
 
         let json = original.to_json().unwrap();
         let deserialized = LogEntry::from_json(&json).unwrap();
@@ -277,4 +1002,264 @@ mod tests {
         assert_eq!(deserialized.pid, original.pid);
         assert_eq!(deserialized.hostname, original.hostname);
     }
+
+    #[test]
+    fn test_compact_json_round_trip() {
+        let mut original = LogEntry::new(LogLevel::Info, "test-daemon".to_string(), "msg".to_string());
+        original.hostname = Some("web01".into());
+
+        let json = original.to_json_compact().unwrap();
+        assert!(!json.contains("\"fields\""));
+        assert!(!json.contains("\"pid\""));
+        // Identical to the full form apart from the omitted keys
+        let mut expected: serde_json::Value = serde_json::from_str(&original.to_json().unwrap()).unwrap();
+        expected.as_object_mut().unwrap().remove("fields");
+        expected.as_object_mut().unwrap().remove("pid");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), expected);
+
+        let parsed = LogEntry::from_json(&json).unwrap();
+        assert_eq!(parsed.id, original.id);
+        assert_eq!(parsed.timestamp, original.timestamp);
+        assert!(parsed.fields.is_empty());
+        assert_eq!(parsed.pid, None);
+        assert_eq!(parsed.hostname.as_deref(), Some("web01"));
+    }
+
+    #[test]
+    fn test_renamed_json_round_trip() {
+        let mut original = LogEntry::new(LogLevel::Error, "test-daemon".to_string(), "msg".to_string());
+        original.fields.insert("user".to_string(), "ada".to_string());
+        let names = HashMap::from([
+            ("message".to_string(), "msg".to_string()),
+            ("level".to_string(), "severity".to_string()),
+        ]);
+
+        // Only the keys change, in place
+        let json = original.to_json_renamed(&names, false, false).unwrap();
+        let expected = original.to_json().unwrap().replacen("\"message\":", "\"msg\":", 1).replacen("\"level\":", "\"severity\":", 1);
+        assert_eq!(json, expected);
+        // Without renames each form matches its dedicated method
+        let none = HashMap::new();
+        assert_eq!(original.to_json_renamed(&none, false, false).unwrap(), original.to_json().unwrap());
+        assert_eq!(original.to_json_renamed(&none, false, true).unwrap(), original.to_json_compact().unwrap());
+        assert_eq!(original.to_json_renamed(&none, true, true).unwrap(), original.to_json_flat(true).unwrap());
+
+        let parsed = LogEntry::from_json_renamed(&json, &names).unwrap();
+        assert_eq!((parsed.id, parsed.level, parsed.message.as_str()), (original.id, LogLevel::Error, "msg"));
+        assert_eq!(parsed.fields, original.fields);
+    }
+
+    #[test]
+    fn test_binary_field_round_trip() {
+        let bytes: Vec<u8> = (0..=255).chain([0xff, 0xfe, 0x00]).collect();
+        let mut entry = LogEntry::new(LogLevel::Info, "scanner".to_string(), "blob".to_string());
+        entry.add_binary_field("sha", &bytes);
+        entry.fields.insert("note".to_string(), "plain".to_string());
+        // Text that happens to look like base64 stays text
+        entry.fields.insert("tag".to_string(), "base64:AAAA".to_string());
+
+        let json = entry.to_json().unwrap();
+        assert!(json.contains(r#""sha":"AAECAwQF"#), "{}", json);
+        assert!(json.contains(r#""binary_fields":["sha"]"#), "{}", json);
+        let parsed = LogEntry::from_json(&json).unwrap();
+        assert_eq!(parsed.binary_field("sha").unwrap(), bytes);
+        assert_eq!(parsed.binary_field("note"), None);
+        assert_eq!(parsed.binary_field("tag"), None);
+        assert_eq!(parsed.binary_field("missing"), None);
+        assert!(parsed.matches_content(&entry));
+
+        let human = parsed.to_human_readable_with(16);
+        assert!(human.ends_with(" note=plain sha=<259 bytes> tag=base64:AAAA"), "{}", human);
+
+        let compact = LogEntry::from_json(&entry.to_json_compact().unwrap()).unwrap();
+        assert_eq!(compact.binary_fields, entry.binary_fields);
+        let flat: serde_json::Value = serde_json::from_str(&entry.to_json_flat(true).unwrap()).unwrap();
+        assert_eq!(flat["binary_fields"], serde_json::json!(["sha"]));
+
+        entry.add_binary_field("empty", &[]);
+        assert_eq!(entry.binary_field("empty").unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_gelf_message() {
+        let mut entry = LogEntry::new(LogLevel::Warning, "api".to_string(), "disk slow\nsdb at 98%".to_string());
+        entry.hostname = Some(Arc::from("web01"));
+        entry.pid = Some(42);
+        entry.fields.insert("user_id".to_string(), "7".to_string());
+        entry.fields.insert("id".to_string(), "req-1".to_string());
+        entry.fields.insert("http status".to_string(), "503".to_string());
+
+        let gelf: serde_json::Value = serde_json::from_str(&entry.to_gelf().unwrap()).unwrap();
+        assert_eq!(gelf["version"], "1.1");
+        assert_eq!(gelf["host"], "web01");
+        assert_eq!(gelf["short_message"], "disk slow");
+        assert_eq!(gelf["full_message"], "disk slow\nsdb at 98%");
+        assert_eq!(gelf["level"], 4);
+        assert_eq!(gelf["timestamp"].as_f64().unwrap().floor() as i64, entry.timestamp.timestamp());
+        assert_eq!((&gelf["_daemon"], &gelf["_pid"]), (&"api".into(), &42.into()));
+        assert_eq!(gelf["_user_id"], "7");
+        assert_eq!(gelf["_field_id"], "req-1");
+        assert_eq!(gelf["_http_status"], "503");
+        // Everything beyond the standard keys is an underscore-prefixed additional field
+        let standard = ["version", "host", "short_message", "full_message", "timestamp", "level"];
+        for key in gelf.as_object().unwrap().keys().filter(|key| !standard.contains(&key.as_str())) {
+            assert!(key.starts_with('_') && key != "_id", "{}", key);
+        }
+
+        entry.hostname = None;
+        entry.message = "one line".to_string();
+        let gelf: serde_json::Value = serde_json::from_str(&entry.to_gelf().unwrap()).unwrap();
+        assert_eq!(gelf["host"], "api");
+        assert!(gelf.get("full_message").is_none());
+    }
+
+    #[test]
+    fn test_resource_attributes_serialize_apart_from_fields() {
+        let mut entry = LogEntry::new(LogLevel::Info, "checkout".to_string(), "order placed".to_string());
+        entry.fields.insert("order_id".to_string(), "42".to_string());
+        entry.resource.insert("service.name".to_string(), "checkout".to_string());
+        entry.resource.insert("deployment.environment".to_string(), "prod".to_string());
+
+        let json: serde_json::Value = serde_json::from_str(&entry.to_json().unwrap()).unwrap();
+        assert_eq!(json["resource"], serde_json::json!({ "service.name": "checkout", "deployment.environment": "prod" }));
+        assert_eq!(json["fields"], serde_json::json!({ "order_id": "42" }));
+
+        let parsed = LogEntry::from_json(&entry.to_json_compact().unwrap()).unwrap();
+        assert_eq!(parsed.resource, entry.resource);
+        assert_eq!(parsed.fields, entry.fields);
+        let flat: serde_json::Value = serde_json::from_str(&entry.to_json_flat(true).unwrap()).unwrap();
+        assert_eq!(flat["resource"], json["resource"]);
+        assert_eq!(flat["field_order_id"], "42");
+
+        // Entries without resource attributes look as they always did
+        entry.resource.clear();
+        assert!(!entry.to_json().unwrap().contains("\"resource\""));
+        assert!(LogEntry::from_json(&entry.to_json().unwrap()).unwrap().resource.is_empty());
+    }
+
+    #[test]
+    fn test_estimated_serialized_size_bounds_json_length() {
+        let simple = LogEntry::new(LogLevel::Info, "svc".to_string(), "ready".to_string());
+
+        let mut complex = LogEntry::new(LogLevel::Emergency, "payment-gateway".to_string(), "x".repeat(4096));
+        complex.pid = Some(u32::MAX);
+        complex.hostname = Some("web-01.eu-west-1.internal.example.com".into());
+        complex.schema_version = u32::MAX;
+        for i in 0..50 {
+            complex.fields.insert(format!("field_{}", i), format!("value number {}", i));
+        }
+        complex.resource.insert("service.name".to_string(), "payments".to_string());
+
+        for entry in [simple, complex] {
+            let actual = entry.to_json().unwrap().len();
+            let estimate = entry.estimated_serialized_size();
+            assert!(estimate >= actual, "estimate {} below actual {}", estimate, actual);
+            assert!(estimate <= actual * 2, "estimate {} too far above actual {}", estimate, actual);
+        }
+    }
+
+    #[test]
+    fn test_shared_hostname_serializes_as_string() {
+        let hostname: Arc<str> = Arc::from("web01.example.com");
+        let mut entry = LogEntry::new(LogLevel::Info, "test-daemon".to_string(), "msg".to_string());
+        entry.hostname = Some(Arc::clone(&hostname));
+
+        let json = entry.to_json().unwrap();
+        assert!(json.contains(r#""hostname":"web01.example.com""#));
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["hostname"], "web01.example.com");
+
+        let deserialized = LogEntry::from_json(&json).unwrap();
+        assert_eq!(deserialized.hostname.as_deref(), Some("web01.example.com"));
+    }
+
+    #[test]
+    fn test_level_ansi_colors() {
+        assert_eq!(LogLevel::Emergency.ansi_color(), "\x1b[1;37;41m");
+        assert_eq!(LogLevel::Alert.ansi_color(), "\x1b[1;35m");
+        assert_eq!(LogLevel::Critical.ansi_color(), "\x1b[1;31m");
+        assert_eq!(LogLevel::Error.ansi_color(), "\x1b[31m");
+        assert_eq!(LogLevel::Warning.ansi_color(), "\x1b[33m");
+        assert_eq!(LogLevel::Notice.ansi_color(), "\x1b[36m");
+        assert_eq!(LogLevel::Info.ansi_color(), "\x1b[32m");
+        assert_eq!(LogLevel::Debug.ansi_color(), "\x1b[90m");
+        assert_eq!(LogLevel::ANSI_RESET, "\x1b[0m");
+    }
+
+    #[test]
+    fn test_level_syslog_severity() {
+        let expected = [
+            (LogLevel::Emergency, 0),
+            (LogLevel::Alert, 1),
+            (LogLevel::Critical, 2),
+            (LogLevel::Error, 3),
+            (LogLevel::Warning, 4),
+            (LogLevel::Notice, 5),
+            (LogLevel::Info, 6),
+            (LogLevel::Debug, 7),
+        ];
+        for (level, severity) in expected {
+            assert_eq!(level.syslog_severity(), severity, "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn test_fields_macro() {
+        let user_id = 123;
+        let fields = crate::fields! {
+            user_id => user_id,
+            action => "login",
+            "latency.ms" => 4.5,
+            admin => false,
+        };
+
+        let mut expected = LogFields::new();
+        expected.insert("user_id".to_string(), "123".to_string());
+        expected.insert("action".to_string(), "login".to_string());
+        expected.insert("latency.ms".to_string(), "4.5".to_string());
+        expected.insert("admin".to_string(), "false".to_string());
+        assert_eq!(fields, expected);
+        assert!(crate::fields! {}.is_empty());
+    }
+
+    #[test]
+    fn test_human_split_puts_message_lines_after_markers() {
+        let mut entry = LogEntry::new(
+            LogLevel::Error,
+            "api".to_string(),
+            "panic: index out of bounds\r\n  at handler.rs:42\n  at main.rs:7\n".to_string(),
+        );
+        entry.fields.insert("request".to_string(), "17".to_string());
+
+        let output = entry.to_human_readable_split(0);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("api: panic: index out of bounds request=17"), "{}", lines[0]);
+        assert_eq!(lines[1], format!("{}  at handler.rs:42", MULTILINE_CONTINUATION_MARKER));
+        assert_eq!(lines[2], format!("{}  at main.rs:7", MULTILINE_CONTINUATION_MARKER));
+
+        entry.message = "single line".to_string();
+        assert_eq!(entry.to_human_readable_split(0), entry.to_human_readable_with(0));
+    }
+
+    #[test]
+    fn test_human_output_escapes_control_characters() {
+        let mut entry = LogEntry::new(
+            LogLevel::Warning,
+            "svc".to_string(),
+            "\x1b[31mred\x1b[0m\nFAKE 2024-01-01 Error other: injected\ttabbed".to_string(),
+        );
+        entry.fields.insert("agent".to_string(), "curl\x07\u{9b}2J".to_string());
+
+        let line = entry.to_human_readable();
+        assert!(!line.chars().any(|c| c.is_control() && c != '\t'));
+        assert!(line.contains("\\u{1b}[31mred\\u{1b}[0m\\nFAKE"));
+        assert!(line.contains("injected\ttabbed"));
+        assert!(line.ends_with("agent=curl\\u{7}\\u{9b}2J"));
+
+        // Machine-readable JSON keeps the original text
+        let parsed = LogEntry::from_json(&entry.to_json().unwrap()).unwrap();
+        assert_eq!(parsed.message, entry.message);
+    }
 }
@@ -1,5 +1,18 @@
 //! Core types used throughout LogStream
 
+pub mod chunk;
+pub mod clock;
+pub mod control;
+#[cfg(any(feature = "log-facade", feature = "tracing-interop"))]
+pub mod interop;
 pub mod log_entry;
+pub mod stream;
 
-pub use log_entry::{LogEntry, LogFields, LogLevel};
\ No newline at end of file
+pub use chunk::{EntryChunk, DEFAULT_MAX_REASSEMBLED_BYTES};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use control::{ControlCommand, StoragePathReply, PONG_RESPONSE};
+pub use log_entry::{
+    escape_control, gelf_field_key, LogEntry, LogFields, LogLevel, DEFAULT_HUMAN_MAX_FIELD_LEN, ENTRY_JSON_KEYS,
+    FLATTENED_FIELD_PREFIX, GELF_VERSION, LOG_ENTRY_SCHEMA_VERSION, MULTILINE_CONTINUATION_MARKER,
+};
+pub use stream::{Frame, LineLimits, LogEntryStream};
\ No newline at end of file
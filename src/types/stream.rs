@@ -0,0 +1,595 @@
+//! Streaming parser for newline-delimited JSON log entries
+
+use crate::types::chunk::DEFAULT_MAX_REASSEMBLED_BYTES;
+use crate::types::{ControlCommand, EntryChunk, LogEntry};
+use crate::{LogStreamError, Result};
+use bytes::{Buf, BytesMut};
+use futures_core::Stream;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::AsyncRead;
+use tokio_util::codec::{Decoder, FramedRead};
+
+/// Default maximum length of a single line in bytes (1 MiB)
+pub const DEFAULT_MAX_LINE_BYTES: usize = 1024 * 1024;
+
+/// Default maximum nesting of JSON objects and arrays in a line
+pub const DEFAULT_MAX_JSON_DEPTH: usize = 16;
+
+/// Field added to entries recovered from invalid UTF-8
+pub const LOSSY_ENCODING_FIELD: &str = "encoding";
+
+/// Bounds checked on each line before it is parsed
+///
+/// Lines that exceed them are rejected without being buffered in full or
+/// handed to the JSON parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineLimits {
+    /// Maximum length of a line in bytes, excluding the newline
+    pub max_line_bytes: usize,
+    /// Maximum nesting of JSON objects and arrays
+    pub max_json_depth: usize,
+    /// Recover lines holding invalid UTF-8 by replacing the bad sequences
+    ///
+    /// Recovered entries carry an `encoding=lossy` field.
+    pub lossy_utf8: bool,
+    /// Maximum size in bytes of an entry reassembled from [`EntryChunk`]s
+    pub max_reassembled_bytes: usize,
+}
+
+impl Default for LineLimits {
+    fn default() -> Self {
+        Self {
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            max_json_depth: DEFAULT_MAX_JSON_DEPTH,
+            lossy_utf8: false,
+            max_reassembled_bytes: DEFAULT_MAX_REASSEMBLED_BYTES,
+        }
+    }
+}
+
+/// A frame produced by [`LineCodec`]
+#[derive(Debug)]
+pub(crate) enum LineFrame {
+    /// A complete line without its newline
+    Line(BytesMut),
+    /// A line longer than the limit, already discarded
+    TooLong,
+}
+
+/// Splits a byte stream into newline-terminated frames without validating UTF-8
+///
+/// Lines longer than `max_length` are dropped as they arrive rather than
+/// buffered, and reported as [`LineFrame::TooLong`] once their end is seen.
+#[derive(Debug)]
+pub(crate) struct LineCodec {
+    max_length: usize,
+    /// Bytes already searched for a newline
+    next_index: usize,
+    /// Inside an oversized line that is being skipped
+    discarding: bool,
+}
+
+impl LineCodec {
+    pub(crate) fn new(max_length: usize) -> Self {
+        Self {
+            max_length,
+            next_index: 0,
+            discarding: false,
+        }
+    }
+}
+
+impl Default for LineCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_LINE_BYTES)
+    }
+}
+
+impl Decoder for LineCodec {
+    type Item = LineFrame;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> std::io::Result<Option<LineFrame>> {
+        let newline = src[self.next_index..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|i| i + self.next_index);
+
+        if self.discarding {
+            return match newline {
+                Some(newline) => {
+                    src.advance(newline + 1);
+                    self.next_index = 0;
+                    self.discarding = false;
+                    Ok(Some(LineFrame::TooLong))
+                }
+                None => {
+                    src.clear();
+                    self.next_index = 0;
+                    Ok(None)
+                }
+            };
+        }
+
+        match newline {
+            Some(newline) if newline <= self.max_length => {
+                let mut line = src.split_to(newline + 1);
+                line.truncate(newline);
+                self.next_index = 0;
+                Ok(Some(LineFrame::Line(line)))
+            }
+            Some(newline) => {
+                src.advance(newline + 1);
+                self.next_index = 0;
+                Ok(Some(LineFrame::TooLong))
+            }
+            None if src.len() > self.max_length => {
+                src.clear();
+                self.next_index = 0;
+                self.discarding = true;
+                Ok(None)
+            }
+            None => {
+                self.next_index = src.len();
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> std::io::Result<Option<LineFrame>> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if self.discarding => {
+                self.discarding = false;
+                Ok(Some(LineFrame::TooLong))
+            }
+            // A final entry without a trailing newline is still a complete line
+            None if !src.is_empty() => {
+                self.next_index = 0;
+                Ok(Some(LineFrame::Line(src.split_to(src.len()))))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Whether JSON in `line` nests objects/arrays deeper than `max_depth`
+///
+/// A single pass that only tracks brackets outside of strings, so it is much
+/// cheaper than a full parse of a pathological input.
+fn exceeds_depth(line: &[u8], max_depth: usize) -> bool {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for &b in line {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max_depth {
+                    return true;
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// One parsed line from a log connection
+#[derive(Debug)]
+// Nearly every frame is an entry, so boxing it would only add an allocation per line
+#[allow(clippy::large_enum_variant)]
+pub enum Frame {
+    /// A log entry to store
+    Entry(LogEntry),
+    /// A control command to answer
+    Control(ControlCommand),
+}
+
+/// Stream of log entries parsed from newline-delimited JSON
+///
+/// Blank lines are skipped. A line that fails to parse or exceeds the
+/// [`LineLimits`] yields an `Err` item but does not end the stream, so callers
+/// can skip it and keep reading. An I/O error from the underlying reader is
+/// yielded once and ends the stream; every complete line received before the
+/// error is yielded first, and a trailing unterminated line is yielded at EOF.
+///
+/// Runs of [`EntryChunk`] lines are joined and yielded as the single entry
+/// they carry.
+pub struct LogEntryStream<R> {
+    lines: FramedRead<R, LineCodec>,
+    limits: LineLimits,
+    /// Entry JSON received so far from chunk lines
+    reassembly: String,
+    /// Skipping the rest of a chunked entry that grew past the limit
+    discarding_chunks: bool,
+}
+
+impl<R: AsyncRead + Unpin> LogEntryStream<R> {
+    /// Create a stream reading entries from the given reader
+    pub fn new(reader: R) -> Self {
+        Self::with_limits(reader, LineLimits::default())
+    }
+
+    /// Create a stream that rejects lines exceeding the given limits
+    pub fn with_limits(reader: R, limits: LineLimits) -> Self {
+        Self {
+            lines: FramedRead::new(reader, LineCodec::new(limits.max_line_bytes)),
+            limits,
+            reassembly: String::new(),
+            discarding_chunks: false,
+        }
+    }
+
+    /// Wait for the next entry, returning `None` once the input is exhausted
+    pub async fn next(&mut self) -> Option<Result<LogEntry>> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Wait for the next entry or control command
+    ///
+    /// Unlike [`LogEntryStream::next`], lines holding a [`ControlCommand`]
+    /// are yielded as [`Frame::Control`] instead of parse errors.
+    pub async fn next_frame(&mut self) -> Option<Result<Frame>> {
+        poll_fn(|cx| self.poll_frame(cx)).await
+    }
+
+    fn poll_frame(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Frame>>> {
+        loop {
+            let line = match Pin::new(&mut self.lines).poll_next(cx) {
+                Poll::Ready(Some(Ok(LineFrame::Line(line)))) => line,
+                Poll::Ready(Some(Ok(LineFrame::TooLong))) => {
+                    return Poll::Ready(Some(Err(LogStreamError::Server(format!(
+                        "Rejected line longer than {} bytes",
+                        self.limits.max_line_bytes
+                    )))));
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+
+            if exceeds_depth(line, self.limits.max_json_depth) {
+                return Poll::Ready(Some(Err(LogStreamError::Server(format!(
+                    "Rejected line nested deeper than {} levels",
+                    self.limits.max_json_depth
+                )))));
+            }
+
+            let frame = match serde_json::from_slice::<LogEntry>(line) {
+                Ok(entry) => Ok(Frame::Entry(entry)),
+                Err(_) if self.limits.lossy_utf8 && std::str::from_utf8(line).is_err() => {
+                    parse_lossy(line)
+                }
+                // Only lines that aren't entries are checked for commands and chunks
+                Err(e) => match serde_json::from_slice::<ControlCommand>(line) {
+                    Ok(cmd) => Ok(Frame::Control(cmd)),
+                    Err(_) => match serde_json::from_slice::<EntryChunk>(line) {
+                        Ok(chunk) => match self.reassemble(chunk) {
+                            Some(frame) => frame,
+                            None => continue,
+                        },
+                        Err(_) => Err(e.into()),
+                    },
+                },
+            };
+            return Poll::Ready(Some(frame));
+        }
+    }
+
+    /// Add a chunk to the entry being reassembled, returning it once complete
+    fn reassemble(&mut self, chunk: EntryChunk) -> Option<Result<Frame>> {
+        if self.discarding_chunks {
+            self.discarding_chunks = chunk.more;
+            return None;
+        }
+
+        if self.reassembly.len() + chunk.chunk.len() > self.limits.max_reassembled_bytes {
+            self.reassembly = String::new();
+            self.discarding_chunks = chunk.more;
+            return Some(Err(LogStreamError::Server(format!(
+                "Rejected chunked entry larger than {} bytes",
+                self.limits.max_reassembled_bytes
+            ))));
+        }
+
+        self.reassembly.push_str(&chunk.chunk);
+        if chunk.more {
+            return None;
+        }
+
+        let json = std::mem::take(&mut self.reassembly);
+        if exceeds_depth(json.as_bytes(), self.limits.max_json_depth) {
+            return Some(Err(LogStreamError::Server(format!(
+                "Rejected chunked entry nested deeper than {} levels",
+                self.limits.max_json_depth
+            ))));
+        }
+        Some(LogEntry::from_json(&json).map(Frame::Entry).map_err(Into::into))
+    }
+}
+
+/// Parse a line after replacing invalid UTF-8 with U+FFFD, flagging the entry
+fn parse_lossy(line: &[u8]) -> Result<Frame> {
+    let text = String::from_utf8_lossy(line);
+    let mut entry: LogEntry = serde_json::from_str(&text)?;
+    entry
+        .fields
+        .insert(LOSSY_ENCODING_FIELD.to_string(), "lossy".to_string());
+    Ok(Frame::Entry(entry))
+}
+
+impl<R: AsyncRead + Unpin> Stream for LogEntryStream<R> {
+    type Item = Result<LogEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_frame(cx).map(|frame| {
+            frame.map(|frame| match frame? {
+                Frame::Entry(entry) => Ok(entry),
+                Frame::Control(cmd) => Err(LogStreamError::Server(format!(
+                    "Unexpected control command {:?} in entry stream",
+                    cmd
+                ))),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LogLevel;
+
+    #[tokio::test]
+    async fn test_stream_mixed_valid_and_invalid_lines() {
+        let first = LogEntry::new(LogLevel::Info, "svc".to_string(), "first".to_string());
+        let second = LogEntry::new(LogLevel::Error, "svc".to_string(), "second".to_string());
+
+        let input = format!(
+            "{}\n\n   \nnot json\n{{\"broken\":\n{}",
+            first.to_json().unwrap(),
+            second.to_json().unwrap(),
+        );
+
+        let mut stream = LogEntry::stream_from_reader(input.as_bytes());
+
+        let entry = stream.next().await.unwrap().unwrap();
+        assert_eq!(entry.id, first.id);
+        assert_eq!(entry.message, "first");
+
+        assert!(matches!(stream.next().await, Some(Err(LogStreamError::Serde(_)))));
+        assert!(matches!(stream.next().await, Some(Err(LogStreamError::Serde(_)))));
+
+        // Final line has no trailing newline but is still yielded
+        let entry = stream.next().await.unwrap().unwrap();
+        assert_eq!(entry.id, second.id);
+        assert_eq!(entry.level, LogLevel::Error);
+
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_byte_parsing_matches_string_parsing() {
+        let mut fielded = LogEntry::new(LogLevel::Warning, "svc".to_string(), "quota at 90%".to_string());
+        fielded.fields.insert("path".to_string(), "C:\\data\t\"quoted\"".to_string());
+        fielded.pid = Some(7);
+        fielded.hostname = Some("höst-01".into());
+        let unicode = LogEntry::new(LogLevel::Info, "svc".to_string(), "grüße ✓ \u{1F600} \\n".to_string());
+        let lines = [
+            fielded.to_json().unwrap(),
+            format!("  {}\r", unicode.to_json().unwrap()),
+            r#"{"id":"7f1c2a9e-3b4d-4e5f-8a6b-9c0d1e2f3a4b","timestamp":1704164645,"level":"Debug","daemon":"sh","message":"\u00e9\ud83d\ude00"}"#.to_string(),
+        ];
+
+        let input = lines.join("\n");
+        let mut stream = LogEntry::stream_from_reader(input.as_bytes());
+        for line in &lines {
+            let from_bytes = stream.next().await.unwrap().unwrap();
+            let from_string = LogEntry::from_json(line.trim()).unwrap();
+            assert_eq!(from_bytes.to_json().unwrap(), from_string.to_json().unwrap());
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    /// Reader that returns its data and then fails instead of reporting EOF
+    struct FailingReader {
+        data: Option<Vec<u8>>,
+    }
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            match self.data.take() {
+                Some(data) => {
+                    buf.put_slice(&data);
+                    Poll::Ready(Ok(()))
+                }
+                None => Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_yields_buffered_lines_before_error() {
+        let entries: Vec<LogEntry> = (0..3)
+            .map(|i| LogEntry::new(LogLevel::Info, "svc".to_string(), format!("entry {}", i)))
+            .collect();
+        let mut data = String::new();
+        for entry in &entries {
+            data.push_str(&entry.to_json().unwrap());
+            data.push('\n');
+        }
+        // Partial line cut off by the failure
+        data.push_str("{\"id\":");
+
+        let mut stream = LogEntry::stream_from_reader(FailingReader {
+            data: Some(data.into_bytes()),
+        });
+
+        for entry in &entries {
+            assert_eq!(stream.next().await.unwrap().unwrap().id, entry.id);
+        }
+        assert!(matches!(stream.next().await, Some(Err(LogStreamError::Io(_)))));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_next_frame_separates_control_commands() {
+        let entry = LogEntry::new(LogLevel::Info, "svc".to_string(), "hello".to_string());
+        let input = format!("{{\"cmd\":\"ping\"}}\n{}\n{{\"cmd\":\"bogus\"}}\n", entry.to_json().unwrap());
+
+        let mut stream = LogEntry::stream_from_reader(input.as_bytes());
+        assert!(matches!(
+            stream.next_frame().await,
+            Some(Ok(Frame::Control(ControlCommand::Ping)))
+        ));
+        match stream.next_frame().await {
+            Some(Ok(Frame::Entry(parsed))) => assert_eq!(parsed.id, entry.id),
+            other => panic!("expected entry, got {:?}", other),
+        }
+        assert!(matches!(stream.next_frame().await, Some(Err(LogStreamError::Serde(_)))));
+        assert!(stream.next_frame().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_empty_input() {
+        let mut stream = LogEntry::stream_from_reader(&b"\n\n"[..]);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_rejects_deep_nesting() {
+        let valid = LogEntry::new(LogLevel::Info, "svc".to_string(), "after".to_string());
+        let nested = format!(
+            "{{\"fields\":{}{}}}",
+            "[".repeat(100_000),
+            "]".repeat(100_000)
+        );
+        let input = format!("{}\n{}\n", nested, valid.to_json().unwrap());
+
+        let mut stream = LogEntry::stream_from_reader(input.as_bytes());
+        match stream.next().await {
+            Some(Err(LogStreamError::Server(msg))) => assert!(msg.contains("nested")),
+            other => panic!("expected nesting rejection, got {:?}", other),
+        }
+        assert_eq!(stream.next().await.unwrap().unwrap().id, valid.id);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_rejects_oversized_line() {
+        let limits = LineLimits {
+            max_line_bytes: 4096,
+            ..Default::default()
+        };
+        let mut huge = LogEntry::new(LogLevel::Info, "svc".to_string(), "huge".to_string());
+        huge.fields.insert("blob".to_string(), "x".repeat(1024 * 1024));
+        let valid = LogEntry::new(LogLevel::Info, "svc".to_string(), "after".to_string());
+
+        // Unterminated oversized line at EOF is rejected too
+        let input = format!(
+            "{}\n{}\n{}",
+            huge.to_json().unwrap(),
+            valid.to_json().unwrap(),
+            huge.to_json().unwrap()
+        );
+
+        let mut stream = LogEntryStream::with_limits(input.as_bytes(), limits);
+        match stream.next().await {
+            Some(Err(LogStreamError::Server(msg))) => assert!(msg.contains("longer than 4096")),
+            other => panic!("expected length rejection, got {:?}", other),
+        }
+        assert_eq!(stream.next().await.unwrap().unwrap().id, valid.id);
+        assert!(matches!(stream.next().await, Some(Err(LogStreamError::Server(_)))));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8_rejected_unless_lossy() {
+        let entry = LogEntry::new(LogLevel::Info, "svc".to_string(), "MARKER".to_string());
+        let mut line = entry.to_json().unwrap().into_bytes();
+        let at = line.windows(6).position(|w| w == b"MARKER").unwrap();
+        line.splice(at..at + 6, *b"bad\xc3(");
+        line.push(b'\n');
+
+        let mut strict = LogEntryStream::new(line.as_slice());
+        assert!(matches!(strict.next().await, Some(Err(LogStreamError::Serde(_)))));
+
+        let limits = LineLimits {
+            lossy_utf8: true,
+            ..Default::default()
+        };
+        let mut lossy = LogEntryStream::with_limits(line.as_slice(), limits);
+        let recovered = lossy.next().await.unwrap().unwrap();
+        assert_eq!(recovered.id, entry.id);
+        assert_eq!(recovered.message, "bad\u{FFFD}(");
+        assert_eq!(recovered.fields[LOSSY_ENCODING_FIELD], "lossy");
+    }
+
+    #[test]
+    fn test_exceeds_depth_ignores_brackets_in_strings() {
+        assert!(!exceeds_depth(br#"{"message":"[[[[[[{{{{"}"#, 2));
+        assert!(!exceeds_depth(br#"{"message":"quote \" [[[["}"#, 2));
+        assert!(!exceeds_depth(br#"{"fields":{"a":"b"}}"#, 2));
+        assert!(exceeds_depth(br#"{"fields":{"a":["b"]}}"#, 2));
+    }
+
+    #[tokio::test]
+    async fn test_chunked_entry_reassembled_within_limit() {
+        let limits = LineLimits {
+            max_line_bytes: 256,
+            max_reassembled_bytes: 4096,
+            ..Default::default()
+        };
+        let mut big = LogEntry::new(LogLevel::Info, "svc".to_string(), "big".to_string());
+        big.fields.insert("body".to_string(), "\"quoted\"\n".repeat(200));
+        let mut too_big = big.clone();
+        too_big.fields.insert("body".to_string(), "y".repeat(8192));
+        let after = LogEntry::new(LogLevel::Info, "svc".to_string(), "after".to_string());
+
+        let mut input = String::new();
+        for entry in [&big, &too_big] {
+            for chunk in EntryChunk::split(&entry.to_json().unwrap(), 200) {
+                input.push_str(&chunk.to_json().unwrap());
+                input.push('\n');
+            }
+        }
+        input.push_str(&after.to_json().unwrap());
+
+        let mut stream = LogEntryStream::with_limits(input.as_bytes(), limits);
+        let reassembled = stream.next().await.unwrap().unwrap();
+        assert_eq!(reassembled.id, big.id);
+        assert_eq!(reassembled.fields["body"], big.fields["body"]);
+        match stream.next().await {
+            Some(Err(LogStreamError::Server(msg))) => assert!(msg.contains("larger than 4096")),
+            other => panic!("expected size rejection, got {:?}", other),
+        }
+        // The rest of the oversized entry's chunks are skipped silently
+        assert_eq!(stream.next().await.unwrap().unwrap().id, after.id);
+        assert!(stream.next().await.is_none());
+    }
+}
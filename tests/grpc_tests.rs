@@ -0,0 +1,79 @@
+//! Integration tests for gRPC ingestion (requires the `grpc` feature)
+
+#![cfg(feature = "grpc")]
+
+use logstream::config::ServerConfig;
+use logstream::server::grpc::proto::log_ingest_client::LogIngestClient;
+use logstream::server::grpc::proto::{LogBatch, LogEntry as ProtoEntry, LogLevel as ProtoLevel};
+use logstream::server::grpc::GrpcIngest;
+use logstream::server::{IngestRouter, StorageBackend};
+use logstream::types::{LogEntry, LogLevel};
+use std::sync::Arc;
+use tempfile::tempdir;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+fn proto_entry(level: ProtoLevel, message: &str) -> ProtoEntry {
+    ProtoEntry {
+        level: level as i32,
+        daemon: "grpc-daemon".to_string(),
+        message: message.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Stream a few batches from a generated client and check they are stored
+#[tokio::test]
+async fn test_submit_logs_stores_entries_and_acks_batches() {
+    let temp_dir = tempdir().unwrap();
+    let mut config = ServerConfig::default();
+    config.storage.output_directory = temp_dir.path().to_path_buf();
+    let storage = Arc::new(StorageBackend::new(&config).await.unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let (router, workers) = IngestRouter::start(&config, &storage);
+    let server = tokio::spawn(GrpcIngest::serve(Arc::clone(&storage), router, listener, shutdown_rx));
+
+    let mut client = LogIngestClient::connect(format!("http://{}", addr)).await.unwrap();
+    let mut invalid = proto_entry(ProtoLevel::Info, "bad id");
+    invalid.id = "not-a-uuid".to_string();
+    let batches = vec![
+        LogBatch {
+            entries: vec![
+                proto_entry(ProtoLevel::Info, "first"),
+                proto_entry(ProtoLevel::Error, "second"),
+            ],
+        },
+        LogBatch {
+            entries: vec![proto_entry(ProtoLevel::Debug, "third"), invalid],
+        },
+    ];
+
+    let mut acks = client
+        .submit_logs(tokio_stream::iter(batches))
+        .await
+        .unwrap()
+        .into_inner();
+    let first = acks.message().await.unwrap().unwrap();
+    assert_eq!((first.batch, first.accepted, first.rejected), (0, 2, 0));
+    let second = acks.message().await.unwrap().unwrap();
+    assert_eq!((second.batch, second.accepted, second.rejected), (1, 1, 1));
+    assert!(acks.message().await.unwrap().is_none());
+    assert_eq!(storage.rejected_lines(), 1);
+
+    // Once the server stops and drops its router, the workers store what was acked
+    let _ = shutdown_tx.send(());
+    server.await.unwrap().unwrap();
+    for worker in workers {
+        worker.await.unwrap();
+    }
+    let contents = tokio::fs::read_to_string(temp_dir.path().join("grpc-daemon.log")).await.unwrap();
+    let stored: Vec<LogEntry> = contents.lines().map(|line| LogEntry::from_json(line).unwrap()).collect();
+    let summary: Vec<(LogLevel, &str)> = stored.iter().map(|e| (e.level, e.message.as_str())).collect();
+    assert_eq!(
+        summary,
+        vec![(LogLevel::Info, "first"), (LogLevel::Error, "second"), (LogLevel::Debug, "third")]
+    );
+}
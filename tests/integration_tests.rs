@@ -1,20 +1,22 @@
 //! Integration tests for LogStream
 
 use logstream::client::LogClient;
-use logstream::config::ServerConfig;
-use logstream::server::LogServer;
+use logstream::config::{ClientConfig, ListenerSpec, ServerConfig};
+use logstream::server::{LogServer, SHUTDOWN_REPORT_FILE};
+use logstream::types::{LogEntry, LogLevel};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use tempfile::tempdir;
 use tokio::fs;
 use tokio::time::{sleep, timeout};
 
 /// Helper function to create a test server config
-async fn create_test_server_config(socket_path: &str, log_dir: &PathBuf) -> ServerConfig {
+async fn create_test_server_config(socket_path: &str, log_dir: &Path) -> ServerConfig {
     let mut config = ServerConfig::default();
     config.server.socket_path = socket_path.to_string();
-    config.storage.output_directory = log_dir.clone();
+    config.storage.output_directory = log_dir.to_path_buf();
     config.backends.file.enabled = true;
     config.backends.file.format = "json".to_string();
     config
@@ -509,4 +511,379 @@ async fn test_log_metadata() {
     
     // Shutdown server
     server_handle.abort();
-}
\ No newline at end of file
+}
+
+/// Test that a pre-built entry is stored unchanged
+#[tokio::test]
+async fn test_send_prebuilt_entry() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("prebuilt.sock");
+    let socket_str = socket_path.to_string_lossy().to_string();
+    let log_dir = temp_dir.path().join("logs");
+    fs::create_dir_all(&log_dir).await.unwrap();
+
+    let config = create_test_server_config(&socket_str, &log_dir).await;
+    let server = LogServer::new(config).await.unwrap();
+
+    let server_handle = tokio::spawn(async move {
+        server.start().await
+    });
+
+    sleep(Duration::from_millis(200)).await;
+
+    let client = LogClient::connect(&socket_str, "prebuilt-client").await.unwrap();
+
+    let id = uuid::Uuid::parse_str("7f1c2a9e-3b4d-4e5f-8a6b-9c0d1e2f3a4b").unwrap();
+    let timestamp = chrono::DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+        .unwrap()
+        .with_timezone(&chrono::Utc);
+    let mut entry = LogEntry::new(LogLevel::Warning, "imported".to_string(), "Replayed entry".to_string());
+    entry.id = id;
+    entry.timestamp = timestamp;
+    entry.pid = Some(4242);
+
+    client.send_entry(entry).await.unwrap();
+    client.close().await.unwrap();
+
+    sleep(Duration::from_millis(100)).await;
+
+    // The entry's own daemon decides where it is stored, not the client's
+    let content = fs::read_to_string(log_dir.join("imported.log")).await.unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 1);
+
+    let stored = LogEntry::from_json(lines[0]).unwrap();
+    assert_eq!(stored.id, id);
+    assert_eq!(stored.timestamp, timestamp);
+    assert_eq!(stored.level, LogLevel::Warning);
+    assert_eq!(stored.pid, Some(4242));
+    assert!(stored.hostname.is_some());
+
+    server_handle.abort();
+}
+
+/// Test that a bound server accepts clients without waiting for startup
+#[tokio::test]
+async fn test_bind_then_connect_without_sleep() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("bound.sock");
+    let socket_str = socket_path.to_string_lossy().to_string();
+    let log_dir = temp_dir.path().join("logs");
+    fs::create_dir_all(&log_dir).await.unwrap();
+
+    let config = create_test_server_config(&socket_str, &log_dir).await;
+    let server = LogServer::new(config).await.unwrap();
+    let bound = server.bind().await.unwrap();
+    assert!(socket_path.exists());
+
+    let server_handle = tokio::spawn(bound.serve());
+
+    let client = LogClient::connect(&socket_str, "bound-daemon").await.unwrap();
+    client.info("Logged right after bind").await.unwrap();
+    client.close().await.unwrap();
+
+    let log_file = log_dir.join("bound-daemon.log");
+    let content = timeout(Duration::from_secs(2), async {
+        loop {
+            if let Ok(content) = fs::read_to_string(&log_file).await {
+                if !content.is_empty() {
+                    return content;
+                }
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(content.contains("Logged right after bind"));
+
+    server_handle.abort();
+}
+
+/// An entry larger than the server's line limit arrives intact when chunked
+#[tokio::test]
+async fn test_chunked_large_entry_stored_intact() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("chunked.sock");
+    let socket_str = socket_path.to_string_lossy().to_string();
+    let log_dir = temp_dir.path().join("logs");
+    fs::create_dir_all(&log_dir).await.unwrap();
+
+    let mut config = create_test_server_config(&socket_str, &log_dir).await;
+    config.server.max_line_bytes = 128 * 1024;
+    config.server.shutdown_timeout_secs = 1;
+    let server = Arc::new(LogServer::new(config).await.unwrap());
+    let bound = server.bind().await.unwrap();
+    let server_handle = tokio::spawn(bound.serve());
+
+    let client_config = ClientConfig {
+        socket_path: socket_str.clone(),
+        daemon_name: "chunked-daemon".to_string(),
+        chunk_bytes: Some(64 * 1024),
+        ..Default::default()
+    };
+    let client = LogClient::with_config(client_config).await.unwrap();
+    let body: String = (0..2 * 1024 * 1024).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+    let mut fields = HashMap::new();
+    fields.insert("body".to_string(), body.clone());
+    client.info_with_fields("captured request", fields).await.unwrap();
+    client.close().await.unwrap();
+
+    server.shutdown();
+    timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+
+    let content = fs::read_to_string(log_dir.join("chunked-daemon.log")).await.unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let entry = LogEntry::from_json(lines[0]).unwrap();
+    assert_eq!(entry.message, "captured request");
+    assert_eq!(entry.fields["body"], body);
+}
+
+/// The metrics snapshot counts what clients logged, without the metrics feature
+#[tokio::test]
+async fn test_metrics_snapshot_reflects_logged_entries() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("snapshot.sock");
+    let socket_str = socket_path.to_string_lossy().to_string();
+    let log_dir = temp_dir.path().join("logs");
+    fs::create_dir_all(&log_dir).await.unwrap();
+
+    let config = create_test_server_config(&socket_str, &log_dir).await;
+    let server = Arc::new(LogServer::new(config).await.unwrap());
+    let bound = server.bind().await.unwrap();
+    let server_handle = tokio::spawn(bound.serve());
+
+    for (daemon, count) in [("snapshot-api", 5), ("snapshot-db", 3)] {
+        let client = LogClient::connect(&socket_str, daemon).await.unwrap();
+        for i in 0..count {
+            client.info(&format!("message {}", i)).await.unwrap();
+        }
+        client.close().await.unwrap();
+    }
+
+    server.shutdown();
+    timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+
+    let snapshot = server.metrics_snapshot();
+    assert_eq!(snapshot.totals.entries_accepted, 8);
+    assert_eq!(snapshot.per_daemon["snapshot-api"], 5);
+    assert_eq!(snapshot.per_daemon["snapshot-db"], 3);
+    assert_eq!(snapshot.open_files, 2);
+    assert_eq!(snapshot.queue_depth, 0);
+    assert!(snapshot.entries_per_sec > 0.0);
+
+    let json = serde_json::to_value(&snapshot).unwrap();
+    assert_eq!(json["totals"]["entries_accepted"], 8);
+    assert_eq!(json["per_daemon"]["snapshot-db"], 3);
+}
+
+/// Graceful shutdown appends a report of the run to the server's own log
+#[tokio::test]
+async fn test_shutdown_report_records_totals() {
+    let temp_dir = tempdir().unwrap();
+    let socket_str = temp_dir.path().join("report.sock").to_string_lossy().to_string();
+    let log_dir = temp_dir.path().join("logs");
+    fs::create_dir_all(&log_dir).await.unwrap();
+
+    let config = create_test_server_config(&socket_str, &log_dir).await;
+    let server = Arc::new(LogServer::new(config).await.unwrap());
+    let bound = server.bind().await.unwrap();
+    let server_handle = tokio::spawn(bound.serve());
+
+    for (daemon, count) in [("report-api", 4), ("report-db", 2)] {
+        let client = LogClient::connect(&socket_str, daemon).await.unwrap();
+        for i in 0..count {
+            client.info(&format!("message {}", i)).await.unwrap();
+        }
+        client.close().await.unwrap();
+    }
+
+    server.shutdown();
+    timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+
+    let content = fs::read_to_string(log_dir.join(SHUTDOWN_REPORT_FILE)).await.unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let report: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(report["event"], "shutdown");
+    assert_eq!(report["totals"]["entries_accepted"], 6);
+    assert_eq!(report["per_daemon"]["report-api"], 4);
+    assert_eq!(report["per_daemon"]["report-db"], 2);
+    assert_eq!(report["rotations"], 0);
+    assert_eq!(report["queue_depth"], 0);
+
+    let mut stored_bytes = 0;
+    for daemon in ["report-api", "report-db"] {
+        stored_bytes += fs::metadata(log_dir.join(format!("{}.log", daemon))).await.unwrap().len();
+    }
+    assert_eq!(report["bytes_written"], stored_bytes);
+}
+
+/// A leaf server forwards what it stores to an upstream server
+#[tokio::test]
+async fn test_leaf_forwards_to_upstream() {
+    let temp_dir = tempdir().unwrap();
+    let upstream_socket = temp_dir.path().join("upstream.sock").to_string_lossy().to_string();
+    let leaf_socket = temp_dir.path().join("leaf.sock").to_string_lossy().to_string();
+    let upstream_dir = temp_dir.path().join("upstream");
+    let leaf_dir = temp_dir.path().join("leaf");
+    fs::create_dir_all(&upstream_dir).await.unwrap();
+    fs::create_dir_all(&leaf_dir).await.unwrap();
+
+    let upstream = LogServer::new(create_test_server_config(&upstream_socket, &upstream_dir).await)
+        .await
+        .unwrap();
+    let upstream_handle = tokio::spawn(upstream.bind().await.unwrap().serve());
+
+    let mut leaf_config = create_test_server_config(&leaf_socket, &leaf_dir).await;
+    leaf_config.backends.forward.enabled = true;
+    leaf_config.backends.forward.upstream_socket = upstream_socket.clone();
+    leaf_config.backends.forward.daemon_prefix = "edge-".to_string();
+    let leaf = LogServer::new(leaf_config).await.unwrap();
+    let leaf_handle = tokio::spawn(leaf.bind().await.unwrap().serve());
+
+    let client = LogClient::connect(&leaf_socket, "web").await.unwrap();
+    for i in 0..5 {
+        client.info(&format!("forwarded {}", i)).await.unwrap();
+    }
+    client.close().await.unwrap();
+
+    let upstream_log = upstream_dir.join("edge-web.log");
+    let messages = timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(content) = fs::read_to_string(&upstream_log).await {
+                let messages: Vec<String> = content
+                    .lines()
+                    .map(|line| LogEntry::from_json(line).unwrap().message)
+                    .collect();
+                if messages.len() == 5 {
+                    return messages;
+                }
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("entries should reach the upstream");
+    let expected: Vec<String> = (0..5).map(|i| format!("forwarded {}", i)).collect();
+    assert_eq!(messages, expected);
+
+    // The leaf keeps its own copy under the original name
+    let local = fs::read_to_string(leaf_dir.join("web.log")).await.unwrap();
+    assert_eq!(local.lines().count(), 5);
+
+    leaf_handle.abort();
+    upstream_handle.abort();
+}
+
+/// Clients on every configured socket reach the same storage, and shutdown closes them all
+#[tokio::test]
+async fn test_multiple_listeners_share_storage() {
+    let temp_dir = tempdir().unwrap();
+    let main_socket = temp_dir.path().join("main.sock").to_string_lossy().to_string();
+    let legacy_socket = temp_dir.path().join("legacy.sock").to_string_lossy().to_string();
+    let log_dir = temp_dir.path().join("logs");
+    fs::create_dir_all(&log_dir).await.unwrap();
+
+    let mut config = create_test_server_config(&main_socket, &log_dir).await;
+    config.server.listeners = vec![ListenerSpec::Unix(legacy_socket.clone())];
+    let server = Arc::new(LogServer::new(config).await.unwrap());
+    let bound = server.bind().await.unwrap();
+    let server_handle = tokio::spawn(bound.serve());
+
+    for socket in [&main_socket, &legacy_socket] {
+        let client = LogClient::connect(socket, "shared-daemon").await.unwrap();
+        client.info(&format!("via {}", socket)).await.unwrap();
+        client.close().await.unwrap();
+    }
+
+    server.shutdown();
+    timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+
+    let content = fs::read_to_string(log_dir.join("shared-daemon.log")).await.unwrap();
+    let messages: Vec<String> = content
+        .lines()
+        .map(|line| LogEntry::from_json(line).unwrap().message)
+        .collect();
+    assert_eq!(messages.len(), 2);
+    assert!(messages.contains(&format!("via {}", main_socket)));
+    assert!(messages.contains(&format!("via {}", legacy_socket)));
+
+    // Both sockets stop accepting once the server is down
+    for socket in [&main_socket, &legacy_socket] {
+        assert!(tokio::net::UnixStream::connect(socket).await.is_err());
+    }
+}
+
+/// Test that --check-config reports valid and invalid configurations through its exit status
+#[test]
+fn test_check_config_flag() {
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path().join("server.toml");
+    let check = |config: &ServerConfig| {
+        std::fs::write(&path, toml::to_string(config).unwrap()).unwrap();
+        std::process::Command::new(env!("CARGO_BIN_EXE_logstream-server"))
+            .arg("--config")
+            .arg(&path)
+            .arg("--check-config")
+            .output()
+            .unwrap()
+    };
+    let mut config = ServerConfig::default();
+    config.server.socket_path = temp_dir.path().join("logstream.sock").to_string_lossy().to_string();
+    config.storage.output_directory = temp_dir.path().join("logs");
+
+    let output = check(&config);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // Nothing but the summary goes to stdout
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Configuration OK"), "{}", stdout);
+    assert!(!stdout.contains("Starting LogStream Server"), "{}", stdout);
+    assert!(!temp_dir.path().join("logs").exists(), "checking must not create the output directory");
+
+    config.backends.file.compression = true;
+    config.backends.file.compression_level = 12;
+    let output = check(&config);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid configuration"));
+}
+
+#[test]
+fn test_validate_entries_subcommand() {
+    use std::io::Write;
+
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path().join("server.toml");
+    let mut config = ServerConfig::default();
+    config.storage.output_directory = temp_dir.path().join("logs");
+    config.server.min_level = Some(LogLevel::Warning);
+    std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_logstream-server"))
+        .arg("--config")
+        .arg(&path)
+        .arg("validate-entries")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let entries = [
+        LogEntry::new(LogLevel::Error, "api".to_string(), "failed".to_string()).to_json().unwrap(),
+        LogEntry::new(LogLevel::Info, "api".to_string(), "served".to_string()).to_json().unwrap(),
+        "not json".to_string(),
+    ];
+    child.stdin.take().unwrap().write_all(entries.join("\n").as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    // Only the reports go to stdout, so it can be piped on
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("line 1: "), "{}", stdout);
+    let api_log = temp_dir.path().join("logs/api.log");
+    assert!(stdout.contains(&format!("line 1: stored in {} via file", api_log.display())), "{}", stdout);
+    assert!(stdout.contains("line 2: dropped: below min_level Warning"), "{}", stdout);
+    assert!(stdout.contains("line 3: rejected: "), "{}", stdout);
+    assert!(stdout.contains("1 stored, 1 dropped, 1 rejected"), "{}", stdout);
+    assert!(!temp_dir.path().join("logs").exists(), "a dry run must not create the output directory");
+}
@@ -3,7 +3,7 @@
 use logstream::client::LogClient;
 use logstream::config::{RotationSettings, ServerConfig};
 use logstream::server::LogServer;
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::Duration;
 use tempfile::tempdir;
 use tokio::fs;
@@ -12,13 +12,13 @@ use tokio::time::sleep;
 /// Helper to create server config with rotation enabled
 async fn create_rotation_config(
     socket_path: &str,
-    log_dir: &PathBuf,
+    log_dir: &Path,
     max_age_hours: u32,
     keep_files: u32,
 ) -> ServerConfig {
     let mut config = ServerConfig::default();
     config.server.socket_path = socket_path.to_string();
-    config.storage.output_directory = log_dir.clone();
+    config.storage.output_directory = log_dir.to_path_buf();
     config.storage.rotation = RotationSettings {
         enabled: true,
         max_age_hours,
@@ -0,0 +1,67 @@
+//! Signal handling tests for LogStream
+//!
+//! Kept in their own test binary: the signals are sent to the whole process.
+
+#![cfg(unix)]
+
+use logstream::client::LogClient;
+use logstream::config::ServerConfig;
+use logstream::server::{shutdown_signal, LogServer};
+use logstream::types::LogEntry;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::fs;
+use tokio::time::timeout;
+
+/// SIGTERM stops the server through the same graceful path as Ctrl+C
+#[tokio::test]
+async fn test_sigterm_drains_and_stops_server() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("signal.sock");
+    let socket_str = socket_path.to_string_lossy().to_string();
+
+    let mut config = ServerConfig::default();
+    config.server.socket_path = socket_str.clone();
+    config.server.shutdown_timeout_secs = 2;
+    config.storage.output_directory = temp_dir.path().to_path_buf();
+    let server = Arc::new(LogServer::new(config).await.unwrap());
+
+    let signal = shutdown_signal().unwrap();
+    let running = Arc::clone(&server);
+    let server_handle = tokio::spawn(async move { running.start_until(signal).await });
+
+    // Wait for the socket before connecting
+    timeout(Duration::from_secs(2), async {
+        while !socket_path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let client = LogClient::connect(&socket_str, "signal-daemon").await.unwrap();
+    for i in 0..20 {
+        client.info(&format!("before sigterm {}", i)).await.unwrap();
+    }
+    client.close().await.unwrap();
+
+    unsafe {
+        libc::kill(libc::getpid(), libc::SIGTERM);
+    }
+    timeout(Duration::from_secs(5), server_handle)
+        .await
+        .expect("server should stop after SIGTERM")
+        .unwrap()
+        .unwrap();
+
+    // Everything sent before the signal was stored
+    let content = fs::read_to_string(temp_dir.path().join("signal-daemon.log")).await.unwrap();
+    let messages: Vec<String> = content
+        .lines()
+        .map(|line| LogEntry::from_json(line).unwrap().message)
+        .collect();
+    let expected: Vec<String> = (0..20).map(|i| format!("before sigterm {}", i)).collect();
+    assert_eq!(messages, expected);
+    assert_eq!(server.metrics_snapshot().queue_depth, 0);
+}
@@ -0,0 +1,69 @@
+//! Socket activation tests for LogStream
+//!
+//! Kept in their own test binary: the passed socket has to sit at file
+//! descriptor 3 and the `LISTEN_*` variables are process-wide.
+
+#![cfg(unix)]
+
+use logstream::client::LogClient;
+use logstream::config::ServerConfig;
+use logstream::server::LogServer;
+use logstream::types::LogEntry;
+use std::os::fd::IntoRawFd;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::time::timeout;
+
+/// The server serves on a socket passed in through LISTEN_FDS instead of binding its own
+#[test]
+fn test_server_uses_inherited_socket() {
+    let temp_dir = tempdir().unwrap();
+    let activated_socket = temp_dir.path().join("activated.sock");
+    let activated_str = activated_socket.to_string_lossy().to_string();
+    let configured_socket = temp_dir.path().join("configured.sock");
+
+    // Put a bound socket where systemd would, before the runtime opens any descriptors
+    let fd = std::os::unix::net::UnixListener::bind(&activated_socket)
+        .unwrap()
+        .into_raw_fd();
+    if fd != 3 {
+        unsafe {
+            assert_eq!(libc::fcntl(3, libc::F_GETFD), -1, "fd 3 already in use");
+            assert_eq!(libc::dup2(fd, 3), 3);
+            libc::close(fd);
+        }
+    }
+    std::env::set_var("LISTEN_PID", std::process::id().to_string());
+    std::env::set_var("LISTEN_FDS", "1");
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut config = ServerConfig::default();
+        config.server.socket_path = configured_socket.to_string_lossy().to_string();
+        config.storage.output_directory = temp_dir.path().to_path_buf();
+        config.backends.file.enabled = true;
+        config.backends.file.format = "json".to_string();
+        let server = Arc::new(LogServer::new(config).await.unwrap());
+        let bound = server.bind().await.unwrap();
+        let server_handle = tokio::spawn(bound.serve());
+
+        // The variables stay set, but the sockets are only claimed once
+        assert!(std::env::var_os("LISTEN_FDS").is_some());
+        assert!(logstream::server::listener::inherited_listeners().unwrap().is_none());
+        assert!(!configured_socket.exists());
+
+        let client = LogClient::connect(&activated_str, "activated-daemon").await.unwrap();
+        client.info("via systemd").await.unwrap();
+        client.close().await.unwrap();
+
+        server.shutdown();
+        timeout(Duration::from_secs(5), server_handle).await.unwrap().unwrap().unwrap();
+
+        let content = tokio::fs::read_to_string(temp_dir.path().join("activated-daemon.log"))
+            .await
+            .unwrap();
+        let entry = LogEntry::from_json(content.lines().next().unwrap()).unwrap();
+        assert_eq!(entry.message, "via systemd");
+    });
+}